@@ -1,6 +1,8 @@
-use criterion::{criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
 
 use hash_bench::bloom_filter::BloomFilter;
+use hash_bench::concurrent_bloom_filter::ConcurrentBloomFilter;
+use hash_bench::siphash::{SipHash13, SipHash24};
 
 fn bench_bloom_filter(c: &mut Criterion) {
     c.bench_function("bench_bloom_filter", |b| {
@@ -16,5 +18,216 @@ fn bench_bloom_filter(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, bench_bloom_filter,);
+fn bench_bloom_filter_hasher(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bloom_filter_hasher");
+    let n = 10_000u32;
+    let f = 0.01;
+    let keys: Vec<[u8; 4]> = (0..n).map(|i| i.to_be_bytes()).collect();
+    let filters = [
+        ("murmur3", BloomFilter::new(n.into(), f)),
+        (
+            "siphash13",
+            BloomFilter::with_hasher(n.into(), f, SipHash13::with_keys(1, 2)),
+        ),
+        (
+            "siphash24",
+            BloomFilter::with_hasher(n.into(), f, SipHash24::with_keys(1, 2)),
+        ),
+    ];
+    for (name, mut filter) in filters {
+        group.bench_function(BenchmarkId::new("insert_lookup", name), |b| {
+            b.iter(|| {
+                for key in &keys {
+                    filter.insert(key);
+                }
+                for key in &keys {
+                    std::hint::black_box(filter.lookup(key));
+                }
+            });
+        });
+    }
+    group.finish();
+}
+
+/// Integer keys hashed directly against the same keys as big-endian bytes.
+fn bench_bloom_filter_u64_keys(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bloom_filter_u64_keys");
+    let n = 10_000u64;
+    let mut filter = BloomFilter::new(n, 0.01);
+    group.bench_function("bytes", |b| {
+        b.iter(|| {
+            for key in 0..n {
+                filter.insert(&key.to_be_bytes());
+                std::hint::black_box(filter.lookup(&key.to_be_bytes()));
+            }
+        });
+    });
+    let mut filter = BloomFilter::new(n, 0.01);
+    group.bench_function("u64", |b| {
+        b.iter(|| {
+            for key in 0..n {
+                filter.insert_u64(key);
+                std::hint::black_box(filter.lookup_u64(key));
+            }
+        });
+    });
+    group.finish();
+}
+
+/// Lookups of absent keys only, which dominate negative-lookup workloads
+/// and exit at the first probe word that is not fully set.
+fn bench_bloom_filter_lookup_miss(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bloom_filter_lookup_miss");
+    let n = 100_000u64;
+    let misses: Vec<u64> = (n..2 * n).collect();
+    for f in [0.01, 0.0001] {
+        let mut murmur3 = BloomFilter::new(n, f);
+        let mut siphash13 = BloomFilter::with_hasher(n, f, SipHash13::with_keys(1, 2));
+        for key in 0..n {
+            murmur3.insert(&key.to_be_bytes());
+            siphash13.insert(&key.to_be_bytes());
+        }
+        group.bench_function(BenchmarkId::new("murmur3_bytes", f), |b| {
+            b.iter(|| {
+                for key in &misses {
+                    std::hint::black_box(murmur3.lookup(&key.to_be_bytes()));
+                }
+            });
+        });
+        group.bench_function(BenchmarkId::new("siphash13_bytes", f), |b| {
+            b.iter(|| {
+                for key in &misses {
+                    std::hint::black_box(siphash13.lookup(&key.to_be_bytes()));
+                }
+            });
+        });
+        let mut murmur3 = BloomFilter::new(n, f);
+        for key in 0..n {
+            murmur3.insert_u64(key);
+        }
+        group.bench_function(BenchmarkId::new("murmur3_u64", f), |b| {
+            b.iter(|| {
+                for &key in &misses {
+                    std::hint::black_box(murmur3.lookup_u64(key));
+                }
+            });
+        });
+    }
+    group.finish();
+}
+
+/// Compares dense and Roaring-backed lookups on a filter sized for one
+/// million items at several fill levels, printing the memory of each so the
+/// crossover point is visible next to the timings.
+#[cfg(feature = "roaring")]
+fn bench_bloom_filter_sparse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bloom_filter_sparse");
+    let n = 1_000_000u32;
+    let probes: Vec<[u8; 4]> = (0..10_000u32).map(|i| (i * 7 + n).to_be_bytes()).collect();
+    for fill in [1u32, 10, 100, 1_000, 10_000, 100_000] {
+        let mut dense = BloomFilter::new(n.into(), 0.01);
+        for i in 0..fill {
+            dense.insert(&i.to_be_bytes());
+        }
+        let sparse = dense.clone().into_sparse();
+        println!(
+            "bloom_filter_sparse fill {}: dense {} bytes, sparse {} bytes",
+            fill,
+            dense.bit_array_bytes(),
+            sparse.bit_array_bytes()
+        );
+        for (name, filter) in [("dense", &dense), ("sparse", &sparse)] {
+            group.bench_function(BenchmarkId::new(name, fill), |b| {
+                b.iter(|| {
+                    for probe in &probes {
+                        std::hint::black_box(filter.lookup(probe));
+                    }
+                });
+            });
+        }
+    }
+    group.finish();
+}
+
+#[cfg(not(feature = "roaring"))]
+fn bench_bloom_filter_sparse(_c: &mut Criterion) {
+    println!("bloom_filter_sparse: run with --features roaring");
+}
+
+/// Inserts and lookups one call per key against the prefetching batch
+/// calls, on a filter too large for the cache.
+fn bench_bloom_filter_batch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bloom_filter_batch");
+    let n = 4_000_000u64;
+    let keys: Vec<[u8; 8]> = (0..100_000u64)
+        .map(|i| i.wrapping_mul(0x9e37_79b9_7f4a_7c15).to_le_bytes())
+        .collect();
+    let mut filter = BloomFilter::new(n, 0.01);
+    group.bench_function("one_by_one", |b| {
+        b.iter(|| {
+            for key in &keys {
+                filter.insert(key);
+            }
+            for key in &keys {
+                std::hint::black_box(filter.lookup(key));
+            }
+        });
+    });
+    let mut filter = BloomFilter::new(n, 0.01);
+    group.bench_function("batch", |b| {
+        b.iter(|| {
+            filter.insert_batch(&keys);
+            std::hint::black_box(filter.contains_batch(&keys));
+        });
+    });
+    group.finish();
+}
+
+/// Shared inserts and lookups of a fixed key set split across 1, 2, 4, ...
+/// threads up to the core count.
+fn bench_concurrent_bloom_filter(c: &mut Criterion) {
+    let mut group = c.benchmark_group("concurrent_bloom_filter");
+    group.sample_size(10);
+    let keys: Vec<[u8; 8]> = (0..400_000u64)
+        .map(|i| i.wrapping_mul(0x9e37_79b9_7f4a_7c15).to_le_bytes())
+        .collect();
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    for threads in (0..).map(|i| 1usize << i).take_while(|&t| t <= cores) {
+        group.bench_with_input(
+            BenchmarkId::new("threads", threads),
+            &threads,
+            |b, &threads| {
+                b.iter(|| {
+                    let filter = ConcurrentBloomFilter::new(keys.len() as u32, 0.01);
+                    std::thread::scope(|s| {
+                        for chunk in keys.chunks(keys.len().div_ceil(threads)) {
+                            let filter = &filter;
+                            s.spawn(move || {
+                                for key in chunk {
+                                    filter.insert(key);
+                                }
+                                for key in chunk {
+                                    std::hint::black_box(filter.contains(key));
+                                }
+                            });
+                        }
+                    });
+                    filter
+                });
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_bloom_filter,
+    bench_bloom_filter_hasher,
+    bench_bloom_filter_u64_keys,
+    bench_bloom_filter_lookup_miss,
+    bench_bloom_filter_batch,
+    bench_concurrent_bloom_filter,
+    bench_bloom_filter_sparse
+);
 criterion_main!(benches);
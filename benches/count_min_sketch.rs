@@ -0,0 +1,58 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use hash_bench::count_min_sketch::{CountMinSketch, IndexMode};
+
+fn bench_count_min_sketch_index_mode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("count_min_sketch_index_mode");
+    let keys: Vec<[u8; 4]> = (0..10_000u32).map(|i| i.to_le_bytes()).collect();
+    for (name, mode) in [
+        ("modulo", IndexMode::Modulo),
+        ("mask", IndexMode::Mask),
+        ("fast_range", IndexMode::FastRange),
+    ] {
+        let mut cms = CountMinSketch::new(0.001, 0.01).with_index_mode(mode);
+        group.bench_function(BenchmarkId::new("update", name), |b| {
+            b.iter(|| {
+                for key in &keys {
+                    cms.update(key, 1);
+                }
+            });
+        });
+        group.bench_function(BenchmarkId::new("estimate", name), |b| {
+            b.iter(|| {
+                for key in &keys {
+                    std::hint::black_box(cms.estimate(key));
+                }
+            });
+        });
+    }
+    group.finish();
+}
+
+fn bench_count_min_sketch_u64_keys(c: &mut Criterion) {
+    let mut group = c.benchmark_group("count_min_sketch_u64_keys");
+    let mut cms = CountMinSketch::new(0.001, 0.01);
+    group.bench_function("bytes", |b| {
+        b.iter(|| {
+            for key in 0..10_000u64 {
+                cms.update(&key.to_le_bytes(), 1);
+            }
+        });
+    });
+    let mut cms = CountMinSketch::new(0.001, 0.01);
+    group.bench_function("u64", |b| {
+        b.iter(|| {
+            for key in 0..10_000u64 {
+                cms.update_u64(key, 1);
+            }
+        });
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_count_min_sketch_index_mode,
+    bench_count_min_sketch_u64_keys
+);
+criterion_main!(benches);
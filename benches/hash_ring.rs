@@ -9,11 +9,11 @@ fn bench_hash_ring_resource_adding(c: &mut Criterion) {
     for k in 2u32..=16 {
         group.bench_with_input(BenchmarkId::from_parameter(k), &k, |b, &k| {
             let mut h = HashRing::new(k);
-            let n = (2 as i32).pow(k);
+            let n = 2_i32.pow(k);
             h.add_node(1);
             b.iter(|| {
                 let mut rng = rand::rng();
-                std::hint::black_box(h.add_resource(rng.random_range(0..n)));
+                h.add_resource(std::hint::black_box(rng.random_range(0..n)));
             });
             h.remove_node(1);
         });
@@ -21,5 +21,36 @@ fn bench_hash_ring_resource_adding(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, bench_hash_ring_resource_adding,);
+fn bench_hash_ring_cached_lookup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("hash_ring_cached_lookup");
+
+    // 256 種類のキーを繰り返し引く、キャッシュが効きやすいトレース
+    for k in [8u32, 12, 16] {
+        group.bench_with_input(BenchmarkId::new("uncached", k), &k, |b, &k| {
+            let mut h = HashRing::new(k);
+            let n = 2_i32.pow(k);
+            for node in (0..n).step_by((n / 16) as usize) {
+                h.add_node(node);
+            }
+            let mut rng = rand::rng();
+            b.iter(|| h.lookup(std::hint::black_box(rng.random_range(0..256))));
+        });
+        group.bench_with_input(BenchmarkId::new("cached", k), &k, |b, &k| {
+            let mut h = HashRing::with_lookup_cache(k, 1024);
+            let n = 2_i32.pow(k);
+            for node in (0..n).step_by((n / 16) as usize) {
+                h.add_node(node);
+            }
+            let mut rng = rand::rng();
+            b.iter(|| h.lookup(std::hint::black_box(rng.random_range(0..256))));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_hash_ring_resource_adding,
+    bench_hash_ring_cached_lookup,
+);
 criterion_main!(benches);
@@ -0,0 +1,33 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use hash_bench::cityhash::CityHash64;
+use hash_bench::crc::Crc64;
+use hash_bench::hasher::{Fnv1a64, Hasher64, Murmur3, XxHash64};
+use hash_bench::siphash::{SipHash13, SipHash24};
+use hash_bench::tabulation::Tabulation;
+
+fn bench_backend<H: Hasher64>(c: &mut Criterion, name: &str, hasher: H) {
+    let mut group = c.benchmark_group(format!("hasher_{}", name));
+    for len in [8usize, 32, 256, 4096] {
+        let input: Vec<u8> = (0..len).map(|i| i as u8).collect();
+        group.throughput(Throughput::Bytes(len as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(len), &input, |b, input| {
+            b.iter(|| hasher.hash64(std::hint::black_box(input)));
+        });
+    }
+    group.finish();
+}
+
+fn bench_hashers(c: &mut Criterion) {
+    bench_backend(c, "murmur3", Murmur3::default());
+    bench_backend(c, "cityhash64", CityHash64::default());
+    bench_backend(c, "crc64", Crc64);
+    bench_backend(c, "fnv1a64", Fnv1a64);
+    bench_backend(c, "xxhash64", XxHash64::with_seed(1));
+    bench_backend(c, "siphash13", SipHash13::with_keys(1, 2));
+    bench_backend(c, "siphash24", SipHash24::with_keys(1, 2));
+    bench_backend(c, "tabulation", Tabulation::with_seed(1));
+}
+
+criterion_group!(benches, bench_hashers);
+criterion_main!(benches);
@@ -0,0 +1,28 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use hash_bench::kv_sim::{KvConfig, KvStore};
+use hash_bench::trace::SyntheticTrace;
+
+/// End-to-end cost of routing, filtering and counting a request stream,
+/// including the rebalancing caused by joins and leaves.
+fn bench_kv_shard_trace(c: &mut Criterion) {
+    let mut group = c.benchmark_group("kv_shard_trace");
+    group.sample_size(10);
+    for write_ratio in [0.1, 0.5] {
+        let events = SyntheticTrace {
+            requests: 20_000,
+            write_ratio,
+            ..Default::default()
+        }
+        .generate();
+        group.bench_with_input(
+            BenchmarkId::from_parameter(format!("writes_{}", write_ratio)),
+            &events,
+            |b, events| b.iter(|| KvStore::from_trace(KvConfig::default(), events)),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_kv_shard_trace);
+criterion_main!(benches);
@@ -1,5 +1,6 @@
 use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
 use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::time::{Duration, Instant};
 
 use hash_bench::quotient_filter::QuotientFilter;
 
@@ -13,7 +14,7 @@ fn bench_quotient_filter_insert(c: &mut Criterion) {
         let capacity = 1usize << q;
         for &load in &load_factors {
             let target_entries = capacity * load / 100;
-            let mut rng = StdRng::seed_from_u64(0xC0FFEEu64 ^ ((q as u64) << 32) ^ load as u64);
+            let mut rng = StdRng::seed_from_u64(0xC0FFEEu64 ^ (q << 32) ^ load as u64);
             let keys: Vec<u64> = (0..target_entries).map(|_| rng.random()).collect();
             let bench_id = BenchmarkId::new(format!("q{q}"), format!("{load}pct"));
 
@@ -44,7 +45,7 @@ fn bench_quotient_filter_lookup(c: &mut Criterion) {
     for &q in &qs {
         let capacity = 1usize << q;
         let target_entries = capacity / 2;
-        let mut rng = StdRng::seed_from_u64(0xFACEFEEDu64 ^ ((q as u64) << 32));
+        let mut rng = StdRng::seed_from_u64(0xFACEFEEDu64 ^ (q << 32));
         let keys: Vec<u64> = (0..target_entries).map(|_| rng.random()).collect();
         let probes: Vec<u64> = (0..target_entries * probe_ratio)
             .map(|i| {
@@ -80,9 +81,243 @@ fn bench_quotient_filter_lookup(c: &mut Criterion) {
     group.finish();
 }
 
+/// Inserts and lookups at half load with 11-bit slots (`r = 8`), which
+/// straddle words, against 16- and 32-bit slots, which never do; the gap
+/// is what reading and writing packed slots costs.
+fn bench_quotient_filter_slot_width(c: &mut Criterion) {
+    let mut group = c.benchmark_group("quotient_filter_slot_width");
+    let q = 16u64;
+    let mut rng = StdRng::seed_from_u64(0x5107u64);
+    let keys: Vec<u64> = (0..1usize << (q - 1)).map(|_| rng.random()).collect();
+
+    for r in [8u64, 13, 29] {
+        let bits = r + 3;
+        group.bench_function(BenchmarkId::new("insert", format!("{bits}bit")), |b| {
+            b.iter_batched(
+                || QuotientFilter::new(q, r),
+                |mut filter| {
+                    filter.extend(keys.iter().copied());
+                    filter
+                },
+                BatchSize::LargeInput,
+            );
+        });
+        let mut filter = QuotientFilter::new(q, r);
+        filter.extend(keys.iter().copied());
+        group.bench_function(BenchmarkId::new("lookup", format!("{bits}bit")), |b| {
+            b.iter(|| {
+                for &key in &keys {
+                    std::hint::black_box(filter.lookup(key));
+                }
+            });
+        });
+    }
+    group.finish();
+}
+
+/// Prints the bytes a filter takes with packed slots against one `u64` per
+/// slot, the layout before packing.
+fn report_quotient_filter_memory(_c: &mut Criterion) {
+    let q = 16u64;
+    let slots = 1usize << q;
+    println!("quotient_filter_memory (q{q}): r packed unpacked bytes");
+    for r in [4u64, 8, 16, 32] {
+        let packed = QuotientFilter::new(q, r).size_in_bytes();
+        let unpacked = slots * 8 + slots / 8;
+        println!(
+            "  r{r}: {packed} {unpacked} ({:.0}%)",
+            100.0 * packed as f64 / unpacked as f64
+        );
+    }
+}
+
+/// Bulk build of a 90%-loaded filter on 1, 2, 4, ... threads up to the core
+/// count, against inserting the keys one by one.
+fn bench_quotient_filter_parallel_build(c: &mut Criterion) {
+    let mut group = c.benchmark_group("quotient_filter_parallel_build");
+    group.sample_size(10);
+    let (q, r) = (20u64, 8u64);
+    let mut rng = StdRng::seed_from_u64(0xB01Du64);
+    let keys: Vec<u64> = (0..(1usize << q) * 9 / 10).map(|_| rng.random()).collect();
+
+    group.bench_function("sequential", |b| {
+        b.iter(|| {
+            let mut filter = QuotientFilter::new(q, r);
+            filter.extend(keys.iter().copied());
+            filter
+        });
+    });
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    for threads in (0..).map(|i| 1usize << i).take_while(|&t| t <= cores) {
+        group.bench_with_input(
+            BenchmarkId::new("threads", threads),
+            &threads,
+            |b, &threads| {
+                b.iter(|| QuotientFilter::build_parallel(q, r, &keys, threads));
+            },
+        );
+    }
+    group.finish();
+}
+
+fn percentile(sorted: &[usize], pct: usize) -> usize {
+    sorted[(sorted.len() - 1) * pct / 100]
+}
+
+/// Prints the distribution of per-insert displacement at several load
+/// factors; tail displacement is what drives insert latency near capacity.
+fn report_quotient_filter_displacement(_c: &mut Criterion) {
+    let r = 8;
+    let q = 12u64;
+    let capacity = 1usize << q;
+    let load_factors = [25usize, 50, 75, 90, 95];
+    let mut rng = StdRng::seed_from_u64(0xD15EA5Eu64);
+
+    println!("quotient_filter_displacement (q{q}): load p50/p90/p99/max shifted, p99/max probe");
+    for &load in &load_factors {
+        let mut filter = QuotientFilter::new(q, r);
+        let mut shifted = Vec::new();
+        let mut probes = Vec::new();
+        for _ in 0..capacity * load / 100 {
+            let stats = filter.insert_with_stats(rng.random());
+            shifted.push(stats.shifted);
+            probes.push(stats.probe_length);
+        }
+        shifted.sort_unstable();
+        probes.sort_unstable();
+        println!(
+            "  {load}pct: {}/{}/{}/{} shifted, {}/{} probe",
+            percentile(&shifted, 50),
+            percentile(&shifted, 90),
+            percentile(&shifted, 99),
+            shifted.last().unwrap(),
+            percentile(&probes, 99),
+            probes.last().unwrap()
+        );
+    }
+}
+
+const HIGH_LOAD_FACTORS: [usize; 3] = [85, 90, 95];
+
+/// Filter filled to one point short of `load` percent, and the keys that
+/// take it the last point; only those final inserts are timed.
+fn high_load_setup(q: u64, r: u64, load: usize, rng: &mut StdRng) -> (QuotientFilter, Vec<u64>) {
+    let capacity = 1usize << q;
+    let mut filter = QuotientFilter::new(q, r);
+    for _ in 0..capacity * (load - 1) / 100 {
+        filter.insert(rng.random());
+    }
+    let band = (0..capacity / 100).map(|_| rng.random()).collect();
+    (filter, band)
+}
+
+/// Mean insert time within the last point of load before 85/90/95%, with
+/// the fill up to there excluded from the measurement.
+fn bench_quotient_filter_insert_high_load(c: &mut Criterion) {
+    let mut group = c.benchmark_group("quotient_filter_insert_high_load");
+    let (q, r) = (14u64, 8u64);
+    for load in HIGH_LOAD_FACTORS {
+        let mut rng = StdRng::seed_from_u64(0x5EED_10ADu64 ^ load as u64);
+        group.bench_function(
+            BenchmarkId::new(format!("q{q}"), format!("{load}pct")),
+            |b| {
+                b.iter_custom(|iters| {
+                    let mut total = Duration::ZERO;
+                    for _ in 0..iters {
+                        let (mut filter, band) = high_load_setup(q, r, load, &mut rng);
+                        let start = Instant::now();
+                        for &key in &band {
+                            filter.insert(key);
+                        }
+                        total += start.elapsed() / band.len() as u32;
+                        std::hint::black_box(filter);
+                    }
+                    total
+                });
+            },
+        );
+    }
+    group.finish();
+}
+
+/// Prints per-insert latency percentiles within the last point of load
+/// before 85/90/95%, pooled over several filters. Long cluster shifts show
+/// up in the tail long before they move the mean.
+fn report_quotient_filter_insert_latency(_c: &mut Criterion) {
+    let (q, r) = (14u64, 8u64);
+    let trials = 50;
+    let mut rng = StdRng::seed_from_u64(0x1A7E_0C1Eu64);
+
+    println!(
+        "quotient_filter_insert_latency (q{q}, {trials} filters): load p50/p90/p99/p99.9/max ns"
+    );
+    for load in HIGH_LOAD_FACTORS {
+        let mut nanos = Vec::new();
+        for _ in 0..trials {
+            let (mut filter, band) = high_load_setup(q, r, load, &mut rng);
+            for key in band {
+                let start = Instant::now();
+                filter.insert(key);
+                nanos.push(start.elapsed().as_nanos() as usize);
+            }
+        }
+        nanos.sort_unstable();
+        println!(
+            "  {load}pct: {}/{}/{}/{}/{}",
+            percentile(&nanos, 50),
+            percentile(&nanos, 90),
+            percentile(&nanos, 99),
+            nanos[(nanos.len() - 1) * 999 / 1000],
+            nanos.last().unwrap()
+        );
+    }
+}
+
+/// Prints false-positive rates measured against the exact key set, so the
+/// accuracy next to the timings is observed rather than assumed.
+#[cfg(feature = "accuracy-tracking")]
+fn report_quotient_filter_accuracy(_c: &mut Criterion) {
+    use hash_bench::accuracy::TrackedQuotientFilter;
+
+    let q = 12u64;
+    let capacity = 1usize << q;
+    let mut rng = StdRng::seed_from_u64(0xACC0u64);
+
+    println!("quotient_filter_accuracy (q{q}): load r fpr fn");
+    for r in [4u64, 8, 12] {
+        for load in [50usize, 90] {
+            let mut filter = TrackedQuotientFilter::new(q, r);
+            for _ in 0..capacity * load / 100 {
+                filter.insert(rng.random());
+            }
+            for _ in 0..capacity * 10 {
+                filter.lookup(rng.random());
+            }
+            let stats = filter.stats();
+            println!(
+                "  {load}pct r{r}: {:.5} {}",
+                stats.false_positive_rate(),
+                stats.false_negatives
+            );
+        }
+    }
+}
+
+#[cfg(not(feature = "accuracy-tracking"))]
+fn report_quotient_filter_accuracy(_c: &mut Criterion) {
+    println!("quotient_filter_accuracy: run with --features accuracy-tracking");
+}
+
 criterion_group!(
     benches,
     bench_quotient_filter_insert,
-    bench_quotient_filter_lookup
+    bench_quotient_filter_lookup,
+    bench_quotient_filter_insert_high_load,
+    bench_quotient_filter_parallel_build,
+    bench_quotient_filter_slot_width,
+    report_quotient_filter_memory,
+    report_quotient_filter_displacement,
+    report_quotient_filter_insert_latency,
+    report_quotient_filter_accuracy
 );
 criterion_main!(benches);
@@ -0,0 +1,85 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use hash_bench::churn::{JumpBackend, RendezvousBackend, SlotsBackend};
+use hash_bench::hash_ring::HashRing;
+use hash_bench::trace::{self, SyntheticTrace, TraceEvent};
+
+const K: u32 = 16;
+const CACHE_CAPACITY: usize = 1024;
+
+// RING_TRACE=<path> で記録済みのトレースを読む。なければ Zipf 分布の合成トレースを使う
+fn load_trace() -> (String, Vec<TraceEvent>) {
+    match std::env::var("RING_TRACE") {
+        Ok(path) => {
+            let text = std::fs::read_to_string(&path)
+                .unwrap_or_else(|err| panic!("cannot read {}: {}", path, err));
+            let events =
+                trace::parse_trace(&text).unwrap_or_else(|err| panic!("{}: {}", path, err));
+            (path, events)
+        }
+        Err(_) => {
+            let events = SyntheticTrace {
+                k: K,
+                requests: 20_000,
+                ..Default::default()
+            }
+            .generate();
+            ("zipf".to_string(), events)
+        }
+    }
+}
+
+fn bench_ring_trace_replay(c: &mut Criterion) {
+    let (name, events) = load_trace();
+
+    // 局所性と移動量は時間計測とは別に一度だけ出力する
+    for report in trace::replay_all(&events, K, CACHE_CAPACITY) {
+        println!(
+            "{} {}: requests {}, hit rate {:.3}, load imbalance {:.2}, migrated {}",
+            name,
+            report.backend,
+            report.requests,
+            report.hit_rate(),
+            report.load_imbalance,
+            report.total_migrated()
+        );
+        for change in &report.membership {
+            println!(
+                "  after {} requests {:?}: {} of {} keys moved",
+                change.after_requests, change.event, change.migrated, change.keys
+            );
+        }
+    }
+
+    let mut group = c.benchmark_group("ring_trace_replay");
+    group.sample_size(10);
+    group.bench_with_input(
+        BenchmarkId::new("hash_ring", &name),
+        &events,
+        |b, events| {
+            b.iter(|| {
+                let mut ring: HashRing<i64> = HashRing::new(K);
+                let report = trace::replay(&mut ring, events, K, CACHE_CAPACITY);
+                ring.remove_all();
+                report
+            })
+        },
+    );
+    group.bench_with_input(
+        BenchmarkId::new("rendezvous", &name),
+        &events,
+        |b, events| {
+            b.iter(|| trace::replay(&mut RendezvousBackend::default(), events, K, CACHE_CAPACITY))
+        },
+    );
+    group.bench_with_input(BenchmarkId::new("jump", &name), &events, |b, events| {
+        b.iter(|| trace::replay(&mut JumpBackend::default(), events, K, CACHE_CAPACITY))
+    });
+    group.bench_with_input(BenchmarkId::new("slots", &name), &events, |b, events| {
+        b.iter(|| trace::replay(&mut SlotsBackend::default(), events, K, CACHE_CAPACITY))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_ring_trace_replay);
+criterion_main!(benches);
@@ -0,0 +1,135 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use hash_bench::count_min_sketch::CountMinSketch;
+use hash_bench::hyperloglog::HyperLogLog;
+use hash_bench::quotient_filter::QuotientFilter;
+use hash_bench::sketch_ring::SketchRing;
+
+fn filled_quotient_filter(q: u64, r: u64, load: usize, seed: u64) -> QuotientFilter {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut filter = QuotientFilter::new(q, r);
+    for _ in 0..(1usize << q) * load / 100 {
+        filter.insert(rng.random());
+    }
+    filter
+}
+
+fn bench_quotient_filter_resize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("quotient_filter_resize");
+    // at low load, skipping empty slots dominates the cost
+    for (q, load) in [(10u64, 90), (14, 90), (14, 5)] {
+        let id = format!("q{q}_load{load}");
+        group.bench_with_input(BenchmarkId::from_parameter(id), &q, |b, &q| {
+            b.iter_batched(
+                || filled_quotient_filter(q, 16, load, q),
+                |mut filter| {
+                    filter.resize();
+                    filter
+                },
+                BatchSize::LargeInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+fn bench_quotient_filter_merge(c: &mut Criterion) {
+    let mut group = c.benchmark_group("quotient_filter_merge");
+    for q in [10u64, 14] {
+        let left = filled_quotient_filter(q, 16, 50, q);
+        let right = filled_quotient_filter(q, 16, 50, q + 1);
+        group.bench_with_input(BenchmarkId::from_parameter(format!("q{q}")), &q, |b, _| {
+            b.iter(|| left.merge(&right));
+        });
+    }
+    group.finish();
+}
+
+fn bench_count_min_sketch_merge(c: &mut Criterion) {
+    let mut group = c.benchmark_group("count_min_sketch_merge");
+    for eps in [0.01f32, 0.001, 0.0001] {
+        let mut left = CountMinSketch::new(eps, 0.01);
+        let mut right = CountMinSketch::new(eps, 0.01);
+        for i in 0..10_000u32 {
+            left.update(&i.to_le_bytes(), 1);
+            right.update(&(i * 3).to_le_bytes(), 2);
+        }
+        group.bench_with_input(BenchmarkId::from_parameter(eps), &eps, |b, _| {
+            b.iter_batched(
+                || left.clone(),
+                |mut left| {
+                    left.merge(&right);
+                    left
+                },
+                BatchSize::LargeInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+fn bench_hyperloglog_merge(c: &mut Criterion) {
+    let mut group = c.benchmark_group("hyperloglog_merge");
+    for p in [10u32, 14, 18] {
+        let mut left = HyperLogLog::new(p);
+        let mut right = HyperLogLog::new(p);
+        for i in 0..100_000u32 {
+            left.insert(&i.to_le_bytes());
+            right.insert(&(i + 50_000).to_le_bytes());
+        }
+        group.bench_with_input(BenchmarkId::new("merge", p), &p, |b, _| {
+            b.iter_batched(
+                || left.clone(),
+                |mut left| {
+                    left.merge(&right);
+                    left
+                },
+                BatchSize::LargeInput,
+            );
+        });
+        let packed = right.to_packed_registers();
+        group.bench_with_input(BenchmarkId::new("merge_packed", p), &p, |b, _| {
+            b.iter_batched(
+                || left.clone(),
+                |mut left| {
+                    left.merge_packed_registers(&packed).unwrap();
+                    left
+                },
+                BatchSize::LargeInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+/// Cost of answering a windowed query, which merges one sketch per bucket.
+fn bench_sketch_ring_window(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sketch_ring_window");
+    let mut ring = SketchRing::new(0.001, 0.01, 1, 60);
+    for t in 0..60u64 {
+        for i in 0..1000u32 {
+            ring.update(t, &i.to_le_bytes(), 1);
+        }
+    }
+    for window in [1u64, 10, 60] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(window),
+            &window,
+            |b, &window| {
+                b.iter(|| ring.window(59, window));
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_quotient_filter_resize,
+    bench_quotient_filter_merge,
+    bench_count_min_sketch_merge,
+    bench_hyperloglog_merge,
+    bench_sketch_ring_window
+);
+criterion_main!(benches);
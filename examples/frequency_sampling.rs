@@ -0,0 +1,75 @@
+//! Samples a skewed stream with `FrequencyAwareSampler` and compares how
+//! many occurrences of head and tail items survive.
+//!
+//! cargo run --example frequency_sampling
+
+use std::collections::HashMap;
+
+use hash_bench::count_min_sketch::CountMinSketch;
+use hash_bench::sampler::FrequencyAwareSampler;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+const ITEMS: u32 = 10_000;
+const EVENTS: usize = 200_000;
+
+/// Draws item ids with a Zipf-like skew: id `i` has weight `1 / (i + 1)`.
+fn zipf_stream(rng: &mut StdRng) -> Vec<u32> {
+    let weights: Vec<f64> = (0..ITEMS).map(|i| 1.0 / (i + 1) as f64).collect();
+    let total: f64 = weights.iter().sum();
+    let mut cumulative = Vec::with_capacity(weights.len());
+    let mut sum = 0.0;
+    for weight in weights {
+        sum += weight / total;
+        cumulative.push(sum);
+    }
+    (0..EVENTS)
+        .map(|_| {
+            let u: f64 = rng.random();
+            cumulative
+                .partition_point(|&c| c < u)
+                .min(ITEMS as usize - 1) as u32
+        })
+        .collect()
+}
+
+fn main() {
+    hash_bench::log::init_logger();
+    let mut rng = StdRng::seed_from_u64(42);
+    let stream = zipf_stream(&mut rng);
+
+    let mut sampler = FrequencyAwareSampler::with_seed(CountMinSketch::new(0.0005, 0.01), 3.0, 7);
+    let mut seen: HashMap<u32, u32> = HashMap::new();
+    let mut kept: HashMap<u32, u32> = HashMap::new();
+    for &item in &stream {
+        *seen.entry(item).or_default() += 1;
+        if sampler.sample(&item.to_le_bytes()) {
+            *kept.entry(item).or_default() += 1;
+        }
+    }
+
+    println!(
+        "kept {} of {} events ({:.2}%)",
+        sampler.kept(),
+        sampler.seen(),
+        100.0 * sampler.kept() as f64 / sampler.seen() as f64
+    );
+    println!(
+        "{:>8} {:>8} {:>8} {:>10}",
+        "item", "seen", "kept", "estimate"
+    );
+    for item in [0, 1, 2, 10, 100, 1000, 5000] {
+        println!(
+            "{:>8} {:>8} {:>8} {:>10}",
+            item,
+            seen.get(&item).copied().unwrap_or(0),
+            kept.get(&item).copied().unwrap_or(0),
+            sampler.frequency(&item.to_le_bytes())
+        );
+    }
+    let distinct_kept = kept.len();
+    println!(
+        "distinct items: {} seen, {} kept at least once",
+        seen.len(),
+        distinct_kept
+    );
+}
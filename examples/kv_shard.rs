@@ -0,0 +1,51 @@
+//! Runs a Zipf-skewed read/write trace with shard joins and leaves through
+//! the simulated sharded key-value store and reports how the filters and
+//! hot-key sketches did.
+//!
+//! cargo run --example kv_shard -- [trace file]
+//!
+//! Without a trace file a synthetic one is generated; the format is the one
+//! read by `trace::parse_trace`.
+
+use hash_bench::kv_sim::{KvConfig, KvStore};
+use hash_bench::trace::{self, SyntheticTrace};
+
+fn main() {
+    hash_bench::log::init_logger();
+    let events = match std::env::args().nth(1) {
+        Some(path) => {
+            let text = std::fs::read_to_string(&path).expect("cannot read trace");
+            trace::parse_trace(&text).expect("invalid trace")
+        }
+        None => SyntheticTrace {
+            write_ratio: 0.3,
+            ..Default::default()
+        }
+        .generate(),
+    };
+
+    let store = KvStore::from_trace(KvConfig::default(), &events);
+    let stats = store.stats();
+    println!(
+        "{} gets, {} puts, {} keys stored",
+        stats.gets,
+        stats.puts,
+        store.len()
+    );
+    println!(
+        "reads: {} hits, {} answered by the filter, {} filter false positives",
+        stats.hits, stats.filtered, stats.false_positives
+    );
+    println!("{} keys migrated by membership changes", stats.migrated);
+    println!("{:>8} {:>8}", "shard", "keys");
+    for (position, size) in store.shard_sizes() {
+        println!("{:>8} {:>8}", position, size);
+    }
+    for (position, keys) in store.hot_keys() {
+        let keys: Vec<String> = keys
+            .iter()
+            .map(|key| String::from_utf8_lossy(key).into_owned())
+            .collect();
+        println!("hot on {}: {}", position, keys.join(", "));
+    }
+}
@@ -0,0 +1,158 @@
+//! Exposes a `HashRing` over a line-based TCP protocol so it can be poked
+//! interactively, e.g. with `nc localhost 7878`.
+//!
+//! cargo run --example ring_server --features ring-server -- [addr] [k]
+//!
+//! Commands, one per line:
+//!   NODES              list node positions
+//!   ADD_NODE <pos>     add a node at ring position <pos>
+//!   REMOVE_NODE <pos>  remove the node at <pos>
+//!   ADD_KEY <key>      store <key> on its owner node
+//!   OWNER <key>        print the position of <key> and its owner
+//!   RESOURCES          list stored positions per node
+//!   QUIT               close the connection
+//!
+//! Keys are arbitrary tokens hashed onto the ring with murmur3.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use hash_bench::hash_ring::{HashRing, HashRingInterface};
+use hash_bench::hasher::{Hasher64, Murmur3};
+use log::{info, warn};
+
+struct Server {
+    k: u32,
+    ring: HashRing<i64>,
+}
+
+impl Server {
+    fn position(&self, key: &str) -> i64 {
+        (Murmur3::default().hash64(key.as_bytes()) % (1u64 << self.k)) as i64
+    }
+
+    fn parse_position(&self, arg: Option<&str>) -> Result<i64, String> {
+        let arg = arg.ok_or("missing position")?;
+        let position: i64 = arg
+            .parse()
+            .map_err(|_| format!("invalid position: {}", arg))?;
+        if !(0..1i64 << self.k).contains(&position) {
+            return Err(format!("position must be in 0..{}", 1i64 << self.k));
+        }
+        Ok(position)
+    }
+
+    fn nodes(&self) -> Vec<i64> {
+        self.ring.snapshot().nodes().to_vec()
+    }
+
+    fn handle(&mut self, line: &str) -> Result<String, String> {
+        let mut parts = line.split_whitespace();
+        let command = parts.next().unwrap_or_default().to_ascii_uppercase();
+        let arg = parts.next();
+        match command.as_str() {
+            "NODES" => Ok(format!("{:?}", self.nodes())),
+            "ADD_NODE" => {
+                let position = self.parse_position(arg)?;
+                if self.nodes().contains(&position) {
+                    return Err(format!("node {} already exists", position));
+                }
+                self.ring.add_node(position);
+                Ok(format!("added {}", position))
+            }
+            "REMOVE_NODE" => {
+                let position = self.parse_position(arg)?;
+                if !self.nodes().contains(&position) {
+                    return Err(format!("node {} not found", position));
+                }
+                self.ring.remove_node(position);
+                Ok(format!("removed {}", position))
+            }
+            "ADD_KEY" | "OWNER" => {
+                let key = arg.ok_or("missing key")?;
+                let position = self.position(key);
+                let owner = self
+                    .ring
+                    .snapshot()
+                    .lookup(position)
+                    .ok_or("ring has no nodes")?;
+                if command == "ADD_KEY" {
+                    self.ring.add_resource(position);
+                }
+                Ok(format!("{} -> {} owned by {}", key, position, owner))
+            }
+            "RESOURCES" => {
+                let mut lines: Vec<String> = self
+                    .ring
+                    .resources()
+                    .into_iter()
+                    .map(|(node, resources)| {
+                        let keys: Vec<i64> = resources.into_iter().map(|(key, _)| key).collect();
+                        format!("{}: {:?}", node, keys)
+                    })
+                    .collect();
+                lines.sort();
+                Ok(lines.join("; "))
+            }
+            _ => Err(format!("unknown command: {}", line.trim())),
+        }
+    }
+}
+
+fn serve(stream: TcpStream, server: Arc<Mutex<Server>>) -> std::io::Result<()> {
+    let peer = stream.peer_addr()?;
+    info!("connection from {}", peer);
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if line.trim().eq_ignore_ascii_case("QUIT") {
+            break;
+        }
+        let reply = server.lock().unwrap().handle(&line);
+        match reply {
+            Ok(reply) => writeln!(writer, "OK {}", reply)?,
+            Err(error) => writeln!(writer, "ERR {}", error)?,
+        }
+    }
+    info!("{} disconnected", peer);
+    Ok(())
+}
+
+fn main() -> std::io::Result<()> {
+    hash_bench::log::init_logger();
+    let mut args = std::env::args().skip(1);
+    let addr = args.next().unwrap_or_else(|| "127.0.0.1:7878".to_string());
+    let k: u32 = args
+        .next()
+        .map(|k| k.parse().expect("k must be a number"))
+        .unwrap_or(16);
+    assert!((1..=62).contains(&k), "k must be between 1 and 62");
+
+    let server = Arc::new(Mutex::new(Server {
+        k,
+        ring: HashRing::new(k),
+    }));
+    let listener = TcpListener::bind(&addr)?;
+    println!("ring server (k = {}) listening on {}", k, addr);
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(error) => {
+                warn!("accept failed: {}", error);
+                continue;
+            }
+        };
+        let server = Arc::clone(&server);
+        thread::spawn(move || {
+            if let Err(error) = serve(stream, server) {
+                warn!("connection error: {}", error);
+            }
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,186 @@
+//! Ground-truth accuracy tracking for approximate filters.
+//!
+//! Only built with the `accuracy-tracking` feature: the shadow set stores
+//! every inserted key exactly, which defeats the point of a filter outside
+//! of tests and benchmarks.
+
+use std::collections::HashSet;
+
+use crate::membership::{self, ApproxMembership};
+use crate::quotient_filter::QuotientFilter;
+
+/// Outcome counts of lookups checked against the exact set of inserted keys.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AccuracyStats {
+    pub true_positives: u64,
+    pub false_positives: u64,
+    pub true_negatives: u64,
+    pub false_negatives: u64,
+}
+
+impl AccuracyStats {
+    pub fn lookups(&self) -> u64 {
+        self.true_positives + self.false_positives + self.true_negatives + self.false_negatives
+    }
+
+    /// Share of lookups for absent keys that the filter answered `true`.
+    pub fn false_positive_rate(&self) -> f64 {
+        let negatives = self.false_positives + self.true_negatives;
+        if negatives == 0 {
+            return 0.0;
+        }
+        self.false_positives as f64 / negatives as f64
+    }
+
+    fn record(&mut self, answered: bool, present: bool) {
+        match (answered, present) {
+            (true, true) => self.true_positives += 1,
+            (true, false) => self.false_positives += 1,
+            (false, false) => self.true_negatives += 1,
+            (false, true) => self.false_negatives += 1,
+        }
+    }
+}
+
+/// A quotient filter paired with a shadow set of the inserted keys, so every
+/// lookup is classified as a true/false positive/negative.
+pub struct TrackedQuotientFilter {
+    filter: QuotientFilter,
+    shadow: HashSet<u64>,
+    stats: AccuracyStats,
+}
+
+impl TrackedQuotientFilter {
+    pub fn new(q: u64, r: u64) -> Self {
+        TrackedQuotientFilter {
+            filter: QuotientFilter::new(q, r),
+            shadow: HashSet::new(),
+            stats: AccuracyStats::default(),
+        }
+    }
+
+    pub fn insert(&mut self, key: u64) {
+        self.filter.insert(key);
+        self.shadow.insert(key);
+    }
+
+    pub fn lookup(&mut self, key: u64) -> bool {
+        let answered = self.filter.lookup(key);
+        self.stats.record(answered, self.shadow.contains(&key));
+        answered
+    }
+
+    pub fn stats(&self) -> AccuracyStats {
+        self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = AccuracyStats::default();
+    }
+
+    pub fn filter(&self) -> &QuotientFilter {
+        &self.filter
+    }
+}
+
+/// Any [`ApproxMembership`] filter paired with a shadow set of the inserted
+/// items, for suites that pick the filter at runtime.
+pub struct TrackedFilter {
+    filter: Box<dyn ApproxMembership>,
+    shadow: HashSet<Vec<u8>>,
+    stats: AccuracyStats,
+}
+
+impl TrackedFilter {
+    pub fn new(filter: Box<dyn ApproxMembership>) -> Self {
+        TrackedFilter {
+            filter,
+            shadow: HashSet::new(),
+            stats: AccuracyStats::default(),
+        }
+    }
+
+    /// Tracks the filter [`membership::build`] makes for `name`, or `None`
+    /// for an unknown name.
+    pub fn by_name(name: &str, n: u32, f: f32) -> Option<Self> {
+        membership::build(name, n, f).map(Self::new)
+    }
+
+    pub fn insert(&mut self, item: &[u8]) {
+        self.filter.insert(item);
+        self.shadow.insert(item.to_vec());
+    }
+
+    pub fn lookup(&mut self, item: &[u8]) -> bool {
+        let answered = self.filter.lookup(item);
+        self.stats.record(answered, self.shadow.contains(item));
+        answered
+    }
+
+    pub fn stats(&self) -> AccuracyStats {
+        self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = AccuracyStats::default();
+    }
+
+    pub fn filter(&self) -> &dyn ApproxMembership {
+        self.filter.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_false_positives_against_shadow_set() {
+        let mut qf = TrackedQuotientFilter::new(4, 4);
+        qf.insert(0x12);
+        assert!(qf.lookup(0x12));
+        // same low q + r bits, so the filter cannot tell them apart
+        assert!(qf.lookup(0x112));
+        assert!(!qf.lookup(0x13));
+        let stats = qf.stats();
+        assert_eq!(stats.true_positives, 1);
+        assert_eq!(stats.false_positives, 1);
+        assert_eq!(stats.true_negatives, 1);
+        assert_eq!(stats.false_negatives, 0);
+        assert_eq!(stats.lookups(), 3);
+        assert_eq!(stats.false_positive_rate(), 0.5);
+        qf.reset_stats();
+        assert_eq!(qf.stats(), AccuracyStats::default());
+    }
+
+    #[test]
+    fn tracks_filters_selected_by_name() {
+        for name in membership::FILTERS {
+            let mut filter = TrackedFilter::by_name(name, 500, 0.01).unwrap();
+            for i in 0..500u32 {
+                filter.insert(&i.to_le_bytes());
+            }
+            for i in 0..5_000u32 {
+                filter.lookup(&i.to_le_bytes());
+            }
+            let stats = filter.stats();
+            assert_eq!(stats.true_positives, 500, "{}", name);
+            assert_eq!(stats.false_negatives, 0, "{}", name);
+            assert!(stats.false_positive_rate() < 0.03, "{}: {:?}", name, stats);
+        }
+        assert!(TrackedFilter::by_name("xor", 500, 0.01).is_none());
+    }
+
+    #[test]
+    fn no_false_negatives_under_load() {
+        let mut qf = TrackedQuotientFilter::new(8, 8);
+        for key in (0..200u64).map(|i| i.wrapping_mul(0x9E3779B97F4A7C15) & 0xFFFF) {
+            qf.insert(key);
+        }
+        for key in (0..200u64).map(|i| i.wrapping_mul(0x9E3779B97F4A7C15) & 0xFFFF) {
+            qf.lookup(key);
+        }
+        assert_eq!(qf.stats().false_negatives, 0);
+        assert_eq!(qf.stats().true_positives, 200);
+    }
+}
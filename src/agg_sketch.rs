@@ -0,0 +1,302 @@
+use crate::hasher::{Hasher64, RowHashing, DEFAULT_SEED};
+use crate::seed::SeedSequence;
+
+/// Aggregates of the values that landed in one cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cell {
+    sum: u64,
+    /// `u64::MAX` until the first value arrives.
+    min: u64,
+    max: u64,
+}
+
+impl Cell {
+    const EMPTY: Cell = Cell {
+        sum: 0,
+        min: u64::MAX,
+        max: 0,
+    };
+
+    fn is_empty(&self) -> bool {
+        self.min == u64::MAX && self.max == 0 && self.sum == 0
+    }
+}
+
+/// Count-min sketch over a stream of `(key, value)` pairs that keeps the
+/// sum, minimum and maximum of the values in every cell, e.g. to track
+/// bytes per client address without a per-key map.
+///
+/// Cells mix the values of every key that hashes to them, so each row
+/// only bounds a key's aggregate; taking the tightest row gives:
+///
+/// - [`estimate_sum`](Self::estimate_sum): never below the true sum, and
+///   with probability `1 - delta` above it by at most `eps` times the sum
+///   of all values.
+/// - [`estimate_max`](Self::estimate_max): never below the true maximum.
+/// - [`estimate_min`](Self::estimate_min): never above the true minimum.
+#[derive(Clone)]
+pub struct AggSketch {
+    width: usize,
+    depth: usize,
+    cells: Vec<Vec<Cell>>,
+    hashing: RowHashing,
+    conservative: bool,
+}
+
+impl AggSketch {
+    pub fn new(eps: f32, delta: f32) -> Self {
+        let depth = Self::calc_depth(delta);
+        let hashing = RowHashing::murmur3(depth, SeedSequence::new(DEFAULT_SEED));
+        Self::with_hashing(eps, delta, hashing)
+    }
+
+    /// Creates a sketch whose row hashes are derived from `hasher`.
+    pub fn with_hasher<H: Hasher64 + PartialEq + Send + Sync + 'static>(
+        eps: f32,
+        delta: f32,
+        hasher: H,
+    ) -> Self {
+        Self::with_hashing(eps, delta, RowHashing::from_hasher(hasher))
+    }
+
+    fn with_hashing(eps: f32, delta: f32, hashing: RowHashing) -> Self {
+        let width = (std::f32::consts::E / eps).ceil() as usize;
+        let depth = Self::calc_depth(delta);
+        AggSketch {
+            width,
+            depth,
+            cells: vec![vec![Cell::EMPTY; width]; depth],
+            hashing,
+            conservative: false,
+        }
+    }
+
+    fn calc_depth(delta: f32) -> usize {
+        (1.0_f32 / delta).ln().ceil() as usize
+    }
+
+    /// Switches sums to conservative update: an update only raises each
+    /// cell to the key's new estimated sum, instead of adding to all of
+    /// them. The error bound still holds and estimates of light keys get
+    /// much tighter, but sketches updated this way can no longer be
+    /// merged. Minima and maxima are unaffected.
+    pub fn with_conservative_update(mut self) -> Self {
+        self.conservative = true;
+        self
+    }
+
+    pub fn is_conservative(&self) -> bool {
+        self.conservative
+    }
+
+    fn columns(&self, key: &[u8]) -> Vec<usize> {
+        let hashes = self.hashing.hashes(key);
+        (0..self.depth)
+            .map(|row| (hashes.row(row as u32) % self.width as u64) as usize)
+            .collect()
+    }
+
+    pub fn update(&mut self, key: &[u8], value: u64) {
+        let columns = self.columns(key);
+        let target = if self.conservative {
+            self.sum_at(&columns).saturating_add(value)
+        } else {
+            0
+        };
+        for (row, &column) in self.cells.iter_mut().zip(&columns) {
+            let cell = &mut row[column];
+            cell.sum = if self.conservative {
+                cell.sum.max(target)
+            } else {
+                cell.sum.saturating_add(value)
+            };
+            cell.min = cell.min.min(value);
+            cell.max = cell.max.max(value);
+        }
+    }
+
+    fn sum_at(&self, columns: &[usize]) -> u64 {
+        self.cells
+            .iter()
+            .zip(columns)
+            .map(|(row, &column)| row[column].sum)
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Upper bound on the sum of `key`'s values; 0 if it was never seen.
+    pub fn estimate_sum(&self, key: &[u8]) -> u64 {
+        self.sum_at(&self.columns(key))
+    }
+
+    /// Lower bound on `key`'s smallest value, or `None` if it was never
+    /// seen.
+    pub fn estimate_min(&self, key: &[u8]) -> Option<u64> {
+        let cells = self.cells_of(key)?;
+        cells.iter().map(|cell| cell.min).max()
+    }
+
+    /// Upper bound on `key`'s largest value, or `None` if it was never
+    /// seen.
+    pub fn estimate_max(&self, key: &[u8]) -> Option<u64> {
+        let cells = self.cells_of(key)?;
+        cells.iter().map(|cell| cell.max).min()
+    }
+
+    /// The key's cell in every row, or `None` if one of them is empty, in
+    /// which case the key was never updated.
+    fn cells_of(&self, key: &[u8]) -> Option<Vec<Cell>> {
+        self.cells
+            .iter()
+            .zip(self.columns(key))
+            .map(|(row, column)| Some(row[column]).filter(|cell| !cell.is_empty()))
+            .collect()
+    }
+
+    /// Whether `other` aggregates into the same cells, so the two can be
+    /// merged.
+    pub fn is_compatible(&self, other: &AggSketch) -> bool {
+        self.width == other.width && self.depth == other.depth && self.hashing == other.hashing
+    }
+
+    /// Combines `other` into this sketch cell by cell; the result
+    /// summarizes both streams.
+    pub fn merge(&mut self, other: &AggSketch) {
+        assert!(
+            self.is_compatible(other),
+            "cannot merge sketches with different dimensions or hashing"
+        );
+        assert!(
+            !self.conservative && !other.conservative,
+            "conservatively updated sums cannot be merged"
+        );
+        for (row, other_row) in self.cells.iter_mut().zip(&other.cells) {
+            for (cell, other_cell) in row.iter_mut().zip(other_row) {
+                cell.sum = cell.sum.saturating_add(other_cell.sum);
+                cell.min = cell.min.min(other_cell.min);
+                cell.max = cell.max.max(other_cell.max);
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for AggSketch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let total: u64 = self.cells[0].iter().map(|cell| cell.sum).sum();
+        f.debug_struct("AggSketch")
+            .field("width", &self.width)
+            .field("depth", &self.depth)
+            .field("conservative", &self.conservative)
+            .field("total", &total)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::collections::HashMap;
+
+    struct Truth {
+        sum: u64,
+        min: u64,
+        max: u64,
+    }
+
+    fn stream(seed: u64) -> (Vec<(u32, u64)>, HashMap<u32, Truth>) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut updates = Vec::new();
+        let mut truth: HashMap<u32, Truth> = HashMap::new();
+        for _ in 0..20_000 {
+            // a few heavy keys and a long tail of light ones
+            let key = if rng.random_bool(0.3) {
+                rng.random_range(0..10)
+            } else {
+                rng.random_range(10..5_000)
+            };
+            let value = rng.random_range(1..1_500);
+            updates.push((key, value));
+            let entry = truth.entry(key).or_insert(Truth {
+                sum: 0,
+                min: u64::MAX,
+                max: 0,
+            });
+            entry.sum += value;
+            entry.min = entry.min.min(value);
+            entry.max = entry.max.max(value);
+        }
+        (updates, truth)
+    }
+
+    #[test]
+    fn estimates_bound_true_aggregates() {
+        let (updates, truth) = stream(1);
+        let mut sketch = AggSketch::new(0.001, 0.01);
+        for &(key, value) in &updates {
+            sketch.update(&key.to_le_bytes(), value);
+        }
+        let total: u64 = truth.values().map(|t| t.sum).sum();
+        let mut within = 0;
+        for (key, t) in &truth {
+            let key = key.to_le_bytes();
+            let sum = sketch.estimate_sum(&key);
+            assert!(sum >= t.sum);
+            if sum - t.sum <= (0.001 * total as f64) as u64 {
+                within += 1;
+            }
+            assert!(sketch.estimate_min(&key).unwrap() <= t.min);
+            assert!(sketch.estimate_max(&key).unwrap() >= t.max);
+        }
+        assert!(within as f64 >= 0.99 * truth.len() as f64);
+        assert_eq!(sketch.estimate_sum(b"unseen key"), 0);
+        assert_eq!(sketch.estimate_max(b"unseen key"), None);
+    }
+
+    #[test]
+    fn conservative_update_tightens_sums() {
+        let (updates, truth) = stream(2);
+        let mut plain = AggSketch::new(0.01, 0.01);
+        let mut conservative = AggSketch::new(0.01, 0.01).with_conservative_update();
+        for &(key, value) in &updates {
+            plain.update(&key.to_le_bytes(), value);
+            conservative.update(&key.to_le_bytes(), value);
+        }
+        let error = |sketch: &AggSketch| -> u64 {
+            truth
+                .iter()
+                .map(|(key, t)| {
+                    let sum = sketch.estimate_sum(&key.to_le_bytes());
+                    assert!(sum >= t.sum);
+                    sum - t.sum
+                })
+                .sum()
+        };
+        assert!(error(&conservative) < error(&plain));
+    }
+
+    #[test]
+    fn merge_matches_single_sketch() {
+        let (updates, _) = stream(3);
+        let mut whole = AggSketch::new(0.01, 0.01);
+        let mut left = AggSketch::new(0.01, 0.01);
+        let mut right = AggSketch::new(0.01, 0.01);
+        for (i, &(key, value)) in updates.iter().enumerate() {
+            whole.update(&key.to_le_bytes(), value);
+            if i % 2 == 0 {
+                left.update(&key.to_le_bytes(), value);
+            } else {
+                right.update(&key.to_le_bytes(), value);
+            }
+        }
+        left.merge(&right);
+        assert_eq!(left.cells, whole.cells);
+    }
+
+    #[test]
+    #[should_panic]
+    fn merge_rejects_conservative_sketches() {
+        let mut a = AggSketch::new(0.01, 0.01).with_conservative_update();
+        a.merge(&AggSketch::new(0.01, 0.01));
+    }
+}
@@ -1,69 +1,881 @@
+use crate::builder::BuildError;
+use crate::hasher::{hash_bytes, Hasher64, RowHashes, RowHashing, DEFAULT_SEED};
+#[cfg(feature = "roaring")]
+use crate::roaring::RoaringBitmap;
+use crate::seed::SeedSequence;
 use bitvec::prelude::BitVec;
-use murmurhash3::murmurhash3_x86_32 as mmh3;
+use std::f64::consts::LN_2;
+use std::hash::Hash;
 
+/// False-positive rate of a filter collected from an iterator.
+pub const DEFAULT_FALSE_POSITIVE_RATE: f32 = 0.01;
+
+/// Most probes a lookup gathers into word masks before checking any;
+/// filters with more hash functions check probe by probe.
+const MAX_BATCHED_PROBES: usize = 32;
+
+/// Items whose probes [`BloomFilter::insert_batch`] and
+/// [`BloomFilter::contains_batch`] compute and prefetch before touching any
+/// bit, so the cache misses of a batch overlap instead of queueing.
+const PREFETCH_BATCH: usize = 16;
+
+/// Words of the output [`BloomFilter::union_many`] fills from every input
+/// before moving on, sized to stay in L1/L2 cache.
+const UNION_CHUNK_WORDS: usize = 1024;
+
+/// Storage for the filter bits: a dense `BitVec`, or with the `roaring`
+/// feature a compressed bitmap that is much smaller while few bits are set.
+#[derive(Clone, PartialEq)]
+enum BitArray {
+    Dense(BitVec),
+    #[cfg(feature = "roaring")]
+    Sparse(RoaringBitmap),
+}
+
+impl BitArray {
+    fn get(&self, index: usize) -> bool {
+        match self {
+            BitArray::Dense(bits) => bits[index],
+            #[cfg(feature = "roaring")]
+            BitArray::Sparse(bitmap) => bitmap.contains(index as u32),
+        }
+    }
+
+    /// Sets the bit, returning whether it was unset before.
+    fn set(&mut self, index: usize) -> bool {
+        match self {
+            BitArray::Dense(bits) => !bits.replace(index, true),
+            #[cfg(feature = "roaring")]
+            BitArray::Sparse(bitmap) => bitmap.insert(index as u32),
+        }
+    }
+
+    /// Unsets every bit; a dense array keeps its allocation.
+    fn clear(&mut self) {
+        match self {
+            BitArray::Dense(bits) => bits.fill(false),
+            #[cfg(feature = "roaring")]
+            BitArray::Sparse(bitmap) => *bitmap = RoaringBitmap::new(),
+        }
+    }
+
+    fn to_dense(&self, m: usize) -> BitVec {
+        match self {
+            BitArray::Dense(bits) => {
+                debug_assert_eq!(bits.len(), m);
+                bits.clone()
+            }
+            #[cfg(feature = "roaring")]
+            BitArray::Sparse(bitmap) => bitmap.to_bitvec(m),
+        }
+    }
+
+    fn count_ones(&self) -> usize {
+        match self {
+            BitArray::Dense(bits) => bits.count_ones(),
+            #[cfg(feature = "roaring")]
+            BitArray::Sparse(bitmap) => bitmap.len() as usize,
+        }
+    }
+
+    fn union_with(&mut self, other: &BitArray) {
+        match (self, other) {
+            (BitArray::Dense(bits), BitArray::Dense(other_bits)) => *bits |= other_bits,
+            #[cfg(feature = "roaring")]
+            (BitArray::Sparse(bitmap), BitArray::Sparse(other_bitmap)) => {
+                bitmap.union_with(other_bitmap)
+            }
+            #[cfg(feature = "roaring")]
+            (BitArray::Dense(bits), other) => *bits |= other.to_dense(bits.len()),
+            #[cfg(feature = "roaring")]
+            (BitArray::Sparse(bitmap), BitArray::Dense(other_bits)) => {
+                bitmap.union_with(&RoaringBitmap::from_bitslice(other_bits))
+            }
+        }
+    }
+}
+
+/// Parameters and fill level of a [`BloomFilter`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BloomFilterSummary {
+    pub n: u64,
+    pub m: u64,
+    pub k: u32,
+    pub f: f32,
+    /// Number of bits set.
+    pub ones: usize,
+    /// Share of bits set; lookups of absent items succeed with roughly
+    /// `fill_ratio^k` probability.
+    pub fill_ratio: f64,
+}
+
+/// Estimated item counts on each side of two filters, from
+/// [`BloomFilter::estimate_difference`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DifferenceEstimate {
+    /// Items in `self` but not in `other`.
+    pub only_self: f64,
+    /// Items in `other` but not in `self`.
+    pub only_other: f64,
+    /// Items in both.
+    pub common: f64,
+}
+
+#[derive(Clone)]
 pub struct BloomFilter {
-    n: u32,
-    m: u32,
+    n: u64,
+    m: u64,
     k: u32,
     f: f32,
-    bit_array: bitvec::prelude::BitVec,
+    bit_array: BitArray,
+    hashing: RowHashing,
 }
 
 impl BloomFilter {
-    pub fn new(n: u32, f: f32) -> Self {
+    pub fn builder() -> BloomFilterBuilder {
+        BloomFilterBuilder::default()
+    }
+
+    pub fn new(n: u64, f: f32) -> Self {
+        Self::with_seed(n, f, DEFAULT_SEED)
+    }
+
+    /// Like [`new`](Self::new), but derives the murmur3 seed of each probe
+    /// from `seed`. Filters with different seeds over the same items set
+    /// unrelated bits, so their false positives are independent, e.g. for
+    /// stacking filters whose answers are combined.
+    pub fn with_seed(n: u64, f: f32, seed: u64) -> Self {
+        let k = Self::calc_k(Self::calc_m(n, f), n);
+        let hashing = RowHashing::murmur3(k as usize, SeedSequence::new(seed));
+        Self::with_hashing(n, f, hashing)
+    }
+
+    /// Creates a filter whose probes are derived from `hasher`.
+    ///
+    /// With a keyed hasher such as SipHash, probe positions cannot be
+    /// predicted without the key, so crafted inputs cannot saturate the
+    /// filter. Filters can be merged only when their hashers have the same
+    /// type and compare equal.
+    pub fn with_hasher<H: Hasher64 + PartialEq + Send + Sync + 'static>(
+        n: u64,
+        f: f32,
+        hasher: H,
+    ) -> Self {
+        Self::with_hashing(n, f, RowHashing::from_hasher(hasher))
+    }
+
+    /// Like [`new`](Self::new), but rounds `m` up to a power of two so the
+    /// filter can later be shrunk with [`fold`](Self::fold). The extra bits
+    /// lower the false-positive rate below `f` until then.
+    pub fn new_foldable(n: u64, f: f32) -> Self {
+        let m = Self::calc_m(n, f).max(1).next_power_of_two();
+        let k = Self::calc_k(m, n);
+        let hashing = RowHashing::murmur3(k as usize, SeedSequence::new(DEFAULT_SEED));
+        Self::with_geometry(n, f, m, k, hashing)
+    }
+
+    /// Creates a filter of exactly `m` bits and `k` hash functions, e.g. to
+    /// match the geometry of a filter built by another system; combine with
+    /// [`BloomFilterBuilder::hasher`] through
+    /// [`BloomFilterBuilder::geometry`] to match its probes too.
+    ///
+    /// `n` becomes the capacity for which `k` is optimal, `m ln 2 / k`, and
+    /// `f` the expected false-positive rate at that capacity. Panics if `m`
+    /// or `k` is zero.
+    pub fn with_params(m: u64, k: u32) -> Self {
+        assert!(m > 0 && k > 0, "m and k must be positive");
+        let (n, f) = Self::capacity_for(m, k);
+        let hashing = RowHashing::murmur3(k as usize, SeedSequence::new(DEFAULT_SEED));
+        Self::with_geometry(n, f, m, k, hashing)
+    }
+
+    /// Builds a filter for a static set in one pass, sized from the exact
+    /// number of keys instead of a guessed `n`.
+    ///
+    /// The keys are expected to be distinct; duplicates are harmless but
+    /// count towards the size, so the filter ends up larger than needed.
+    pub fn from_unique_keys<I>(keys: I, f: f32) -> Self
+    where
+        I: IntoIterator,
+        I::IntoIter: ExactSizeIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let keys = keys.into_iter();
+        let n = keys.len() as u64;
+        let mut filter = Self::new(n.max(1), f);
+        for key in keys {
+            filter.insert(key.as_ref());
+        }
+        filter
+    }
+
+    fn with_hashing(n: u64, f: f32, hashing: RowHashing) -> Self {
         let m = Self::calc_m(n, f);
         let k = Self::calc_k(m, n);
+        Self::with_geometry(n, f, m, k, hashing)
+    }
+
+    fn with_geometry(n: u64, f: f32, m: u64, k: u32, hashing: RowHashing) -> Self {
         let mut vec = BitVec::new();
         vec.resize(m.try_into().unwrap(), false);
+        Self::from_bits(n, f, k, vec, hashing)
+    }
+
+    /// Wraps an already filled bit array; `m` is its length.
+    pub(crate) fn from_bits(n: u64, f: f32, k: u32, bits: BitVec, hashing: RowHashing) -> Self {
         BloomFilter {
             n,
-            m,
+            m: bits.len() as u64,
             k,
             f,
-            bit_array: vec,
+            bit_array: BitArray::Dense(bits),
+            hashing,
         }
     }
 
-    fn calc_m(n: u32, f: f32) -> u32 {
-        let x = 2.0f32;
-        (-f.ln() * (n as f32) / x.ln().powi(2)) as u32
+    /// The `n` and `f` of a filter whose geometry was given directly.
+    fn capacity_for(m: u64, k: u32) -> (u64, f32) {
+        let n = ((m as f64 * LN_2 / k as f64).round() as u64).max(1);
+        (n, expected_false_positive_rate(m, k, n) as f32)
+    }
+
+    pub(crate) fn calc_m(n: u64, f: f32) -> u64 {
+        (-(f as f64).ln() * n as f64 / LN_2.powi(2)) as u64
+    }
+    pub(crate) fn calc_k(m: u64, n: u64) -> u32 {
+        (m as f64 * LN_2 / n as f64) as u32
+    }
+
+    /// `m` and `k` for the variants that keep 32-bit sizes. Panics if `m`
+    /// does not fit.
+    pub(crate) fn geometry_u32(n: u32, f: f32) -> (u32, u32) {
+        let m = Self::calc_m(n.into(), f);
+        let k = Self::calc_k(m, n.into());
+        (m.try_into().expect("filter too large for 32-bit sizes"), k)
+    }
+
+    /// Bit of probe `i`.
+    fn index(&self, hashes: &RowHashes, i: u32) -> usize {
+        probe(hashes, i, self.m) as usize
     }
-    fn calc_k(m: u32, n: u32) -> u32 {
-        let x = 2.0f32;
-        ((m as f32) * x.ln() / (n as f32)) as u32
+    /// Inserts `item`, returning whether any of its bits was unset before.
+    /// `false` means the filter already reported `item` as present, so the
+    /// result doubles as a first-seen check, with the false-positive rate
+    /// of a lookup, without a separate [`lookup`](Self::lookup).
+    pub fn insert(&mut self, item: &[u8]) -> bool {
+        let hashes = self.hashing.hashes(item);
+        let mut new = false;
+        for i in 0..self.k {
+            let index = self.index(&hashes, i);
+            new |= self.bit_array.set(index);
+        }
+        new
+    }
+    pub fn lookup(&self, item: &[u8]) -> bool {
+        self.lookup_hashes(self.hashing.hashes(item))
     }
-    pub fn insert(&mut self, item: &[u8]) {
+
+    /// Inserts an integer key without serializing it. Integer keys live
+    /// apart from byte keys: look them up with [`lookup_u64`](Self::lookup_u64),
+    /// not with `lookup(&key.to_le_bytes())`.
+    /// Returns whether any bit was unset before, as [`insert`](Self::insert).
+    pub fn insert_u64(&mut self, key: u64) -> bool {
+        let hashes = self.hashing.hashes_u64(key);
+        let mut new = false;
         for i in 0..self.k {
-            let index = mmh3(item, i) % self.m;
-            self.bit_array.set(index as usize, true);
+            let index = self.index(&hashes, i);
+            new |= self.bit_array.set(index);
+        }
+        new
+    }
+
+    pub fn lookup_u64(&self, key: u64) -> bool {
+        self.lookup_hashes(self.hashing.hashes_u64(key))
+    }
+
+    /// Inserts every item, hashing a batch of items and prefetching the
+    /// words of all their probes before setting any bit. On large filters
+    /// this hides most of the cache misses that dominate one-by-one inserts.
+    pub fn insert_batch<T: AsRef<[u8]>>(&mut self, items: &[T]) {
+        let mut indexes = Vec::with_capacity(PREFETCH_BATCH * self.k as usize);
+        for chunk in items.chunks(PREFETCH_BATCH) {
+            self.batch_indexes(chunk, &mut indexes);
+            for &index in &indexes {
+                self.bit_array.set(index);
+            }
+        }
+    }
+
+    /// Looks up every item as [`insert_batch`](Self::insert_batch) inserts
+    /// them; the answers are in the order of `items`.
+    pub fn contains_batch<T: AsRef<[u8]>>(&self, items: &[T]) -> Vec<bool> {
+        if self.k == 0 {
+            return vec![true; items.len()];
+        }
+        let mut found = Vec::with_capacity(items.len());
+        let mut indexes = Vec::with_capacity(PREFETCH_BATCH * self.k as usize);
+        for chunk in items.chunks(PREFETCH_BATCH) {
+            self.batch_indexes(chunk, &mut indexes);
+            found.extend(
+                indexes
+                    .chunks(self.k as usize)
+                    .map(|probes| probes.iter().all(|&index| self.bit_array.get(index))),
+            );
+        }
+        found
+    }
+
+    /// Fills `indexes` with the `k` probes of each item of `chunk` in turn
+    /// and prefetches the words they fall in.
+    fn batch_indexes<T: AsRef<[u8]>>(&self, chunk: &[T], indexes: &mut Vec<usize>) {
+        indexes.clear();
+        for item in chunk {
+            let hashes = self.hashing.hashes(item.as_ref());
+            indexes.extend((0..self.k).map(|i| self.index(&hashes, i)));
+        }
+        match &self.bit_array {
+            BitArray::Dense(bits) => {
+                let words = bits.as_raw_slice();
+                for &index in indexes.iter() {
+                    prefetch(&words[index / usize::BITS as usize]);
+                }
+            }
+            #[cfg(feature = "roaring")]
+            BitArray::Sparse(_) => {}
+        }
+    }
+
+    /// Inserts any `Hash` value, such as a struct or tuple, by the bytes
+    /// its `Hash` impl writes. Those bytes depend on the platform and Rust
+    /// version, so filters filled this way should not be persisted or
+    /// shared between builds. Such items live apart from byte keys (`str`
+    /// writes a terminator, for one): look them up with
+    /// [`lookup_hashable`](Self::lookup_hashable).
+    pub fn insert_hashable<T: Hash + ?Sized>(&mut self, item: &T) -> bool {
+        self.insert(&hash_bytes(item))
+    }
+
+    pub fn lookup_hashable<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        self.lookup(&hash_bytes(item))
+    }
+
+    fn lookup_hashes(&self, hashes: RowHashes) -> bool {
+        match &self.bit_array {
+            BitArray::Dense(bits)
+                if hashes.rows_are_cheap() && self.k as usize <= MAX_BATCHED_PROBES =>
+            {
+                self.lookup_words(bits, &hashes)
+            }
+            _ => {
+                for i in 0..self.k {
+                    if !self.bit_array.get(self.index(&hashes, i)) {
+                        return false;
+                    }
+                }
+                true
+            }
         }
     }
-    pub fn lookup(&mut self, item: &[u8]) -> bool {
+
+    /// Checks the probes a storage word at a time: probes landing in the
+    /// same word become one mask and one load. Words holding more probes go
+    /// first, since a word with `j` probes is fully set with probability
+    /// about `fill_ratio^j` and so rejects an absent item most often.
+    fn lookup_words(&self, bits: &BitVec, hashes: &RowHashes) -> bool {
+        let word_bits = usize::BITS as usize;
+        let mut masks = [(0usize, 0usize); MAX_BATCHED_PROBES];
+        let mut len = 0;
         for i in 0..self.k {
-            let index = mmh3(item, i) % self.m;
-            if !self.bit_array[index as usize] {
-                return false;
+            let index = self.index(hashes, i);
+            let (word, bit) = (index / word_bits, 1 << (index % word_bits));
+            match masks[..len].iter_mut().find(|(w, _)| *w == word) {
+                Some((_, mask)) => *mask |= bit,
+                None => {
+                    masks[len] = (word, bit);
+                    len += 1;
+                }
             }
         }
-        true
+        let masks = &mut masks[..len];
+        masks.sort_unstable_by_key(|(_, mask)| std::cmp::Reverse(mask.count_ones()));
+        let words = bits.as_raw_slice();
+        masks.iter().all(|&(word, mask)| words[word] & mask == mask)
+    }
+    /// Whether `other` has the same geometry and hashing, i.e. whether the
+    /// two bit arrays can be combined bit by bit.
+    pub(crate) fn is_compatible(&self, other: &Self) -> bool {
+        self.m == other.m && self.k == other.k && self.hashing == other.hashing
     }
-    pub fn print(self) {
-        println!(
+
+    #[cfg(feature = "mmap")]
+    pub(crate) fn hashing(&self) -> &RowHashing {
+        &self.hashing
+    }
+
+    /// The bits as a dense array, whatever the storage.
+    #[cfg(feature = "mmap")]
+    pub(crate) fn dense_bits(&self) -> BitVec {
+        self.bit_array.to_dense(self.m as usize)
+    }
+
+    /// ORs `other` into `self`; the result contains every item of both.
+    pub(crate) fn union_with(&mut self, other: &Self) {
+        assert!(
+            self.is_compatible(other),
+            "cannot union bloom filters with different geometry or hashing"
+        );
+        self.bit_array.union_with(&other.bit_array);
+    }
+
+    /// Empties the filter in place, keeping its geometry, hashing and bit
+    /// array allocation, so a long-running service can recycle it instead
+    /// of building a new one.
+    pub fn clear(&mut self) {
+        self.bit_array.clear();
+    }
+
+    /// ORs a compatible filter into this one in place, e.g. to absorb the
+    /// delta filter of a worker. Afterwards every item of `other` is found.
+    /// Panics if the filters differ in geometry or hashing.
+    pub fn extend_from(&mut self, other: &Self) {
+        self.union_with(other);
+    }
+
+    /// Union of many filters, e.g. per-shard filters aggregated on a server;
+    /// `None` if there are none. The result contains every item of every
+    /// input and is always dense.
+    ///
+    /// Dense inputs are ORed word by word one chunk of the output at a
+    /// time, so each output chunk stays in cache while all inputs are
+    /// folded into it instead of being streamed once per input. Panics if
+    /// the filters differ in geometry or hashing.
+    pub fn union_many<'a, I>(filters: I) -> Option<BloomFilter>
+    where
+        I: IntoIterator<Item = &'a BloomFilter>,
+    {
+        let filters: Vec<&BloomFilter> = filters.into_iter().collect();
+        let (first, rest) = filters.split_first()?;
+        assert!(
+            rest.iter().all(|filter| first.is_compatible(filter)),
+            "cannot union bloom filters with different geometry or hashing"
+        );
+        let mut dense = Vec::with_capacity(rest.len());
+        #[cfg(feature = "roaring")]
+        let mut sparse = Vec::new();
+        for filter in rest {
+            match &filter.bit_array {
+                BitArray::Dense(bits) => dense.push(bits.as_raw_slice()),
+                #[cfg(feature = "roaring")]
+                BitArray::Sparse(_) => sparse.push(&filter.bit_array),
+            }
+        }
+
+        let mut bits = first.bit_array.to_dense(first.m as usize);
+        let words = bits.as_raw_mut_slice();
+        for start in (0..words.len()).step_by(UNION_CHUNK_WORDS) {
+            let end = (start + UNION_CHUNK_WORDS).min(words.len());
+            for other in &dense {
+                for (word, other) in words[start..end].iter_mut().zip(&other[start..end]) {
+                    *word |= other;
+                }
+            }
+        }
+        #[cfg(feature = "roaring")]
+        for other in sparse {
+            bits |= other.to_dense(first.m as usize);
+        }
+        Some(BloomFilter {
+            bit_array: BitArray::Dense(bits),
+            hashing: first.hashing.clone(),
+            ..**first
+        })
+    }
+
+    /// Estimated number of distinct items behind `ones` set bits, by
+    /// inverting the expected fill `1 - e^{-kn/m}`. A saturated array gives
+    /// infinity.
+    fn estimate_items(&self, ones: usize) -> f64 {
+        let m = self.m as f64;
+        -(m / self.k as f64) * (1.0 - ones as f64 / m).ln()
+    }
+
+    /// Estimates how many items each filter holds that the other lacks,
+    /// e.g. to notice drift between replicas that exchange filters.
+    ///
+    /// Item counts are inferred from the number of set bits in `self`,
+    /// `other` and their union; the difference `self \ other` is then
+    /// `|union| - |other|`. Noise can push a count slightly below zero,
+    /// so results are clamped at zero. The estimates degrade as the filters
+    /// fill up, and are meaningless once either is near saturation.
+    pub fn estimate_difference(&self, other: &Self) -> DifferenceEstimate {
+        assert!(
+            self.is_compatible(other),
+            "cannot compare bloom filters with different geometry or hashing"
+        );
+        let mut union = self.bit_array.clone();
+        union.union_with(&other.bit_array);
+        let n_self = self.estimate_items(self.bit_array.count_ones());
+        let n_other = self.estimate_items(other.bit_array.count_ones());
+        let n_union = self.estimate_items(union.count_ones());
+        DifferenceEstimate {
+            only_self: (n_union - n_other).max(0.0),
+            only_other: (n_union - n_self).max(0.0),
+            common: (n_self + n_other - n_union).max(0.0),
+        }
+    }
+
+    /// Halves the filter by OR-ing its upper half onto its lower half.
+    ///
+    /// Probes are taken modulo `m`, and for a power-of-two `m` a probe
+    /// modulo `m / 2` lands on the folded position of the original bit, so
+    /// every inserted item is still found. The bits become twice as dense,
+    /// so the false-positive rate rises; `f` is updated to the expected
+    /// rate for `n` items. Useful for demoting a cold filter to a cheaper
+    /// storage tier. The result is always dense.
+    ///
+    /// Fails unless `m` is a power of two of at least 2. A filter of 2^33
+    /// bits cannot be folded either: the result would switch from 64-bit
+    /// to 32-bit probes and lose its members.
+    pub fn fold(&self) -> Result<Self, BuildError> {
+        Self::check_foldable(self.m)?;
+        let half = self.m as usize / 2;
+        let bits = self.bit_array.to_dense(self.m as usize);
+        let (low, high) = bits.split_at(half);
+        let mut folded = low.to_bitvec();
+        for i in high.iter_ones() {
+            folded.set(i, true);
+        }
+        let m = self.m / 2;
+        let fill = 1.0 - (-(self.k as f64) * self.n as f64 / m as f64).exp();
+        Ok(BloomFilter {
+            n: self.n,
+            m,
+            k: self.k,
+            f: fill.powi(self.k as i32) as f32,
+            bit_array: BitArray::Dense(folded),
+            hashing: self.hashing.clone(),
+        })
+    }
+
+    fn check_foldable(m: u64) -> Result<(), BuildError> {
+        if !(m.is_power_of_two() && m >= 2) {
+            return Err(BuildError::Invalid {
+                option: "m",
+                reason: "must be a power of two of at least 2 to fold",
+            });
+        }
+        if m == 1 << 33 {
+            return Err(BuildError::Invalid {
+                option: "m",
+                reason: "must not be 2^33 to fold: 2^32 bits are probed differently",
+            });
+        }
+        Ok(())
+    }
+
+    /// Whether the bits are held in a compressed Roaring bitmap.
+    #[cfg(feature = "roaring")]
+    pub fn is_sparse(&self) -> bool {
+        matches!(self.bit_array, BitArray::Sparse(_))
+    }
+
+    /// Converts the bit array to a Roaring bitmap, which is smaller than
+    /// the dense array while only a small fraction of bits is set.
+    #[cfg(feature = "roaring")]
+    pub fn into_sparse(mut self) -> Self {
+        assert!(self.m <= 1 << 32, "roaring bitmaps hold at most 2^32 bits");
+        if let BitArray::Dense(bits) = &self.bit_array {
+            self.bit_array = BitArray::Sparse(RoaringBitmap::from_bitslice(bits));
+        }
+        self
+    }
+
+    /// Converts the bit array back to a dense `BitVec`.
+    #[cfg(feature = "roaring")]
+    pub fn into_dense(mut self) -> Self {
+        self.bit_array = BitArray::Dense(self.bit_array.to_dense(self.m as usize));
+        self
+    }
+
+    /// Approximate heap size of the bit array.
+    pub fn bit_array_bytes(&self) -> usize {
+        match &self.bit_array {
+            BitArray::Dense(bits) => std::mem::size_of_val(bits.as_raw_slice()),
+            #[cfg(feature = "roaring")]
+            BitArray::Sparse(bitmap) => bitmap.size_in_bytes(),
+        }
+    }
+
+    /// False-positive rate in theory after `items` distinct inserts,
+    /// `(1 - e^(-k * items / m))^k`.
+    pub fn expected_false_positive_rate(&self, items: u64) -> f64 {
+        expected_false_positive_rate(self.m, self.k, items)
+    }
+
+    /// Number of bits `m`.
+    pub fn m(&self) -> u64 {
+        self.m
+    }
+
+    /// Number of hash functions `k`.
+    pub fn k(&self) -> u32 {
+        self.k
+    }
+
+    /// The murmur3 seed of each probe, derived from the seed the filter was
+    /// created with; these are what a saved filter stores. `None` when a
+    /// custom hasher drives the probes.
+    pub fn seeds(&self) -> Option<&[u32]> {
+        match &self.hashing {
+            RowHashing::Murmur3(seeds) => Some(seeds),
+            RowHashing::Hasher(_) => None,
+        }
+    }
+
+    /// Number of bits set.
+    pub fn bits_set(&self) -> usize {
+        self.bit_array.count_ones()
+    }
+
+    /// Share of bits set. About one half at capacity with the optimal `k`;
+    /// far above that the filter is saturated and should be rebuilt larger.
+    pub fn fill_ratio(&self) -> f64 {
+        self.bits_set() as f64 / self.m as f64
+    }
+
+    /// False-positive rate measured from the bits actually set,
+    /// `fill_ratio^k`. Unlike [`expected_false_positive_rate`](Self::expected_false_positive_rate)
+    /// it needs no item count, so it also covers duplicate inserts and
+    /// unions.
+    pub fn estimated_fpr(&self) -> f64 {
+        self.fill_ratio().powi(self.k as i32)
+    }
+
+    pub fn summary(&self) -> BloomFilterSummary {
+        let ones = self.bits_set();
+        BloomFilterSummary {
+            n: self.n,
+            m: self.m,
+            k: self.k,
+            f: self.f,
+            ones,
+            fill_ratio: ones as f64 / self.m as f64,
+        }
+    }
+}
+
+/// Theoretical false-positive rate of `k` probes into `m` bits after
+/// `items` distinct inserts, shared by the Bloom filter variants.
+pub(crate) fn expected_false_positive_rate(m: u64, k: u32, items: u64) -> f64 {
+    let fill = -(-(k as f64) * items as f64 / m as f64).exp_m1();
+    fill.powi(k as i32)
+}
+
+/// Options for a [`BloomFilter`], checked together by
+/// [`build`](Self::build).
+///
+/// `capacity` is required. The false-positive rate defaults to 1%, and
+/// probes to murmur3 seeded from `seed` (or the default seed);
+/// `with_hasher` replaces murmur3 entirely, so it cannot be combined with
+/// `seed`.
+#[derive(Clone, Default)]
+pub struct BloomFilterBuilder {
+    capacity: Option<u64>,
+    false_positive_rate: Option<f32>,
+    seed: Option<u64>,
+    hashing: Option<RowHashing>,
+    foldable: bool,
+    geometry: Option<(u64, u32)>,
+}
+
+impl BloomFilterBuilder {
+    /// Number of items the filter is sized for (`n`).
+    pub fn capacity(mut self, n: u64) -> Self {
+        self.capacity = Some(n);
+        self
+    }
+
+    /// Target false-positive rate at `capacity` items (`f`).
+    pub fn false_positive_rate(mut self, f: f32) -> Self {
+        self.false_positive_rate = Some(f);
+        self
+    }
+
+    /// Master seed for the per-probe murmur3 seeds.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Derives probes from `hasher` instead, as in
+    /// [`BloomFilter::with_hasher`]: probe `i` is `h1 + i * h2` on the low
+    /// and high 32 bits of one hash, so e.g. [`XxHash64`](crate::hasher::XxHash64)
+    /// or [`Fnv1a64`](crate::hasher::Fnv1a64) reproduces filters built
+    /// elsewhere with the same scheme. Filters built with equal hashers,
+    /// even separate instances, can be merged.
+    pub fn with_hasher<H: Hasher64 + PartialEq + Send + Sync + 'static>(
+        mut self,
+        hasher: H,
+    ) -> Self {
+        self.hashing = Some(RowHashing::from_hasher(hasher));
+        self
+    }
+
+    /// Rounds `m` up to a power of two, as in [`BloomFilter::new_foldable`].
+    pub fn foldable(mut self, foldable: bool) -> Self {
+        self.foldable = foldable;
+        self
+    }
+
+    /// Exactly `m` bits and `k` hash functions, as in
+    /// [`BloomFilter::with_params`], instead of sizing from `capacity` and
+    /// `false_positive_rate`.
+    pub fn geometry(mut self, m: u64, k: u32) -> Self {
+        self.geometry = Some((m, k));
+        self
+    }
+
+    pub fn build(self) -> Result<BloomFilter, BuildError> {
+        if self.seed.is_some() && self.hashing.is_some() {
+            return Err(BuildError::Conflict("seed", "with_hasher"));
+        }
+        if let Some((m, k)) = self.geometry {
+            return self.build_with_geometry(m, k);
+        }
+        let n = self.capacity.ok_or(BuildError::Missing("capacity"))?;
+        if n == 0 {
+            return Err(BuildError::Invalid {
+                option: "capacity",
+                reason: "must be positive",
+            });
+        }
+        let f = self.false_positive_rate.unwrap_or(0.01);
+        // above 1/2 the optimal k rounds down to zero probes
+        if !(f > 0.0 && f <= 0.5) {
+            return Err(BuildError::Invalid {
+                option: "false_positive_rate",
+                reason: "must be in (0, 0.5]",
+            });
+        }
+        let mut m = BloomFilter::calc_m(n, f);
+        if self.foldable {
+            m = m.max(1).next_power_of_two();
+        }
+        let k = BloomFilter::calc_k(m, n);
+        let hashing = self.row_hashing(k);
+        Ok(BloomFilter::with_geometry(n, f, m, k, hashing))
+    }
+
+    fn build_with_geometry(self, m: u64, k: u32) -> Result<BloomFilter, BuildError> {
+        for (set, option) in [
+            (self.capacity.is_some(), "capacity"),
+            (self.false_positive_rate.is_some(), "false_positive_rate"),
+            (self.foldable, "foldable"),
+        ] {
+            if set {
+                return Err(BuildError::Conflict("geometry", option));
+            }
+        }
+        if m == 0 || k == 0 {
+            return Err(BuildError::Invalid {
+                option: "geometry",
+                reason: "m and k must be positive",
+            });
+        }
+        let (n, f) = BloomFilter::capacity_for(m, k);
+        let hashing = self.row_hashing(k);
+        Ok(BloomFilter::with_geometry(n, f, m, k, hashing))
+    }
+
+    fn row_hashing(self, k: u32) -> RowHashing {
+        self.hashing.unwrap_or_else(|| {
+            let seeds = SeedSequence::new(self.seed.unwrap_or(DEFAULT_SEED));
+            RowHashing::murmur3(k as usize, seeds)
+        })
+    }
+}
+
+/// Probe `i` of an item in a filter of `m` bits. Filters of more than
+/// 2^32 bits take each probe from a full 64-bit hash, since murmur3 rows
+/// and the halves of double hashing only span 32 bits; smaller filters keep
+/// the 32-bit probes, so their bits do not change.
+pub(crate) fn probe(hashes: &RowHashes, i: u32, m: u64) -> u64 {
+    let hash = if m > 1 << 32 {
+        hashes.wide_row(i)
+    } else {
+        hashes.row(i)
+    };
+    hash % m
+}
+
+/// Hints the CPU to pull `word` into cache. A no-op where no stable
+/// prefetch instruction is exposed.
+#[inline(always)]
+fn prefetch(word: &usize) {
+    #[cfg(target_arch = "x86_64")]
+    // SAFETY: SSE is part of the x86_64 baseline, and a prefetch is only a
+    // hint that never faults or changes memory
+    unsafe {
+        use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+        _mm_prefetch::<_MM_HINT_T0>(word as *const usize as *const i8);
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    let _ = word;
+}
+
+impl<T: AsRef<[u8]>> Extend<T> for BloomFilter {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, items: I) {
+        for item in items {
+            self.insert(item.as_ref());
+        }
+    }
+}
+
+/// Sizes the filter for exactly the collected items at
+/// [`DEFAULT_FALSE_POSITIVE_RATE`], as in
+/// [`from_unique_keys`](BloomFilter::from_unique_keys).
+impl<T: AsRef<[u8]>> FromIterator<T> for BloomFilter {
+    fn from_iter<I: IntoIterator<Item = T>>(items: I) -> Self {
+        let items: Vec<T> = items.into_iter().collect();
+        Self::from_unique_keys(items, DEFAULT_FALSE_POSITIVE_RATE)
+    }
+}
+
+impl std::fmt::Debug for BloomFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let summary = self.summary();
+        f.debug_struct("BloomFilter")
+            .field("n", &summary.n)
+            .field("m", &summary.m)
+            .field("k", &summary.k)
+            .field("f", &summary.f)
+            .field("ones", &summary.ones)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The parameters followed by every bit of the array.
+impl std::fmt::Display for BloomFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
             "parameters: n = {}, m = {}, k = {}, f = {}",
             self.n, self.m, self.k, self.f
-        );
-        print!("bit_array = [ ");
-        for v in self.bit_array.as_bitslice() {
-            print!("{} ", v);
+        )?;
+        write!(f, "bit_array = [ ")?;
+        for bit in self.bit_array.to_dense(self.m as usize).iter() {
+            write!(f, "{} ", *bit as u8)?;
         }
-        println!("]");
+        write!(f, "]")
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::hasher::{Fnv1a64, XxHash64};
     use crate::log;
+    use crate::siphash::SipHash13;
 
     #[test]
     fn calc_parameters() {
@@ -84,4 +896,748 @@ mod test {
         b.insert(b"123");
         assert!(b.lookup(b"123"));
     }
+    #[test]
+    fn insert_reports_first_sighting() {
+        log::init_test_logger();
+        let mut b = BloomFilter::new(1000, 0.001);
+        assert!(b.insert(b"1"));
+        assert!(!b.insert(b"1"));
+        assert!(b.insert_u64(1));
+        assert!(!b.insert_u64(1));
+        let fresh = (0u32..1000).filter(|i| b.insert(&i.to_be_bytes())).count();
+        assert!(fresh >= 995, "{}", fresh);
+        assert!((0u32..1000).all(|i| !b.insert(&i.to_be_bytes())));
+    }
+    #[test]
+    fn from_unique_keys_sizes_from_key_count() {
+        log::init_test_logger();
+        let keys: Vec<[u8; 4]> = (0u32..1000).map(|i| i.to_be_bytes()).collect();
+        let b = BloomFilter::from_unique_keys(&keys, 0.01);
+        assert_eq!(b.n, 1000);
+        assert_eq!(b.m, BloomFilter::calc_m(1000, 0.01));
+        assert!(keys.iter().all(|key| b.lookup(key)));
+
+        let empty = BloomFilter::from_unique_keys(Vec::<Vec<u8>>::new(), 0.01);
+        assert!(!empty.lookup(b"anything"));
+    }
+    #[test]
+    fn expected_false_positive_rate_meets_target_at_capacity() {
+        let b = BloomFilter::new(10_000, 0.01);
+        assert_eq!(b.expected_false_positive_rate(0), 0.0);
+        let at_capacity = b.expected_false_positive_rate(10_000);
+        assert!((at_capacity - 0.01).abs() < 0.001, "{}", at_capacity);
+        assert!(b.expected_false_positive_rate(5_000) < at_capacity);
+    }
+
+    #[test]
+    fn fold_keeps_members_and_raises_fpr() {
+        log::init_test_logger();
+        let mut b = BloomFilter::new_foldable(1000, 0.01);
+        assert_eq!(b.m, 16384);
+        for i in 0u32..1000 {
+            b.insert(&i.to_be_bytes());
+        }
+        let once = b.fold().unwrap();
+        let twice = once.fold().unwrap();
+        assert_eq!((once.m, twice.m), (8192, 4096));
+        assert_eq!(twice.k, b.k);
+        assert!(b.f < once.f && once.f < twice.f);
+        for filter in [&once, &twice] {
+            assert!((0u32..1000).all(|i| filter.lookup(&i.to_be_bytes())));
+        }
+
+        let false_positives = (1000u32..11_000)
+            .filter(|i| twice.lookup(&i.to_be_bytes()))
+            .count();
+        let observed = false_positives as f32 / 10_000.0;
+        assert!(
+            (observed - twice.f).abs() < 0.03,
+            "observed {observed}, expected {}",
+            twice.f
+        );
+    }
+    #[test]
+    fn fold_rejects_sizes_it_cannot_halve() {
+        log::init_test_logger();
+        let not_power_of_two = Some(BuildError::Invalid {
+            option: "m",
+            reason: "must be a power of two of at least 2 to fold",
+        });
+        assert_eq!(BloomFilter::new(1000, 0.01).fold().err(), not_power_of_two);
+        assert_eq!(
+            BloomFilter::with_params(1, 1).fold().err(),
+            not_power_of_two
+        );
+        // a real 2^33-bit filter takes a gigabyte, so only the check runs
+        assert!(matches!(
+            BloomFilter::check_foldable(1 << 33),
+            Err(BuildError::Invalid { option: "m", .. })
+        ));
+        assert!(BloomFilter::check_foldable(1 << 34).is_ok());
+    }
+    #[test]
+    fn summary_and_formatting() {
+        log::init_test_logger();
+        let mut b = BloomFilter::new(2, 0.1);
+        assert_eq!(b.summary().ones, 0);
+        b.insert(b"a");
+        let summary = b.summary();
+        assert_eq!((summary.n, summary.m, summary.k), (2, 9, 3));
+        assert!(summary.ones > 0 && summary.ones <= 3);
+        assert_eq!(summary.fill_ratio, summary.ones as f64 / 9.0);
+
+        let shown = b.to_string();
+        let mut lines = shown.lines();
+        assert_eq!(
+            lines.next(),
+            Some("parameters: n = 2, m = 9, k = 3, f = 0.1")
+        );
+        let bits = lines.next().unwrap();
+        assert_eq!(bits.matches('1').count(), summary.ones);
+        assert_eq!(bits.matches(['0', '1']).count(), 9);
+        assert!(format!("{:?}", b).starts_with("BloomFilter { n: 2, m: 9, k: 3"));
+    }
+    #[test]
+    fn fill_ratio_and_estimated_fpr_track_saturation() {
+        log::init_test_logger();
+        let mut b = BloomFilter::new(1000, 0.01);
+        assert_eq!(
+            (b.bits_set(), b.fill_ratio(), b.estimated_fpr()),
+            (0, 0.0, 0.0)
+        );
+        for i in 0u32..1000 {
+            b.insert(&i.to_be_bytes());
+        }
+        assert_eq!(b.bits_set(), b.summary().ones);
+        assert!((b.fill_ratio() - 0.5).abs() < 0.05, "{}", b.fill_ratio());
+        let expected = b.expected_false_positive_rate(1000);
+        assert!((b.estimated_fpr() / expected - 1.0).abs() < 0.2);
+
+        // duplicates leave the estimate alone; overfilling raises it
+        let before = b.estimated_fpr();
+        for i in 0u32..1000 {
+            b.insert(&i.to_be_bytes());
+        }
+        assert_eq!(b.estimated_fpr(), before);
+        for i in 1000u32..4000 {
+            b.insert(&i.to_be_bytes());
+        }
+        assert!(b.fill_ratio() > 0.9 && b.estimated_fpr() > 0.5);
+    }
+    /// Bit positions `item` probes in `b`.
+    fn probes(b: &BloomFilter, item: &[u8]) -> Vec<usize> {
+        let hashes = b.hashing.hashes(item);
+        (0..b.k).map(|i| (hashes.row(i) % b.m) as usize).collect()
+    }
+
+    fn false_positive_rate(b: &BloomFilter, probes: impl Iterator<Item = u32>) -> f64 {
+        let probes: Vec<u32> = probes.collect();
+        let hits = probes.iter().filter(|i| b.lookup(&i.to_be_bytes())).count();
+        hits as f64 / probes.len() as f64
+    }
+
+    // An attacker who knows the unkeyed hash can pick queries that are all
+    // false positives; against a secret SipHash key they are ordinary keys.
+    #[test]
+    fn adversarial_queries_are_harmless_with_keyed_hashing() {
+        log::init_test_logger();
+        let (n, f) = (1000u32, 0.01);
+        let mut unkeyed = BloomFilter::new(n.into(), f);
+        let mut keyed = BloomFilter::with_hasher(n.into(), f, SipHash13::with_keys(0x5eed, 0xcafe));
+        for i in 0u32..n {
+            unkeyed.insert(&i.to_be_bytes());
+            keyed.insert(&i.to_be_bytes());
+        }
+
+        let crafted: Vec<u32> = (n..)
+            .filter(|i| unkeyed.lookup(&i.to_be_bytes()))
+            .take(500)
+            .collect();
+        assert_eq!(false_positive_rate(&unkeyed, crafted.iter().copied()), 1.0);
+        let keyed_rate = false_positive_rate(&keyed, crafted.iter().copied());
+        assert!(keyed_rate < 3.0 * f as f64, "keyed rate {keyed_rate}");
+    }
+
+    // Inserts chosen so each sets k fresh bits of the unkeyed filter fill it
+    // far faster than random keys; under a secret key they fill it normally.
+    #[test]
+    fn adversarial_inserts_do_not_pollute_keyed_filter() {
+        log::init_test_logger();
+        let (n, f) = (1000, 0.01);
+        let shadow = BloomFilter::new(n, f);
+        let mut used = vec![false; shadow.m as usize];
+        let mut crafted = Vec::new();
+        let mut candidate = 0u32;
+        while crafted.len() < n as usize {
+            candidate += 1;
+            let mut positions = probes(&shadow, &candidate.to_be_bytes());
+            positions.sort_unstable();
+            positions.dedup();
+            if positions.len() == shadow.k as usize && positions.iter().all(|&p| !used[p]) {
+                positions.iter().for_each(|&p| used[p] = true);
+                crafted.push(candidate);
+            }
+        }
+
+        let mut unkeyed = BloomFilter::new(n, f);
+        let mut keyed = BloomFilter::with_hasher(n, f, SipHash13::with_keys(0x5eed, 0xcafe));
+        for item in &crafted {
+            unkeyed.insert(&item.to_be_bytes());
+            keyed.insert(&item.to_be_bytes());
+        }
+        let probes = || (0..20_000u32).map(|i| i | 0x8000_0000);
+        let unkeyed_rate = false_positive_rate(&unkeyed, probes());
+        let keyed_rate = false_positive_rate(&keyed, probes());
+        // k * n bits of m = 9585 are set: fill 0.63, FPR ~0.06
+        assert_eq!(unkeyed.summary().ones, (n * unkeyed.k as u64) as usize);
+        assert!(unkeyed_rate > 0.04, "unkeyed rate {unkeyed_rate}");
+        assert!(keyed_rate < 2.0 * f as f64, "keyed rate {keyed_rate}");
+    }
+    #[test]
+    fn builder_matches_constructors() {
+        let built = BloomFilter::builder()
+            .capacity(1000)
+            .false_positive_rate(0.01)
+            .build()
+            .unwrap();
+        let summary = built.summary();
+        assert_eq!(summary, BloomFilter::new(1000, 0.01).summary());
+        assert!(built.hashing == BloomFilter::new(1000, 0.01).hashing);
+
+        let foldable = BloomFilter::builder()
+            .capacity(1000)
+            .foldable(true)
+            .build()
+            .unwrap();
+        assert_eq!(foldable.m, BloomFilter::new_foldable(1000, 0.01).m);
+
+        let mut seeded = BloomFilter::builder()
+            .capacity(1000)
+            .seed(7)
+            .build()
+            .unwrap();
+        assert!(seeded.hashing != built.hashing);
+        assert_eq!(
+            seeded.seeds(),
+            BloomFilter::with_seed(1000, 0.01, 7).seeds()
+        );
+        seeded.insert(b"item");
+        assert!(seeded.lookup(b"item"));
+    }
+
+    #[test]
+    fn seeds_make_false_positives_independent() {
+        log::init_test_logger();
+        let mut a = BloomFilter::with_seed(1000, 0.05, 1);
+        let mut b = BloomFilter::with_seed(1000, 0.05, 2);
+        assert_ne!(a.seeds(), b.seeds());
+        assert_eq!(
+            BloomFilter::new(10, 0.01).seeds().map(<[u32]>::len),
+            Some(6)
+        );
+        assert_eq!(BloomFilter::with_hasher(10, 0.01, Fnv1a64).seeds(), None);
+        for i in 0u32..1000 {
+            a.insert(&i.to_be_bytes());
+            b.insert(&i.to_be_bytes());
+        }
+        let (mut only_a, mut only_b, mut both) = (0, 0, 0);
+        for i in 1000u32..101_000 {
+            match (a.lookup(&i.to_be_bytes()), b.lookup(&i.to_be_bytes())) {
+                (true, true) => both += 1,
+                (true, false) => only_a += 1,
+                (false, true) => only_b += 1,
+                _ => {}
+            }
+        }
+        // about f^2 of the probes fool both, against f for one
+        assert!(
+            both * 5 < only_a.min(only_b),
+            "{} {} {}",
+            only_a,
+            only_b,
+            both
+        );
+    }
+
+    #[test]
+    fn builder_hasher_reproduces_external_probes() {
+        log::init_test_logger();
+        // another service probes h1 + i * h2 on the halves of one XXH64
+        let mut b = BloomFilter::builder()
+            .capacity(1000)
+            .with_hasher(XxHash64::with_seed(0))
+            .build()
+            .unwrap();
+        b.insert(b"order-42");
+        let h = XxHash64::with_seed(0).hash64(b"order-42");
+        let (h1, h2) = (h & 0xffff_ffff, h >> 32);
+        let probes: std::collections::BTreeSet<u64> = (0..b.k as u64)
+            .map(|i| h1.wrapping_add(i * h2) % b.m)
+            .collect();
+        for index in 0..b.m {
+            assert_eq!(b.bit_array.get(index as usize), probes.contains(&index));
+        }
+
+        let mut fnv = BloomFilter::builder()
+            .capacity(1000)
+            .with_hasher(Fnv1a64)
+            .build()
+            .unwrap();
+        let mut sip = BloomFilter::builder()
+            .capacity(1000)
+            .with_hasher(SipHash13::with_keys(1, 2))
+            .build()
+            .unwrap();
+        fnv.insert(b"order-42");
+        sip.insert(b"order-42");
+        assert!(fnv.lookup(b"order-42") && !fnv.lookup(b"order-43"));
+        assert!(sip.lookup(b"order-42") && !sip.lookup(b"order-43"));
+    }
+    #[test]
+    fn explicit_geometry_matches_an_external_filter() {
+        log::init_test_logger();
+        let b = BloomFilter::with_params(9586, 7);
+        assert_eq!((b.m(), b.k()), (9586, 7));
+        assert_eq!(
+            b.bit_array_bytes(),
+            9586usize.div_ceil(usize::BITS as usize) * 8
+        );
+        let summary = b.summary();
+        assert_eq!(summary.n, 949);
+        // half the bits set at capacity
+        assert!((summary.f - 0.5f32.powi(7)).abs() < 1e-4, "{}", summary.f);
+        // the geometry new() picks for the same n and f
+        let sized = BloomFilter::new(1000, 0.01);
+        let same = BloomFilter::with_params(sized.m(), sized.k());
+        assert!(same.is_compatible(&sized));
+
+        let mut external = BloomFilter::builder()
+            .geometry(1 << 16, 5)
+            .with_hasher(XxHash64::with_seed(0))
+            .build()
+            .unwrap();
+        assert_eq!((external.m(), external.k()), (1 << 16, 5));
+        external.insert(b"order-42");
+        let h = XxHash64::with_seed(0).hash64(b"order-42");
+        let (h1, h2) = (h & 0xffff_ffff, h >> 32);
+        assert!((0..5u64).all(|i| external
+            .bit_array
+            .get((h1.wrapping_add(i * h2) % (1 << 16)) as usize)));
+    }
+
+    #[test]
+    fn sizing_beyond_u32_bits() {
+        log::init_test_logger();
+        // 20 billion keys need about 1.9e11 bits; only the math is checked,
+        // the array itself would take 24 GB
+        let n = 20_000_000_000u64;
+        let m = BloomFilter::calc_m(n, 0.01);
+        let k = BloomFilter::calc_k(m, n);
+        assert!(m > 40 * u32::MAX as u64, "{}", m);
+        assert_eq!(k, 6);
+        assert!(expected_false_positive_rate(m, k, n) < 0.011);
+        let (capacity, f) = BloomFilter::capacity_for(m, k);
+        assert!(capacity > n && (f - 0.5f32.powi(6)).abs() < 1e-4);
+
+        // probes reach the whole array, not just its first 2^32 bits
+        for hashing in [
+            RowHashing::murmur3(k as usize, SeedSequence::new(DEFAULT_SEED)),
+            RowHashing::from_hasher(SipHash13::with_keys(1, 2)),
+        ] {
+            let indexes: Vec<u64> = (0u32..10_000)
+                .flat_map(|i| {
+                    let item = i.to_be_bytes();
+                    let hashes = hashing.hashes(&item);
+                    (0..k).map(|row| probe(&hashes, row, m)).collect::<Vec<_>>()
+                })
+                .collect();
+            assert!(indexes.iter().all(|&index| index < m));
+            let mean =
+                indexes.iter().map(|&index| index as f64).sum::<f64>() / indexes.len() as f64;
+            assert!((mean / m as f64 - 0.5).abs() < 0.01, "{}", mean / m as f64);
+        }
+
+        // up to 2^32 bits the 32-bit probes are kept
+        let hashing = RowHashing::murmur3(3, SeedSequence::new(DEFAULT_SEED));
+        let hashes = hashing.hashes(b"key");
+        assert_eq!(probe(&hashes, 2, 1 << 32), hashes.row(2));
+    }
+
+    #[test]
+    fn filters_rebuilt_with_the_same_hasher_merge() {
+        log::init_test_logger();
+        // two services each build their filter with their own FNV-1a
+        // instance, as a rebuild of an external filter would
+        let service = || {
+            BloomFilter::builder()
+                .capacity(1000)
+                .false_positive_rate(0.01)
+                .with_hasher(Fnv1a64)
+                .build()
+                .unwrap()
+        };
+        let (mut ours, mut theirs) = (service(), service());
+        ours.insert(b"order-42");
+        theirs.insert(b"order-43");
+        let merged = BloomFilter::union_many([&ours, &theirs]).unwrap();
+        ours.extend_from(&theirs);
+        assert_eq!(merged.summary(), ours.summary());
+        assert!(ours.lookup(b"order-42") && ours.lookup(b"order-43"));
+    }
+
+    #[test]
+    fn builder_rejects_bad_options() {
+        assert_eq!(
+            BloomFilter::builder().build().err(),
+            Some(BuildError::Missing("capacity"))
+        );
+        for f in [0.0, 0.7, f32::NAN] {
+            assert!(matches!(
+                BloomFilter::builder()
+                    .capacity(10)
+                    .false_positive_rate(f)
+                    .build(),
+                Err(BuildError::Invalid {
+                    option: "false_positive_rate",
+                    ..
+                })
+            ));
+        }
+        assert_eq!(
+            BloomFilter::builder()
+                .capacity(10)
+                .seed(1)
+                .with_hasher(SipHash13::with_keys(1, 2))
+                .build()
+                .err(),
+            Some(BuildError::Conflict("seed", "with_hasher"))
+        );
+        assert_eq!(
+            BloomFilter::builder()
+                .geometry(1000, 7)
+                .capacity(10)
+                .build()
+                .err(),
+            Some(BuildError::Conflict("geometry", "capacity"))
+        );
+        assert!(matches!(
+            BloomFilter::builder().geometry(1000, 0).build(),
+            Err(BuildError::Invalid {
+                option: "geometry",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn union_contains_both_inputs() {
+        log::init_test_logger();
+        let mut a = BloomFilter::new(100, 0.01);
+        let mut b = BloomFilter::new(100, 0.01);
+        a.insert(b"left");
+        b.insert(b"right");
+        a.union_with(&b);
+        assert!(a.lookup(b"left"));
+        assert!(a.lookup(b"right"));
+    }
+    #[test]
+    fn clear_and_extend_from_recycle_a_filter() {
+        log::init_test_logger();
+        let mut service = BloomFilter::new(1000, 0.01);
+        let bytes = service.bit_array_bytes();
+        for i in 0u32..1000 {
+            service.insert(&i.to_be_bytes());
+        }
+        service.clear();
+        assert_eq!(service.bits_set(), 0);
+        assert_eq!(service.bit_array_bytes(), bytes);
+        assert!(!(0u32..1000).any(|i| service.lookup(&i.to_be_bytes())));
+
+        let mut delta = BloomFilter::new(1000, 0.01);
+        delta.insert(b"from worker");
+        service.insert(b"local");
+        service.extend_from(&delta);
+        assert!(service.lookup(b"from worker") && service.lookup(b"local"));
+        delta.insert(b"local");
+        assert_eq!(service.summary(), delta.summary());
+    }
+
+    #[test]
+    #[should_panic(expected = "different geometry or hashing")]
+    fn extend_from_rejects_incompatible_filters() {
+        log::init_test_logger();
+        let mut a = BloomFilter::new(1000, 0.01);
+        a.extend_from(&BloomFilter::new(100, 0.01));
+    }
+
+    #[test]
+    fn extend_from_accepts_equal_hashers_built_apart() {
+        log::init_test_logger();
+        let mut a = BloomFilter::with_hasher(1000, 0.01, XxHash64::with_seed(7));
+        let mut b = BloomFilter::with_hasher(1000, 0.01, XxHash64::with_seed(7));
+        a.insert(b"a");
+        b.insert(b"b");
+        a.extend_from(&b);
+        assert!(a.lookup(b"a") && a.lookup(b"b"));
+
+        let other_seed = BloomFilter::with_hasher(1000, 0.01, XxHash64::with_seed(8));
+        assert!(!a.is_compatible(&other_seed));
+        let other_hasher = BloomFilter::with_hasher(1000, 0.01, SipHash13::with_keys(7, 0));
+        assert!(!a.is_compatible(&other_hasher));
+    }
+
+    #[test]
+    fn union_many_matches_pairwise_union() {
+        log::init_test_logger();
+        assert!(BloomFilter::union_many([]).is_none());
+        // large enough for several chunks of words
+        let shards: Vec<BloomFilter> = (0u32..40)
+            .map(|shard| {
+                let mut b = BloomFilter::new(100_000, 0.01);
+                for i in 0..500 {
+                    b.insert(&(shard * 1000 + i).to_be_bytes());
+                }
+                b
+            })
+            .collect();
+        let union = BloomFilter::union_many(&shards).unwrap();
+        let mut expected = shards[0].clone();
+        for shard in &shards[1..] {
+            expected.union_with(shard);
+        }
+        assert!(union.bit_array == expected.bit_array);
+        assert!(union.is_compatible(&shards[0]));
+        assert!((0u32..40).all(|shard| union.lookup(&(shard * 1000 + 499).to_be_bytes())));
+    }
+    #[test]
+    #[should_panic(expected = "different geometry")]
+    fn union_many_rejects_different_geometry() {
+        log::init_test_logger();
+        let shards = [BloomFilter::new(100, 0.01), BloomFilter::new(1000, 0.01)];
+        BloomFilter::union_many(&shards);
+    }
+    #[test]
+    fn estimate_difference_tracks_drift() {
+        log::init_test_logger();
+        let mut a = BloomFilter::new(10_000, 0.01);
+        let mut b = BloomFilter::new(10_000, 0.01);
+        for i in 0u32..4_000 {
+            a.insert(&i.to_be_bytes());
+            b.insert(&i.to_be_bytes());
+        }
+        for i in 4_000u32..4_500 {
+            a.insert(&i.to_be_bytes());
+        }
+        for i in 10_000u32..10_100 {
+            b.insert(&i.to_be_bytes());
+        }
+        let estimate = a.estimate_difference(&b);
+        assert!((estimate.only_self - 500.0).abs() < 50.0, "{:?}", estimate);
+        assert!((estimate.only_other - 100.0).abs() < 30.0, "{:?}", estimate);
+        assert!((estimate.common - 4_000.0).abs() < 100.0, "{:?}", estimate);
+
+        let same = a.estimate_difference(&a.clone());
+        assert_eq!(same.only_self, 0.0);
+        assert_eq!(same.only_other, 0.0);
+    }
+    #[test]
+    fn u64_keys_insert_lookup() {
+        log::init_test_logger();
+        for mut b in [
+            BloomFilter::new(1000, 0.01),
+            BloomFilter::with_hasher(1000, 0.01, SipHash13::with_keys(1, 2)),
+        ] {
+            for key in 0u64..1000 {
+                b.insert_u64(key * 7919);
+            }
+            assert!((0u64..1000).all(|key| b.lookup_u64(key * 7919)));
+            let false_positives = (0u64..10_000)
+                .filter(|key| b.lookup_u64(key * 7919 + 1))
+                .count();
+            assert!(false_positives < 300, "{}", false_positives);
+        }
+    }
+    #[test]
+    fn collect_and_extend() {
+        log::init_test_logger();
+        let mut b: BloomFilter = (0u32..100).map(u32::to_be_bytes).collect();
+        assert_eq!(b.n, 100);
+        assert_eq!(b.f, DEFAULT_FALSE_POSITIVE_RATE);
+        b.extend(["a", "b"]);
+        assert!((0u32..100).all(|i| b.lookup(&i.to_be_bytes())));
+        assert!(b.lookup(b"a") && b.lookup(b"b"));
+    }
+    #[test]
+    fn word_batched_lookup_matches_probes() {
+        log::init_test_logger();
+        // two words of bits, so most probes share a word with another
+        let mut b = BloomFilter::with_hasher(10, 0.01, SipHash13::with_keys(1, 2));
+        for key in 0u64..5 {
+            b.insert_u64(key);
+        }
+        let mut hits = 0;
+        for key in 0u32..1000 {
+            let expected = probes(&b, &key.to_be_bytes())
+                .into_iter()
+                .all(|i| b.bit_array.get(i));
+            assert_eq!(b.lookup(&key.to_be_bytes()), expected);
+            hits += expected as usize;
+        }
+        assert!(hits > 0 && hits < 1000, "{}", hits);
+    }
+    #[test]
+    #[should_panic]
+    fn estimate_difference_rejects_different_geometry() {
+        log::init_test_logger();
+        let a = BloomFilter::new(100, 0.01);
+        a.estimate_difference(&BloomFilter::new(1000, 0.01));
+    }
+    #[test]
+    #[should_panic]
+    fn union_rejects_different_geometry() {
+        log::init_test_logger();
+        let mut a = BloomFilter::new(100, 0.01);
+        let b = BloomFilter::new(1000, 0.01);
+        a.union_with(&b);
+    }
+    #[test]
+    fn keyed_hasher_insert_lookup() {
+        log::init_test_logger();
+        let mut a = BloomFilter::with_hasher(100, 0.01, SipHash13::with_keys(1, 2));
+        let mut b = BloomFilter::with_hasher(100, 0.01, SipHash13::with_keys(3, 4));
+        for i in 0u32..100 {
+            a.insert(&i.to_be_bytes());
+            b.insert(&i.to_be_bytes());
+        }
+        for i in 0u32..100 {
+            assert!(a.lookup(&i.to_be_bytes()));
+            assert!(b.lookup(&i.to_be_bytes()));
+        }
+        assert!(
+            a.bit_array != b.bit_array,
+            "different keys must place items differently"
+        );
+    }
+    #[test]
+    fn hashable_items_need_no_byte_encoding() {
+        log::init_test_logger();
+        #[derive(Hash)]
+        struct Session {
+            user: u32,
+            device: &'static str,
+        }
+        let mut b = BloomFilter::with_hasher(1000, 0.01, SipHash13::with_keys(1, 2));
+        for user in 0u32..500 {
+            b.insert_hashable(&Session {
+                user,
+                device: "phone",
+            });
+            b.insert_hashable(&(user, -1i64));
+        }
+        b.insert_hashable("text");
+        for user in 0u32..500 {
+            assert!(b.lookup_hashable(&Session {
+                user,
+                device: "phone"
+            }));
+            assert!(b.lookup_hashable(&(user, -1i64)));
+        }
+        assert!(b.lookup_hashable("text") && b.lookup_hashable(&String::from("text")));
+        let false_positives = (0u32..500)
+            .filter(|&user| {
+                b.lookup_hashable(&Session {
+                    user,
+                    device: "laptop",
+                })
+            })
+            .count();
+        assert!(false_positives < 25, "{}", false_positives);
+    }
+    #[test]
+    fn batches_match_single_inserts_and_lookups() {
+        log::init_test_logger();
+        let keys: Vec<[u8; 4]> = (0u32..1000).map(|i| i.to_be_bytes()).collect();
+        let mut one_by_one = BloomFilter::new(1000, 0.01);
+        for key in &keys {
+            one_by_one.insert(key);
+        }
+        let mut batched = BloomFilter::with_hasher(1000, 0.01, SipHash13::with_keys(1, 2));
+        batched.insert_batch(&keys[..0]);
+        batched.insert_batch(&keys);
+        let mut batched_murmur = BloomFilter::new(1000, 0.01);
+        batched_murmur.insert_batch(&keys);
+        assert!(batched_murmur.bit_array == one_by_one.bit_array);
+
+        let probes: Vec<[u8; 4]> = (500u32..5000).map(|i| i.to_be_bytes()).collect();
+        for filter in [&one_by_one, &batched] {
+            let found = filter.contains_batch(&probes);
+            assert_eq!(found.len(), probes.len());
+            for (probe, found) in probes.iter().zip(found) {
+                assert_eq!(found, filter.lookup(probe));
+            }
+        }
+        assert!(batched.contains_batch(&keys).into_iter().all(|found| found));
+        assert!(batched.contains_batch::<&[u8]>(&[]).is_empty());
+    }
+
+    #[test]
+    fn shared_filter_answers_concurrent_readers() {
+        log::init_test_logger();
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<BloomFilter>();
+
+        for mut b in [
+            BloomFilter::new(1000, 0.01),
+            BloomFilter::with_hasher(1000, 0.01, SipHash13::with_keys(1, 2)),
+        ] {
+            for i in 0u32..1000 {
+                b.insert(&i.to_be_bytes());
+            }
+            let false_positives = |b: &BloomFilter| {
+                (1000u32..20_000)
+                    .filter(|i| b.lookup(&i.to_be_bytes()))
+                    .count()
+            };
+            let expected = false_positives(&b);
+            let shared = std::sync::Arc::new(b);
+            let readers: Vec<_> = (0..4)
+                .map(|_| {
+                    let b = shared.clone();
+                    std::thread::spawn(move || {
+                        assert!((0u32..1000).all(|i| b.lookup(&i.to_be_bytes())));
+                        false_positives(&b)
+                    })
+                })
+                .collect();
+            for reader in readers {
+                assert_eq!(reader.join().unwrap(), expected);
+            }
+        }
+    }
+    #[cfg(feature = "roaring")]
+    #[test]
+    fn sparse_round_trip_keeps_members() {
+        log::init_test_logger();
+        let mut b = BloomFilter::new(1000, 0.01);
+        for i in 0u32..50 {
+            b.insert(&i.to_be_bytes());
+        }
+        let dense_bits = b.bit_array.to_dense(b.m as usize);
+        let mut sparse = b.into_sparse();
+        assert!(sparse.is_sparse());
+        assert!((0u32..50).all(|i| sparse.lookup(&i.to_be_bytes())));
+        sparse.insert(b"late");
+        assert!(sparse.lookup(b"late"));
+
+        let mut other = BloomFilter::new(1000, 0.01);
+        other.insert(b"other");
+        sparse.union_with(&other);
+        assert!(sparse.lookup(b"other"));
+
+        let dense = sparse.into_dense();
+        assert!(!dense.is_sparse());
+        assert!(dense.lookup(b"late") && dense.lookup(b"other"));
+        let bits = dense.bit_array.to_dense(dense.m as usize);
+        assert!(dense_bits.iter_ones().all(|i| bits[i]));
+    }
 }
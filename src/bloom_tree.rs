@@ -0,0 +1,160 @@
+use crate::bloom_filter::BloomFilter;
+
+/// A hierarchical index over many compatible Bloom filters, in the style of
+/// a sequence Bloom tree.
+///
+/// The given filters are the leaves; every internal node is the union of
+/// its children. A query descends only into subtrees whose union may
+/// contain the item, so sparse hits over hundreds of per-shard filters
+/// probe far fewer than all of them.
+pub struct BloomTree {
+    fanout: usize,
+    /// `levels[0]` are the leaves, the last level holds the single root.
+    levels: Vec<Vec<BloomFilter>>,
+}
+
+impl BloomTree {
+    pub fn new(leaves: Vec<BloomFilter>, fanout: usize) -> Self {
+        assert!(fanout >= 2, "fanout must be at least 2");
+        assert!(!leaves.is_empty(), "a bloom tree needs at least one leaf");
+        for leaf in &leaves[1..] {
+            assert!(
+                leaves[0].is_compatible(leaf),
+                "all leaves must share geometry and hashing"
+            );
+        }
+
+        let mut levels = vec![leaves];
+        while levels.last().unwrap().len() > 1 {
+            let parents = levels
+                .last()
+                .unwrap()
+                .chunks(fanout)
+                .map(|children| {
+                    let mut parent = children[0].clone();
+                    for child in &children[1..] {
+                        parent.union_with(child);
+                    }
+                    parent
+                })
+                .collect();
+            levels.push(parents);
+        }
+        BloomTree { fanout, levels }
+    }
+
+    pub fn len(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels[0].is_empty()
+    }
+
+    pub fn leaf(&self, index: usize) -> &BloomFilter {
+        &self.levels[0][index]
+    }
+
+    /// Inserts `item` into leaf `index` and every ancestor covering it.
+    pub fn insert(&mut self, index: usize, item: &[u8]) {
+        assert!(index < self.len(), "leaf {} does not exist", index);
+        let mut node = index;
+        for level in self.levels.iter_mut() {
+            level[node].insert(item);
+            node /= self.fanout;
+        }
+    }
+
+    /// Returns the indexes of the leaves that may contain `item`, in order.
+    pub fn which_children_contain(&self, item: &[u8]) -> Vec<usize> {
+        let mut found = Vec::new();
+        let root_level = self.levels.len() - 1;
+        self.descend(root_level, 0, item, &mut found);
+        found
+    }
+
+    fn descend(&self, level: usize, node: usize, item: &[u8], found: &mut Vec<usize>) {
+        if !self.levels[level][node].lookup(item) {
+            return;
+        }
+        if level == 0 {
+            found.push(node);
+            return;
+        }
+        let children = self.levels[level - 1].len();
+        let first = node * self.fanout;
+        for child in first..(first + self.fanout).min(children) {
+            self.descend(level - 1, child, item, found);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn shard_tree(shards: usize, fanout: usize) -> BloomTree {
+        let leaves = (0..shards)
+            .map(|shard| {
+                let mut filter = BloomFilter::new(1000, 0.001);
+                for i in 0..100u32 {
+                    filter.insert(format!("shard{}-{}", shard, i).as_bytes());
+                }
+                filter
+            })
+            .collect();
+        BloomTree::new(leaves, fanout)
+    }
+
+    #[test]
+    fn finds_the_shard_holding_an_item() {
+        for fanout in [2, 3, 8] {
+            let tree = shard_tree(20, fanout);
+            assert_eq!(tree.len(), 20);
+            for shard in 0..20 {
+                let item = format!("shard{}-{}", shard, 42);
+                assert_eq!(tree.which_children_contain(item.as_bytes()), vec![shard]);
+            }
+        }
+    }
+
+    #[test]
+    fn absent_item_matches_no_shard() {
+        let tree = shard_tree(16, 2);
+        let hits = (0..1000u32)
+            .filter(|i| {
+                !tree
+                    .which_children_contain(format!("absent-{}", i).as_bytes())
+                    .is_empty()
+            })
+            .count();
+        // 16 leaves at 0.1% each, plus slack
+        assert!(hits < 50, "{} absent items matched a shard", hits);
+    }
+
+    #[test]
+    fn insert_updates_leaf_and_ancestors() {
+        let mut tree = shard_tree(9, 2);
+        tree.insert(3, b"shared");
+        tree.insert(8, b"shared");
+        assert_eq!(tree.which_children_contain(b"shared"), vec![3, 8]);
+        assert!(tree.leaf(8).lookup(b"shared"));
+    }
+
+    #[test]
+    fn single_leaf_tree() {
+        let mut tree = BloomTree::new(vec![BloomFilter::new(10, 0.01)], 2);
+        assert!(tree.which_children_contain(b"x").is_empty());
+        tree.insert(0, b"x");
+        assert_eq!(tree.which_children_contain(b"x"), vec![0]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_incompatible_leaves() {
+        BloomTree::new(
+            vec![BloomFilter::new(10, 0.01), BloomFilter::new(100, 0.01)],
+            2,
+        );
+    }
+}
@@ -0,0 +1,32 @@
+//! Errors shared by the `builder()` types of the filters, sketches and
+//! rings.
+//!
+//! Builders collect options in any order and check them together in
+//! `build()`, so a bad combination is reported as a value instead of a
+//! panic deep inside a constructor.
+
+/// Why a builder refused to build.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildError {
+    /// A required option was never set.
+    Missing(&'static str),
+    /// An option is out of its valid range.
+    Invalid {
+        option: &'static str,
+        reason: &'static str,
+    },
+    /// Two options cannot be used together.
+    Conflict(&'static str, &'static str),
+}
+
+impl std::fmt::Display for BuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuildError::Missing(option) => write!(f, "{} must be set", option),
+            BuildError::Invalid { option, reason } => write!(f, "{} {}", option, reason),
+            BuildError::Conflict(a, b) => write!(f, "{} and {} cannot be combined", a, b),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
@@ -0,0 +1,503 @@
+use crate::hash_ring::{HashRing, HashRingInterface, Identity};
+use crate::jump::JumpHash;
+use crate::rendezvous::Rendezvous;
+use crate::simple_ch::{self, NodeId};
+use crate::slots::SlotMap;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::collections::{BTreeSet, HashMap};
+
+/// A ring implementation that can be driven by the churn scenario.
+///
+/// Positions and resource keys are both points on a ring of size `2^k`.
+pub trait RingBackend {
+    fn name(&self) -> &'static str;
+    fn join(&mut self, position: u64);
+    fn leave(&mut self, position: u64);
+    fn insert(&mut self, key: u64);
+    /// Returns the node that owns `key`, whether or not it is stored.
+    fn owner(&self, key: u64) -> Option<u64>;
+    /// Returns the current owner node for every stored resource.
+    fn owners(&self) -> HashMap<u64, u64>;
+}
+
+impl RingBackend for HashRing<i64> {
+    fn name(&self) -> &'static str {
+        "hash_ring"
+    }
+
+    fn join(&mut self, position: u64) {
+        self.add_node(position as i64);
+    }
+
+    fn leave(&mut self, position: u64) {
+        self.remove_node(position as i64);
+    }
+
+    fn insert(&mut self, key: u64) {
+        self.add_resource(key as i64);
+    }
+
+    fn owner(&self, key: u64) -> Option<u64> {
+        self.lookup(key as i64)
+            .map(|node| *node.lock().unwrap().value() as u64)
+    }
+
+    fn owners(&self) -> HashMap<u64, u64> {
+        let mut owners = HashMap::new();
+        for (node, resources) in self.resources() {
+            for (key, _) in resources {
+                owners.insert(key as u64, node as u64);
+            }
+        }
+        owners
+    }
+}
+
+/// Rendezvous hashing keeps no per-node state, so the stored keys are kept
+/// alongside it and re-resolved whenever ownership is inspected.
+#[derive(Debug, Default)]
+pub struct RendezvousBackend {
+    rendezvous: Rendezvous,
+    keys: Vec<u64>,
+}
+
+impl RingBackend for RendezvousBackend {
+    fn name(&self) -> &'static str {
+        "rendezvous"
+    }
+
+    fn join(&mut self, position: u64) {
+        self.rendezvous.add_node(position);
+    }
+
+    fn leave(&mut self, position: u64) {
+        self.rendezvous.remove_node(position);
+    }
+
+    fn insert(&mut self, key: u64) {
+        self.keys.push(key);
+    }
+
+    fn owner(&self, key: u64) -> Option<u64> {
+        self.rendezvous.lookup(&key.to_be_bytes())
+    }
+
+    fn owners(&self) -> HashMap<u64, u64> {
+        self.keys
+            .iter()
+            .filter_map(|&key| self.owner(key).map(|owner| (key, owner)))
+            .collect()
+    }
+}
+
+/// Jump consistent hashing with unit weights. Like rendezvous it keeps no
+/// per-node state, so the stored keys are kept alongside it.
+#[derive(Debug, Default)]
+pub struct JumpBackend {
+    jump: JumpHash,
+    keys: Vec<u64>,
+}
+
+impl RingBackend for JumpBackend {
+    fn name(&self) -> &'static str {
+        "jump"
+    }
+
+    fn join(&mut self, position: u64) {
+        self.jump.add_node(position);
+    }
+
+    fn leave(&mut self, position: u64) {
+        self.jump.remove_node(position);
+    }
+
+    fn insert(&mut self, key: u64) {
+        self.keys.push(key);
+    }
+
+    fn owner(&self, key: u64) -> Option<u64> {
+        self.jump.lookup(&key.to_be_bytes())
+    }
+
+    fn owners(&self) -> HashMap<u64, u64> {
+        self.keys
+            .iter()
+            .filter_map(|&key| self.owner(key).map(|owner| (key, owner)))
+            .collect()
+    }
+}
+
+/// [`simple_ch::HashRing`] with nodes named by their positions and placed
+/// by [`Identity`], so it owns keys exactly like `hash_ring` does.
+#[derive(Debug)]
+pub struct SimpleChBackend {
+    ring: simple_ch::HashRing<[u8; 8], Identity>,
+    ids: HashMap<u64, NodeId>,
+    keys: Vec<u64>,
+}
+
+impl Default for SimpleChBackend {
+    fn default() -> Self {
+        SimpleChBackend {
+            ring: simple_ch::HashRing::with_placement(Identity),
+            ids: HashMap::new(),
+            keys: Vec::new(),
+        }
+    }
+}
+
+impl RingBackend for SimpleChBackend {
+    fn name(&self) -> &'static str {
+        "simple_ch"
+    }
+
+    fn join(&mut self, position: u64) {
+        let id = self.ring.add_node(position.to_be_bytes()).unwrap();
+        self.ids.insert(position, id);
+    }
+
+    fn leave(&mut self, position: u64) {
+        if let Some(id) = self.ids.remove(&position) {
+            self.ring.remove(id);
+        }
+    }
+
+    fn insert(&mut self, key: u64) {
+        self.keys.push(key);
+    }
+
+    fn owner(&self, key: u64) -> Option<u64> {
+        self.ring
+            .lookup(&key.to_be_bytes())
+            .map(|(id, _)| id.position())
+    }
+
+    fn owners(&self) -> HashMap<u64, u64> {
+        self.keys
+            .iter()
+            .filter_map(|&key| self.owner(key).map(|owner| (key, owner)))
+            .collect()
+    }
+}
+
+/// Slot-based sharding: joins and leaves are applied as slot migration plans.
+#[derive(Debug, Default)]
+pub struct SlotsBackend {
+    map: SlotMap,
+    keys: Vec<u64>,
+}
+
+impl RingBackend for SlotsBackend {
+    fn name(&self) -> &'static str {
+        "slots"
+    }
+
+    fn join(&mut self, position: u64) {
+        let plan = self.map.plan_add_node(position);
+        self.map.apply(&plan);
+    }
+
+    fn leave(&mut self, position: u64) {
+        let plan = self.map.plan_remove_node(position);
+        self.map.apply(&plan);
+    }
+
+    fn insert(&mut self, key: u64) {
+        self.keys.push(key);
+    }
+
+    fn owner(&self, key: u64) -> Option<u64> {
+        self.map.lookup(&key.to_be_bytes())
+    }
+
+    fn owners(&self) -> HashMap<u64, u64> {
+        self.keys
+            .iter()
+            .filter_map(|&key| self.owner(key).map(|owner| (key, owner)))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ChurnConfig {
+    pub k: u32,
+    pub initial_nodes: usize,
+    pub steps: usize,
+    pub inserts_per_step: usize,
+    /// Probability that a node joins during a step.
+    pub join_rate: f64,
+    /// Probability that a node leaves during a step.
+    pub leave_rate: f64,
+    pub seed: u64,
+}
+
+impl Default for ChurnConfig {
+    fn default() -> Self {
+        ChurnConfig {
+            k: 16,
+            initial_nodes: 8,
+            steps: 20,
+            inserts_per_step: 1000,
+            join_rate: 0.2,
+            leave_rate: 0.2,
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChurnStep {
+    pub step: usize,
+    pub nodes: usize,
+    pub resources: usize,
+    pub joined: usize,
+    pub left: usize,
+    /// Resources whose owner changed during the step.
+    pub migrated: usize,
+    /// Fraction of the resources present before the step that kept their owner.
+    pub stability: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChurnReport {
+    pub backend: &'static str,
+    pub steps: Vec<ChurnStep>,
+}
+
+impl ChurnReport {
+    pub fn total_migrated(&self) -> usize {
+        self.steps.iter().map(|s| s.migrated).sum()
+    }
+}
+
+/// Runs the churn scenario against every ring backend in the crate.
+pub fn run_all(config: &ChurnConfig) -> Vec<ChurnReport> {
+    let mut hash_ring: HashRing<i64> = HashRing::new(config.k);
+    let ring_report = run(&mut hash_ring, config);
+    hash_ring.remove_all();
+
+    let simple_ch_report = run(&mut SimpleChBackend::default(), config);
+    let rendezvous_report = run(&mut RendezvousBackend::default(), config);
+    let jump_report = run(&mut JumpBackend::default(), config);
+    let slots_report = run(&mut SlotsBackend::default(), config);
+    vec![
+        ring_report,
+        simple_ch_report,
+        rendezvous_report,
+        jump_report,
+        slots_report,
+    ]
+}
+
+/// Streams resource insertions into `backend` while nodes join and leave,
+/// recording migration volume and ownership stability for every step.
+pub fn run<B: RingBackend>(backend: &mut B, config: &ChurnConfig) -> ChurnReport {
+    assert!(config.k > 0 && config.k < 63, "k must be in 1..63");
+    assert!(
+        config.initial_nodes > 0,
+        "at least one initial node is required"
+    );
+    let ring_size = 1u64 << config.k;
+    assert!(
+        (config.initial_nodes as u64) <= ring_size,
+        "more initial nodes than ring positions"
+    );
+
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut nodes = BTreeSet::new();
+    while nodes.len() < config.initial_nodes {
+        let position = rng.random_range(0..ring_size);
+        if nodes.insert(position) {
+            backend.join(position);
+        }
+    }
+
+    let mut steps = Vec::with_capacity(config.steps);
+    for step in 0..config.steps {
+        let before = backend.owners();
+
+        let mut joined = 0;
+        if rng.random_bool(config.join_rate) && (nodes.len() as u64) < ring_size {
+            loop {
+                let position = rng.random_range(0..ring_size);
+                if nodes.insert(position) {
+                    backend.join(position);
+                    joined += 1;
+                    break;
+                }
+            }
+        }
+
+        let mut left = 0;
+        if rng.random_bool(config.leave_rate) && nodes.len() > 1 {
+            let victim = rng.random_range(0..nodes.len());
+            let position = *nodes.iter().nth(victim).unwrap();
+            nodes.remove(&position);
+            backend.leave(position);
+            left += 1;
+        }
+
+        for _ in 0..config.inserts_per_step {
+            backend.insert(rng.random_range(0..ring_size));
+        }
+
+        let after = backend.owners();
+        let migrated = before
+            .iter()
+            .filter(|(key, owner)| after.get(key) != Some(owner))
+            .count();
+        let stability = if before.is_empty() {
+            1.0
+        } else {
+            1.0 - migrated as f64 / before.len() as f64
+        };
+
+        steps.push(ChurnStep {
+            step,
+            nodes: nodes.len(),
+            resources: after.len(),
+            joined,
+            left,
+            migrated,
+            stability,
+        });
+    }
+
+    ChurnReport {
+        backend: backend.name(),
+        steps,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log;
+
+    #[test]
+    fn no_churn_means_no_migration() {
+        log::init_test_logger();
+        let config = ChurnConfig {
+            k: 10,
+            steps: 5,
+            inserts_per_step: 50,
+            join_rate: 0.0,
+            leave_rate: 0.0,
+            ..Default::default()
+        };
+        let reports = run_all(&config);
+        let backends: Vec<&str> = reports.iter().map(|r| r.backend).collect();
+        assert_eq!(
+            backends,
+            vec!["hash_ring", "simple_ch", "rendezvous", "jump", "slots"]
+        );
+        for report in &reports {
+            assert_eq!(report.steps.len(), 5);
+            assert_eq!(report.total_migrated(), 0);
+            assert!(report.steps.iter().all(|s| s.stability == 1.0));
+            assert!(report.steps.iter().all(|s| s.nodes == config.initial_nodes));
+        }
+    }
+
+    /// Applies one random sequence of joins, leaves and inserts to every
+    /// backend and checks each ownership answer after every operation.
+    /// The two consistent-hash rings must agree exactly. Rendezvous assigns
+    /// keys by a different rule, so it is held to what all three share: the
+    /// owner is a live node, and a key only moves to a joining node or away
+    /// from a leaving one.
+    #[test]
+    fn backends_agree_on_ownership() {
+        log::init_test_logger();
+        let k = 12;
+        let ring_size = 1u64 << k;
+        let mut hash_ring: HashRing<i64> = HashRing::new(k);
+        let mut simple_ch = SimpleChBackend::default();
+        let mut rendezvous = RendezvousBackend::default();
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut nodes = BTreeSet::from([0, ring_size / 2]);
+        for &position in &nodes {
+            hash_ring.join(position);
+            simple_ch.join(position);
+            rendezvous.join(position);
+        }
+        let mut keys = BTreeSet::new();
+
+        for _ in 0..600 {
+            let backends: [&mut dyn RingBackend; 3] =
+                [&mut hash_ring, &mut simple_ch, &mut rendezvous];
+            let before: Vec<HashMap<u64, u64>> = backends.iter().map(|b| b.owners()).collect();
+            let (mut joined, mut left) = (None, None);
+            match rng.random_range(0..10) {
+                0..=1 => {
+                    let position = rng.random_range(0..ring_size);
+                    if nodes.insert(position) {
+                        backends.into_iter().for_each(|b| b.join(position));
+                        joined = Some(position);
+                    }
+                }
+                2 if nodes.len() > 1 => {
+                    let victim = rng.random_range(0..nodes.len());
+                    let position = *nodes.iter().nth(victim).unwrap();
+                    nodes.remove(&position);
+                    backends.into_iter().for_each(|b| b.leave(position));
+                    left = Some(position);
+                }
+                _ => {
+                    let key = rng.random_range(0..ring_size);
+                    backends.into_iter().for_each(|b| b.insert(key));
+                    keys.insert(key);
+                }
+            }
+
+            let probe = rng.random_range(0..ring_size);
+            let expected = hash_ring.owner(probe);
+            assert_eq!(simple_ch.owner(probe), expected, "probe {}", probe);
+            assert!(nodes.contains(&expected.unwrap()));
+            assert!(nodes.contains(&rendezvous.owner(probe).unwrap()));
+
+            let backends: [&dyn RingBackend; 3] = [&hash_ring, &simple_ch, &rendezvous];
+            let after: Vec<HashMap<u64, u64>> = backends.iter().map(|b| b.owners()).collect();
+            assert_eq!(after[0], after[1]);
+            for (before, after) in before.iter().zip(&after) {
+                assert_eq!(after.len(), keys.len());
+                for (key, owner) in after {
+                    assert!(nodes.contains(owner));
+                    match before.get(key) {
+                        Some(old) if old != owner => {
+                            assert!(joined == Some(*owner) || left == Some(*old));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        assert!(nodes.len() > 2 && keys.len() > 300);
+        hash_ring.remove_all();
+    }
+
+    #[test]
+    fn churn_moves_resources_and_is_deterministic() {
+        log::init_test_logger();
+        let config = ChurnConfig {
+            k: 10,
+            steps: 10,
+            inserts_per_step: 50,
+            join_rate: 1.0,
+            leave_rate: 1.0,
+            seed: 42,
+            ..Default::default()
+        };
+        let first = run_all(&config);
+        let second = run_all(&config);
+        for (report, again) in first.iter().zip(&second) {
+            assert_eq!(report.steps, again.steps);
+            assert!(report.total_migrated() > 0);
+            for step in &report.steps {
+                assert_eq!(step.joined, 1);
+                assert_eq!(step.left, 1);
+                assert_eq!(step.nodes, config.initial_nodes);
+                assert!((0.0..=1.0).contains(&step.stability));
+            }
+        }
+    }
+}
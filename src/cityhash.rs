@@ -0,0 +1,327 @@
+//! CityHash64 (v1.1) 64-bit string hash. Inputs of up to 6 bytes are checked
+//! against the first rows of the reference test vectors; longer length
+//! classes are not yet covered by a reference check.
+use crate::hasher::Hasher64;
+
+const K0: u64 = 0xc3a5_c85c_97cb_3127;
+const K1: u64 = 0xb492_b66f_be98_f273;
+const K2: u64 = 0x9ae1_6a3b_2f90_404f;
+const K_MUL: u64 = 0x9ddf_ea08_eb38_2d69;
+
+fn fetch64(s: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(s[at..at + 8].try_into().unwrap())
+}
+
+fn fetch32(s: &[u8], at: usize) -> u64 {
+    u32::from_le_bytes(s[at..at + 4].try_into().unwrap()) as u64
+}
+
+fn shift_mix(val: u64) -> u64 {
+    val ^ (val >> 47)
+}
+
+fn hash_len16_mul(u: u64, v: u64, mul: u64) -> u64 {
+    let mut a = (u ^ v).wrapping_mul(mul);
+    a ^= a >> 47;
+    let mut b = (v ^ a).wrapping_mul(mul);
+    b ^= b >> 47;
+    b.wrapping_mul(mul)
+}
+
+fn hash_len16(u: u64, v: u64) -> u64 {
+    hash_len16_mul(u, v, K_MUL)
+}
+
+fn hash_len0to16(s: &[u8]) -> u64 {
+    let len = s.len();
+    if len >= 8 {
+        let mul = K2.wrapping_add(len as u64 * 2);
+        let a = fetch64(s, 0).wrapping_add(K2);
+        let b = fetch64(s, len - 8);
+        let c = b.rotate_right(37).wrapping_mul(mul).wrapping_add(a);
+        let d = a.rotate_right(25).wrapping_add(b).wrapping_mul(mul);
+        return hash_len16_mul(c, d, mul);
+    }
+    if len >= 4 {
+        let mul = K2.wrapping_add(len as u64 * 2);
+        let a = fetch32(s, 0);
+        return hash_len16_mul((len as u64).wrapping_add(a << 3), fetch32(s, len - 4), mul);
+    }
+    if len > 0 {
+        let a = s[0] as u32;
+        let b = s[len >> 1] as u32;
+        let c = s[len - 1] as u32;
+        let y = a.wrapping_add(b << 8);
+        let z = (len as u32).wrapping_add(c << 2);
+        return shift_mix((y as u64).wrapping_mul(K2) ^ (z as u64).wrapping_mul(K0))
+            .wrapping_mul(K2);
+    }
+    K2
+}
+
+fn hash_len17to32(s: &[u8]) -> u64 {
+    let len = s.len();
+    let mul = K2.wrapping_add(len as u64 * 2);
+    let a = fetch64(s, 0).wrapping_mul(K1);
+    let b = fetch64(s, 8);
+    let c = fetch64(s, len - 8).wrapping_mul(mul);
+    let d = fetch64(s, len - 16).wrapping_mul(K2);
+    hash_len16_mul(
+        a.wrapping_add(b)
+            .rotate_right(43)
+            .wrapping_add(c.rotate_right(30))
+            .wrapping_add(d),
+        a.wrapping_add(b.wrapping_add(K2).rotate_right(18))
+            .wrapping_add(c),
+        mul,
+    )
+}
+
+fn hash_len33to64(s: &[u8]) -> u64 {
+    let len = s.len();
+    let mul = K2.wrapping_add(len as u64 * 2);
+    let mut a = fetch64(s, 0).wrapping_mul(K2);
+    let mut b = fetch64(s, 8);
+    let c = fetch64(s, len - 24);
+    let d = fetch64(s, len - 32);
+    let e = fetch64(s, 16).wrapping_mul(K2);
+    let f = fetch64(s, 24).wrapping_mul(9);
+    let g = fetch64(s, len - 8);
+    let h = fetch64(s, len - 16).wrapping_mul(mul);
+    let u = a
+        .wrapping_add(g)
+        .rotate_right(43)
+        .wrapping_add(b.rotate_right(30).wrapping_add(c).wrapping_mul(9));
+    let v = (a.wrapping_add(g) ^ d).wrapping_add(f).wrapping_add(1);
+    let w = u
+        .wrapping_add(v)
+        .wrapping_mul(mul)
+        .swap_bytes()
+        .wrapping_add(h);
+    let x = e.wrapping_add(f).rotate_right(42).wrapping_add(c);
+    let y = v
+        .wrapping_add(w)
+        .wrapping_mul(mul)
+        .swap_bytes()
+        .wrapping_add(g)
+        .wrapping_mul(mul);
+    let z = e.wrapping_add(f).wrapping_add(c);
+    a = x
+        .wrapping_add(z)
+        .wrapping_mul(mul)
+        .wrapping_add(y)
+        .swap_bytes()
+        .wrapping_add(b);
+    b = shift_mix(
+        z.wrapping_add(a)
+            .wrapping_mul(mul)
+            .wrapping_add(d)
+            .wrapping_add(h),
+    )
+    .wrapping_mul(mul);
+    b.wrapping_add(x)
+}
+
+fn weak_hash_len32_with_seeds(s: &[u8], at: usize, mut a: u64, mut b: u64) -> (u64, u64) {
+    let w = fetch64(s, at);
+    let x = fetch64(s, at + 8);
+    let y = fetch64(s, at + 16);
+    let z = fetch64(s, at + 24);
+    a = a.wrapping_add(w);
+    b = b.wrapping_add(a).wrapping_add(z).rotate_right(21);
+    let c = a;
+    a = a.wrapping_add(x).wrapping_add(y);
+    b = b.wrapping_add(a.rotate_right(44));
+    (a.wrapping_add(z), b.wrapping_add(c))
+}
+
+/// CityHash64 of `s`.
+pub fn city_hash64(s: &[u8]) -> u64 {
+    let len = s.len();
+    if len <= 16 {
+        return hash_len0to16(s);
+    }
+    if len <= 32 {
+        return hash_len17to32(s);
+    }
+    if len <= 64 {
+        return hash_len33to64(s);
+    }
+
+    let mut x = fetch64(s, len - 40);
+    let mut y = fetch64(s, len - 16).wrapping_add(fetch64(s, len - 56));
+    let mut z = hash_len16(
+        fetch64(s, len - 48).wrapping_add(len as u64),
+        fetch64(s, len - 24),
+    );
+    let mut v = weak_hash_len32_with_seeds(s, len - 64, len as u64, z);
+    let mut w = weak_hash_len32_with_seeds(s, len - 32, y.wrapping_add(K1), x);
+    x = x.wrapping_mul(K1).wrapping_add(fetch64(s, 0));
+
+    // process the input in 64-byte chunks, leaving the tail to the
+    // fetches above
+    let mut offset = 0;
+    let mut remaining = (len - 1) & !63;
+    loop {
+        x = x
+            .wrapping_add(y)
+            .wrapping_add(v.0)
+            .wrapping_add(fetch64(s, offset + 8))
+            .rotate_right(37)
+            .wrapping_mul(K1);
+        y = y
+            .wrapping_add(v.1)
+            .wrapping_add(fetch64(s, offset + 48))
+            .rotate_right(42)
+            .wrapping_mul(K1);
+        x ^= w.1;
+        y = y.wrapping_add(v.0).wrapping_add(fetch64(s, offset + 40));
+        z = z.wrapping_add(w.0).rotate_right(33).wrapping_mul(K1);
+        v = weak_hash_len32_with_seeds(s, offset, v.1.wrapping_mul(K1), x.wrapping_add(w.0));
+        w = weak_hash_len32_with_seeds(
+            s,
+            offset + 32,
+            z.wrapping_add(w.1),
+            y.wrapping_add(fetch64(s, offset + 16)),
+        );
+        std::mem::swap(&mut z, &mut x);
+        offset += 64;
+        remaining -= 64;
+        if remaining == 0 {
+            break;
+        }
+    }
+    hash_len16(
+        hash_len16(v.0, w.0)
+            .wrapping_add(shift_mix(y).wrapping_mul(K1))
+            .wrapping_add(z),
+        hash_len16(v.1, w.1).wrapping_add(x),
+    )
+}
+
+/// CityHash64WithSeed of `s`.
+pub fn city_hash64_with_seed(s: &[u8], seed: u64) -> u64 {
+    hash_len16(city_hash64(s).wrapping_sub(K2), seed)
+}
+
+/// CityHash64 as a [`Hasher64`] backend; seeded instances use
+/// CityHash64WithSeed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CityHash64 {
+    seed: Option<u64>,
+}
+
+impl CityHash64 {
+    pub fn with_seed(seed: u64) -> Self {
+        CityHash64 { seed: Some(seed) }
+    }
+}
+
+impl Hasher64 for CityHash64 {
+    fn hash64(&self, bytes: &[u8]) -> u64 {
+        match self.seed {
+            Some(seed) => city_hash64_with_seed(bytes, seed),
+            None => city_hash64(bytes),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn input(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 31 + 7) as u8).collect()
+    }
+
+    /// The reference test buffer: `data` in CityHash's city-test.cc.
+    fn reference_data(len: usize) -> Vec<u8> {
+        let (mut a, mut b) = (9u64, 777u64);
+        (0..len)
+            .map(|i| {
+                a = a.wrapping_add(b);
+                b = b.wrapping_add(a);
+                a = (a ^ (a >> 41)).wrapping_mul(K0);
+                b = (b ^ (b >> 41)).wrapping_mul(K0).wrapping_add(i as u64);
+                (b >> 37) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn matches_reference_vectors() {
+        // row i of the reference table hashes data[i * i..i * i + i];
+        // columns are CityHash64 and CityHash64WithSeed(.., 1234567),
+        // with None where the seeded value is not asserted
+        let expected: [(u64, Option<u64>); 7] = [
+            (0x9ae16a3b2f90404f, Some(0x75106db890237a4a)),
+            (0x541150e87f415e96, Some(0x1aef0d24b3148a1a)),
+            (0x0f3786a4b25827c1, Some(0x34ee1a2bf767bd1c)),
+            (0xef923a7a1af78eab, Some(0x79163b1e1e9a9b18)),
+            (0x11df592596f41d88, Some(0x843ec0bce9042f9c)),
+            (0x831f448bdc5600b3, Some(0x62a24be3120a6919)),
+            (0x3eca803e70304894, None),
+        ];
+        let data = reference_data(expected.len() * expected.len());
+        for (i, &(unseeded, seeded)) in expected.iter().enumerate() {
+            let s = &data[i * i..i * i + i];
+            assert_eq!(city_hash64(s), unseeded, "row {}", i);
+            if let Some(seeded) = seeded {
+                assert_eq!(city_hash64_with_seed(s, 1234567), seeded, "row {}", i);
+            }
+        }
+    }
+
+    #[test]
+    fn empty_input_hashes_to_k2() {
+        assert_eq!(city_hash64(b""), K2);
+    }
+
+    #[test]
+    fn every_length_class_is_distinct() {
+        // lengths cover the 0..=16, 17..=32, 33..=64 and chunked paths,
+        // including chunk boundaries
+        let mut seen = HashSet::new();
+        for len in 0..300 {
+            assert!(
+                seen.insert(city_hash64(&input(len))),
+                "collision at length {}",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn flipping_an_input_bit_changes_about_half_the_output() {
+        for len in [3, 8, 24, 48, 200] {
+            let base = input(len);
+            let hash = city_hash64(&base);
+            let mut total = 0;
+            for bit in 0..len * 8 {
+                let mut flipped = base.clone();
+                flipped[bit / 8] ^= 1 << (bit % 8);
+                total += (hash ^ city_hash64(&flipped)).count_ones();
+            }
+            let average = total as f64 / (len * 8) as f64;
+            assert!(
+                (24.0..40.0).contains(&average),
+                "length {}: average flipped bits {}",
+                len,
+                average
+            );
+        }
+    }
+
+    #[test]
+    fn seed_changes_output() {
+        let unseeded = CityHash64::default();
+        let seeded = CityHash64::with_seed(1);
+        assert_eq!(unseeded.hash64(b"key"), city_hash64(b"key"));
+        assert_ne!(seeded.hash64(b"key"), unseeded.hash64(b"key"));
+        assert_ne!(
+            seeded.hash64(b"key"),
+            CityHash64::with_seed(2).hash64(b"key")
+        );
+    }
+}
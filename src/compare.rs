@@ -0,0 +1,174 @@
+//! Side-by-side comparison of membership filters on one workload.
+//!
+//! Every structure is built from the same keys and probed with the same
+//! queries: the inserted keys for hits, and as many keys that were never
+//! inserted for misses, so every accepted miss is a false positive.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use crate::membership::{self, ApproxMembership};
+
+/// Structure names [`compare`] does not know.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownStructures(pub Vec<String>);
+
+impl std::fmt::Display for UnknownStructures {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "unknown structures: {} (expected some of {})",
+            self.0.join(", "),
+            membership::FILTERS.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for UnknownStructures {}
+
+/// Measurements of one structure.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub structure: String,
+    /// Time to create the structure and insert every key.
+    pub build: Duration,
+    /// Bytes of the structure's tables.
+    pub bytes: usize,
+    /// Lookups of inserted keys per second.
+    pub hit_throughput: f64,
+    /// Lookups of absent keys per second.
+    pub miss_throughput: f64,
+    /// Share of absent keys accepted.
+    pub false_positive_rate: f64,
+    /// What the structure's design predicts for the same share.
+    pub expected_false_positive_rate: f64,
+}
+
+/// The key inserted (`i < keys`) or probed as a miss (`i >= keys`).
+fn key(i: u64) -> [u8; 8] {
+    i.to_le_bytes()
+}
+
+/// Builds each of `structures` for `keys` keys at false-positive rate
+/// `fpp`, inserts the keys, then runs `queries` hit and `queries` miss
+/// lookups. Fails before building anything if a name is unknown.
+pub fn compare(
+    structures: &[&str],
+    keys: u32,
+    fpp: f32,
+    queries: u32,
+) -> Result<Vec<Comparison>, UnknownStructures> {
+    let unknown: Vec<String> = structures
+        .iter()
+        .filter(|name| !membership::FILTERS.contains(name))
+        .map(|name| name.to_string())
+        .collect();
+    if !unknown.is_empty() {
+        return Err(UnknownStructures(unknown));
+    }
+    assert!(keys > 0 && queries > 0, "keys and queries must be positive");
+
+    Ok(structures
+        .iter()
+        .map(|name| {
+            let start = Instant::now();
+            let mut filter = membership::build(name, keys, fpp).unwrap();
+            for i in 0..keys as u64 {
+                filter.insert(&key(i));
+            }
+            let build = start.elapsed();
+
+            let hits = (0..queries as u64).map(|i| i % keys as u64);
+            let (hit_time, _) = time_lookups(filter.as_ref(), hits);
+            let misses = (0..queries as u64).map(|i| keys as u64 + i);
+            let (miss_time, false_positives) = time_lookups(filter.as_ref(), misses);
+            Comparison {
+                structure: name.to_string(),
+                build,
+                bytes: filter.size_in_bytes(),
+                hit_throughput: queries as f64 / hit_time.as_secs_f64(),
+                miss_throughput: queries as f64 / miss_time.as_secs_f64(),
+                false_positive_rate: false_positives as f64 / queries as f64,
+                expected_false_positive_rate: filter.expected_false_positive_rate(keys as u64),
+            }
+        })
+        .collect())
+}
+
+/// Looks up every key of `indexes`, returning the time taken and how many
+/// lookups answered `true`.
+fn time_lookups(
+    filter: &dyn ApproxMembership,
+    indexes: impl Iterator<Item = u64>,
+) -> (Duration, usize) {
+    let start = Instant::now();
+    let found = indexes
+        .filter(|&i| black_box(filter.lookup(&key(i))))
+        .count();
+    (start.elapsed(), found)
+}
+
+/// Formats `rows` as an aligned text table; memory is also shown in bits
+/// per key for `keys` keys.
+pub fn render_table(rows: &[Comparison], keys: u32) -> String {
+    let mut table = format!(
+        "{:<16} {:>10} {:>12} {:>9} {:>12} {:>12} {:>10} {:>10}\n",
+        "structure",
+        "build ms",
+        "bytes",
+        "bits/key",
+        "hit Mops/s",
+        "miss Mops/s",
+        "fpr",
+        "expected"
+    );
+    for row in rows {
+        table.push_str(&format!(
+            "{:<16} {:>10.1} {:>12} {:>9.2} {:>12.2} {:>12.2} {:>10.6} {:>10.6}\n",
+            row.structure,
+            row.build.as_secs_f64() * 1e3,
+            row.bytes,
+            row.bytes as f64 * 8.0 / keys as f64,
+            row.hit_throughput / 1e6,
+            row.miss_throughput / 1e6,
+            row.false_positive_rate,
+            row.expected_false_positive_rate
+        ));
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_every_structure_on_the_same_workload() {
+        let rows = compare(&membership::FILTERS, 2_000, 0.01, 20_000).unwrap();
+        let names: Vec<&str> = rows.iter().map(|row| row.structure.as_str()).collect();
+        assert_eq!(names, membership::FILTERS);
+        for row in &rows {
+            assert!(row.bytes > 0 && row.hit_throughput > 0.0 && row.miss_throughput > 0.0);
+            assert!(
+                row.false_positive_rate < 3.0 * row.expected_false_positive_rate + 0.001,
+                "{:?}",
+                row
+            );
+        }
+        // a counting filter spends 4 bits where a Bloom filter spends one
+        assert!(rows[1].bytes > 3 * rows[0].bytes);
+
+        let table = render_table(&rows, 2_000);
+        assert_eq!(table.lines().count(), 1 + rows.len());
+        assert!(table.lines().nth(2).unwrap().starts_with("counting_bloom "));
+    }
+
+    #[test]
+    fn rejects_unknown_structures_before_building() {
+        let err = compare(&["bloom", "ribbon", "xor"], 10, 0.01, 10).unwrap_err();
+        assert_eq!(err, UnknownStructures(vec!["ribbon".into(), "xor".into()]));
+        assert!(err
+            .to_string()
+            .starts_with("unknown structures: ribbon, xor"));
+    }
+}
@@ -0,0 +1,174 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use bitvec::prelude::BitVec;
+
+use crate::bloom_filter::BloomFilter;
+use crate::hasher::{Hasher64, RowHashing, DEFAULT_SEED};
+use crate::seed::SeedSequence;
+
+/// A Bloom filter that many threads can insert into and query at once
+/// without locks.
+///
+/// The bits live in `AtomicU64` words: an insert sets each probe with
+/// `fetch_or` and a lookup loads each probe's word. Bits are only ever set,
+/// so no interleaving can lose an insert, and a lookup that runs after an
+/// insert has returned (as ordered by e.g. a channel or a join) finds the
+/// item. A lookup racing with the insert of the same item may miss it.
+///
+/// The geometry and hashing match [`BloomFilter::new`] with the same `n`
+/// and `f`, so a snapshot can be taken with
+/// [`to_bloom_filter`](Self::to_bloom_filter).
+pub struct ConcurrentBloomFilter {
+    n: u32,
+    f: f32,
+    m: u32,
+    k: u32,
+    words: Box<[AtomicU64]>,
+    hashing: RowHashing,
+}
+
+impl ConcurrentBloomFilter {
+    pub fn new(n: u32, f: f32) -> Self {
+        let (_, k) = BloomFilter::geometry_u32(n, f);
+        let hashing = RowHashing::murmur3(k as usize, SeedSequence::new(DEFAULT_SEED));
+        Self::with_hashing(n, f, hashing)
+    }
+
+    /// Creates a filter whose probes are derived from `hasher`.
+    pub fn with_hasher<H: Hasher64 + PartialEq + Send + Sync + 'static>(
+        n: u32,
+        f: f32,
+        hasher: H,
+    ) -> Self {
+        Self::with_hashing(n, f, RowHashing::from_hasher(hasher))
+    }
+
+    fn with_hashing(n: u32, f: f32, hashing: RowHashing) -> Self {
+        let (m, k) = BloomFilter::geometry_u32(n, f);
+        ConcurrentBloomFilter {
+            n,
+            f,
+            m,
+            k,
+            words: (0..(m as usize).div_ceil(64))
+                .map(|_| AtomicU64::new(0))
+                .collect(),
+            hashing,
+        }
+    }
+
+    /// Word and bit mask of each of `item`'s probes.
+    fn probes<'a>(&'a self, item: &'a [u8]) -> impl Iterator<Item = (usize, u64)> + 'a {
+        let hashes = self.hashing.hashes(item);
+        (0..self.k).map(move |i| {
+            let index = (hashes.row(i) % self.m as u64) as usize;
+            (index / 64, 1 << (index % 64))
+        })
+    }
+
+    pub fn insert(&self, item: &[u8]) {
+        for (word, mask) in self.probes(item) {
+            // skip the write, and the cache line invalidation, when the bit
+            // is already set
+            if self.words[word].load(Ordering::Relaxed) & mask == 0 {
+                self.words[word].fetch_or(mask, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn contains(&self, item: &[u8]) -> bool {
+        self.probes(item)
+            .all(|(word, mask)| self.words[word].load(Ordering::Relaxed) & mask != 0)
+    }
+
+    /// Bytes held by the bit array.
+    pub fn size_in_bytes(&self) -> usize {
+        std::mem::size_of_val(&*self.words)
+    }
+
+    /// False-positive rate in theory after `items` distinct inserts, the
+    /// same as a [`BloomFilter`] of this geometry.
+    pub fn expected_false_positive_rate(&self, items: u64) -> f64 {
+        crate::bloom_filter::expected_false_positive_rate(self.m.into(), self.k, items)
+    }
+
+    /// Copies the bits into a plain `BloomFilter`. Inserts that run during
+    /// the copy may or may not be included.
+    pub fn to_bloom_filter(&self) -> BloomFilter {
+        let bits: BitVec = (0..self.m as usize)
+            .map(|index| self.words[index / 64].load(Ordering::Relaxed) & (1 << (index % 64)) != 0)
+            .collect();
+        BloomFilter::from_bits(self.n.into(), self.f, self.k, bits, self.hashing.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log;
+    use crate::siphash::SipHash13;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn insert_and_contains() {
+        log::init_test_logger();
+        let b = ConcurrentBloomFilter::new(100, 0.01);
+        assert!(!b.contains(b"1"));
+        b.insert(b"1");
+        b.insert(b"1");
+        assert!(b.contains(b"1"));
+        assert!(!b.contains(b"2"));
+    }
+
+    #[test]
+    fn concurrent_inserts_are_never_lost() {
+        log::init_test_logger();
+        let b = Arc::new(ConcurrentBloomFilter::with_hasher(
+            40_000,
+            0.01,
+            SipHash13::with_keys(1, 2),
+        ));
+        let writers: Vec<_> = (0..4u32)
+            .map(|t| {
+                let b = b.clone();
+                thread::spawn(move || {
+                    // interleaved keys so every writer hits every word
+                    for i in (t..40_000).step_by(4) {
+                        b.insert(&i.to_be_bytes());
+                        assert!(b.contains(&i.to_be_bytes()));
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        assert!((0u32..40_000).all(|i| b.contains(&i.to_be_bytes())));
+        let false_positives = (40_000u32..140_000)
+            .filter(|i| b.contains(&i.to_be_bytes()))
+            .count();
+        assert!(false_positives < 1_300, "{}", false_positives);
+    }
+
+    #[test]
+    fn snapshot_matches_a_sequential_filter() {
+        log::init_test_logger();
+        let concurrent = ConcurrentBloomFilter::new(1000, 0.01);
+        let mut sequential = BloomFilter::new(1000, 0.01);
+        for i in 0u32..1000 {
+            concurrent.insert(&i.to_be_bytes());
+            sequential.insert(&i.to_be_bytes());
+        }
+        let snapshot = concurrent.to_bloom_filter();
+        assert!(snapshot.is_compatible(&sequential));
+        assert_eq!(snapshot.summary(), sequential.summary());
+        assert!((0u32..5000).all(|i| {
+            snapshot.lookup(&i.to_be_bytes()) == concurrent.contains(&i.to_be_bytes())
+        }));
+        assert_eq!(
+            concurrent.expected_false_positive_rate(1000),
+            sequential.expected_false_positive_rate(1000)
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use std::sync::atomic::{fence, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::quotient_filter::{layout, QuotientFilter};
+
+/// A fixed-size quotient filter that many threads can query while one
+/// thread at a time inserts.
+///
+/// Lookups take no lock. The slots are mirrored into atomic words guarded
+/// by a generation counter (a seqlock): an insert makes the generation odd,
+/// rewrites the slots it touched and makes it even again. A reader notes
+/// the generation, walks the cluster, and retries if the generation was odd
+/// or has moved on, so it never acts on a half-shifted cluster.
+///
+/// Inserts are serialized by a mutex around a private [`QuotientFilter`]
+/// that does the actual shifting. Because readers hold no lock the table
+/// cannot be reallocated, so unlike `QuotientFilter` this filter does not
+/// resize; [`insert`](Self::insert) fails once every slot is in use.
+pub struct ConcurrentQuotientFilter {
+    q: u64,
+    r: u64,
+    generation: AtomicU64,
+    slots: Box<[AtomicU64]>,
+    entries: AtomicUsize,
+    writer: Mutex<QuotientFilter>,
+}
+
+impl ConcurrentQuotientFilter {
+    pub fn new(q: u64, r: u64) -> Self {
+        let size = 1usize << q;
+        ConcurrentQuotientFilter {
+            q,
+            r,
+            generation: AtomicU64::new(0),
+            slots: (0..size).map(|_| AtomicU64::new(0)).collect(),
+            entries: AtomicUsize::new(0),
+            writer: Mutex::new(QuotientFilter::new(q, r)),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of inserts published so far, times two; odd while an insert
+    /// is being published.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// Inserts `key`, returning `false` without inserting when the filter
+    /// is full.
+    pub fn insert(&self, key: u64) -> bool {
+        let mut filter = self.writer.lock().unwrap();
+        if filter.is_full() {
+            return false;
+        }
+        let stats = filter.insert_with_stats(key);
+        debug_assert!(!stats.resized);
+
+        // an insert only touches the home slot (its occupied bit) and the
+        // slots from there up to the empty slot the cluster shifted into
+        let size = self.slots.len();
+        let home = self.split(key).0 as usize;
+        let touched = stats.probe_length + stats.shifted + 1;
+
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        for offset in 0..touched {
+            let idx = (home + offset) % size;
+            self.slots[idx].store(filter.slot_word(idx), Ordering::Relaxed);
+        }
+        self.generation.fetch_add(1, Ordering::Release);
+
+        self.entries.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    /// Looks up `key` without locking, retrying while an insert is being
+    /// published.
+    pub fn lookup(&self, key: u64) -> bool {
+        loop {
+            let before = self.generation.load(Ordering::Acquire);
+            if before % 2 == 1 {
+                std::hint::spin_loop();
+                continue;
+            }
+            let found = self.try_lookup(key);
+            fence(Ordering::Acquire);
+            if self.generation.load(Ordering::Relaxed) == before {
+                if let Some(found) = found {
+                    return found;
+                }
+            }
+        }
+    }
+
+    /// One optimistic pass over the cluster. Returns `None` when the slots
+    /// read are inconsistent, which can only happen if a writer raced us.
+    fn try_lookup(&self, key: u64) -> Option<bool> {
+        let (quotient, remainder) = self.split(key);
+        let home = quotient as usize;
+        if self.word(home) & layout::OCCUPIED == 0 {
+            return Some(false);
+        }
+
+        let run_head = self.find_run_head(home)?;
+        let mut idx = run_head;
+        for _ in 0..self.slots.len() {
+            if self.word(idx) >> layout::REMAINDER_SHIFT == remainder {
+                return Some(true);
+            }
+            idx = self.next_index(idx);
+            if self.word(idx) & layout::CONTINUED == 0 {
+                return Some(false);
+            }
+        }
+        None
+    }
+
+    /// Same walk as `QuotientFilter::find_run_head`, but every loop is
+    /// bounded so torn reads cannot spin forever.
+    fn find_run_head(&self, home: usize) -> Option<usize> {
+        let size = self.slots.len();
+        let mut bucket = home;
+        let mut steps = 0;
+        while self.word(bucket) & layout::SHIFTED != 0 {
+            bucket = (bucket + size - 1) % size;
+            steps += 1;
+            if steps > size {
+                return None;
+            }
+        }
+
+        let mut run_head = bucket;
+        let mut probe = bucket;
+        while probe != home {
+            run_head = self.next_index(run_head);
+            while self.word(run_head) & layout::CONTINUED != 0 {
+                run_head = self.next_index(run_head);
+                steps += 1;
+                if steps > 2 * size {
+                    return None;
+                }
+            }
+            probe = self.next_index(probe);
+            while self.word(probe) & layout::OCCUPIED == 0 {
+                probe = self.next_index(probe);
+                steps += 1;
+                if steps > 2 * size {
+                    return None;
+                }
+            }
+        }
+        Some(run_head)
+    }
+
+    fn word(&self, idx: usize) -> u64 {
+        self.slots[idx].load(Ordering::Relaxed)
+    }
+
+    fn next_index(&self, idx: usize) -> usize {
+        (idx + 1) % self.slots.len()
+    }
+
+    fn split(&self, key: u64) -> (u64, u64) {
+        let quotient = (key >> self.r) & ((1 << self.q) - 1);
+        let remainder = key & ((1 << self.r) - 1);
+        (quotient, remainder)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_matches_quotient_filter() {
+        let concurrent = ConcurrentQuotientFilter::new(6, 8);
+        let mut plain = QuotientFilter::new(6, 8);
+        // clustered keys so inserts shift existing runs
+        let keys: Vec<u64> = (0..50u64)
+            .map(|i| ((i % 7) << 8) | ((i * 37) % 256))
+            .collect();
+        for &key in &keys {
+            assert!(concurrent.insert(key));
+            plain.insert(key);
+        }
+        assert_eq!(concurrent.len(), keys.len());
+        assert_eq!(concurrent.generation(), 2 * keys.len() as u64);
+        for key in 0..1u64 << 14 {
+            assert_eq!(concurrent.lookup(key), plain.lookup(key), "key {key}");
+        }
+    }
+
+    #[test]
+    fn test_insert_fails_when_full() {
+        let filter = ConcurrentQuotientFilter::new(2, 4);
+        for key in 0..4u64 {
+            assert!(filter.insert(key));
+        }
+        assert!(!filter.insert(4));
+        assert_eq!(filter.len(), filter.capacity());
+        assert!((0..4u64).all(|key| filter.lookup(key)));
+    }
+
+    #[test]
+    fn test_readers_see_published_inserts() {
+        let filter = Arc::new(ConcurrentQuotientFilter::new(12, 12));
+        let published = Arc::new(AtomicUsize::new(0));
+        let done = Arc::new(AtomicBool::new(false));
+        // keys crowd into the low quotients so inserts keep shifting the
+        // cluster readers are walking
+        let key = |i: usize| (((i % 64) << 12) | ((i * 2654435761) % 4096)) as u64;
+
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let (filter, published, done) = (filter.clone(), published.clone(), done.clone());
+                thread::spawn(move || {
+                    while !done.load(Ordering::Acquire) {
+                        let upto = published.load(Ordering::Acquire);
+                        for i in (0..upto).rev().take(64) {
+                            assert!(filter.lookup(key(i)), "lost key {i}");
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for i in 0..3000 {
+            assert!(filter.insert(key(i)));
+            published.store(i + 1, Ordering::Release);
+        }
+        done.store(true, Ordering::Release);
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        assert!((0..3000).all(|i| filter.lookup(key(i))));
+    }
+}
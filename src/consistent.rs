@@ -0,0 +1,103 @@
+//! Consistent hashing schemes behind one object-safe trait.
+//!
+//! [`ConsistentHasher`] names nodes by `u64` ids and keys by bytes, so a
+//! [`HashRing`], [`JumpHash`] or [`Rendezvous`] can be chosen at runtime
+//! and boxed as `dyn ConsistentHasher`.
+
+use crate::hash_ring::{HashRing, HashRingInterface};
+use crate::jump::JumpHash;
+use crate::rendezvous::Rendezvous;
+
+/// Assigns byte keys to one of a changing set of nodes, moving few keys
+/// when a node joins or leaves.
+pub trait ConsistentHasher {
+    fn join(&mut self, node: u64);
+    fn leave(&mut self, node: u64);
+    /// The node that owns `key`, or `None` while there are no nodes.
+    fn node_for(&self, key: &[u8]) -> Option<u64>;
+}
+
+/// Node ids are ring positions, so they must lie in `0..2^k`; a node
+/// already at that position or outside the ring panics, as with
+/// [`HashRingInterface::add_node`]. Keys are placed with the ring's
+/// [`Placement`](crate::hash_ring::Placement).
+impl ConsistentHasher for HashRing<u64> {
+    fn join(&mut self, node: u64) {
+        self.add_node(node);
+    }
+
+    fn leave(&mut self, node: u64) {
+        self.remove_node(node);
+    }
+
+    fn node_for(&self, key: &[u8]) -> Option<u64> {
+        self.lookup_key(key)
+    }
+}
+
+/// Nodes join with weight 1.0.
+impl ConsistentHasher for JumpHash {
+    fn join(&mut self, node: u64) {
+        self.add_node(node);
+    }
+
+    fn leave(&mut self, node: u64) {
+        self.remove_node(node);
+    }
+
+    fn node_for(&self, key: &[u8]) -> Option<u64> {
+        self.lookup(key)
+    }
+}
+
+/// Nodes join with weight 1.0.
+impl ConsistentHasher for Rendezvous {
+    fn join(&mut self, node: u64) {
+        self.add_node(node);
+    }
+
+    fn leave(&mut self, node: u64) {
+        self.remove_node(node);
+    }
+
+    fn node_for(&self, key: &[u8]) -> Option<u64> {
+        self.lookup(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_hasher_maps_keys_to_live_nodes() {
+        let mut hashers: Vec<(&str, Box<dyn ConsistentHasher>)> = vec![
+            ("hash_ring", Box::new(HashRing::<u64>::new(16))),
+            ("jump", Box::new(JumpHash::new())),
+            ("rendezvous", Box::new(Rendezvous::new())),
+        ];
+        let nodes = [100, 20_000, 40_000, 60_000];
+        for (name, hasher) in &mut hashers {
+            assert_eq!(hasher.node_for(b"key"), None, "{}", name);
+            for node in nodes {
+                hasher.join(node);
+            }
+            let keys: Vec<[u8; 4]> = (0..1000u32).map(|i| i.to_le_bytes()).collect();
+            let before: Vec<u64> = keys
+                .iter()
+                .map(|key| hasher.node_for(key).unwrap())
+                .collect();
+            assert!(before.iter().all(|node| nodes.contains(node)), "{}", name);
+
+            hasher.leave(20_000);
+            for (key, &owner) in keys.iter().zip(&before) {
+                let now = hasher.node_for(key).unwrap();
+                assert!(nodes.contains(&now) && now != 20_000, "{}", name);
+                // jump hash compacts its table on removal, so it may move others
+                if *name != "jump" && owner != 20_000 {
+                    assert_eq!(now, owner, "{}", name);
+                }
+            }
+        }
+    }
+}
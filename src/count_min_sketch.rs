@@ -1,5 +1,160 @@
-use murmurhash3::murmurhash3_x86_32 as mmh3;
+use crate::builder::BuildError;
+#[cfg(feature = "quotient")]
+use crate::decode::DecodeError;
+use crate::hasher::{Hasher64, RowHashes, RowHashing, DEFAULT_SEED};
+use crate::seed::{splitmix64, SeedSequence};
 
+/// Relative error bound of a sketch collected from an iterator.
+pub const DEFAULT_EPS: f32 = 0.001;
+
+/// Failure probability of a sketch collected from an iterator.
+pub const DEFAULT_DELTA: f32 = 0.01;
+
+/// Diagnostics for one row of a [`CountMinSketch`].
+///
+/// Every row sees the same updates, so rows of a healthy sketch look alike;
+/// a row with a much larger `max` or lower `occupied` than its siblings
+/// points at a bad seed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RowStats {
+    /// Sum of all counters, i.e. the total frequency added to the sketch.
+    pub total: u64,
+    /// Largest single counter.
+    pub max: u32,
+    /// Number of non-zero counters.
+    pub occupied: usize,
+    /// Estimated distinct items per counter (linear counting over the
+    /// occupied counters); the expected overestimate of a point query
+    /// grows with it.
+    pub load: f64,
+}
+
+/// Change between two snapshots of one stream's [`CountMinSketch`], from
+/// [`delta`](CountMinSketch::delta).
+#[derive(Debug, Clone)]
+pub struct CountMinDelta {
+    /// Sketch of the updates between the snapshots.
+    pub interval: CountMinSketch,
+    /// The earlier snapshot.
+    pub previous: CountMinSketch,
+    /// Total frequency added between the snapshots.
+    pub total: u64,
+    /// Total frequency in the earlier snapshot.
+    pub previous_total: u64,
+}
+
+impl CountMinDelta {
+    /// Frequency added per second over an interval of `elapsed`.
+    pub fn rate(&self, elapsed: std::time::Duration) -> f64 {
+        self.total as f64 / elapsed.as_secs_f64()
+    }
+
+    /// Candidates estimated at `phi` or more of the interval's total that
+    /// were below `phi` of the earlier snapshot's total, with their
+    /// interval estimates, most frequent first.
+    ///
+    /// A sketch cannot list its items, so the caller supplies the keys
+    /// worth checking, e.g. those seen by a sampler during the interval.
+    pub fn new_heavy_hitters<T: AsRef<[u8]>>(
+        &self,
+        candidates: impl IntoIterator<Item = T>,
+        phi: f64,
+    ) -> Vec<(T, u32)> {
+        assert!(phi > 0.0 && phi <= 1.0, "phi must be in (0, 1]");
+        let threshold = phi * self.total as f64;
+        let previous_threshold = phi * self.previous_total as f64;
+        let mut hitters: Vec<(T, u32)> = candidates
+            .into_iter()
+            .filter_map(|item| {
+                let count = self.interval.estimate(item.as_ref());
+                let heavy = self.total > 0 && count as f64 >= threshold;
+                let was_heavy = self.previous_total > 0
+                    && self.previous.estimate(item.as_ref()) as f64 >= previous_threshold;
+                (heavy && !was_heavy).then_some((item, count))
+            })
+            .collect();
+        hitters.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+        hitters
+    }
+}
+
+/// Keys hashed by the audit debug builds run on every new sketch.
+const AUDIT_KEYS: usize = 512;
+
+/// Collisions among the row hashes of a [`CountMinSketch`] on sampled keys,
+/// from [`audit_hashing`](CountMinSketch::audit_hashing).
+///
+/// With `p` pairs of distinct keys and width `w`, a row whose hash is
+/// universal puts about `p / w` pairs in a shared column, and two
+/// independent rows put about `p / w^2` pairs in a shared column in both.
+/// Double hashing (`h1 + i * h2`) legitimately inflates the joint count of
+/// rows `i` and `j` by up to `gcd(j - i, w)`, so the check on row pairs
+/// only flags rows that are nearly copies of each other, which is what
+/// reused or related seeds produce.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HashingAudit {
+    /// Pairs of distinct sampled keys.
+    pub pairs: u64,
+    /// Colliding pairs in each row.
+    pub row_collisions: Vec<u64>,
+    /// `(i, j, n)`: `n` pairs collide in both row `i` and row `j`.
+    pub joint_collisions: Vec<(usize, usize, u64)>,
+    /// Expected colliding pairs in one row.
+    pub expected_row: f64,
+    /// Expected colliding pairs in two independent rows at once.
+    pub expected_joint: f64,
+}
+
+impl HashingAudit {
+    /// Rows colliding more than six Poisson standard deviations (plus a
+    /// little slack for tiny expectations) above `expected_row`.
+    pub fn biased_rows(&self) -> Vec<usize> {
+        let bound = self.expected_row + 6.0 * self.expected_row.sqrt() + 6.0;
+        (0..self.row_collisions.len())
+            .filter(|&i| self.row_collisions[i] as f64 > bound)
+            .collect()
+    }
+
+    /// Row pairs where at least three quarters of the pairs colliding in
+    /// the emptier row also collide in the other, so the second row barely
+    /// tightens the estimate. Empty for sketches narrower than eight
+    /// columns per row, where even healthy double hashing can map two rows
+    /// onto each other.
+    pub fn correlated_rows(&self) -> Vec<(usize, usize)> {
+        let rows = self.row_collisions.len().max(2) as f64;
+        if self.expected_row < 8.0 * (rows - 1.0) * self.expected_joint {
+            return Vec::new();
+        }
+        self.joint_collisions
+            .iter()
+            .filter(|&&(i, j, joint)| {
+                let alone = self.row_collisions[i].min(self.row_collisions[j]);
+                alone > 0 && 4 * joint >= 3 * alone
+            })
+            .map(|&(i, j, _)| (i, j))
+            .collect()
+    }
+
+    /// Whether no row is biased and no two rows are correlated.
+    pub fn is_ok(&self) -> bool {
+        self.biased_rows().is_empty() && self.correlated_rows().is_empty()
+    }
+}
+
+/// How a row hash is reduced to a column of the sketch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IndexMode {
+    /// `hash % width`; works for any width but costs a division per row.
+    #[default]
+    Modulo,
+    /// `hash & (width - 1)`; the width is rounded up to a power of two.
+    Mask,
+    /// Lemire's multiply-shift reduction `(hash * width) >> 32` over the
+    /// low 32 bits of the hash; works for any width without a division.
+    FastRange,
+}
+
+#[derive(Clone)]
 pub struct CountMinSketch {
     #[allow(dead_code)]
     eps: f32,
@@ -8,44 +163,451 @@ pub struct CountMinSketch {
     width: usize,
     depth: usize,
     sketch: Vec<Vec<u32>>,
+    hashing: RowHashing,
+    mode: IndexMode,
 }
 
 impl CountMinSketch {
+    pub fn builder() -> CountMinSketchBuilder {
+        CountMinSketchBuilder::default()
+    }
+
     pub fn new(eps: f32, delta: f32) -> Self {
+        let depth = Self::calc_depth(delta);
+        let hashing = RowHashing::murmur3(depth, SeedSequence::new(DEFAULT_SEED));
+        Self::with_hashing(eps, delta, hashing)
+    }
+
+    /// Creates a sketch whose row hashes are derived from `hasher`, e.g. a
+    /// keyed SipHash so adversaries cannot aim updates at one counter.
+    pub fn with_hasher<H: Hasher64 + PartialEq + Send + Sync + 'static>(
+        eps: f32,
+        delta: f32,
+        hasher: H,
+    ) -> Self {
+        Self::with_hashing(eps, delta, RowHashing::from_hasher(hasher))
+    }
+
+    fn with_hashing(eps: f32, delta: f32, hashing: RowHashing) -> Self {
         let width = (std::f32::consts::E / eps).ceil() as usize;
-        let depth = (1.0_f32 / delta).ln().ceil() as usize;
+        let depth = Self::calc_depth(delta);
         let sketch = vec![vec![0u32; width]; depth];
-        CountMinSketch {
+        let cms = CountMinSketch {
             eps,
             delta,
             width,
             depth,
             sketch,
+            hashing,
+            mode: IndexMode::Modulo,
+        };
+        cms.debug_assert_hashing();
+        cms
+    }
+
+    /// Selects how row hashes are mapped to columns. `IndexMode::Mask`
+    /// widens the sketch to the next power of two, which also tightens
+    /// `eps`. Resets all counters, so call it before the first update.
+    pub fn with_index_mode(mut self, mode: IndexMode) -> Self {
+        if mode == IndexMode::Mask {
+            self.width = self.width.next_power_of_two();
         }
+        self.sketch = vec![vec![0u32; self.width]; self.depth];
+        self.mode = mode;
+        self
+    }
+
+    pub fn index_mode(&self) -> IndexMode {
+        self.mode
+    }
+
+    fn column(&self, hash: u64) -> usize {
+        match self.mode {
+            IndexMode::Modulo => (hash % self.width as u64) as usize,
+            IndexMode::Mask => (hash & (self.width as u64 - 1)) as usize,
+            IndexMode::FastRange => ((hash as u32 as u64 * self.width as u64) >> 32) as usize,
+        }
+    }
+
+    fn calc_depth(delta: f32) -> usize {
+        (1.0_f32 / delta).ln().ceil() as usize
     }
 
     pub fn update(&mut self, item: &[u8], freq: u32) {
+        let hashes = self.hashing.hashes(item);
         for i in 0..self.depth {
-            let index = mmh3(item, i as u32) % self.width as u32;
-            self.sketch[i][index as usize] += freq;
+            let index = self.column(hashes.row(i as u32));
+            self.sketch[i][index] = self.sketch[i][index].saturating_add(freq);
         }
     }
 
     pub fn estimate(&self, item: &[u8]) -> u32 {
+        self.estimate_hashes(self.hashing.hashes(item))
+    }
+
+    /// Counts an integer key without serializing it. Integer keys live
+    /// apart from byte keys: query them with
+    /// [`estimate_u64`](Self::estimate_u64), not with
+    /// `estimate(&key.to_le_bytes())`.
+    pub fn update_u64(&mut self, key: u64, freq: u32) {
+        let hashes = self.hashing.hashes_u64(key);
+        for i in 0..self.depth {
+            let index = self.column(hashes.row(i as u32));
+            self.sketch[i][index] = self.sketch[i][index].saturating_add(freq);
+        }
+    }
+
+    pub fn estimate_u64(&self, key: u64) -> u32 {
+        self.estimate_hashes(self.hashing.hashes_u64(key))
+    }
+
+    fn estimate_hashes(&self, hashes: RowHashes) -> u32 {
         let mut min = u32::MAX;
         for i in 0..self.depth {
-            let index = mmh3(item, i as u32) % self.width as u32;
-            if self.sketch[i][index as usize] < min {
-                min = self.sketch[i][index as usize];
+            let index = self.column(hashes.row(i as u32));
+            if self.sketch[i][index] < min {
+                min = self.sketch[i][index];
             }
         }
         min
     }
+
+    /// Counts how often `keys` sampled keys collide within each row and in
+    /// each pair of rows, as a proxy for the pairwise independence the
+    /// sketch's error bound assumes. The keys are pseudo-random rather than
+    /// sequential, which simple hashes such as FNV or CRC spread unevenly,
+    /// and are hashed as bytes, the path [`update`](Self::update) takes.
+    pub fn audit_hashing(&self, keys: usize) -> HashingAudit {
+        let mut state = 0;
+        let columns: Vec<Vec<usize>> = (0..keys)
+            .map(|_| {
+                let key = splitmix64(&mut state).to_le_bytes();
+                let hashes = self.hashing.hashes(&key);
+                (0..self.depth)
+                    .map(|i| self.column(hashes.row(i as u32)))
+                    .collect()
+            })
+            .collect();
+        let colliding_pairs = |counts: &mut dyn Iterator<Item = u64>| {
+            counts
+                .map(|count| count * count.saturating_sub(1) / 2)
+                .sum()
+        };
+
+        let row_collisions = (0..self.depth)
+            .map(|i| {
+                let mut counts = vec![0u64; self.width];
+                for key in &columns {
+                    counts[key[i]] += 1;
+                }
+                colliding_pairs(&mut counts.into_iter())
+            })
+            .collect();
+        let mut joint_collisions = Vec::new();
+        for i in 0..self.depth {
+            for j in i + 1..self.depth {
+                let mut counts = std::collections::HashMap::new();
+                for key in &columns {
+                    *counts.entry((key[i], key[j])).or_insert(0u64) += 1;
+                }
+                joint_collisions.push((i, j, colliding_pairs(&mut counts.into_values())));
+            }
+        }
+
+        let pairs = (keys as u64) * (keys as u64).saturating_sub(1) / 2;
+        let width = self.width as f64;
+        HashingAudit {
+            pairs,
+            row_collisions,
+            joint_collisions,
+            expected_row: pairs as f64 / width,
+            expected_joint: pairs as f64 / (width * width),
+        }
+    }
+
+    /// In debug builds, panics if murmur3 rows collide far more than
+    /// independent hashes would, e.g. after a seed-derivation regression
+    /// hands several rows the same seed. Rows of a caller's hasher are left
+    /// to [`audit_hashing`](Self::audit_hashing).
+    fn debug_assert_hashing(&self) {
+        if cfg!(debug_assertions) && matches!(self.hashing, RowHashing::Murmur3(_)) {
+            let audit = self.audit_hashing(AUDIT_KEYS);
+            assert!(
+                audit.is_ok(),
+                "count-min rows are biased or correlated: {:?}",
+                audit
+            );
+        }
+    }
+
+    /// Returns per-row totals, maxima and collision-load estimates.
+    pub fn row_stats(&self) -> Vec<RowStats> {
+        let width = self.width as f64;
+        self.sketch
+            .iter()
+            .map(|row| {
+                let occupied = row.iter().filter(|&&count| count > 0).count();
+                let load = if occupied == self.width {
+                    f64::INFINITY
+                } else {
+                    -(1.0 - occupied as f64 / width).ln()
+                };
+                RowStats {
+                    total: row.iter().map(|&count| count as u64).sum(),
+                    max: row.iter().copied().max().unwrap_or(0),
+                    occupied,
+                    load,
+                }
+            })
+            .collect()
+    }
+
+    /// Appends the sketch to `out`, little-endian: `eps` and `delta` as
+    /// `f32`, `width` and `depth` as `u64`, one `u32` seed per row, then the
+    /// `u32` counters row by row. Only murmur3-seeded sketches can be
+    /// encoded, since a custom hasher cannot be written out, and only with
+    /// modulo or mask indexing, which agree on a power-of-two width.
+    #[cfg(feature = "quotient")]
+    pub(crate) fn encode(&self, out: &mut Vec<u8>) {
+        let RowHashing::Murmur3(seeds) = &self.hashing else {
+            panic!("only murmur3-seeded sketches can be serialized");
+        };
+        assert!(
+            self.mode != IndexMode::FastRange,
+            "fast-range sketches cannot be serialized"
+        );
+        out.extend_from_slice(&self.eps.to_le_bytes());
+        out.extend_from_slice(&self.delta.to_le_bytes());
+        out.extend_from_slice(&(self.width as u64).to_le_bytes());
+        out.extend_from_slice(&(self.depth as u64).to_le_bytes());
+        for seed in seeds {
+            out.extend_from_slice(&seed.to_le_bytes());
+        }
+        for count in self.sketch.iter().flatten() {
+            out.extend_from_slice(&count.to_le_bytes());
+        }
+    }
+
+    /// Decodes a sketch written by `encode` from the start of `bytes`,
+    /// returning it with the number of bytes consumed.
+    #[cfg(feature = "quotient")]
+    pub(crate) fn decode(bytes: &[u8]) -> Result<(Self, usize), DecodeError> {
+        let truncated = |expected: usize| DecodeError::Length {
+            expected,
+            actual: bytes.len(),
+        };
+        if bytes.len() < 24 {
+            return Err(truncated(24));
+        }
+        let eps = f32::from_le_bytes(bytes[0..4].try_into().unwrap());
+        let delta = f32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        let width = u64::from_le_bytes(bytes[8..16].try_into().unwrap()) as usize;
+        let depth = u64::from_le_bytes(bytes[16..24].try_into().unwrap()) as usize;
+        let len = width
+            .checked_mul(depth)
+            .and_then(|cells| cells.checked_add(depth))
+            .and_then(|words| words.checked_mul(4))
+            .and_then(|len| len.checked_add(24))
+            .ok_or(DecodeError::Corrupt)?;
+        if width == 0 || depth == 0 {
+            return Err(DecodeError::Corrupt);
+        }
+        if bytes.len() < len {
+            return Err(truncated(len));
+        }
+        let mut words = bytes[24..len]
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes(word.try_into().unwrap()));
+        let seeds = words.by_ref().take(depth).collect();
+        let sketch = (0..depth)
+            .map(|_| words.by_ref().take(width).collect())
+            .collect();
+        let cms = CountMinSketch {
+            eps,
+            delta,
+            width,
+            depth,
+            sketch,
+            hashing: RowHashing::Murmur3(seeds),
+            mode: IndexMode::Modulo,
+        };
+        Ok((cms, len))
+    }
+
+    /// Returns true when `other` counts into the same cells, i.e. has the
+    /// same dimensions, row hashing and column mapping, so the two can be
+    /// merged. Modulo and mask indexing agree whenever the widths match.
+    pub fn is_compatible(&self, other: &CountMinSketch) -> bool {
+        let fast_range = |cms: &CountMinSketch| cms.mode == IndexMode::FastRange;
+        self.width == other.width
+            && self.depth == other.depth
+            && self.hashing == other.hashing
+            && fast_range(self) == fast_range(other)
+    }
+
+    /// Adds every counter of `other` into this sketch. The result estimates
+    /// the combined stream of both sketches.
+    pub fn merge(&mut self, other: &CountMinSketch) {
+        assert!(
+            self.is_compatible(other),
+            "cannot merge sketches with different dimensions or hashing"
+        );
+        for (row, other_row) in self.sketch.iter_mut().zip(&other.sketch) {
+            for (count, other_count) in row.iter_mut().zip(other_row) {
+                *count = count.saturating_add(*other_count);
+            }
+        }
+    }
+
+    /// Subtracts every counter of `other` from this sketch, flooring at zero.
+    ///
+    /// When `other` summarizes a prefix of this sketch's stream, e.g. a
+    /// cumulative snapshot taken an hour ago, no counter goes below zero and
+    /// the result is exactly the sketch of the remaining suffix: estimates
+    /// never undercount and, with probability `1 - delta`, overcount by at
+    /// most `eps` times the suffix's total. If `other` saw updates this
+    /// sketch did not, the floor hides them and estimates can undercount.
+    pub fn subtract(&mut self, other: &CountMinSketch) {
+        assert!(
+            self.is_compatible(other),
+            "cannot subtract sketches with different dimensions or hashing"
+        );
+        for (row, other_row) in self.sketch.iter_mut().zip(&other.sketch) {
+            for (count, other_count) in row.iter_mut().zip(other_row) {
+                *count = count.saturating_sub(*other_count);
+            }
+        }
+    }
+
+    /// Summarizes what this sketch saw since `previous`, an earlier snapshot
+    /// of the same stream, as [`subtract`](Self::subtract) would: the
+    /// interval's own sketch and the totals on both sides.
+    pub fn delta(&self, previous: &CountMinSketch) -> CountMinDelta {
+        let mut interval = self.clone();
+        interval.subtract(previous);
+        let previous_total = previous.total();
+        CountMinDelta {
+            total: interval.total(),
+            interval,
+            previous: previous.clone(),
+            previous_total,
+        }
+    }
+
+    /// Total frequency added, i.e. the sum of any one row.
+    fn total(&self) -> u64 {
+        self.sketch[0].iter().map(|&count| count as u64).sum()
+    }
+}
+
+/// Options for a [`CountMinSketch`], checked together by
+/// [`build`](Self::build).
+///
+/// `eps` and `delta` are required. Rows hash with murmur3 seeded from
+/// `seed` (or the default seed) unless a `hasher` is given; the two cannot
+/// be combined.
+#[derive(Clone, Default)]
+pub struct CountMinSketchBuilder {
+    eps: Option<f32>,
+    delta: Option<f32>,
+    seed: Option<u64>,
+    hashing: Option<RowHashing>,
+    mode: IndexMode,
+}
+
+impl CountMinSketchBuilder {
+    /// Relative error bound; the width is `ceil(e / eps)`.
+    pub fn eps(mut self, eps: f32) -> Self {
+        self.eps = Some(eps);
+        self
+    }
+
+    /// Failure probability; the depth is `ceil(ln(1 / delta))`.
+    pub fn delta(mut self, delta: f32) -> Self {
+        self.delta = Some(delta);
+        self
+    }
+
+    /// Master seed for the per-row murmur3 seeds.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Derives row hashes from `hasher` instead, as in
+    /// [`CountMinSketch::with_hasher`].
+    pub fn hasher<H: Hasher64 + PartialEq + Send + Sync + 'static>(mut self, hasher: H) -> Self {
+        self.hashing = Some(RowHashing::from_hasher(hasher));
+        self
+    }
+
+    pub fn index_mode(mut self, mode: IndexMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn build(self) -> Result<CountMinSketch, BuildError> {
+        let eps = self.eps.ok_or(BuildError::Missing("eps"))?;
+        let delta = self.delta.ok_or(BuildError::Missing("delta"))?;
+        if !(eps > 0.0 && eps < 1.0) {
+            return Err(BuildError::Invalid {
+                option: "eps",
+                reason: "must be in (0, 1)",
+            });
+        }
+        if !(delta > 0.0 && delta < 1.0) {
+            return Err(BuildError::Invalid {
+                option: "delta",
+                reason: "must be in (0, 1)",
+            });
+        }
+        if self.seed.is_some() && self.hashing.is_some() {
+            return Err(BuildError::Conflict("seed", "hasher"));
+        }
+        let hashing = self.hashing.unwrap_or_else(|| {
+            let seeds = SeedSequence::new(self.seed.unwrap_or(DEFAULT_SEED));
+            RowHashing::murmur3(CountMinSketch::calc_depth(delta), seeds)
+        });
+        Ok(CountMinSketch::with_hashing(eps, delta, hashing).with_index_mode(self.mode))
+    }
+}
+
+/// Adds each `(item, count)` pair as in [`update`](CountMinSketch::update).
+impl<T: AsRef<[u8]>> Extend<(T, u32)> for CountMinSketch {
+    fn extend<I: IntoIterator<Item = (T, u32)>>(&mut self, updates: I) {
+        for (item, freq) in updates {
+            self.update(item.as_ref(), freq);
+        }
+    }
+}
+
+/// Builds a sketch with [`DEFAULT_EPS`] and [`DEFAULT_DELTA`].
+impl<T: AsRef<[u8]>> FromIterator<(T, u32)> for CountMinSketch {
+    fn from_iter<I: IntoIterator<Item = (T, u32)>>(updates: I) -> Self {
+        let mut sketch = Self::new(DEFAULT_EPS, DEFAULT_DELTA);
+        sketch.extend(updates);
+        sketch
+    }
+}
+
+impl std::fmt::Debug for CountMinSketch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let total: u64 = self.sketch[0].iter().map(|&count| count as u64).sum();
+        f.debug_struct("CountMinSketch")
+            .field("eps", &self.eps)
+            .field("delta", &self.delta)
+            .field("width", &self.width)
+            .field("depth", &self.depth)
+            .field("mode", &self.mode)
+            .field("total", &total)
+            .finish_non_exhaustive()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::siphash::SipHash24;
 
     #[test]
     fn new_sets_expected_dimensions_and_initial_state() {
@@ -60,6 +622,87 @@ mod tests {
             .all(|&count| count == 0));
     }
 
+    #[test]
+    fn audit_passes_for_every_hashing_and_mode() {
+        for mode in [IndexMode::Modulo, IndexMode::Mask, IndexMode::FastRange] {
+            for cms in [
+                CountMinSketch::new(0.01, 0.01),
+                CountMinSketch::with_hasher(0.01, 0.01, SipHash24::with_keys(1, 2)),
+            ] {
+                let audit = cms.with_index_mode(mode).audit_hashing(2_000);
+                assert_eq!(audit.pairs, 1_999_000);
+                assert_eq!(audit.joint_collisions.len(), 10);
+                assert!(audit.is_ok(), "{:?}", audit);
+            }
+        }
+    }
+
+    #[test]
+    fn simple_hashers_build_in_debug_builds() {
+        use crate::crc::{Crc32, Crc64};
+        use crate::hasher::Fnv1a64;
+
+        let builder = || CountMinSketch::builder().eps(0.01).delta(0.1);
+        let fnv = builder().hasher(Fnv1a64).build().unwrap();
+        let crc32 = builder().hasher(Crc32).build().unwrap();
+        let crc64 = builder().hasher(Crc64).build().unwrap();
+        // the audit's pseudo-random keys spread evenly under both
+        assert!(fnv.audit_hashing(2_000).is_ok());
+        assert!(crc64.audit_hashing(2_000).is_ok());
+        // a 32-bit hash has no upper half to step rows by, so every row is
+        // the same; the audit reports it without refusing to build
+        assert_eq!(crc32.audit_hashing(2_000).correlated_rows().len(), 3);
+    }
+
+    #[test]
+    #[cfg_attr(debug_assertions, should_panic(expected = "biased or correlated"))]
+    fn audit_catches_rows_sharing_a_seed() {
+        let cms =
+            CountMinSketch::with_hashing(0.01, 0.01, RowHashing::Murmur3(vec![7, 8, 7, 9, 10]));
+        let audit = cms.audit_hashing(2_000);
+        assert!(!audit.is_ok());
+        assert_eq!(audit.correlated_rows(), vec![(0, 2)]);
+        // rows 0 and 2 collide together exactly as often as alone
+        assert!(audit
+            .joint_collisions
+            .contains(&(0, 2, audit.row_collisions[0])));
+    }
+
+    #[test]
+    fn delta_reports_interval_totals_and_new_heavy_hitters() {
+        let mut cms = CountMinSketch::new(0.001, 0.01);
+        cms.update(b"old", 900);
+        for i in 0..100u32 {
+            cms.update(&i.to_le_bytes(), 1);
+        }
+        let previous = cms.clone();
+        cms.update(b"old", 500);
+        cms.update(b"new", 400);
+        cms.update(b"rising", 50);
+
+        let delta = cms.delta(&previous);
+        assert_eq!(delta.previous_total, 1000);
+        assert_eq!(delta.total, 950);
+        assert_eq!(delta.interval.estimate(b"new"), 400);
+        assert_eq!(delta.rate(std::time::Duration::from_secs(10)), 95.0);
+        // "old" was already heavy and "rising" stays below 10%
+        let hitters = delta.new_heavy_hitters([&b"old"[..], b"new", b"rising", b"absent"], 0.1);
+        assert_eq!(hitters, vec![(&b"new"[..], 400)]);
+
+        let empty = cms.delta(&cms);
+        assert_eq!(empty.total, 0);
+        assert!(empty.new_heavy_hitters([b"new"], 0.1).is_empty());
+    }
+
+    #[test]
+    fn collect_and_extend_pairs() {
+        let mut cms: CountMinSketch = [("a", 3), ("b", 1), ("a", 2)].into_iter().collect();
+        assert_eq!((cms.eps, cms.delta), (DEFAULT_EPS, DEFAULT_DELTA));
+        cms.extend([(b"b".to_vec(), 4)]);
+        assert_eq!(cms.estimate(b"a"), 5);
+        assert_eq!(cms.estimate(b"b"), 5);
+    }
+
     #[test]
     fn estimate_returns_zero_before_any_updates() {
         let cms = CountMinSketch::new(0.01, 0.1);
@@ -106,6 +749,262 @@ mod tests {
         assert_eq!(beta_estimate, 8);
     }
 
+    #[test]
+    fn keyed_hasher_accumulates_counts() {
+        let mut cms = CountMinSketch::with_hasher(0.01, 0.1, SipHash24::with_keys(5, 6));
+        cms.update(b"key", 4);
+        cms.update(b"key", 6);
+        cms.update(b"other", 1);
+        assert_eq!(cms.estimate(b"key"), 10);
+        assert!(cms.estimate(b"other") >= 1);
+    }
+
+    #[test]
+    fn merge_adds_counts_of_both_sketches() {
+        let mut a = CountMinSketch::new(0.01, 0.1);
+        let mut b = CountMinSketch::new(0.01, 0.1);
+        a.update(b"key", 4);
+        b.update(b"key", 6);
+        b.update(b"other", 2);
+        a.merge(&b);
+        assert_eq!(a.estimate(b"key"), 10);
+        assert_eq!(a.estimate(b"other"), 2);
+    }
+
+    #[test]
+    fn counters_saturate_instead_of_overflowing() {
+        let mut a = CountMinSketch::new(0.01, 0.1);
+        a.update(b"key", u32::MAX - 1);
+        a.update(b"key", 5);
+        assert_eq!(a.estimate(b"key"), u32::MAX);
+        a.update_u64(7, u32::MAX);
+        a.update_u64(7, 1);
+        assert_eq!(a.estimate_u64(7), u32::MAX);
+
+        let mut b = CountMinSketch::new(0.01, 0.1);
+        b.update(b"key", 10);
+        b.merge(&a);
+        assert_eq!(b.estimate(b"key"), u32::MAX);
+    }
+
+    #[test]
+    fn merge_accepts_equal_hashers_built_apart() {
+        let mut a = CountMinSketch::with_hasher(0.01, 0.1, SipHash24::with_keys(1, 2));
+        let mut b = CountMinSketch::with_hasher(0.01, 0.1, SipHash24::with_keys(1, 2));
+        a.update(b"key", 4);
+        b.update(b"key", 6);
+        assert!(a.is_compatible(&b));
+        a.merge(&b);
+        assert_eq!(a.estimate(b"key"), 10);
+        let other = CountMinSketch::with_hasher(0.01, 0.1, SipHash24::with_keys(2, 1));
+        assert!(!a.is_compatible(&other));
+    }
+
+    #[test]
+    #[should_panic]
+    fn merge_rejects_different_dimensions() {
+        let mut a = CountMinSketch::new(0.01, 0.1);
+        a.merge(&CountMinSketch::new(0.1, 0.1));
+    }
+
+    #[test]
+    fn subtract_prefix_leaves_suffix_sketch() {
+        let mut cumulative = CountMinSketch::new(0.01, 0.01);
+        let mut suffix = CountMinSketch::new(0.01, 0.01);
+        // zipf-like stream: item i appears 1000 / (i + 1) times per hour
+        let hour = |cms: &mut CountMinSketch, shift: u32| {
+            for i in 0..200u32 {
+                cms.update(&(i + shift).to_le_bytes(), 1000 / (i + 1));
+            }
+        };
+        hour(&mut cumulative, 0);
+        let snapshot = cumulative.clone();
+        hour(&mut cumulative, 50);
+        hour(&mut suffix, 50);
+
+        cumulative.subtract(&snapshot);
+        assert_eq!(cumulative.sketch, suffix.sketch);
+        // the suffix totals ~5900, so collisions add at most ~59
+        let hot = cumulative.estimate(&50u32.to_le_bytes());
+        assert!((1000..1060).contains(&hot), "hot {hot}");
+        assert!(cumulative.estimate(&0u32.to_le_bytes()) < 60);
+    }
+
+    #[test]
+    fn subtract_floors_at_zero() {
+        let mut a = CountMinSketch::new(0.01, 0.1);
+        let mut b = CountMinSketch::new(0.01, 0.1);
+        a.update(b"key", 3);
+        b.update(b"key", 5);
+        b.update(b"other", 2);
+        a.subtract(&b);
+        assert_eq!(a.estimate(b"key"), 0);
+        assert_eq!(a.estimate(b"other"), 0);
+        assert!(a.row_stats().iter().all(|row| row.total == 0));
+    }
+
+    #[test]
+    fn index_modes_keep_estimates() {
+        for mode in [IndexMode::Modulo, IndexMode::Mask, IndexMode::FastRange] {
+            let mut cms = CountMinSketch::new(0.01, 0.01).with_index_mode(mode);
+            assert_eq!(cms.index_mode(), mode);
+            for i in 0..200u32 {
+                cms.update(&i.to_le_bytes(), i % 7 + 1);
+            }
+            for i in 0..200u32 {
+                assert!(cms.estimate(&i.to_le_bytes()) > i % 7, "{mode:?}");
+            }
+            let stats = cms.row_stats();
+            // ~140 of 272 columns are expected to be hit
+            assert!(stats.iter().all(|row| row.occupied > 110), "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn mask_mode_rounds_width_and_matches_modulo() {
+        let masked = CountMinSketch::new(0.01, 0.1).with_index_mode(IndexMode::Mask);
+        assert_eq!(masked.width, 512);
+        let mut modulo = CountMinSketch::new(0.0054, 0.1);
+        assert_eq!(modulo.width, 504);
+        modulo = modulo.with_index_mode(IndexMode::Mask);
+        assert!(modulo.is_compatible(&masked));
+
+        let fast = CountMinSketch::new(0.01, 0.1).with_index_mode(IndexMode::FastRange);
+        assert!(!fast.is_compatible(&CountMinSketch::new(0.01, 0.1)));
+
+        // a masked sketch decodes as a modulo sketch with identical estimates
+        #[cfg(feature = "quotient")]
+        {
+            let mut masked = masked;
+            masked.update(b"key", 3);
+            let mut bytes = Vec::new();
+            masked.encode(&mut bytes);
+            let (decoded, _) = CountMinSketch::decode(&bytes).unwrap();
+            assert!(decoded.is_compatible(&masked));
+            assert_eq!(decoded.estimate(b"key"), 3);
+        }
+    }
+
+    #[test]
+    fn row_stats_reflect_updates() {
+        let mut cms = CountMinSketch::new(0.01, 0.1);
+        assert!(cms
+            .row_stats()
+            .iter()
+            .all(|row| row.total == 0 && row.max == 0 && row.load == 0.0));
+        for i in 0..100u32 {
+            cms.update(&i.to_le_bytes(), 2);
+        }
+        cms.update(b"hot", 50);
+        let stats = cms.row_stats();
+        assert_eq!(stats.len(), cms.depth);
+        for row in stats {
+            assert_eq!(row.total, 250);
+            assert!(row.max >= 50);
+            assert!(row.occupied > 60 && row.occupied <= 101);
+            // ~101 items over 272 counters
+            assert!((row.load - 101.0 / 272.0).abs() < 0.1, "load {}", row.load);
+        }
+    }
+
+    #[cfg(feature = "quotient")]
+    #[test]
+    fn encode_decode_round_trip() {
+        let mut cms = CountMinSketch::new(0.05, 0.1);
+        cms.update(b"key", 7);
+        cms.update(b"other", 2);
+        let mut bytes = Vec::new();
+        cms.encode(&mut bytes);
+        bytes.extend_from_slice(b"trailing");
+
+        let (decoded, len) = CountMinSketch::decode(&bytes).unwrap();
+        assert_eq!(len, bytes.len() - 8);
+        assert!(decoded.is_compatible(&cms));
+        assert_eq!(decoded.sketch, cms.sketch);
+        assert_eq!(decoded.estimate(b"key"), 7);
+        assert!(matches!(
+            CountMinSketch::decode(&bytes[..len - 1]),
+            Err(DecodeError::Length { .. })
+        ));
+    }
+
+    #[test]
+    fn u64_keys_are_counted() {
+        for mut cms in [
+            CountMinSketch::new(0.01, 0.01),
+            CountMinSketch::with_hasher(0.01, 0.01, SipHash24::with_keys(1, 2)),
+        ] {
+            for key in 0u64..100 {
+                cms.update_u64(key, key as u32 + 1);
+            }
+            for key in 0u64..100 {
+                assert!(cms.estimate_u64(key) > key as u32);
+            }
+            let total: u32 = (1..=100).sum();
+            let overcount: u32 = (0u64..100)
+                .map(|key| cms.estimate_u64(key) - (key as u32 + 1))
+                .sum();
+            assert!(overcount < total, "{}", overcount);
+        }
+    }
+
+    #[test]
+    fn builder_applies_options() {
+        let cms = CountMinSketch::builder()
+            .eps(0.01)
+            .delta(0.1)
+            .index_mode(IndexMode::Mask)
+            .build()
+            .unwrap();
+        let expected = CountMinSketch::new(0.01, 0.1).with_index_mode(IndexMode::Mask);
+        assert_eq!(cms.width, expected.width);
+        assert_eq!(cms.depth, expected.depth);
+        assert!(cms.is_compatible(&expected));
+
+        let seeded = CountMinSketch::builder()
+            .eps(0.01)
+            .delta(0.1)
+            .seed(42)
+            .build()
+            .unwrap();
+        assert!(!seeded.is_compatible(&expected));
+    }
+
+    #[test]
+    fn builder_rejects_bad_options() {
+        assert_eq!(
+            CountMinSketch::builder().delta(0.1).build().err(),
+            Some(BuildError::Missing("eps"))
+        );
+        assert!(matches!(
+            CountMinSketch::builder().eps(0.01).delta(1.0).build(),
+            Err(BuildError::Invalid {
+                option: "delta",
+                ..
+            })
+        ));
+        assert_eq!(
+            CountMinSketch::builder()
+                .eps(0.01)
+                .delta(0.1)
+                .seed(1)
+                .hasher(SipHash24::with_keys(1, 2))
+                .build()
+                .err(),
+            Some(BuildError::Conflict("seed", "hasher"))
+        );
+    }
+
+    #[test]
+    fn debug_shows_dimensions_and_total() {
+        let mut cms = CountMinSketch::new(0.5, 0.5);
+        cms.update(b"key", 3);
+        assert_eq!(
+            format!("{:?}", cms),
+            "CountMinSketch { eps: 0.5, delta: 0.5, width: 6, depth: 1, mode: Modulo, total: 3, .. }"
+        );
+    }
+
     #[test]
     fn depth_is_one_when_delta_close_to_one() {
         let cms = CountMinSketch::new(0.01, 0.9);
@@ -0,0 +1,271 @@
+use crate::bloom_filter::BloomFilter;
+use crate::hasher::{Hasher64, RowHashing, DEFAULT_SEED};
+use crate::seed::SeedSequence;
+use bitvec::prelude::BitVec;
+
+/// Largest value a 4-bit counter holds.
+pub const MAX_COUNT: u8 = 0xf;
+
+/// Counting Bloom filter: every bit of a Bloom filter becomes a 4-bit
+/// counter, two to a byte, so items can be removed by decrementing their
+/// counters.
+///
+/// A counter that reaches [`MAX_COUNT`] sticks there, since its true value
+/// is no longer known; removals leave it alone, trading a little accuracy
+/// for never producing a false negative. With the optimal `k` and at most
+/// `n` items, the chance that any counter overflows is below
+/// `m * (e * ln 2 / 16)^16`, about `1.37e-15 * m` (Fan et al., Summary
+/// Cache), so 4 bits suffice unless the same items are inserted many times.
+///
+/// The geometry and hashing match [`BloomFilter::new`] with the same `n`
+/// and `f`, so a filter built here can be frozen into a plain, 4x smaller
+/// `BloomFilter` with [`to_bloom_filter`](Self::to_bloom_filter).
+#[derive(Clone)]
+pub struct CountingBloomFilter {
+    n: u32,
+    f: f32,
+    m: u32,
+    k: u32,
+    /// Counter `i` is the low nibble of byte `i / 2` when `i` is even and
+    /// the high nibble otherwise.
+    counters: Vec<u8>,
+    hashing: RowHashing,
+}
+
+impl CountingBloomFilter {
+    pub fn new(n: u32, f: f32) -> Self {
+        let (_, k) = BloomFilter::geometry_u32(n, f);
+        let hashing = RowHashing::murmur3(k as usize, SeedSequence::new(DEFAULT_SEED));
+        Self::with_hashing(n, f, hashing)
+    }
+
+    /// Creates a filter whose probes are derived from `hasher`.
+    pub fn with_hasher<H: Hasher64 + PartialEq + Send + Sync + 'static>(
+        n: u32,
+        f: f32,
+        hasher: H,
+    ) -> Self {
+        Self::with_hashing(n, f, RowHashing::from_hasher(hasher))
+    }
+
+    fn with_hashing(n: u32, f: f32, hashing: RowHashing) -> Self {
+        let (m, k) = BloomFilter::geometry_u32(n, f);
+        CountingBloomFilter {
+            n,
+            f,
+            m,
+            k,
+            counters: vec![0; (m as usize).div_ceil(2)],
+            hashing,
+        }
+    }
+
+    fn indexes(&self, item: &[u8]) -> Vec<usize> {
+        let hashes = self.hashing.hashes(item);
+        (0..self.k)
+            .map(|i| (hashes.row(i) % self.m as u64) as usize)
+            .collect()
+    }
+
+    fn counter(&self, index: usize) -> u8 {
+        (self.counters[index / 2] >> (index % 2 * 4)) & MAX_COUNT
+    }
+
+    fn set_counter(&mut self, index: usize, count: u8) {
+        let shift = index % 2 * 4;
+        let byte = &mut self.counters[index / 2];
+        *byte = (*byte & !(MAX_COUNT << shift)) | (count << shift);
+    }
+
+    pub fn insert(&mut self, item: &[u8]) {
+        for index in self.indexes(item) {
+            let count = self.counter(index);
+            if count < MAX_COUNT {
+                self.set_counter(index, count + 1);
+            }
+        }
+    }
+
+    pub fn lookup(&self, item: &[u8]) -> bool {
+        self.count(item) > 0
+    }
+
+    /// Upper bound on how often `item` was inserted: the smallest of its
+    /// counters, capped at [`MAX_COUNT`].
+    pub fn count(&self, item: &[u8]) -> u8 {
+        self.indexes(item)
+            .into_iter()
+            .map(|index| self.counter(index))
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Removes one occurrence of `item`. Returns false, leaving the filter
+    /// unchanged, if the item is definitely absent.
+    ///
+    /// Removing an item that was never inserted (a false positive)
+    /// decrements counters of other items and can cause false negatives.
+    pub fn remove(&mut self, item: &[u8]) -> bool {
+        let indexes = self.indexes(item);
+        if !indexes.iter().all(|&index| self.counter(index) > 0) {
+            return false;
+        }
+        for index in indexes {
+            let count = self.counter(index);
+            if count < MAX_COUNT {
+                self.set_counter(index, count - 1);
+            }
+        }
+        true
+    }
+
+    /// Halves every counter, rounding down, so old insertions fade as in
+    /// TinyLFU's reset. Items inserted once are forgotten, and saturated
+    /// counters become ordinary ones again; removing an item after a decay
+    /// can therefore cause false negatives.
+    pub fn decay_halve(&mut self) {
+        for byte in &mut self.counters {
+            *byte = (*byte >> 1) & 0x77;
+        }
+    }
+
+    /// Bytes held by the counters.
+    pub fn size_in_bytes(&self) -> usize {
+        self.counters.len()
+    }
+
+    /// False-positive rate in theory after `items` distinct inserts, the
+    /// same as a [`BloomFilter`] of this geometry.
+    pub fn expected_false_positive_rate(&self, items: u64) -> f64 {
+        crate::bloom_filter::expected_false_positive_rate(self.m.into(), self.k, items)
+    }
+
+    /// Freezes the filter into a plain `BloomFilter` with a bit set for
+    /// every non-zero counter. The result answers lookups exactly like this
+    /// filter but can no longer remove items.
+    pub fn to_bloom_filter(&self) -> BloomFilter {
+        let bits: BitVec = (0..self.m as usize)
+            .map(|index| self.counter(index) > 0)
+            .collect();
+        BloomFilter::from_bits(self.n.into(), self.f, self.k, bits, self.hashing.clone())
+    }
+}
+
+impl From<CountingBloomFilter> for BloomFilter {
+    fn from(filter: CountingBloomFilter) -> Self {
+        filter.to_bloom_filter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log;
+
+    #[test]
+    fn insert_lookup_remove() {
+        log::init_test_logger();
+        let mut b = CountingBloomFilter::new(100, 0.01);
+        b.insert(b"1");
+        b.insert(b"1");
+        assert!(b.lookup(b"1"));
+        assert!(!b.lookup(b"2"));
+        assert!(!b.remove(b"2"));
+        assert!(b.remove(b"1"));
+        assert!(b.lookup(b"1"));
+        assert!(b.remove(b"1"));
+        assert!(!b.lookup(b"1"));
+        assert!(b.counters.iter().all(|&count| count == 0));
+    }
+
+    #[test]
+    fn cache_eviction_churn_keeps_live_items() {
+        log::init_test_logger();
+        // a cache of 1000 entries where every insert evicts the oldest one
+        let mut b = CountingBloomFilter::new(1000, 0.01);
+        for i in 0u32..20_000 {
+            b.insert(&i.to_be_bytes());
+            if i >= 1000 {
+                assert!(b.remove(&(i - 1000).to_be_bytes()));
+            }
+        }
+        assert!((19_000u32..20_000).all(|i| b.lookup(&i.to_be_bytes())));
+        let false_positives = (0u32..19_000)
+            .filter(|i| b.lookup(&i.to_be_bytes()))
+            .count();
+        assert!(false_positives < 19_000 / 40, "{}", false_positives);
+
+        for i in 19_000u32..20_000 {
+            assert!(b.remove(&i.to_be_bytes()));
+        }
+        assert!(b.counters.iter().all(|&count| count == 0));
+    }
+
+    #[test]
+    fn saturated_counters_stick() {
+        log::init_test_logger();
+        let mut b = CountingBloomFilter::new(100, 0.01);
+        for _ in 0..300 {
+            b.insert(b"hot");
+        }
+        assert_eq!(b.count(b"hot"), MAX_COUNT);
+        for _ in 0..300 {
+            b.remove(b"hot");
+        }
+        assert!(b.lookup(b"hot"));
+    }
+
+    #[test]
+    fn neighbouring_counters_stay_apart() {
+        log::init_test_logger();
+        let mut b = CountingBloomFilter::new(100, 0.01);
+        for count in 0..MAX_COUNT {
+            b.set_counter(6, count);
+            b.set_counter(7, MAX_COUNT - count);
+            assert_eq!(b.counter(6), count);
+            assert_eq!(b.counter(7), MAX_COUNT - count);
+        }
+        assert_eq!(b.counters.len(), (b.m as usize).div_ceil(2));
+    }
+
+    #[test]
+    fn decay_halve_ages_counts() {
+        log::init_test_logger();
+        let mut b = CountingBloomFilter::new(100, 0.01);
+        for _ in 0..20 {
+            b.insert(b"hot");
+        }
+        for _ in 0..4 {
+            b.insert(b"warm");
+        }
+        b.insert(b"cold");
+        b.decay_halve();
+        assert_eq!(b.count(b"hot"), MAX_COUNT / 2);
+        assert!(b.count(b"warm") >= 2);
+        assert!(!b.lookup(b"cold"));
+        // a once-saturated counter counts down again after decay
+        for _ in 0..b.count(b"hot") {
+            assert!(b.remove(b"hot"));
+        }
+        assert_eq!(b.count(b"hot"), 0);
+    }
+
+    #[test]
+    fn frozen_filter_matches_counting_filter() {
+        log::init_test_logger();
+        let mut b = CountingBloomFilter::new(1000, 0.01);
+        for i in 0u32..1000 {
+            b.insert(&i.to_be_bytes());
+        }
+        for i in 0u32..500 {
+            assert!(b.remove(&i.to_be_bytes()));
+        }
+        let frozen: BloomFilter = b.clone().into();
+        let plain = BloomFilter::new(1000, 0.01);
+        assert!(plain.is_compatible(&frozen));
+        for i in 0u32..5000 {
+            assert_eq!(frozen.lookup(&i.to_be_bytes()), b.lookup(&i.to_be_bytes()));
+        }
+        assert!((500u32..1000).all(|i| frozen.lookup(&i.to_be_bytes())));
+    }
+}
@@ -0,0 +1,564 @@
+use crate::fingerprint::{CanonicalFingerprinter, Fingerprinter};
+use crate::quotient_filter::{Slot, SlotArray};
+use std::collections::VecDeque;
+
+/// A run's home, as an offset into the decoded slots, and its remainders
+/// with their counts in increasing order.
+type Run = (usize, Vec<(u64, u64)>);
+
+/// Counting quotient filter (Pandey et al.): a quotient filter that keeps
+/// one entry per distinct fingerprint and stores how often it was inserted
+/// in the slots that follow it, instead of one slot per occurrence as
+/// [`QuotientFilter`](crate::quotient_filter::QuotientFilter) does.
+///
+/// Counts use the paper's encoding, so a run stays sorted by remainder and
+/// a counter can be told apart from the next remainder. For a remainder
+/// `x > 0`, a count of 1 is `x`, of 2 is `x x`, and larger counts `C` are
+/// `x`, the digits of `C - 3` in base `2^r - 2`, then `x` again. Digits
+/// skip the values 0 and `x`, and a leading 0 is added when the first
+/// digit would exceed `x`, since a slot below `x` is what announces a
+/// counter. Remainder 0 cannot use that, so its counts are `0`, `0 0`,
+/// `0 0 0`, and above 3 `0`, the digits of `C - 4` in base `2^r - 1`
+/// (never 0), then `0 0`.
+///
+/// A key with many repeats costs a few slots instead of one slot each,
+/// while keys seen once cost one slot as before. Counts are exact per
+/// fingerprint: keys that share one are counted together.
+#[derive(Clone)]
+pub struct CountingQuotientFilter {
+    q: u64,
+    r: u64,
+    size: usize,
+    /// Slots holding a remainder or a counter digit.
+    used: usize,
+    filter: SlotArray,
+}
+
+impl CountingQuotientFilter {
+    /// Creates a filter of `2^q` slots with `r`-bit remainders. Counters
+    /// need at least two digit values, so `r` must be at least 2.
+    pub fn new(q: u64, r: u64) -> Self {
+        assert!(r >= 2, "counters need at least 2 remainder bits");
+        assert!(q + r <= 64, "fingerprints are at most 64 bits");
+        let size = 1usize << q;
+        CountingQuotientFilter {
+            q,
+            r,
+            size,
+            used: 0,
+            filter: SlotArray::new(size, r),
+        }
+    }
+
+    pub fn insert(&mut self, key: u64) {
+        self.update(key, 1);
+    }
+
+    /// Adds `count` occurrences of `key`; the stored count saturates at
+    /// `u64::MAX`. Doubles the table like
+    /// [`QuotientFilter::resize`](crate::quotient_filter::QuotientFilter::resize)
+    /// when the slots run out.
+    pub fn update(&mut self, key: u64, count: u64) {
+        self.update_growing(key, count, false);
+    }
+
+    /// Adds `count` to `key`, doubling the table until it fits. With
+    /// `keep_width` the table grows by moving remainder bits to the
+    /// quotient while `r` is above 2, as hashed keys use every fingerprint
+    /// bit and would otherwise be looked up in a different run.
+    fn update_growing(&mut self, key: u64, count: u64, keep_width: bool) {
+        if count == 0 {
+            return;
+        }
+        loop {
+            let (quotient, remainder) = self.split(key);
+            if self.try_update(quotient as usize, remainder, count) {
+                return;
+            }
+            if keep_width && self.r > 2 {
+                self.resize(self.q + 1, self.r - 1);
+            } else {
+                self.resize(self.q + 1, self.r);
+            }
+        }
+    }
+
+    /// How often `key` was inserted, 0 if never. Keys that share a
+    /// fingerprint report their combined count.
+    pub fn count(&self, key: u64) -> u64 {
+        let (quotient, remainder) = self.split(key);
+        let quotient = quotient as usize;
+        if !self.filter.get(quotient).is_occupied() {
+            return 0;
+        }
+        let run_head = self.find_run_head(quotient);
+        let mut values = vec![self.filter.get(run_head).remainder()];
+        let mut idx = self.next_index(run_head);
+        while self.filter.get(idx).is_continued() {
+            values.push(self.filter.get(idx).remainder());
+            idx = self.next_index(idx);
+        }
+        decode_run(&values, self.r)
+            .into_iter()
+            .find(|&(entry, _)| entry == remainder)
+            .map_or(0, |(_, count)| count)
+    }
+
+    pub fn lookup(&self, key: u64) -> bool {
+        self.count(key) > 0
+    }
+
+    /// Inserts a byte-string key by its canonical fingerprint, as
+    /// [`QuotientFilter::insert_bytes`](crate::quotient_filter::QuotientFilter::insert_bytes)
+    /// does, and like it keeps the fingerprint width while growing until
+    /// `r` is down to 2.
+    pub fn insert_bytes(&mut self, key: &[u8]) {
+        self.update_bytes(key, 1);
+    }
+
+    /// Adds `count` occurrences of a byte-string key, growing like
+    /// [`insert_bytes`](Self::insert_bytes).
+    pub fn update_bytes(&mut self, key: &[u8], count: u64) {
+        // the low q + r bits are the fingerprint at whatever width the
+        // filter has once it fits
+        self.update_growing(CanonicalFingerprinter.hash(key), count, true);
+    }
+
+    pub fn count_bytes(&self, key: &[u8]) -> u64 {
+        self.count(CanonicalFingerprinter.fingerprint(key, (self.q + self.r) as u32))
+    }
+
+    /// Slots in use, for remainders and counter digits together.
+    pub fn slots_used(&self) -> usize {
+        self.used
+    }
+
+    /// Number of slots, `2^q`.
+    pub fn capacity(&self) -> usize {
+        self.size
+    }
+
+    /// Bytes held by the slots.
+    pub fn size_in_bytes(&self) -> usize {
+        self.filter.size_in_bytes()
+    }
+
+    fn split(&self, key: u64) -> (u64, u64) {
+        let quotient = (key >> self.r) & ((1 << self.q) - 1);
+        let remainder = key & ((1 << self.r) - 1);
+        (quotient, remainder)
+    }
+
+    fn prev_index(&self, idx: usize) -> usize {
+        (idx + self.size - 1) % self.size
+    }
+
+    fn next_index(&self, idx: usize) -> usize {
+        (idx + 1) % self.size
+    }
+
+    fn find_run_head(&self, home_idx: usize) -> usize {
+        let mut bucket = home_idx;
+        while self.filter.get(bucket).is_shifted() {
+            bucket = self.prev_index(bucket);
+        }
+        let mut run_head = bucket;
+        while bucket != home_idx {
+            run_head = self.next_index(run_head);
+            while self.filter.get(run_head).is_continued() {
+                run_head = self.next_index(run_head);
+            }
+            bucket = self.next_index(bucket);
+            while !self.filter.get(bucket).is_occupied() {
+                bucket = self.next_index(bucket);
+            }
+        }
+        run_head
+    }
+
+    /// Offset from `start` of the first empty slot at or after `offset`.
+    fn first_empty(&self, start: usize, mut offset: usize) -> usize {
+        while !self.filter.get((start + offset) % self.size).is_empty() {
+            offset += 1;
+        }
+        offset
+    }
+
+    /// Adds `count` to `remainder` in the run of `quotient`, or returns
+    /// false if that needs more slots than are free.
+    ///
+    /// The clusters around the run are decoded, updated and laid out
+    /// again. A longer encoding pushes the slots after it right, into the
+    /// next empty slot or through the clusters that follow, which are then
+    /// rewritten too.
+    fn try_update(&mut self, quotient: usize, remainder: u64, count: u64) -> bool {
+        let mut start = quotient;
+        while self.filter.get(start).is_shifted() {
+            start = self.prev_index(start);
+        }
+        let home = (quotient + self.size - start) % self.size;
+        let mut end = self.first_empty(start, 0);
+        loop {
+            let (mut runs, old_len) = self.decode_window(start, end);
+            add_count(&mut runs, home, remainder, count);
+            let encoded: Vec<(usize, Vec<u64>)> = runs
+                .into_iter()
+                .map(|(home, entries)| {
+                    let mut values = Vec::new();
+                    for (remainder, count) in entries {
+                        encode(remainder, count, self.r, &mut values);
+                    }
+                    (home, values)
+                })
+                .collect();
+            let new_len: usize = encoded.iter().map(|(_, values)| values.len()).sum();
+            // one slot always stays empty, so clusters end somewhere
+            if self.used + new_len - old_len >= self.size {
+                return false;
+            }
+            let layout_end = encoded
+                .iter()
+                .fold(0, |cursor, (home, values)| cursor.max(*home) + values.len());
+            debug_assert!(layout_end < self.size);
+            if (end..layout_end)
+                .all(|offset| self.filter.get((start + offset) % self.size).is_empty())
+            {
+                self.write_window(start, end.max(layout_end), &encoded);
+                self.used += new_len - old_len;
+                return true;
+            }
+            // take in the clusters the longer layout runs into
+            end = self.first_empty(start, layout_end);
+        }
+    }
+
+    /// Decodes the runs in the `len` slots from `start`, which begin a
+    /// cluster and end at an empty slot. Returns each run's home as an
+    /// offset from `start`, with the entries of the run, and the number of
+    /// slots in use.
+    fn decode_window(&self, start: usize, len: usize) -> (Vec<Run>, usize) {
+        let mut runs = Vec::new();
+        let mut homes = VecDeque::new();
+        let mut values = Vec::new();
+        let mut used = 0;
+        for offset in 0..len {
+            let slot = self.filter.get((start + offset) % self.size);
+            if slot.is_occupied() {
+                homes.push_back(offset);
+            }
+            if slot.is_empty() {
+                continue;
+            }
+            used += 1;
+            if !slot.is_continued() && !values.is_empty() {
+                let home = homes.pop_front().unwrap();
+                runs.push((home, decode_run(&values, self.r)));
+                values.clear();
+            }
+            values.push(slot.remainder());
+        }
+        if !values.is_empty() {
+            let home = homes.pop_front().unwrap();
+            runs.push((home, decode_run(&values, self.r)));
+        }
+        debug_assert!(homes.is_empty());
+        (runs, used)
+    }
+
+    /// Clears the `len` slots from `start` and lays out `runs` in them, each
+    /// at its home or right after the previous run.
+    fn write_window(&mut self, start: usize, len: usize, runs: &[(usize, Vec<u64>)]) {
+        for offset in 0..len {
+            self.filter
+                .set((start + offset) % self.size, Slot::default());
+        }
+        let mut cursor = 0;
+        for (home, values) in runs {
+            cursor = cursor.max(*home);
+            for (i, &value) in values.iter().enumerate() {
+                let mut slot = Slot::default();
+                slot.set_remainder(value);
+                slot.set_continued(i > 0);
+                slot.set_shifted(cursor != *home);
+                self.filter.set((start + cursor) % self.size, slot);
+                cursor += 1;
+            }
+        }
+        for (home, _) in runs {
+            self.filter
+                .update((start + home) % self.size, |slot| slot.set_occupied(true));
+        }
+    }
+
+    /// Every stored fingerprint with its count, in quotient order.
+    fn entries(&self) -> Vec<(u64, u64)> {
+        let mut entries = Vec::new();
+        for quotient in (0..self.size).filter(|&idx| self.filter.get(idx).is_occupied()) {
+            let run_head = self.find_run_head(quotient);
+            let mut values = vec![self.filter.get(run_head).remainder()];
+            let mut idx = self.next_index(run_head);
+            while self.filter.get(idx).is_continued() {
+                values.push(self.filter.get(idx).remainder());
+                idx = self.next_index(idx);
+            }
+            entries.extend(
+                decode_run(&values, self.r)
+                    .into_iter()
+                    .map(|(remainder, count)| (((quotient as u64) << self.r) | remainder, count)),
+            );
+        }
+        entries
+    }
+
+    /// Rebuilds the filter with `2^q` slots and `r`-bit remainders,
+    /// keeping every fingerprint and count.
+    fn resize(&mut self, q: u64, r: u64) {
+        let mut bigger = CountingQuotientFilter::new(q, r);
+        for (fingerprint, count) in self.entries() {
+            bigger.update(fingerprint, count);
+        }
+        *self = bigger;
+    }
+}
+
+/// Adds `count` to `remainder` in the run whose home is `home`, creating
+/// the run or the entry as needed and keeping both sorted.
+fn add_count(runs: &mut Vec<Run>, home: usize, remainder: u64, count: u64) {
+    let run = match runs.binary_search_by_key(&home, |(home, _)| *home) {
+        Ok(run) => run,
+        Err(run) => {
+            runs.insert(run, (home, Vec::new()));
+            run
+        }
+    };
+    let entries = &mut runs[run].1;
+    match entries.binary_search_by_key(&remainder, |(remainder, _)| *remainder) {
+        Ok(entry) => entries[entry].1 = entries[entry].1.saturating_add(count),
+        Err(entry) => entries.insert(entry, (remainder, count)),
+    }
+}
+
+/// Digits of `value` in `base`, most significant first.
+fn digits(mut value: u64, base: u64) -> Vec<u64> {
+    let mut digits = Vec::new();
+    loop {
+        digits.push(value % base);
+        value /= base;
+        if value == 0 {
+            break;
+        }
+    }
+    digits.reverse();
+    digits
+}
+
+fn undigits(digits: impl IntoIterator<Item = u64>, base: u64) -> u64 {
+    digits.into_iter().fold(0u64, |value, digit| {
+        value.saturating_mul(base).saturating_add(digit)
+    })
+}
+
+/// Appends the slots of `count` occurrences of `x` to `out`.
+fn encode(x: u64, count: u64, r: u64, out: &mut Vec<u64>) {
+    let symbols = 1u64 << r;
+    match (x, count) {
+        (_, 1) => out.push(x),
+        (_, 2) => out.extend([x, x]),
+        (0, 3) => out.extend([0, 0, 0]),
+        (0, _) => {
+            out.push(0);
+            out.extend(digits(count - 4, symbols - 1).into_iter().map(|d| d + 1));
+            out.extend([0, 0]);
+        }
+        _ => {
+            out.push(x);
+            // digit values skip 0 and x
+            let counter: Vec<u64> = digits(count - 3, symbols - 2)
+                .into_iter()
+                .map(|d| if d + 1 < x { d + 1 } else { d + 2 })
+                .collect();
+            if counter[0] > x {
+                out.push(0);
+            }
+            out.extend(counter);
+            out.push(x);
+        }
+    }
+}
+
+/// Decodes the slot values of one run into its remainders and counts.
+fn decode_run(values: &[u64], r: u64) -> Vec<(u64, u64)> {
+    let symbols = 1u64 << r;
+    let mut entries = Vec::new();
+    let mut i = 0;
+    while i < values.len() {
+        let x = values[i];
+        let rest = &values[i + 1..];
+        let (count, len) = if x == 0 {
+            match rest {
+                [0, 0, ..] => (3, 3),
+                [0, ..] => (2, 2),
+                // only the counter of remainder 0 holds two zeros in a row
+                _ => match rest.windows(2).position(|pair| pair == [0, 0]) {
+                    Some(end) => {
+                        let counter = rest[..end].iter().map(|&digit| digit - 1);
+                        (4 + undigits(counter, symbols - 1), end + 3)
+                    }
+                    None => (1, 1),
+                },
+            }
+        } else {
+            match rest.first() {
+                Some(&next) if next == x => (2, 2),
+                Some(&next) if next < x => {
+                    let end = rest.iter().position(|&digit| digit == x).unwrap();
+                    let counter = rest[..end]
+                        .iter()
+                        .skip_while(|&&digit| digit == 0)
+                        .map(|&digit| if digit < x { digit - 1 } else { digit - 2 });
+                    (3 + undigits(counter, symbols - 2), end + 2)
+                }
+                _ => (1, 1),
+            }
+        };
+        entries.push((x, count));
+        i += len;
+    }
+    entries
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log;
+    use crate::quotient_filter::QuotientFilter;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::collections::HashMap;
+
+    #[test]
+    fn counts_repeated_keys() {
+        log::init_test_logger();
+        let mut cqf = CountingQuotientFilter::new(8, 8);
+        for _ in 0..5 {
+            cqf.insert(0x1234);
+        }
+        cqf.update(0x1235, 1_000_000);
+        cqf.insert_bytes(b"key");
+        assert_eq!(cqf.count(0x1234), 5);
+        assert_eq!(cqf.count(0x1235), 1_000_000);
+        assert_eq!(cqf.count(0x1236), 0);
+        assert_eq!(cqf.count_bytes(b"key"), 1);
+        assert!(cqf.lookup(0x1234) && !cqf.lookup(0x1236));
+        // 5 is x, c, x; a million takes x, 3 digits, x
+        assert_eq!(cqf.slots_used(), 3 + 5 + 1);
+    }
+
+    #[test]
+    fn encoding_round_trips() {
+        for r in [2u64, 3, 8] {
+            let max = (1u64 << r) - 1;
+            for x in [0, 1, max / 2, max] {
+                for count in (1..=300).chain([u64::MAX / 3, u64::MAX]) {
+                    let mut values = Vec::new();
+                    encode(x, count, r, &mut values);
+                    // a run holding the entry between its neighbours
+                    let mut run = Vec::new();
+                    if x > 0 {
+                        encode(x - 1, 7, r, &mut run);
+                    }
+                    run.extend(&values);
+                    if x < max {
+                        encode(x + 1, 1, r, &mut run);
+                    }
+                    let entries = decode_run(&run, r);
+                    assert!(
+                        entries.contains(&(x, count)),
+                        "r {r} x {x} count {count}: {values:?} in {run:?} gave {entries:?}"
+                    );
+                    assert!(values.iter().all(|&value| value <= max));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn matches_a_model_under_random_updates() {
+        log::init_test_logger();
+        let mut rng = StdRng::seed_from_u64(0xC0F);
+        for trial in 0..20 {
+            // resizes several times as the counters grow
+            let (q, r) = (4, 6);
+            let mut cqf = CountingQuotientFilter::new(q, r);
+            let mut model: HashMap<u64, u64> = HashMap::new();
+            // few quotients, so runs and clusters crowd together and wrap
+            let keys: Vec<u64> = (0..12).map(|_| rng.random_range(0..1 << (q + r))).collect();
+            for _ in 0..200 {
+                let key = keys[rng.random_range(0..keys.len())];
+                let count = if rng.random_bool(0.8) {
+                    1
+                } else {
+                    rng.random_range(1..500)
+                };
+                cqf.update(key, count);
+                *model.entry(key).or_default() += count;
+                for (&key, &count) in &model {
+                    assert_eq!(cqf.count(key), count, "trial {trial} key {key:#x}");
+                }
+            }
+            let mut entries = cqf.entries();
+            entries.sort_unstable();
+            let mut expected: Vec<(u64, u64)> = model.into_iter().collect();
+            expected.sort_unstable();
+            assert_eq!(entries, expected, "trial {trial}");
+        }
+    }
+
+    #[test]
+    fn grows_from_small_remainders() {
+        log::init_test_logger();
+        for (q, r) in [(3, 2), (2, 3), (2, 2)] {
+            let mut cqf = CountingQuotientFilter::new(q, r);
+            // every key fits the fingerprint width it is inserted at
+            for key in 0..64 {
+                cqf.insert(key);
+            }
+            assert_eq!(cqf.r, r, "growth must keep the remainder width");
+            assert!((0..64).all(|key| cqf.count(key) == 1), "q {q} r {r}");
+
+            // a huge count needs many counter digits at once
+            let mut cqf = CountingQuotientFilter::new(q, r);
+            cqf.update(1, u64::MAX);
+            cqf.insert(2);
+            assert_eq!((cqf.count(1), cqf.count(2)), (u64::MAX, 1), "q {q} r {r}");
+        }
+    }
+
+    #[test]
+    fn byte_keys_grow_from_small_remainders() {
+        log::init_test_logger();
+        let mut cqf = CountingQuotientFilter::new(2, 6);
+        for i in 0..40 {
+            cqf.insert_bytes(format!("key-{i}").as_bytes());
+        }
+        assert_eq!((cqf.q, cqf.r), (6, 2), "growth must keep q + r");
+        assert!((0..40).all(|i| cqf.count_bytes(format!("key-{i}").as_bytes()) >= 1));
+    }
+
+    #[test]
+    fn repeats_take_fewer_slots_than_in_a_quotient_filter() {
+        log::init_test_logger();
+        let mut cqf = CountingQuotientFilter::new(10, 8);
+        let mut qf = QuotientFilter::new(12, 8);
+        for key in 0u64..100 {
+            for _ in 0..30 {
+                cqf.insert_bytes(&key.to_le_bytes());
+                qf.insert_bytes(&key.to_le_bytes());
+            }
+        }
+        assert!((0u64..100).all(|key| cqf.count_bytes(&key.to_le_bytes()) >= 30));
+        assert_eq!(qf.summary().entries, 3000);
+        // x, a digit, x, and a leading zero digit for small remainders
+        assert!(cqf.slots_used() <= 400, "{}", cqf.slots_used());
+        assert!(cqf.size_in_bytes() * 3 < qf.size_in_bytes());
+    }
+}
@@ -0,0 +1,157 @@
+//! Table-driven CRC implementations.
+//!
+//! * CRC-16/XMODEM: poly 0x1021, init 0, not reflected (Redis Cluster slots)
+//! * CRC-32/ISO-HDLC: poly 0x04C11DB7 reflected, init and xorout 0xFFFFFFFF
+//! * CRC-64/XZ: poly 0x42F0E1EBA9EA3693 reflected, init and xorout all ones
+use crate::hasher::Hasher64;
+
+const CRC16_POLY: u16 = 0x1021;
+const CRC32_POLY_REFLECTED: u32 = 0xEDB8_8320;
+const CRC64_POLY_REFLECTED: u64 = 0xC96C_5795_D787_0F42;
+
+const CRC16_TABLE: [u16; 256] = crc16_table();
+const CRC32_TABLE: [u32; 256] = crc32_table();
+const CRC64_TABLE: [u64; 256] = crc64_table();
+
+const fn crc16_table() -> [u16; 256] {
+    let mut table = [0u16; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u16) << 8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ CRC16_POLY
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ CRC32_POLY_REFLECTED
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+const fn crc64_table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u64;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ CRC64_POLY_REFLECTED
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+pub fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for &byte in bytes {
+        crc = (crc << 8) ^ CRC16_TABLE[((crc >> 8) as u8 ^ byte) as usize];
+    }
+    crc
+}
+
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = u32::MAX;
+    for &byte in bytes {
+        crc = (crc >> 8) ^ CRC32_TABLE[(crc as u8 ^ byte) as usize];
+    }
+    !crc
+}
+
+pub fn crc64(bytes: &[u8]) -> u64 {
+    let mut crc = u64::MAX;
+    for &byte in bytes {
+        crc = (crc >> 8) ^ CRC64_TABLE[(crc as u8 ^ byte) as usize];
+    }
+    !crc
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Crc16;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Crc32;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Crc64;
+
+impl Hasher64 for Crc16 {
+    fn hash64(&self, bytes: &[u8]) -> u64 {
+        crc16(bytes) as u64
+    }
+}
+
+impl Hasher64 for Crc32 {
+    fn hash64(&self, bytes: &[u8]) -> u64 {
+        crc32(bytes) as u64
+    }
+}
+
+impl Hasher64 for Crc64 {
+    fn hash64(&self, bytes: &[u8]) -> u64 {
+        crc64(bytes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CHECK_INPUT: &[u8] = b"123456789";
+
+    #[test]
+    fn crc16_check_value() {
+        assert_eq!(crc16(CHECK_INPUT), 0x31C3);
+        assert_eq!(crc16(b""), 0);
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(CHECK_INPUT), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn crc64_check_value() {
+        assert_eq!(crc64(CHECK_INPUT), 0x995D_C9BB_DF19_39FA);
+        assert_eq!(crc64(b""), 0);
+    }
+
+    #[test]
+    fn hasher_backends_widen_checksums() {
+        assert_eq!(Crc16.hash64(CHECK_INPUT), 0x31C3);
+        assert_eq!(Crc32.hash64(CHECK_INPUT), 0xCBF4_3926);
+        assert_eq!(Crc64.hash64(CHECK_INPUT), 0x995D_C9BB_DF19_39FA);
+    }
+}
@@ -0,0 +1,293 @@
+//! A cuckoo filter: fingerprints in buckets of four slots.
+//!
+//! Every item has two candidate buckets, its hash's bucket and that bucket
+//! XORed with a hash of its fingerprint, so either one can be found again
+//! from the other and the fingerprint alone. An insert into two full
+//! buckets evicts a random fingerprint to its alternate bucket, and so on
+//! for up to [`MAX_KICKS`] moves. Unlike a Bloom filter it supports
+//! deletion, and a lookup reads at most two buckets.
+
+use crate::fingerprint::{CanonicalFingerprinter, Fingerprinter};
+use crate::quotient_filter::PackedArray;
+use crate::seed::splitmix64;
+
+/// Slots per bucket.
+pub const BUCKET_SIZE: usize = 4;
+
+/// Evictions an insert tries before the filter counts as full.
+pub const MAX_KICKS: usize = 500;
+
+/// Highest load [`CuckooFilter::new`] sizes for; with four-slot buckets
+/// inserts start failing at about 95%.
+const MAX_LOAD: f64 = 0.9;
+
+#[derive(Clone)]
+pub struct CuckooFilter {
+    /// Number of buckets, a power of two.
+    buckets: usize,
+    fingerprint_bits: u32,
+    /// `buckets * BUCKET_SIZE` fingerprints; zero marks an empty slot.
+    slots: PackedArray,
+    len: usize,
+    /// A fingerprint left homeless by the last failed eviction chain, with
+    /// one of its buckets. While it is set the filter is full.
+    victim: Option<(usize, u64)>,
+    kick_state: u64,
+}
+
+impl CuckooFilter {
+    /// A filter for `n` items at false-positive rate `f`: enough buckets
+    /// to hold them at 90% load and `ceil(log2(2 * BUCKET_SIZE / f))`
+    /// fingerprint bits, since a lookup compares against two buckets.
+    pub fn new(n: u32, f: f32) -> Self {
+        assert!(n > 0, "capacity must be positive");
+        assert!(f > 0.0 && f < 1.0, "false-positive rate must be in (0, 1)");
+        let buckets = (n as f64 / (BUCKET_SIZE as f64 * MAX_LOAD)).ceil() as usize;
+        let bits = (2.0 * BUCKET_SIZE as f64 / f as f64).log2().ceil() as u32;
+        Self::with_geometry(buckets.next_power_of_two(), bits)
+    }
+
+    /// A filter of `buckets` buckets, rounded up to a power of two, each
+    /// slot holding a fingerprint of `fingerprint_bits` bits (1 to 32).
+    pub fn with_geometry(buckets: usize, fingerprint_bits: u32) -> Self {
+        assert!(
+            (1..=32).contains(&fingerprint_bits),
+            "fingerprint bits must be 1..=32"
+        );
+        let buckets = buckets.max(1).next_power_of_two();
+        CuckooFilter {
+            buckets,
+            fingerprint_bits,
+            slots: PackedArray::new(buckets * BUCKET_SIZE, fingerprint_bits),
+            len: 0,
+            victim: None,
+            kick_state: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Slots in the filter, the most items it can hold.
+    pub fn capacity(&self) -> usize {
+        self.buckets * BUCKET_SIZE
+    }
+
+    pub fn fingerprint_bits(&self) -> u32 {
+        self.fingerprint_bits
+    }
+
+    pub fn load_factor(&self) -> f64 {
+        self.len as f64 / self.capacity() as f64
+    }
+
+    /// Bytes of the slot table.
+    pub fn size_in_bytes(&self) -> usize {
+        self.slots.size_in_bytes()
+    }
+
+    /// Nonzero fingerprint of the high hash bits and primary bucket of the
+    /// low ones.
+    fn locate(&self, item: &[u8]) -> (u64, usize) {
+        let hash = CanonicalFingerprinter.hash(item);
+        let fingerprint = (hash >> 32) % ((1 << self.fingerprint_bits) - 1) + 1;
+        (fingerprint, hash as usize & (self.buckets - 1))
+    }
+
+    /// The other bucket of a fingerprint found in `bucket`.
+    fn alternate(&self, bucket: usize, fingerprint: u64) -> usize {
+        let mut state = fingerprint;
+        (bucket ^ splitmix64(&mut state) as usize) & (self.buckets - 1)
+    }
+
+    fn bucket_contains(&self, bucket: usize, fingerprint: u64) -> bool {
+        (0..BUCKET_SIZE).any(|i| self.slots.get(bucket * BUCKET_SIZE + i) == fingerprint)
+    }
+
+    /// Stores `fingerprint` in a free slot of `bucket`, if it has one.
+    fn try_store(&mut self, bucket: usize, fingerprint: u64) -> bool {
+        for slot in bucket * BUCKET_SIZE..(bucket + 1) * BUCKET_SIZE {
+            if self.slots.get(slot) == 0 {
+                self.slots.set(slot, fingerprint);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Clears one slot of `bucket` holding `fingerprint`.
+    fn try_remove(&mut self, bucket: usize, fingerprint: u64) -> bool {
+        for slot in bucket * BUCKET_SIZE..(bucket + 1) * BUCKET_SIZE {
+            if self.slots.get(slot) == fingerprint {
+                self.slots.set(slot, 0);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Inserts `item`, or returns false, leaving the filter unchanged, if
+    /// it is full. The insert that fills it still succeeds: the fingerprint
+    /// its eviction chain could not place is kept aside and found by
+    /// lookups until a delete frees a slot for it.
+    pub fn insert(&mut self, item: &[u8]) -> bool {
+        if self.victim.is_some() {
+            return false;
+        }
+        let (mut fingerprint, first) = self.locate(item);
+        let second = self.alternate(first, fingerprint);
+        self.len += 1;
+        if self.try_store(first, fingerprint) || self.try_store(second, fingerprint) {
+            return true;
+        }
+
+        let mut bucket = if splitmix64(&mut self.kick_state) & 1 == 0 {
+            first
+        } else {
+            second
+        };
+        for _ in 0..MAX_KICKS {
+            let slot =
+                bucket * BUCKET_SIZE + splitmix64(&mut self.kick_state) as usize % BUCKET_SIZE;
+            let evicted = self.slots.get(slot);
+            self.slots.set(slot, fingerprint);
+            fingerprint = evicted;
+            bucket = self.alternate(bucket, fingerprint);
+            if self.try_store(bucket, fingerprint) {
+                return true;
+            }
+        }
+        self.victim = Some((bucket, fingerprint));
+        true
+    }
+
+    pub fn lookup(&self, item: &[u8]) -> bool {
+        let (fingerprint, first) = self.locate(item);
+        let second = self.alternate(first, fingerprint);
+        if let Some((bucket, victim)) = self.victim {
+            if victim == fingerprint && (bucket == first || bucket == second) {
+                return true;
+            }
+        }
+        self.bucket_contains(first, fingerprint) || self.bucket_contains(second, fingerprint)
+    }
+
+    /// Removes one copy of `item`'s fingerprint. Deleting an item that was
+    /// never inserted may remove another item sharing its fingerprint and
+    /// buckets, so only delete what was inserted.
+    pub fn delete(&mut self, item: &[u8]) -> bool {
+        let (fingerprint, first) = self.locate(item);
+        let second = self.alternate(first, fingerprint);
+        if self.try_remove(first, fingerprint) || self.try_remove(second, fingerprint) {
+            self.len -= 1;
+            // the freed slot may take the homeless fingerprint back
+            if let Some((bucket, victim)) = self.victim.take() {
+                let other = self.alternate(bucket, victim);
+                if !self.try_store(bucket, victim) && !self.try_store(other, victim) {
+                    self.victim = Some((bucket, victim));
+                }
+            }
+            return true;
+        }
+        match self.victim {
+            Some((bucket, victim))
+                if victim == fingerprint && (bucket == first || bucket == second) =>
+            {
+                self.victim = None;
+                self.len -= 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// False-positive rate after `items` inserts: a lookup compares its
+    /// fingerprint against the `2 * BUCKET_SIZE` slots of its two buckets,
+    /// each filled with probability equal to the load factor and matching
+    /// with probability `1 / (2^bits - 1)` when filled.
+    pub fn expected_false_positive_rate(&self, items: u64) -> f64 {
+        let load = (items as f64 / self.capacity() as f64).min(1.0);
+        let match_one = 1.0 / ((1u64 << self.fingerprint_bits) - 1) as f64;
+        -(2.0 * BUCKET_SIZE as f64 * load * (-match_one).ln_1p()).exp_m1()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sizes_for_capacity_and_rate() {
+        let filter = CuckooFilter::new(1000, 0.01);
+        // 1000 / (4 * 0.9) = 278 buckets, rounded up to 512
+        assert_eq!(filter.capacity(), 512 * BUCKET_SIZE);
+        assert_eq!(filter.fingerprint_bits(), 10);
+        assert_eq!(filter.size_in_bytes(), 512 * BUCKET_SIZE * 10 / 8);
+        assert!(filter.expected_false_positive_rate(1000) < 0.01);
+    }
+
+    #[test]
+    fn inserted_items_are_found_and_deleted() {
+        let mut filter = CuckooFilter::new(2000, 0.001);
+        for i in 0..2000u32 {
+            assert!(filter.insert(&i.to_le_bytes()));
+        }
+        assert_eq!(filter.len(), 2000);
+        assert!((0..2000u32).all(|i| filter.lookup(&i.to_le_bytes())));
+        let false_positives = (2000..102_000u32)
+            .filter(|i| filter.lookup(&i.to_le_bytes()))
+            .count();
+        assert!(false_positives < 300, "{}", false_positives);
+
+        for i in 0..1000u32 {
+            assert!(filter.delete(&i.to_le_bytes()));
+        }
+        assert_eq!(filter.len(), 1000);
+        assert!((1000..2000u32).all(|i| filter.lookup(&i.to_le_bytes())));
+        let remaining = (0..1000u32)
+            .filter(|i| filter.lookup(&i.to_le_bytes()))
+            .count();
+        assert!(remaining < 10, "{}", remaining);
+    }
+
+    #[test]
+    fn keeps_every_item_once_full() {
+        let mut filter = CuckooFilter::with_geometry(64, 16);
+        let mut inserted = 0u32;
+        while filter.insert(&inserted.to_le_bytes()) {
+            inserted += 1;
+        }
+        assert_eq!(filter.len(), inserted as usize);
+        assert!(filter.load_factor() > 0.9, "{}", filter.load_factor());
+        assert!((0..inserted).all(|i| filter.lookup(&i.to_le_bytes())));
+        assert!(!filter.insert(&inserted.to_le_bytes()));
+        assert_eq!(filter.len(), inserted as usize);
+
+        // deletes free slots for the homeless fingerprint, which stays
+        // findable until one of its buckets has room
+        for i in 0..inserted {
+            assert!(filter.delete(&i.to_le_bytes()), "{}", i);
+            assert!((i + 1..inserted).all(|j| filter.lookup(&j.to_le_bytes())));
+        }
+        assert!(filter.is_empty());
+        assert!(filter.victim.is_none());
+    }
+
+    #[test]
+    fn duplicate_fingerprints_are_counted() {
+        let mut filter = CuckooFilter::new(100, 0.01);
+        assert!(filter.insert(b"key"));
+        assert!(filter.insert(b"key"));
+        assert!(filter.delete(b"key"));
+        assert!(filter.lookup(b"key"));
+        assert!(filter.delete(b"key"));
+        assert!(!filter.lookup(b"key"));
+        assert!(!filter.delete(b"key"));
+        assert!(filter.is_empty());
+    }
+}
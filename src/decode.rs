@@ -0,0 +1,41 @@
+//! The error returned when decoding the serialized form of a filter or
+//! sketch. It lives outside the structures so each can be compiled alone.
+
+/// Why a byte buffer could not be decoded as a serialized filter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    BadMagic,
+    UnsupportedVersion(u32),
+    /// `q` or `r` is zero or does not fit the slot format.
+    InvalidParameters {
+        q: u64,
+        r: u64,
+    },
+    /// The buffer length does not match the header.
+    Length {
+        expected: usize,
+        actual: usize,
+    },
+    /// The entry count or a slot is inconsistent with the parameters.
+    Corrupt,
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::BadMagic => write!(f, "not a quotient filter (bad magic)"),
+            DecodeError::UnsupportedVersion(version) => {
+                write!(f, "unsupported layout version {}", version)
+            }
+            DecodeError::InvalidParameters { q, r } => {
+                write!(f, "invalid parameters q = {}, r = {}", q, r)
+            }
+            DecodeError::Length { expected, actual } => {
+                write!(f, "expected {} bytes, got {}", expected, actual)
+            }
+            DecodeError::Corrupt => write!(f, "corrupt filter contents"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
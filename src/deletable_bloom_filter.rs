@@ -0,0 +1,180 @@
+use crate::bloom_filter::BloomFilter;
+use crate::hasher::{Hasher64, RowHashing, DEFAULT_SEED};
+use crate::seed::SeedSequence;
+use bitvec::prelude::BitVec;
+
+/// Deletable Bloom filter (Rothenberg et al.).
+///
+/// The bit array is split into `regions` equal regions, and one extra bit
+/// per region records whether two inserts ever set the same bit in it. A
+/// bit in a collision-free region belongs to exactly one item, so that item
+/// can be removed by clearing it. This costs `regions` bits instead of the
+/// per-cell counters of a counting Bloom filter.
+#[derive(Clone)]
+pub struct DeletableBloomFilter {
+    m: u32,
+    k: u32,
+    region_size: u32,
+    bit_array: BitVec,
+    collisions: BitVec,
+    hashing: RowHashing,
+}
+
+impl DeletableBloomFilter {
+    pub fn new(n: u32, f: f32, regions: u32) -> Self {
+        let (_, k) = BloomFilter::geometry_u32(n, f);
+        let hashing = RowHashing::murmur3(k as usize, SeedSequence::new(DEFAULT_SEED));
+        Self::with_hashing(n, f, regions, hashing)
+    }
+
+    /// Creates a filter whose probes are derived from `hasher`.
+    pub fn with_hasher<H: Hasher64 + PartialEq + Send + Sync + 'static>(
+        n: u32,
+        f: f32,
+        regions: u32,
+        hasher: H,
+    ) -> Self {
+        Self::with_hashing(n, f, regions, RowHashing::from_hasher(hasher))
+    }
+
+    fn with_hashing(n: u32, f: f32, regions: u32, hashing: RowHashing) -> Self {
+        let (m, k) = BloomFilter::geometry_u32(n, f);
+        assert!(
+            regions > 0 && regions <= m,
+            "regions must be between 1 and m = {}",
+            m
+        );
+        let mut bit_array = BitVec::new();
+        bit_array.resize(m as usize, false);
+        let mut collisions = BitVec::new();
+        collisions.resize(regions as usize, false);
+        DeletableBloomFilter {
+            m,
+            k,
+            region_size: m.div_ceil(regions),
+            bit_array,
+            collisions,
+            hashing,
+        }
+    }
+
+    fn region(&self, index: usize) -> usize {
+        index / self.region_size as usize
+    }
+
+    fn indexes(&self, item: &[u8]) -> Vec<usize> {
+        let hashes = self.hashing.hashes(item);
+        (0..self.k)
+            .map(|i| (hashes.row(i) % self.m as u64) as usize)
+            .collect()
+    }
+
+    pub fn insert(&mut self, item: &[u8]) {
+        let mut indexes = self.indexes(item);
+        // an item probing one bit twice does not collide with itself
+        indexes.sort_unstable();
+        indexes.dedup();
+        for index in indexes {
+            if self.bit_array[index] {
+                let region = self.region(index);
+                self.collisions.set(region, true);
+            } else {
+                self.bit_array.set(index, true);
+            }
+        }
+    }
+
+    pub fn lookup(&self, item: &[u8]) -> bool {
+        self.indexes(item)
+            .into_iter()
+            .all(|index| self.bit_array[index])
+    }
+
+    /// Removes `item` if at least one of its bits lies in a collision-free
+    /// region, clearing every such bit. Returns false, leaving the filter
+    /// unchanged, if the item is absent or all its bits may be shared.
+    ///
+    /// Like any Bloom filter deletion, removing an item that was never
+    /// inserted (a false positive) removes whatever items set those bits.
+    pub fn try_remove(&mut self, item: &[u8]) -> bool {
+        let indexes = self.indexes(item);
+        if !indexes.iter().all(|&index| self.bit_array[index]) {
+            return false;
+        }
+        let deletable: Vec<usize> = indexes
+            .into_iter()
+            .filter(|&index| !self.collisions[self.region(index)])
+            .collect();
+        for &index in &deletable {
+            self.bit_array.set(index, false);
+        }
+        !deletable.is_empty()
+    }
+
+    /// Bytes held by the bit array and the collision bits.
+    pub fn size_in_bytes(&self) -> usize {
+        std::mem::size_of_val(self.bit_array.as_raw_slice())
+            + std::mem::size_of_val(self.collisions.as_raw_slice())
+    }
+
+    /// False-positive rate in theory after `items` distinct inserts, the
+    /// same as a [`BloomFilter`] of this geometry.
+    pub fn expected_false_positive_rate(&self, items: u64) -> f64 {
+        crate::bloom_filter::expected_false_positive_rate(self.m.into(), self.k, items)
+    }
+
+    /// Fraction of regions that are still collision-free, i.e. the rough
+    /// share of bits through which deletions remain possible.
+    pub fn deletable_fraction(&self) -> f64 {
+        self.collisions.count_zeros() as f64 / self.collisions.len() as f64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log;
+
+    #[test]
+    fn insert_lookup_remove() {
+        log::init_test_logger();
+        let mut b = DeletableBloomFilter::new(100, 0.01, 64);
+        b.insert(b"1");
+        assert!(b.lookup(b"1"));
+        assert!(!b.lookup(b"2"));
+        assert_eq!(b.deletable_fraction(), 1.0);
+        assert!(b.try_remove(b"1"));
+        assert!(!b.lookup(b"1"));
+        assert!(!b.try_remove(b"1"));
+    }
+
+    #[test]
+    fn most_items_are_deletable_when_lightly_loaded() {
+        log::init_test_logger();
+        let mut b = DeletableBloomFilter::new(1000, 0.01, 1000);
+        let items: Vec<String> = (0..200).map(|i| format!("item-{}", i)).collect();
+        for item in &items {
+            b.insert(item.as_bytes());
+        }
+        let removed: Vec<&String> = items
+            .iter()
+            .filter(|item| b.try_remove(item.as_bytes()))
+            .collect();
+        assert!(removed.len() > 150, "only {} removed", removed.len());
+        // removal never clears a bit another item depends on
+        for item in items.iter().filter(|item| !removed.contains(item)) {
+            assert!(b.lookup(item.as_bytes()));
+        }
+    }
+
+    #[test]
+    fn fully_collided_item_is_kept() {
+        log::init_test_logger();
+        let mut b = DeletableBloomFilter::new(100, 0.01, 1);
+        b.insert(b"a");
+        b.insert(b"a");
+        assert_eq!(b.deletable_fraction(), 0.0);
+        assert!(!b.try_remove(b"a"));
+        assert!(b.lookup(b"a"));
+    }
+}
@@ -0,0 +1,93 @@
+//! Frequency estimators behind one object-safe trait.
+//!
+//! [`FrequencyEstimator`] takes byte keys and `u64` counts, so a Count-Min
+//! sketch, a spectral Bloom filter and a counting quotient filter can be
+//! swapped for one another, or boxed as `dyn FrequencyEstimator`, to
+//! compare their error and size on the same stream.
+
+#[cfg(feature = "cms")]
+use crate::count_min_sketch::CountMinSketch;
+#[cfg(feature = "quotient")]
+use crate::counting_quotient_filter::CountingQuotientFilter;
+#[cfg(feature = "bloom")]
+use crate::spectral_bloom_filter::SpectralBloomFilter;
+
+/// Counts how often byte keys were seen. Estimates may be too high, e.g.
+/// when keys share counters or fingerprints, but never too low.
+pub trait FrequencyEstimator {
+    /// Records `count` more occurrences of `item`.
+    fn update(&mut self, item: &[u8], count: u64);
+    fn estimate(&self, item: &[u8]) -> u64;
+}
+
+/// Counters are `u32`, so counts saturate at `u32::MAX`.
+#[cfg(feature = "cms")]
+impl FrequencyEstimator for CountMinSketch {
+    fn update(&mut self, item: &[u8], count: u64) {
+        CountMinSketch::update(self, item, count.try_into().unwrap_or(u32::MAX));
+    }
+
+    fn estimate(&self, item: &[u8]) -> u64 {
+        CountMinSketch::estimate(self, item).into()
+    }
+}
+
+/// Counters are `u32`, so counts saturate at `u32::MAX`.
+#[cfg(feature = "bloom")]
+impl FrequencyEstimator for SpectralBloomFilter {
+    fn update(&mut self, item: &[u8], count: u64) {
+        SpectralBloomFilter::update(self, item, count.try_into().unwrap_or(u32::MAX));
+    }
+
+    fn estimate(&self, item: &[u8]) -> u64 {
+        SpectralBloomFilter::estimate(self, item).into()
+    }
+}
+
+/// Items are counted by their canonical fingerprint, as with
+/// [`CountingQuotientFilter::insert_bytes`].
+#[cfg(feature = "quotient")]
+impl FrequencyEstimator for CountingQuotientFilter {
+    fn update(&mut self, item: &[u8], count: u64) {
+        self.update_bytes(item, count);
+    }
+
+    fn estimate(&self, item: &[u8]) -> u64 {
+        self.count_bytes(item)
+    }
+}
+
+#[cfg(all(test, feature = "cms", feature = "quotient", feature = "bloom"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_estimator_counts_byte_keys_without_underestimating() {
+        let mut estimators: Vec<(&str, Box<dyn FrequencyEstimator>)> = vec![
+            ("count_min", Box::new(CountMinSketch::new(0.001, 0.01))),
+            (
+                "spectral_bloom",
+                Box::new(SpectralBloomFilter::new(1000, 0.01)),
+            ),
+            (
+                "counting_quotient",
+                Box::new(CountingQuotientFilter::new(8, 12)),
+            ),
+        ];
+        for (name, estimator) in &mut estimators {
+            let count = |i: u64| i % 7 + 1;
+            for i in 0..500u64 {
+                estimator.update(&i.to_le_bytes(), count(i));
+            }
+            estimator.update(b"hot", 1 << 40);
+            assert!(
+                (0..500u64).all(|i| estimator.estimate(&i.to_le_bytes()) >= count(i)),
+                "{}",
+                name
+            );
+            assert!(estimator.estimate(b"hot") >= u32::MAX.into(), "{}", name);
+        }
+        // only the counting quotient filter keeps counts past u32::MAX
+        assert_eq!(estimators[2].1.estimate(b"hot"), 1 << 40);
+    }
+}
@@ -0,0 +1,159 @@
+use crate::count_min_sketch::CountMinSketch;
+use crate::decode::DecodeError;
+use crate::quotient_filter::QuotientFilter;
+
+const MAGIC: [u8; 4] = *b"HBFF";
+const VERSION: u32 = 1;
+
+/// Membership and frequency for the same keys behind one insert.
+///
+/// A quotient filter (with canonical byte-key fingerprints) answers "was
+/// this key seen", and a Count-Min sketch answers "how often". The filter
+/// has no false negatives, so keys it rejects report a count of zero
+/// instead of the sketch's collision noise.
+pub struct FrequencyFilter {
+    membership: QuotientFilter,
+    frequency: CountMinSketch,
+}
+
+impl FrequencyFilter {
+    pub fn new(q: u64, r: u64, eps: f32, delta: f32) -> Self {
+        FrequencyFilter {
+            membership: QuotientFilter::new(q, r),
+            frequency: CountMinSketch::new(eps, delta),
+        }
+    }
+
+    pub fn insert(&mut self, key: &[u8]) {
+        self.update(key, 1);
+    }
+
+    /// Records `freq` occurrences of `key`.
+    pub fn update(&mut self, key: &[u8], freq: u32) {
+        if !self.membership.lookup_bytes(key) {
+            self.membership.insert_bytes(key);
+        }
+        self.frequency.update(key, freq);
+    }
+
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.membership.lookup_bytes(key)
+    }
+
+    /// Estimated number of occurrences; never below the true count.
+    pub fn count(&self, key: &[u8]) -> u32 {
+        if !self.contains(key) {
+            return 0;
+        }
+        self.frequency.estimate(key)
+    }
+
+    /// Encodes both structures: magic `b"HBFF"`, a `u32` version, the
+    /// quotient filter's length as `u64` followed by its bytes, then the
+    /// sketch. All integers are little-endian.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let membership = self.membership.to_bytes();
+        let mut bytes = Vec::with_capacity(16 + membership.len());
+        bytes.extend_from_slice(&MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(membership.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&membership);
+        self.frequency.encode(&mut bytes);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        if bytes.len() < 16 {
+            return Err(DecodeError::Length {
+                expected: 16,
+                actual: bytes.len(),
+            });
+        }
+        if bytes[0..4] != MAGIC {
+            return Err(DecodeError::BadMagic);
+        }
+        let version = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        if version != VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let membership_len = u64::from_le_bytes(bytes[8..16].try_into().unwrap());
+        let rest = &bytes[16..];
+        if membership_len > rest.len() as u64 {
+            return Err(DecodeError::Corrupt);
+        }
+        let (membership, sketch) = rest.split_at(membership_len as usize);
+        let membership = QuotientFilter::from_bytes(membership)?;
+        let (frequency, len) = CountMinSketch::decode(sketch)?;
+        if len != sketch.len() {
+            return Err(DecodeError::Length {
+                expected: 16 + membership_len as usize + len,
+                actual: bytes.len(),
+            });
+        }
+        Ok(FrequencyFilter {
+            membership,
+            frequency,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answers_membership_and_frequency() {
+        let mut filter = FrequencyFilter::new(6, 16, 0.01, 0.01);
+        for i in 0..200u32 {
+            filter.update(format!("key-{}", i).as_bytes(), i % 5 + 1);
+        }
+        filter.insert(b"key-3");
+        assert!(filter.contains(b"key-3"));
+        assert_eq!(filter.count(b"key-3"), 5);
+        assert!(filter.count(b"key-7") >= 3);
+        assert!(!filter.contains(b"absent"));
+        assert_eq!(filter.count(b"absent"), 0);
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let mut filter = FrequencyFilter::new(4, 12, 0.05, 0.1);
+        for i in 0..40u32 {
+            filter.update(&i.to_le_bytes(), i);
+        }
+        let bytes = filter.to_bytes();
+        let decoded = FrequencyFilter::from_bytes(&bytes).unwrap();
+        for i in 1..40u32 {
+            assert!(decoded.contains(&i.to_le_bytes()));
+            assert_eq!(
+                decoded.count(&i.to_le_bytes()),
+                filter.count(&i.to_le_bytes())
+            );
+        }
+        assert_eq!(decoded.to_bytes(), bytes);
+
+        let mut bad = bytes.clone();
+        bad[0] = 0;
+        assert_eq!(
+            FrequencyFilter::from_bytes(&bad).err(),
+            Some(DecodeError::BadMagic)
+        );
+        let mut extra = bytes.clone();
+        extra.push(0);
+        assert!(FrequencyFilter::from_bytes(&extra).is_err());
+        assert!(FrequencyFilter::from_bytes(&bytes[..bytes.len() - 4]).is_err());
+    }
+
+    #[test]
+    fn corrupt_membership_slots_are_rejected() {
+        use crate::quotient_filter::layout;
+
+        let mut bytes = FrequencyFilter::new(1, 4, 0.05, 0.1).to_bytes();
+        // a shifted slot in an otherwise empty filter
+        bytes[16 + layout::HEADER_LEN] = layout::SHIFTED as u8;
+        assert_eq!(
+            FrequencyFilter::from_bytes(&bytes).err(),
+            Some(DecodeError::Corrupt)
+        );
+    }
+}
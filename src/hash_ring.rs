@@ -1,14 +1,214 @@
 use core::panic;
 use log::{info, warn};
 use num_traits;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::marker::PhantomData;
 use std::sync::{Arc, Mutex};
 
+use crate::builder::BuildError;
+use crate::hasher::{Hasher64, Murmur3, DEFAULT_SEED};
+use crate::lru::LruCache;
+
+// ノードの位置が既存のノードと衝突したときの扱い
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CollisionPolicy {
+    // エラーにする
+    #[default]
+    Reject,
+    // 時計回りに次の空いている位置に置く。同じ順序で追加すれば配置は常に同じになる
+    NextFree,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RingError<T> {
+    OutOfRange(T),
+    Collision(T),
+    Full,
+    // リソースを担当するノード (Spill なら全ノード) が容量いっぱい
+    Overloaded(T),
+}
+
+impl<T: std::fmt::Display> std::fmt::Display for RingError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RingError::OutOfRange(hash) => write!(f, "hash {} is out of range", hash),
+            RingError::Collision(hash) => write!(f, "node {} already exists", hash),
+            RingError::Full => write!(f, "every ring position is taken"),
+            RingError::Overloaded(node) => write!(f, "node {} is at capacity", node),
+        }
+    }
+}
+
+impl<T: std::fmt::Debug + std::fmt::Display> std::error::Error for RingError<T> {}
+
+// リソースのキーをリング上の位置 0..2^k に写す方法。
+// add_resource などは位置を直接受け取るので、キーから位置を求めるときだけ使う
+pub trait Placement: Send + Sync {
+    fn place(&self, key: &[u8], k: u32) -> u64;
+
+    // 位置が 0..2^k に収まらなければ None。place が panic する実装は上書きする
+    fn try_place(&self, key: &[u8], k: u32) -> Option<u64> {
+        let position = self.place(key, k);
+        (position & !mask(k) == 0).then_some(position)
+    }
+}
+
+// キーは big-endian の整数で、すでにリング上の位置になっている
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Identity;
+
+impl Placement for Identity {
+    fn place(&self, key: &[u8], k: u32) -> u64 {
+        assert!(
+            key.len() <= 8,
+            "identity placement takes keys of at most 8 bytes"
+        );
+        let position = key.iter().fold(0u64, |acc, &byte| (acc << 8) | byte as u64);
+        assert!(
+            k >= 64 || position >> k == 0,
+            "key {} is outside the ring",
+            position
+        );
+        position
+    }
+
+    fn try_place(&self, key: &[u8], k: u32) -> Option<u64> {
+        if key.len() > 8 {
+            return None;
+        }
+        let position = key.iter().fold(0u64, |acc, &byte| (acc << 8) | byte as u64);
+        (position & !mask(k) == 0).then_some(position)
+    }
+}
+
+// キーをハッシュして下位 k ビットを位置にする。Murmur3 や XxHash64 など
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Hashed<H>(pub H);
+
+impl<H: Hasher64 + Send + Sync> Placement for Hashed<H> {
+    fn place(&self, key: &[u8], k: u32) -> u64 {
+        self.0.hash64(key) & mask(k)
+    }
+}
+
+// 任意の関数も使える。結果の下位 k ビットを位置にする
+impl<F: Fn(&[u8]) -> u64 + Send + Sync> Placement for F {
+    fn place(&self, key: &[u8], k: u32) -> u64 {
+        self(key) & mask(k)
+    }
+}
+
+fn mask(k: u32) -> u64 {
+    u64::MAX.checked_shr(64 - k).unwrap_or(0)
+}
+
+// 大きさ 2^k のリングで a から時計回りに b まで進む距離 (b - a) mod 2^k。
+// a と b は 0..2^k の位置とする。途中の値はすべて mask = 2^k - 1 以下に収まるので、
+// k が T のビット幅いっぱいでもオーバーフローしない
+pub fn ring_distance<T: num_traits::PrimInt>(a: T, b: T, k: u32) -> T {
+    if a <= b {
+        return b - a;
+    }
+    let bits = T::zero().count_zeros();
+    let mask = if k >= bits {
+        T::max_value()
+    } else {
+        !(!T::zero() << k as usize)
+    };
+    // a > b なので 1 <= a - b <= mask
+    mask - (a - b) + T::one()
+}
+
+// 容量いっぱいのノードから追い出すリソースを選ぶ。resources はノードが持つ
+// リソースを昇順に並べたもの。incoming を返すと新しいリソースのほうを捨てる
+pub trait Evictor<T>: Send + Sync {
+    fn victim(&self, node: T, resources: &[T], incoming: T) -> T;
+}
+
+// ノードから反時計回りに一番遠いリソースを追い出す。ノードを追加したときに
+// 最初に移動していくリソースなので、残ったリソースの担当範囲が狭くなる
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EvictFarthest;
+
+impl<T: Ord + Copy> Evictor<T> for EvictFarthest {
+    fn victim(&self, node: T, resources: &[T], _incoming: T) -> T {
+        // node より大きいリソースは 0 をまたいだ先にあるので、そちらのほうが遠い
+        let wrapped = resources.partition_point(|&key| key <= node);
+        resources
+            .get(wrapped)
+            .or(resources.first())
+            .copied()
+            .expect("a full node has resources")
+    }
+}
+
+impl<T, F: Fn(T, &[T], T) -> T + Send + Sync> Evictor<T> for F {
+    fn victim(&self, node: T, resources: &[T], incoming: T) -> T {
+        self(node, resources, incoming)
+    }
+}
+
+// ノードが容量いっぱいのときの add_resource の扱い
+pub enum OverflowPolicy<T> {
+    // RingError::Overloaded にする
+    Reject,
+    // 時計回りに次の空きのあるノードに置く。一周して空きがなければ Overloaded
+    Spill,
+    // Evictor が選んだリソースを追い出して入れ替える
+    Evict(Arc<dyn Evictor<T>>),
+}
+
+impl<T> OverflowPolicy<T> {
+    pub fn evict<E: Evictor<T> + 'static>(evictor: E) -> Self {
+        OverflowPolicy::Evict(Arc::new(evictor))
+    }
+}
+
+impl<T> Clone for OverflowPolicy<T> {
+    fn clone(&self) -> Self {
+        match self {
+            OverflowPolicy::Reject => OverflowPolicy::Reject,
+            OverflowPolicy::Spill => OverflowPolicy::Spill,
+            OverflowPolicy::Evict(evictor) => OverflowPolicy::Evict(Arc::clone(evictor)),
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for OverflowPolicy<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OverflowPolicy::Reject => write!(f, "Reject"),
+            OverflowPolicy::Spill => write!(f, "Spill"),
+            OverflowPolicy::Evict(_) => write!(f, "Evict(..)"),
+        }
+    }
+}
+
+// try_add_resource の結果。node はリソースを置いたノード、evicted は
+// 追い出されたリソース (新しいリソース自身のこともある)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Admission<T> {
+    pub node: T,
+    pub evicted: Option<T>,
+}
+
+// ノードごとのリソース数の上限と、それを超えたときの扱い
+struct CapacityLimit<T> {
+    limit: usize,
+    policy: OverflowPolicy<T>,
+    // Spill で担当ノード以外に置いたリソースと、それを持っているノード
+    spilled: Mutex<HashMap<T, T>>,
+}
+
+// move_resource が一度に移動するリソース数
+pub const MIGRATION_BATCH_SIZE: usize = 1024;
+
 pub trait HashRingInterface<T: std::hash::Hash> {
     fn add_node(&mut self, hash: T);
     fn remove_node(&mut self, hash: T);
     fn lookup(&self, hash: T) -> Option<Arc<Mutex<Node<T>>>>;
-    fn move_resource(&self, dest: T, src: T, is_delete: bool);
+    fn move_resource(&self, dest: T, src: T, is_delete: bool) -> Vec<T>;
+    fn move_resource_batch(&self, dest: T, src: T, is_delete: bool, limit: usize) -> Vec<T>;
     fn add_resource(&self, hash: T);
 }
 
@@ -16,21 +216,170 @@ pub trait HashRingInterface<T: std::hash::Hash> {
 pub struct Node<T> {
     value: T,
     resource: HashMap<T, T>,
+    // TTL 付きのリソースの期限。ここにないリソースは期限切れにならない
+    expiry: HashMap<T, u64>,
     prev: Option<Arc<Mutex<Node<T>>>>,
     next: Option<Arc<Mutex<Node<T>>>>,
 }
 
+type NodeRef<T> = Arc<Mutex<Node<T>>>;
+
 impl<T> Node<T> {
     pub fn value(&self) -> &T {
         &self.value
     }
 }
 
+// ある時点のノード一覧を固定した読み取り専用のビュー。
+// clone は Arc のコピーだけなので、書き込み側がリングを更新している間も
+// ロックなしで lookup できる。
+#[derive(Debug, Clone)]
+pub struct RingSnapshot<T> {
+    nodes: Arc<[T]>,
+}
+
+impl<T: Ord + Copy> RingSnapshot<T> {
+    pub fn nodes(&self) -> &[T] {
+        &self.nodes
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    // hash 以上で最小のノードを返す。なければ先頭に巻き戻る
+    pub fn lookup(&self, hash: T) -> Option<T> {
+        let index = self.nodes.partition_point(|node| *node < hash);
+        self.nodes
+            .get(index)
+            .or_else(|| self.nodes.first())
+            .copied()
+    }
+}
+
+// iter / iter_from が返すイテレータ。ノードを位置の昇順に、そのリソースと
+// 一緒に返す。ノードは Arc で辿り、ロックは各ノードを読む間だけ取るので、
+// 走査の合間にリングを更新しても構わない
+pub struct RingCursor<T> {
+    next: Option<Arc<Mutex<Node<T>>>>,
+}
+
+impl<T: Ord + Copy> Iterator for RingCursor<T> {
+    type Item = (T, Vec<(T, T)>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node_ref = self.next.take()?;
+        let (value, mut resources, next) = {
+            let node = node_ref.lock().unwrap();
+            let resources: Vec<(T, T)> = node.resource.iter().map(|(k, v)| (*k, *v)).collect();
+            (*node.value(), resources, node.next.clone())
+        };
+        resources.sort();
+        // 位置が戻ったら一周したので終わる
+        self.next = next.filter(|next| *next.lock().unwrap().value() > value);
+        Some((value, resources))
+    }
+}
+
+// move_resource_batches が返すイテレータ。集めた移動対象を limit 件ずつ移す。
+// 途中で消されたリソースは飛ばす
+pub struct MigrationBatches<'a, T> {
+    ring: &'a HashRing<T>,
+    // 移動先と移動元のノード。自分自身への移動なら None
+    nodes: Option<(NodeRef<T>, NodeRef<T>)>,
+    dest: T,
+    keys: std::vec::IntoIter<T>,
+    limit: usize,
+}
+
+impl<
+        T: std::fmt::Debug
+            + std::fmt::Display
+            + PartialOrd
+            + PartialEq
+            + Copy
+            + std::hash::Hash
+            + num_traits::Zero
+            + num_traits::FromPrimitive
+            + num_traits::One
+            + num_traits::NumOps
+            + num_traits::PrimInt,
+    > Iterator for MigrationBatches<'_, T>
+{
+    type Item = Vec<T>;
+
+    fn next(&mut self) -> Option<Vec<T>> {
+        let (dest_node, src_node) = self.nodes.as_ref()?;
+        loop {
+            let batch: Vec<T> = self.keys.by_ref().take(self.limit).collect();
+            if batch.is_empty() {
+                return None;
+            }
+            let moved = self.ring.transfer(dest_node, src_node, self.dest, &batch);
+            if !moved.is_empty() {
+                return Some(moved);
+            }
+        }
+    }
+}
+
+// lookup キャッシュのヒット数とミス数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CacheStats {
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / total as f64
+    }
+}
+
+// リング全体の状態をまとめた値。print の代わりに呼び出し側で整形・検証できる
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RingSummary<T> {
+    pub k: u32,
+    pub nodes: usize,
+    pub resources: usize,
+    // リソースを一番多く持つノードとその数
+    pub busiest: Option<(T, usize)>,
+}
+
+// rehash_into の結果
+pub struct Rehash<T> {
+    pub ring: HashRing<T>,
+    // 旧リングのノードの位置から新しいリングでの位置へ
+    pub nodes: HashMap<T, T>,
+    // 新しいリングのリソース数。写した先が重なったリソースは一つになる
+    pub resources: usize,
+    // 持ち主のノードが変わったリソースの数。resources と同じく重なったものは一つと数える
+    pub moved: usize,
+}
+
+struct LookupCache<T> {
+    entries: LruCache<T, Arc<Mutex<Node<T>>>>,
+    stats: CacheStats,
+}
+
 pub struct HashRing<T> {
     head: Option<Arc<Mutex<Node<T>>>>,
     k: u32,
     min: T,
     max: T,
+    // lookup は &self なので Mutex 越しに更新する
+    cache: Option<Mutex<LookupCache<T>>>,
+    collision_policy: CollisionPolicy,
+    placement: Arc<dyn Placement>,
+    capacity: Option<CapacityLimit<T>>,
 }
 
 impl<
@@ -48,18 +397,317 @@ impl<
     > HashRingInterface<T> for HashRing<T>
 {
     fn add_node(&mut self, hash: T) {
+        if let Err(err) = self.try_add_node(hash) {
+            panic!("{}", err);
+        }
+    }
+
+    fn remove_node(&mut self, hash: T) {
+        let node_ref = self.find_node(hash);
+        let node_value = self.get_node_value(&node_ref);
+        let next_value = self.get_next_value(&node_ref);
+        if node_value != hash {
+            warn!("node {} is not found, skip removing", hash);
+            return;
+        }
+        info!(
+            "remove node: {}, and now moving resources to {}...",
+            node_value, next_value
+        );
+        self.move_resource(next_value, node_value, true);
+
+        let head_value = self.get_head_value();
+        let head_next_value = self.get_next_value(&self.head.clone());
+        let next_node_ref = self.get_next_node_ref(&node_ref);
+        if let Some(node) = &node_ref {
+            self.remove_node_inner(node.clone());
+        }
+        // 削除したノードの系列が古い値のまま残らないようにする
+        #[cfg(feature = "metrics")]
+        metrics::gauge!("hash_ring_node_resources", "node" => hash.to_string()).set(0.0);
+        if head_value == head_next_value {
+            self.head = self.get_next_node_ref(&node_ref);
+            if head_value == hash {
+                self.head = None;
+            }
+        } else if head_value == hash {
+            // head は最小のノードなので、その次のノードが新しい head になる
+            self.head = next_node_ref;
+        }
+        self.invalidate_cache();
+    }
+
+    // 呼び出し側からの lookup だけを数える。リング内部の探索は find_node を使う
+    fn lookup(&self, hash: T) -> Option<Arc<Mutex<Node<T>>>> {
+        self.count("hash_ring_lookups_total", 1);
+        self.find_node(hash)
+    }
+
+    // MIGRATION_BATCH_SIZE 件ずつ移動する。移動したキーをすべて返す
+    fn move_resource(&self, dest: T, src: T, is_delete: bool) -> Vec<T> {
+        self.move_resource_batches(dest, src, is_delete, MIGRATION_BATCH_SIZE)
+            .flatten()
+            .collect()
+    }
+
+    // src から dest へ最大 limit 件のリソースを移動し、移動したキーを昇順で返す。
+    // 呼ぶたびに移動対象を集め直すので、全部移すまで繰り返すなら move_resource_batches を使う
+    fn move_resource_batch(&self, dest: T, src: T, is_delete: bool, limit: usize) -> Vec<T> {
+        self.move_resource_batches(dest, src, is_delete, limit)
+            .next()
+            .unwrap_or_default()
+    }
+
+    fn add_resource(&self, hash: T) {
+        if let Err(err) = self.try_add_resource(hash) {
+            panic!("{}", err);
+        }
+    }
+}
+
+impl<
+        T: std::fmt::Debug
+            + std::fmt::Display
+            + PartialOrd
+            + PartialEq
+            + Copy
+            + std::hash::Hash
+            + num_traits::Zero
+            + num_traits::FromPrimitive
+            + num_traits::One
+            + num_traits::NumOps
+            + num_traits::PrimInt,
+    > HashRing<T>
+{
+    pub fn builder() -> HashRingBuilder<T> {
+        HashRingBuilder {
+            k: None,
+            collision_policy: CollisionPolicy::default(),
+            cache_capacity: None,
+            placement: None,
+            capacity_limit: None,
+            ring: PhantomData,
+        }
+    }
+
+    pub fn new(k: u32) -> Self {
+        Self {
+            head: None,
+            k,
+            min: num_traits::Zero::zero(),
+            max: num_traits::FromPrimitive::from_i64((1 << k) - 1).unwrap(),
+            cache: None,
+            collision_policy: CollisionPolicy::default(),
+            placement: Arc::new(Hashed(Murmur3::with_seed(DEFAULT_SEED))),
+            capacity: None,
+        }
+    }
+
+    // add_key などでキーを位置に写す方法を変える。既定は murmur3
+    pub fn with_placement<P: Placement + 'static>(mut self, placement: P) -> Self {
+        self.placement = Arc::new(placement);
+        self
+    }
+
+    // キーのリング上の位置
+    pub fn key_position(&self, key: &[u8]) -> T {
+        let position = self.placement.place(key, self.k);
+        num_traits::FromPrimitive::from_u64(position).unwrap()
+    }
+
+    // キーを位置に写してリソースとして追加し、その位置を返す
+    pub fn add_key(&self, key: &[u8]) -> T {
+        let position = self.key_position(key);
+        self.add_resource(position);
+        position
+    }
+
+    // キーを担当するノードの位置
+    pub fn lookup_key(&self, key: &[u8]) -> Option<T> {
+        self.lookup(self.key_position(key))
+            .map(|node| *node.lock().unwrap().value())
+    }
+
+    pub fn with_collision_policy(mut self, policy: CollisionPolicy) -> Self {
+        self.collision_policy = policy;
+        self
+    }
+
+    // ノードごとのリソース数を limit 件までにし、超えた分を policy で扱う。
+    // 上限を見るのは add_resource のときだけで、ノードの追加・削除による
+    // リソースの移動では上限を超えることがある
+    pub fn with_capacity_limit(mut self, limit: usize, policy: OverflowPolicy<T>) -> Self {
+        assert!(limit > 0, "capacity limit must be positive");
+        self.capacity = Some(CapacityLimit {
+            limit,
+            policy,
+            spilled: Mutex::new(HashMap::new()),
+        });
+        self
+    }
+
+    // リソースを追加し、置いたノードと追い出したリソースを返す。
+    // ノードが一つもなければ panic する
+    pub fn try_add_resource(&self, hash: T) -> Result<Admission<T>, RingError<T>> {
         if !self.legal_range(hash) {
-            panic!("hash {} is out of range", hash);
+            return Err(RingError::OutOfRange(hash));
         }
+        let owner = self.find_node(hash).expect("node is not found");
+        let mut evicted = None;
+        let target = match &self.capacity {
+            None => owner,
+            Some(capacity) => {
+                if let Some(node) = self.spilled_node(hash) {
+                    // Spill 済みのリソースは置いてあるノードで上書きする
+                    return Ok(self.store_resource(&node, hash, None));
+                }
+                let (owner_value, full) = {
+                    let node = owner.try_lock().unwrap();
+                    let full =
+                        node.resource.len() >= capacity.limit && !node.resource.contains_key(&hash);
+                    (node.value, full)
+                };
+                if !full {
+                    owner
+                } else {
+                    match &capacity.policy {
+                        OverflowPolicy::Reject => return Err(RingError::Overloaded(owner_value)),
+                        OverflowPolicy::Spill => {
+                            let node = self
+                                .next_with_room(&owner, capacity.limit)
+                                .ok_or(RingError::Overloaded(owner_value))?;
+                            let node_value = *node.lock().unwrap().value();
+                            capacity.spilled.lock().unwrap().insert(hash, node_value);
+                            info!("spill resource {} from node {}", hash, owner_value);
+                            node
+                        }
+                        OverflowPolicy::Evict(evictor) => {
+                            let mut node = owner.try_lock().unwrap();
+                            let mut keys: Vec<T> = node.resource.keys().copied().collect();
+                            keys.sort();
+                            let victim = evictor.victim(owner_value, &keys, hash);
+                            evicted = Some(victim);
+                            if victim == hash {
+                                info!("node {} refused resource {}", owner_value, hash);
+                                self.count("hash_ring_evicted_resources_total", 1);
+                                return Ok(Admission {
+                                    node: owner_value,
+                                    evicted,
+                                });
+                            }
+                            assert!(
+                                node.resource.remove(&victim).is_some(),
+                                "victim {} is not on node {}",
+                                victim,
+                                owner_value
+                            );
+                            node.expiry.remove(&victim);
+                            capacity.spilled.lock().unwrap().remove(&victim);
+                            info!("evict resource {} from node {}", victim, owner_value);
+                            self.count("hash_ring_evicted_resources_total", 1);
+                            drop(node);
+                            owner
+                        }
+                    }
+                }
+            }
+        };
+        Ok(self.store_resource(&target, hash, evicted))
+    }
+
+    fn store_resource(
+        &self,
+        node: &Arc<Mutex<Node<T>>>,
+        hash: T,
+        evicted: Option<T>,
+    ) -> Admission<T> {
+        let mut node = node.try_lock().unwrap();
+        node.resource.insert(hash, hash);
+        node.expiry.remove(&hash);
+        info!("add resource {} to node {}", hash, node.value);
+        Admission {
+            node: node.value,
+            evicted,
+        }
+    }
+
+    // start の次から時計回りに、リソースが limit 件未満のノードを探す
+    fn next_with_room(
+        &self,
+        start: &Arc<Mutex<Node<T>>>,
+        limit: usize,
+    ) -> Option<Arc<Mutex<Node<T>>>> {
+        let mut current = start.lock().unwrap().next.clone()?;
+        while !Arc::ptr_eq(&current, start) {
+            let next = {
+                let node = current.lock().unwrap();
+                if node.resource.len() < limit {
+                    None
+                } else {
+                    node.next.clone()
+                }
+            };
+            match next {
+                None => return Some(current),
+                Some(next) => current = next,
+            }
+        }
+        None
+    }
+
+    fn spilled_node(&self, hash: T) -> Option<Arc<Mutex<Node<T>>>> {
+        let capacity = self.capacity.as_ref()?;
+        let node = *capacity.spilled.lock().unwrap().get(&hash)?;
+        self.find_node(node)
+    }
+
+    // リソースを持っているノード。Spill したリソースは担当ノードとは限らない
+    pub fn locate_resource(&self, hash: T) -> Option<T> {
+        let node = self.spilled_node(hash).or_else(|| self.find_node(hash))?;
+        let node = node.lock().unwrap();
+        node.resource.contains_key(&hash).then_some(node.value)
+    }
+
+    fn contains_node(&self, hash: T) -> bool {
+        self.get_node_value(&self.find_node(hash)) == hash && self.head.is_some()
+    }
+
+    // ノードを追加し、実際に置かれた位置を返す
+    pub fn try_add_node(&mut self, hash: T) -> Result<T, RingError<T>> {
+        if !self.legal_range(hash) {
+            return Err(RingError::OutOfRange(hash));
+        }
+        let mut position = hash;
+        while self.contains_node(position) {
+            match self.collision_policy {
+                CollisionPolicy::Reject => return Err(RingError::Collision(hash)),
+                CollisionPolicy::NextFree => {
+                    position = if position == self.max {
+                        self.min
+                    } else {
+                        position + num_traits::One::one()
+                    };
+                    if position == hash {
+                        return Err(RingError::Full);
+                    }
+                }
+            }
+        }
+        self.insert_node(position);
+        Ok(position)
+    }
+
+    fn insert_node(&mut self, hash: T) {
         let new_node = Arc::new(Mutex::new(Node {
             value: hash,
             resource: HashMap::new(),
+            expiry: HashMap::new(),
             prev: None,
             next: None,
         }));
 
         let next_node_value: T;
-        if let Some(ref found) = self.lookup(hash).clone() {
+        if let Some(ref found) = self.find_node(hash).clone() {
             // すでにノードが存在する場合はその前に挿入する
             self.add_node_prev(found, &new_node);
             next_node_value = self.get_node_value(&Some(found.clone()));
@@ -75,42 +723,192 @@ impl<
             head_mut.prev = Some(Arc::clone(&new_node));
             next_node_value = hash;
         }
+        // 挿入前の lookup 結果は新しいノードを指さないので捨てる
+        self.invalidate_cache();
         info!("add node: {}, and now moving resources...", hash);
         self.move_resource(hash, next_node_value, false);
         let head_value = self.get_head_value();
         if hash < head_value {
             self.head = Some(Arc::clone(&new_node));
         }
+        self.invalidate_cache();
+    }
+
+    // 直近 capacity 件の lookup 結果をキャッシュする。ノードの追加・削除で破棄される
+    pub fn with_lookup_cache(k: u32, capacity: usize) -> Self {
+        let mut ring = Self::new(k);
+        ring.cache = Some(Mutex::new(LookupCache {
+            entries: LruCache::new(capacity),
+            stats: CacheStats::default(),
+        }));
+        ring
+    }
+
+    // ノード数とノードごとのリソース数を gauge として書き出す
+    // lookup 回数・リソース移動数は metrics の counter に随時送るが、
+    // ノードごとのリソース数はこれを呼んだ時点の値になる
+    #[cfg(feature = "metrics")]
+    pub fn publish_metrics(&self) {
+        let resources = self.resources();
+        metrics::gauge!("hash_ring_nodes").set(resources.len() as f64);
+        for (node, keys) in resources {
+            metrics::gauge!("hash_ring_node_resources", "node" => node.to_string())
+                .set(keys.len() as f64);
+        }
+    }
+
+    // lookup と同じだが hash_ring_lookups_total には数えない
+    fn find_node(&self, hash: T) -> Option<NodeRef<T>> {
+        let Some(cache) = &self.cache else {
+            return self.lookup_uncached(hash);
+        };
+        let mut cache = cache.lock().unwrap();
+        if let Some(node) = cache.entries.get(&hash) {
+            let node = Arc::clone(node);
+            cache.stats.hits += 1;
+            return Some(node);
+        }
+        cache.stats.misses += 1;
+        let found = self.lookup_uncached(hash);
+        if let Some(node) = &found {
+            cache.entries.insert(hash, Arc::clone(node));
+        }
+        found
+    }
+
+    // 移動元と移動先のノード。どちらかがリングになければ panic する
+    fn migration_nodes(&self, dest: T, src: T) -> (NodeRef<T>, NodeRef<T>) {
+        let dest_node = self.find_node(dest);
+        let src_node = self.find_node(src);
+        if src != self.get_node_value(&src_node) {
+            panic!("src {} is not found", src);
+        }
+        if dest != self.get_node_value(&dest_node) {
+            panic!("dest {} is not found", dest);
+        }
+        (dest_node.unwrap(), src_node.unwrap())
+    }
+
+    // src から dest へ移すべきリソースのキーを昇順で返す
+    fn migration_candidates(
+        &self,
+        src_node: &NodeRef<T>,
+        dest: T,
+        src: T,
+        is_delete: bool,
+    ) -> Vec<T> {
+        let src_node = src_node.try_lock().unwrap();
+        assert!(src == *src_node.value());
+        let mut keys: Vec<T> = src_node
+            .resource
+            .keys()
+            .copied()
+            .filter(|&key| {
+                let (to_dest, to_src) = (self.distance(key, dest), self.distance(key, src));
+                if to_dest < to_src || is_delete {
+                    info!(
+                        "{} will move because distance dest {}: {}, distance src {}: {}",
+                        key, dest, to_dest, src, to_src
+                    );
+                    return true;
+                }
+                false
+            })
+            .collect();
+        // HashMap の走査順は不定なので、キー順に並べる
+        keys.sort();
+        keys
+    }
+
+    // src から dest へ limit 件ずつリソースを移すイテレータを返す。移動対象はここで
+    // 一度だけ集めて並べ、next を呼ぶたびに次の limit 件を移して、移したキーを返す。
+    // 合間に lookup などをしても構わない
+    pub fn move_resource_batches(
+        &self,
+        dest: T,
+        src: T,
+        is_delete: bool,
+        limit: usize,
+    ) -> MigrationBatches<'_, T> {
+        assert!(limit > 0, "batch limit must be positive");
+        if dest == src {
+            // 自分自身への移動は何も変わらず、is_delete だと終わらなくなる
+            return MigrationBatches {
+                ring: self,
+                nodes: None,
+                dest,
+                keys: Vec::new().into_iter(),
+                limit,
+            };
+        }
+        let (dest_node, src_node) = self.migration_nodes(dest, src);
+        let keys = self.migration_candidates(&src_node, dest, src, is_delete);
+        MigrationBatches {
+            ring: self,
+            nodes: Some((dest_node, src_node)),
+            dest,
+            keys: keys.into_iter(),
+            limit,
+        }
+    }
+
+    // keys のリソースを TTL ごと src_node から dest_node へ移し、実際に移したキーを返す
+    fn transfer(
+        &self,
+        dest_node: &NodeRef<T>,
+        src_node: &NodeRef<T>,
+        dest: T,
+        keys: &[T],
+    ) -> Vec<T> {
+        let mut resources = Vec::with_capacity(keys.len());
+        let mut expiries = Vec::new();
+        {
+            let mut src_node = src_node.try_lock().unwrap();
+            for key in keys {
+                if let Some(value) = src_node.resource.remove(key) {
+                    resources.push((*key, value));
+                }
+                if let Some(expires_at) = src_node.expiry.remove(key) {
+                    expiries.push((*key, expires_at));
+                }
+            }
+        }
+        self.count("hash_ring_migrated_resources_total", resources.len() as u64);
+        if let Some(capacity) = &self.capacity {
+            let mut spilled = capacity.spilled.lock().unwrap();
+            for key in keys {
+                if let Some(node) = spilled.get_mut(key) {
+                    *node = dest;
+                }
+            }
+        }
+        let moved = resources.iter().map(|(key, _)| *key).collect();
+        let mut dest_node = dest_node.try_lock().unwrap();
+        assert!(dest == *dest_node.value());
+        dest_node.resource.extend(resources);
+        dest_node.expiry.extend(expiries);
+        moved
     }
 
-    fn remove_node(&mut self, hash: T) {
-        let node_ref = self.lookup(hash);
-        let node_value = self.get_node_value(&node_ref);
-        let next_value = self.get_next_value(&node_ref);
-        if node_value != hash {
-            warn!("node {} is not found, skip removing", hash);
-            return;
-        }
-        info!(
-            "remove node: {}, and now moving resources to {}...",
-            node_value, next_value
-        );
-        self.move_resource(next_value, node_value, true);
+    #[cfg(feature = "metrics")]
+    fn count(&self, name: &'static str, value: u64) {
+        metrics::counter!(name).increment(value);
+    }
 
-        let head_value = self.get_head_value();
-        let head_next_value = self.get_next_value(&self.head.clone());
-        if let Some(node) = &node_ref {
-            self.remove_node_inner(node.clone());
-        }
-        if head_value == head_next_value {
-            self.head = self.get_next_node_ref(&node_ref);
-            if head_value == hash {
-                self.head = None;
-            }
+    #[cfg(not(feature = "metrics"))]
+    fn count(&self, _name: &'static str, _value: u64) {}
+
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.cache.as_ref().map(|cache| cache.lock().unwrap().stats)
+    }
+
+    fn invalidate_cache(&self) {
+        if let Some(cache) = &self.cache {
+            cache.lock().unwrap().entries.clear();
         }
     }
 
-    fn lookup(&self, hash: T) -> Option<Arc<Mutex<Node<T>>>> {
+    fn lookup_uncached(&self, hash: T) -> Option<Arc<Mutex<Node<T>>>> {
         let mut current = self.head.clone();
         let mut current_value: T = self.get_node_value(&current);
         let mut next_node_ref = self.get_next_node_ref(&current);
@@ -140,89 +938,6 @@ impl<
         next_node_ref
     }
 
-    fn move_resource(&self, dest: T, src: T, is_delete: bool) {
-        let mut resources: Vec<(T, T)> = Vec::new();
-        let dest_node = self.lookup(dest);
-        let src_node = self.lookup(src);
-        let dest_value = self.get_node_value(&dest_node);
-
-        let src_value = self.get_node_value(&src_node);
-        if src != src_value {
-            panic!("src {} is not found", src);
-        }
-        if dest != dest_value {
-            panic!("dest {} is not found", dest);
-        }
-
-        if let Some(src_node_ref) = src_node {
-            let mut _src_node = src_node_ref.try_lock().unwrap();
-            assert!(src == *_src_node.value());
-            for (key, value) in _src_node.resource.iter() {
-                if self.distance(*key, dest) < self.distance(*key, src) || is_delete {
-                    info!(
-                        "{} will move because distance dest {}: {}, distance src {}: {}",
-                        *key,
-                        dest,
-                        self.distance(*key, dest),
-                        src,
-                        self.distance(*key, src)
-                    );
-                    resources.push((*key, *value));
-                }
-            }
-            for (key, _) in &resources {
-                _src_node.resource.remove(key);
-            }
-        }
-
-        if let Some(dest_node_ref) = dest_node {
-            let mut dest_node = dest_node_ref.try_lock().unwrap();
-            assert!(dest == *dest_node.value());
-            for (key, value) in resources {
-                dest_node.resource.insert(key, value);
-            }
-        }
-    }
-
-    fn add_resource(&self, hash: T) {
-        if !self.legal_range(hash) {
-            panic!("hash {} is out of range", hash);
-        }
-        let node_ref = self.lookup(hash);
-        if let Some(node) = node_ref {
-            let mut node = node.try_lock().unwrap();
-            node.resource.insert(hash, hash);
-
-            info!("add resource {} to node {}", hash, node.value);
-        } else {
-            panic!("node is not found");
-        }
-    }
-}
-
-impl<
-        T: std::fmt::Debug
-            + std::fmt::Display
-            + PartialOrd
-            + PartialEq
-            + Copy
-            + std::hash::Hash
-            + num_traits::Zero
-            + num_traits::FromPrimitive
-            + num_traits::One
-            + num_traits::NumOps
-            + num_traits::PrimInt,
-    > HashRing<T>
-{
-    pub fn new(k: u32) -> Self {
-        Self {
-            head: None,
-            k,
-            min: num_traits::Zero::zero(),
-            max: num_traits::FromPrimitive::from_i64((1 << k) - 1).unwrap(),
-        }
-    }
-
     fn add_node_prev(&mut self, target: &Arc<Mutex<Node<T>>>, new_node: &Arc<Mutex<Node<T>>>) {
         let prev_node_ref = {
             let mut node = target.try_lock().unwrap();
@@ -297,6 +1012,77 @@ impl<
             node.next = None;
         }
     }
+    // now + ttl を過ぎると expire で削除されるリソースを追加する
+    pub fn add_resource_with_ttl(&self, hash: T, now: u64, ttl: u64) {
+        self.add_resource(hash);
+        let Some(node) = self.locate_resource(hash) else {
+            // Evictor が新しいリソースを受け入れなかった
+            return;
+        };
+        let node = self.find_node(node).expect("node is not found");
+        let mut node = node.try_lock().unwrap();
+        node.expiry.insert(hash, now.saturating_add(ttl));
+    }
+
+    // リソースを削除し、存在したかどうかを返す
+    pub fn remove_resource(&self, hash: T) -> bool {
+        let spilled = self.spilled_node(hash);
+        if spilled.is_some() {
+            if let Some(capacity) = &self.capacity {
+                capacity.spilled.lock().unwrap().remove(&hash);
+            }
+        }
+        let Some(node) = spilled.or_else(|| self.find_node(hash)) else {
+            return false;
+        };
+        let mut node = node.try_lock().unwrap();
+        node.expiry.remove(&hash);
+        let removed = node.resource.remove(&hash).is_some();
+        if removed {
+            info!("remove resource {} from node {}", hash, node.value);
+        }
+        removed
+    }
+
+    // 期限が now 以前のリソースを削除し、ノードごとの削除数を返す
+    pub fn expire(&self, now: u64) -> HashMap<T, usize> {
+        let mut expired = HashMap::new();
+        let Some(head) = self.head.clone() else {
+            return expired;
+        };
+        let mut current = head.clone();
+        loop {
+            let next = {
+                let mut node = current.try_lock().unwrap();
+                let keys: Vec<T> = node
+                    .expiry
+                    .iter()
+                    .filter(|(_, &expires_at)| expires_at <= now)
+                    .map(|(key, _)| *key)
+                    .collect();
+                for key in &keys {
+                    node.expiry.remove(key);
+                    node.resource.remove(key);
+                }
+                if let Some(capacity) = &self.capacity {
+                    let mut spilled = capacity.spilled.lock().unwrap();
+                    for key in &keys {
+                        spilled.remove(key);
+                    }
+                }
+                if !keys.is_empty() {
+                    info!("expired {} resources on node {}", keys.len(), node.value);
+                    expired.insert(node.value, keys.len());
+                }
+                node.next.clone().expect("ring node must have a next node")
+            };
+            if Arc::ptr_eq(&next, &head) {
+                return expired;
+            }
+            current = next;
+        }
+    }
+
     pub fn remove_all(&mut self) {
         let mut head = self.head.clone();
         let head_value = self.get_head_value();
@@ -309,25 +1095,106 @@ impl<
             head = node.next.clone();
         }
         self.head = None;
+        if let Some(capacity) = &self.capacity {
+            capacity.spilled.lock().unwrap().clear();
+        }
+        self.invalidate_cache();
     }
-    pub fn print(&self) {
-        let nodes = self.nodes();
-        println!("min: {}, max: {}", self.min, self.max);
-        println!("{:?}", nodes);
-        let head_value = {
-            if let Some(head_node) = self.head.clone() {
-                *head_node.try_lock().unwrap().value()
-            } else {
-                num_traits::Zero::zero()
-            }
+
+    // ビット幅を new_k に変えたリングを作り直す (2^5 から 2^20 への移行など)。
+    // ノードとリソースの位置は、旧位置を big-endian の 8 バイトにして placement で
+    // new_k ビットに写す。Identity なら k を広げても位置は変わらない。
+    // ノードは旧位置の昇順に追加するので、衝突の扱いはこのリングの設定に従う。
+    // リソースは新しい担当ノードに置き直し (容量上限は見ない)、TTL も引き継ぐ。
+    // 写した先が new_k ビットに収まらない位置 (Identity で k を狭めたときなど) は
+    // OutOfRange で返し、self は変えない
+    pub fn rehash_into<P: Placement + ?Sized>(
+        &self,
+        new_k: u32,
+        placement: &P,
+    ) -> Result<Rehash<T>, RingError<T>> {
+        assert!(
+            new_k > 0
+                && new_k <= 62
+                && <T as num_traits::FromPrimitive>::from_i64(1 << new_k).is_some(),
+            "2^{} does not fit the hash type",
+            new_k
+        );
+        let remap = |position: T| -> Result<T, RingError<T>> {
+            let bytes = position.to_u64().unwrap().to_be_bytes();
+            placement
+                .try_place(&bytes, new_k)
+                .map(|placed| num_traits::FromPrimitive::from_u64(placed).unwrap())
+                .ok_or(RingError::OutOfRange(position))
         };
-        println!("head: {:?}", head_value);
-        for (key, vec) in self.resources().iter() {
-            println!("node: {}, value: {:?}", key, vec);
+
+        let mut ring = Self::new(new_k).with_collision_policy(self.collision_policy);
+        ring.placement = Arc::clone(&self.placement);
+        if let Some(cache) = &self.cache {
+            let capacity = cache.lock().unwrap().entries.capacity();
+            ring.cache = Some(Mutex::new(LookupCache {
+                entries: LruCache::new(capacity),
+                stats: CacheStats::default(),
+            }));
+        }
+        if let Some(capacity) = &self.capacity {
+            ring = ring.with_capacity_limit(capacity.limit, capacity.policy.clone());
+        }
+
+        let mut nodes = HashMap::new();
+        for node in self.nodes() {
+            nodes.insert(node, ring.try_add_node(remap(node)?)?);
+        }
+
+        // 写した先が重なったリソースは一つとして数える
+        let mut moved = HashSet::new();
+        for &value in nodes.keys() {
+            let node_ref = self.lookup_uncached(value).expect("node is not found");
+            let node = node_ref.lock().unwrap();
+            for &resource in node.resource.keys() {
+                let position = remap(resource)?;
+                let owner = ring.find_node(position).expect("node is not found");
+                let mut owner = owner.lock().unwrap();
+                owner.resource.insert(position, position);
+                match node.expiry.get(&resource) {
+                    Some(&deadline) => owner.expiry.insert(position, deadline),
+                    None => owner.expiry.remove(&position),
+                };
+                if owner.value != nodes[&node.value] {
+                    moved.insert(position);
+                }
+            }
+        }
+        let moved = moved.len();
+        let resources = ring.summary().resources;
+        info!(
+            "rehash from k = {} to {}: {} of {} resources moved",
+            self.k, new_k, moved, resources
+        );
+        Ok(Rehash {
+            ring,
+            nodes,
+            resources,
+            moved,
+        })
+    }
+
+    pub fn summary(&self) -> RingSummary<T> {
+        let resources = self.resources();
+        let busiest = resources
+            .iter()
+            .map(|(node, keys)| (*node, keys.len()))
+            // 同数のときは小さいノードを選んで結果を決定的にする
+            .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0)));
+        RingSummary {
+            k: self.k,
+            nodes: resources.len(),
+            resources: resources.values().map(Vec::len).sum(),
+            busiest,
         }
     }
 
-    fn resources(&self) -> HashMap<T, Vec<(T, T)>> {
+    pub fn resources(&self) -> HashMap<T, Vec<(T, T)>> {
         let mut head = self.head.clone();
         let mut resources: HashMap<T, Vec<(T, T)>> = HashMap::new();
         let head_value: T = {
@@ -362,6 +1229,29 @@ impl<
         resources
     }
 
+    // 全ノードを位置の昇順に返す。順序は追加の順番によらない
+    pub fn iter(&self) -> RingCursor<T> {
+        RingCursor {
+            next: self.head.clone(),
+        }
+    }
+
+    // position 以上のノードを位置の昇順に返す。一周はしないので、最後に
+    // 受け取ったノードの位置 + 1 から呼び直せば、次の tick で走査を再開できる
+    pub fn iter_from(&self, position: T) -> RingCursor<T> {
+        let start = self
+            .lookup_uncached(position)
+            .filter(|node| *node.lock().unwrap().value() >= position);
+        RingCursor { next: start }
+    }
+
+    pub fn snapshot(&self) -> RingSnapshot<T> {
+        // nodes() は head (最小のノード) から辿るので昇順になっている
+        RingSnapshot {
+            nodes: self.nodes().into(),
+        }
+    }
+
     fn nodes(&self) -> Vec<T> {
         let mut head = self.head.clone();
         let mut nodes = Vec::new();
@@ -392,21 +1282,301 @@ impl<
     }
 
     fn distance(&self, a: T, b: T) -> T {
-        match a.cmp(&b) {
-            std::cmp::Ordering::Equal => num_traits::Zero::zero(),
-            std::cmp::Ordering::Less => b - a,
-            std::cmp::Ordering::Greater => {
-                let x: T = num_traits::FromPrimitive::from_i64(2).unwrap();
-                x.pow(self.k) + (b - a)
+        ring_distance(a, b, self.k)
+    }
+}
+
+// HashRing のオプションをまとめて受け取り、build でまとめて検証する。
+// k は必須で、最大の位置 2^k - 1 が T に収まらなければエラーにする
+pub struct HashRingBuilder<T> {
+    k: Option<u32>,
+    collision_policy: CollisionPolicy,
+    cache_capacity: Option<usize>,
+    placement: Option<Arc<dyn Placement>>,
+    capacity_limit: Option<(usize, OverflowPolicy<T>)>,
+    ring: PhantomData<T>,
+}
+
+impl<
+        T: std::fmt::Debug
+            + std::fmt::Display
+            + PartialOrd
+            + PartialEq
+            + Copy
+            + std::hash::Hash
+            + num_traits::Zero
+            + num_traits::FromPrimitive
+            + num_traits::One
+            + num_traits::NumOps
+            + num_traits::PrimInt,
+    > HashRingBuilder<T>
+{
+    pub fn k(mut self, k: u32) -> Self {
+        self.k = Some(k);
+        self
+    }
+
+    pub fn collision_policy(mut self, policy: CollisionPolicy) -> Self {
+        self.collision_policy = policy;
+        self
+    }
+
+    // with_lookup_cache と同じく直近 capacity 件の lookup 結果をキャッシュする
+    pub fn lookup_cache(mut self, capacity: usize) -> Self {
+        self.cache_capacity = Some(capacity);
+        self
+    }
+
+    pub fn placement<P: Placement + 'static>(mut self, placement: P) -> Self {
+        self.placement = Some(Arc::new(placement));
+        self
+    }
+
+    // with_capacity_limit と同じくノードごとのリソース数を limit 件までにする
+    pub fn capacity_limit(mut self, limit: usize, policy: OverflowPolicy<T>) -> Self {
+        self.capacity_limit = Some((limit, policy));
+        self
+    }
+
+    pub fn build(self) -> Result<HashRing<T>, BuildError> {
+        let k = self.k.ok_or(BuildError::Missing("k"))?;
+        // new は最大の位置 2^k - 1 を i64 経由で T に変換する
+        if k == 0 || k > 62 || <T as num_traits::FromPrimitive>::from_i64(1 << k).is_none() {
+            return Err(BuildError::Invalid {
+                option: "k",
+                reason: "must be positive and 2^k must fit the hash type",
+            });
+        }
+        let mut ring = match self.cache_capacity {
+            Some(0) => {
+                return Err(BuildError::Invalid {
+                    option: "lookup_cache",
+                    reason: "capacity must be positive",
+                })
+            }
+            Some(capacity) => HashRing::with_lookup_cache(k, capacity),
+            None => HashRing::new(k),
+        };
+        ring = ring.with_collision_policy(self.collision_policy);
+        if let Some(placement) = self.placement {
+            ring.placement = placement;
+        }
+        if let Some((limit, policy)) = self.capacity_limit {
+            if limit == 0 {
+                return Err(BuildError::Invalid {
+                    option: "capacity_limit",
+                    reason: "limit must be positive",
+                });
             }
+            ring = ring.with_capacity_limit(limit, policy);
+        }
+        Ok(ring)
+    }
+}
+
+// 各ノードを add_node で追加する。衝突すれば add_node と同じく panic する
+impl<
+        T: std::fmt::Debug
+            + std::fmt::Display
+            + PartialOrd
+            + PartialEq
+            + Copy
+            + std::hash::Hash
+            + num_traits::Zero
+            + num_traits::FromPrimitive
+            + num_traits::One
+            + num_traits::NumOps
+            + num_traits::PrimInt,
+    > Extend<T> for HashRing<T>
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, nodes: I) {
+        for node in nodes {
+            self.add_node(node);
+        }
+    }
+}
+
+// k は T で 2^k を扱える最大の値 (i64 なら 62) にする
+impl<
+        T: std::fmt::Debug
+            + std::fmt::Display
+            + PartialOrd
+            + PartialEq
+            + Copy
+            + std::hash::Hash
+            + num_traits::Zero
+            + num_traits::FromPrimitive
+            + num_traits::One
+            + num_traits::NumOps
+            + num_traits::PrimInt,
+    > FromIterator<T> for HashRing<T>
+{
+    fn from_iter<I: IntoIterator<Item = T>>(nodes: I) -> Self {
+        let k = (1..=62)
+            .rev()
+            .find(|k| <T as num_traits::FromPrimitive>::from_i64(1 << k).is_some())
+            .unwrap();
+        let mut ring = HashRing::new(k);
+        ring.extend(nodes);
+        ring
+    }
+}
+
+// 範囲・ノード一覧・head と、ノードごとのリソースをノード順に出力する
+impl<
+        T: std::fmt::Debug
+            + std::fmt::Display
+            + PartialOrd
+            + PartialEq
+            + Copy
+            + std::hash::Hash
+            + num_traits::Zero
+            + num_traits::FromPrimitive
+            + num_traits::One
+            + num_traits::NumOps
+            + num_traits::PrimInt,
+    > std::fmt::Display for HashRing<T>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "min: {}, max: {}", self.min, self.max)?;
+        writeln!(f, "{:?}", self.nodes())?;
+        write!(f, "head: {:?}", self.get_head_value())?;
+        let mut resources: Vec<_> = self.resources().into_iter().collect();
+        resources.sort_by_key(|(node, _)| *node);
+        for (node, keys) in resources {
+            write!(f, "\nnode: {}, value: {:?}", node, keys)?;
+        }
+        Ok(())
+    }
+}
+
+// ノードは prev/next で循環参照しているので、derive せずに要約だけを出す
+impl<
+        T: std::fmt::Debug
+            + std::fmt::Display
+            + PartialOrd
+            + PartialEq
+            + Copy
+            + std::hash::Hash
+            + num_traits::Zero
+            + num_traits::FromPrimitive
+            + num_traits::One
+            + num_traits::NumOps
+            + num_traits::PrimInt,
+    > std::fmt::Debug for HashRing<T>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let summary = self.summary();
+        f.debug_struct("HashRing")
+            .field("k", &self.k)
+            .field("nodes", &self.nodes())
+            .field("resources", &summary.resources)
+            .field("collision_policy", &self.collision_policy)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hasher::XxHash64;
+    use crate::log;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn builder_applies_options() {
+        log::init_test_logger();
+        let mut h = HashRing::<i32>::builder()
+            .k(4)
+            .collision_policy(CollisionPolicy::NextFree)
+            .lookup_cache(8)
+            .build()
+            .unwrap();
+        assert_eq!(h.max, 15);
+        h.add_node(3);
+        assert_eq!(h.try_add_node(3), Ok(4));
+        h.lookup(10);
+        assert!(h.cache_stats().is_some());
+    }
+
+    #[test]
+    fn placement_maps_keys_to_positions() {
+        log::init_test_logger();
+        let mut h = HashRing::<i64>::new(8).with_placement(Identity);
+        h.add_node(100);
+        h.add_node(200);
+        assert_eq!(h.add_key(&[150]), 150);
+        assert_eq!(h.lookup_key(&[150]), Some(200));
+        assert_eq!(h.lookup_key(&[250]), Some(100));
+        assert_eq!(h.resources()[&200], vec![(150, 150)]);
+
+        let murmur = HashRing::<i64>::new(16);
+        let xxhash = HashRing::<i64>::builder()
+            .k(16)
+            .placement(Hashed(XxHash64::default()))
+            .build()
+            .unwrap();
+        assert_eq!(
+            murmur.key_position(b"key"),
+            (Murmur3::default().hash64(b"key") & 0xffff) as i64
+        );
+        assert_eq!(
+            xxhash.key_position(b"key"),
+            (XxHash64::default().hash64(b"key") & 0xffff) as i64
+        );
+
+        let custom = HashRing::<i64>::new(4).with_placement(|key: &[u8]| key.len() as u64 + 16);
+        assert_eq!(custom.key_position(b"abc"), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "outside the ring")]
+    fn identity_placement_rejects_out_of_range_keys() {
+        log::init_test_logger();
+        HashRing::<i64>::new(8)
+            .with_placement(Identity)
+            .key_position(&[1, 0]);
+    }
+
+    #[test]
+    fn builder_rejects_bad_options() {
+        log::init_test_logger();
+        assert_eq!(
+            HashRing::<i32>::builder().build().err().unwrap(),
+            BuildError::Missing("k")
+        );
+        for k in [0, 7] {
+            assert!(matches!(
+                HashRing::<i8>::builder().k(k).build().err().unwrap(),
+                BuildError::Invalid { option: "k", .. }
+            ));
         }
+        assert!(matches!(
+            HashRing::<i32>::builder()
+                .k(5)
+                .lookup_cache(0)
+                .build()
+                .err()
+                .unwrap(),
+            BuildError::Invalid {
+                option: "lookup_cache",
+                ..
+            }
+        ));
+        assert!(matches!(
+            HashRing::<i64>::builder()
+                .k(5)
+                .capacity_limit(0, OverflowPolicy::Spill)
+                .build()
+                .err()
+                .unwrap(),
+            BuildError::Invalid {
+                option: "capacity_limit",
+                ..
+            }
+        ));
     }
-}
-
-#[cfg(test)]
-mod test {
-    use super::*;
-    use crate::log;
 
     #[test]
     fn distance_ring_5() {
@@ -424,6 +1594,43 @@ mod test {
         assert_eq!(h.distance(18, 24), 6);
     }
 
+    #[test]
+    fn ring_distance_wraps_without_overflow() {
+        // k がビット幅いっぱいのとき 2^k は T に収まらない
+        assert_eq!(ring_distance(250u8, 4u8, 8), 10);
+        assert_eq!(ring_distance(255u8, 0u8, 8), 1);
+        assert_eq!(ring_distance(1u8, 0u8, 8), 255);
+        assert_eq!(ring_distance(u64::MAX, 0, 64), 1);
+        assert_eq!(ring_distance(0, u64::MAX, 64), u64::MAX);
+        assert_eq!(ring_distance(i32::MAX, 0, 31), 1);
+        assert_eq!(ring_distance(7i32, 3i32, 3), 4);
+        assert_eq!(ring_distance(9u16, 9u16, 4), 0);
+        for k in 1..=8u32 {
+            let size = 1u32 << k;
+            for a in 0..size {
+                for b in 0..size {
+                    let expected = ((b + size - a) % size) as u8;
+                    assert_eq!(ring_distance(a as u8, b as u8, k), expected);
+                    assert_eq!(ring_distance(a as u64, b as u64, k), expected as u64);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ring_distance_there_and_back_is_the_ring_size() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for k in [5, 32, 62] {
+            for _ in 0..1000 {
+                let a = rng.random_range(0..1u64 << k);
+                let b = rng.random_range(0..1u64 << k);
+                if a != b {
+                    assert_eq!(ring_distance(a, b, k) + ring_distance(b, a, k), 1 << k);
+                }
+            }
+        }
+    }
+
     #[test]
     fn hash_ring_add_node_lookup() {
         log::init_test_logger();
@@ -438,13 +1645,13 @@ mod test {
         log::init_test_logger();
         let mut h = HashRing::new(5);
         h.add_node(5);
-        h.print();
+        info!("{}", h);
         h.add_node(12);
-        h.print();
+        info!("{}", h);
         h.add_node(18);
-        h.print();
+        info!("{}", h);
         h.add_node(29);
-        h.print();
+        info!("{}", h);
         let lookup_5 = h.lookup(5);
         assert!(lookup_5.is_some());
         if let Some(node) = lookup_5 {
@@ -472,7 +1679,7 @@ mod test {
         h.add_resource(28);
         h.add_resource(7);
         h.add_resource(10);
-        h.print();
+        info!("{}", h);
         assert_eq!(h.resources().len(), 2);
         assert_eq!(h.resources().get(&12).unwrap().len(), 8);
         assert_eq!(h.resources().get(&18).unwrap().len(), 1);
@@ -493,13 +1700,99 @@ mod test {
         remove_all();
     }
 
+    #[test]
+    fn capacity_limit_rejects_overloaded_nodes() {
+        let mut h = HashRing::new(5).with_capacity_limit(2, OverflowPolicy::Reject);
+        h.add_node(12);
+        h.add_node(18);
+        assert_eq!(
+            h.try_add_resource(13),
+            Ok(Admission {
+                node: 18,
+                evicted: None
+            })
+        );
+        h.add_resource(14);
+        assert_eq!(h.try_add_resource(15), Err(RingError::Overloaded(18)));
+        // すでにあるリソースは数えない
+        assert!(h.try_add_resource(14).is_ok());
+        assert!(h.try_add_resource(20).is_ok());
+        assert_eq!(h.locate_resource(15), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "node 18 is at capacity")]
+    fn add_resource_panics_on_overloaded_node() {
+        let mut h = HashRing::new(5).with_capacity_limit(1, OverflowPolicy::Reject);
+        h.add_node(18);
+        h.add_resource(13);
+        h.add_resource(14);
+    }
+
+    #[test]
+    fn capacity_limit_spills_to_successor() {
+        let mut h = HashRing::new(5).with_capacity_limit(2, OverflowPolicy::Spill);
+        h.add_node(12);
+        h.add_node(18);
+        h.add_resource(13);
+        h.add_resource(14);
+        assert_eq!(h.try_add_resource(15).unwrap().node, 12);
+        assert_eq!(h.locate_resource(15), Some(12));
+        assert_eq!(h.try_add_resource(15).unwrap().node, 12);
+        h.add_resource(2);
+        assert_eq!(h.try_add_resource(3), Err(RingError::Overloaded(12)));
+
+        // 移動しても Spill したリソースを見失わない
+        h.add_node(16);
+        assert_eq!(h.locate_resource(15), Some(12));
+        h.remove_node(12);
+        assert_eq!(h.locate_resource(15), Some(16));
+        assert!(h.remove_resource(15));
+        assert_eq!(h.locate_resource(15), None);
+        assert!(!h.remove_resource(15));
+    }
+
+    #[test]
+    fn capacity_limit_evicts_by_policy() {
+        let mut h = HashRing::new(5).with_capacity_limit(3, OverflowPolicy::evict(EvictFarthest));
+        h.add_node(12);
+        h.add_node(18);
+        for key in [2, 7, 30] {
+            h.add_resource(key);
+        }
+        // 0 をまたいだ 30 が 12 から一番遠い
+        assert_eq!(h.try_add_resource(10).unwrap().evicted, Some(30));
+        assert_eq!(h.locate_resource(30), None);
+        assert_eq!(h.try_add_resource(11).unwrap().evicted, Some(2));
+        assert_eq!(
+            h.resources().get(&12),
+            Some(&vec![(7, 7), (10, 10), (11, 11)])
+        );
+
+        let mut h = HashRing::new(5).with_capacity_limit(
+            1,
+            OverflowPolicy::evict(|_node: i32, _resources: &[i32], incoming: i32| incoming),
+        );
+        h.add_node(18);
+        h.add_resource(13);
+        assert_eq!(
+            h.try_add_resource(14),
+            Ok(Admission {
+                node: 18,
+                evicted: Some(14)
+            })
+        );
+        h.add_resource_with_ttl(15, 0, 10);
+        assert_eq!(h.resources().get(&18), Some(&vec![(13, 13)]));
+    }
+
     #[test]
     fn move_resource() {
         log::init_test_logger();
         let mut h = HashRing::new(5);
         h.add_node(12);
         h.add_node(18);
-        h.print();
+        info!("{}", h);
         h.add_resource(24);
         h.add_resource(21);
         h.add_resource(16);
@@ -509,9 +1802,9 @@ mod test {
         h.add_resource(28);
         h.add_resource(7);
         h.add_resource(10);
-        h.print();
-        h.move_resource(12, 18, true);
-        h.print();
+        info!("{}", h);
+        assert_eq!(h.move_resource(12, 18, true), vec![16]);
+        info!("{}", h);
         assert_eq!(h.resources().get(&18).unwrap().len(), 0);
         assert_eq!(h.resources().get(&12).unwrap().len(), 9);
         assert_eq!(h.resources().get(&18), Some(&vec![]));
@@ -532,6 +1825,82 @@ mod test {
         remove_all();
     }
 
+    #[test]
+    fn move_resource_batch_respects_limit() {
+        log::init_test_logger();
+        let mut h = HashRing::new(5);
+        h.add_node(12);
+        h.add_node(18);
+        for key in [2, 7, 10, 21, 23, 24, 28, 29] {
+            h.add_resource(key);
+        }
+        assert_eq!(h.move_resource_batch(18, 12, true, 3), vec![2, 7, 10]);
+        assert_eq!(h.resources().get(&12).unwrap().len(), 5);
+        assert_eq!(h.move_resource_batch(18, 12, true, 3), vec![21, 23, 24]);
+        assert_eq!(h.move_resource_batch(18, 12, true, 3), vec![28, 29]);
+        assert!(h.move_resource_batch(18, 12, true, 3).is_empty());
+        assert_eq!(h.resources().get(&18).unwrap().len(), 8);
+        // 自分自身への移動は何もしない
+        assert!(h.move_resource_batch(18, 18, true, 3).is_empty());
+        assert_eq!(h.resources().get(&18).unwrap().len(), 8);
+        remove_all();
+    }
+
+    #[test]
+    fn move_resource_batches_drains_source_once() {
+        log::init_test_logger();
+        let mut h = HashRing::new(5);
+        h.add_node(12);
+        h.add_node(18);
+        for key in [2, 7, 10, 21, 23, 24, 28, 29] {
+            h.add_resource(key);
+        }
+        let mut batches = h.move_resource_batches(18, 12, true, 3);
+        assert_eq!(batches.next(), Some(vec![2, 7, 10]));
+        // 合間に読み書きしてもよい。消したリソースは飛ばす
+        assert_eq!(h.resources().get(&12).unwrap().len(), 5);
+        assert!(h.remove_resource(21));
+        assert_eq!(batches.next(), Some(vec![23, 24]));
+        assert_eq!(batches.next(), Some(vec![28, 29]));
+        assert_eq!(batches.next(), None);
+        assert_eq!(h.resources().get(&18).unwrap().len(), 7);
+        assert!(h.move_resource_batches(18, 18, true, 3).next().is_none());
+        remove_all();
+    }
+
+    #[test]
+    fn move_resource_spans_several_batches() {
+        log::init_test_logger();
+        let mut h = HashRing::new(16);
+        h.add_node(100);
+        h.add_node(40_000);
+        let keys: Vec<i64> = (0..3 * MIGRATION_BATCH_SIZE as i64)
+            .map(|i| 101 + i * 7)
+            .collect();
+        for &key in &keys {
+            h.add_resource(key);
+        }
+        assert_eq!(h.move_resource(100, 40_000, true), keys);
+        assert_eq!(h.resources().get(&40_000), Some(&vec![]));
+        assert_eq!(h.resources().get(&100).unwrap().len(), keys.len());
+        remove_all();
+    }
+
+    #[test]
+    fn remove_last_node_with_resources() {
+        log::init_test_logger();
+        let mut h = HashRing::new(5);
+        h.add_node(12);
+        for key in [2, 12, 20, 31] {
+            h.add_resource(key);
+        }
+        // 移動先が自分自身になるので、移動せずに終わる
+        assert!(h.move_resource(12, 12, true).is_empty());
+        h.remove_node(12);
+        assert!(h.nodes().is_empty());
+        assert!(h.lookup(2).is_none());
+    }
+
     #[test]
     fn add_resource_with_resource_move() {
         log::init_test_logger();
@@ -547,15 +1916,15 @@ mod test {
         h.add_resource(28);
         h.add_resource(7);
         h.add_resource(10);
-        h.print();
+        info!("{}", h);
         assert_eq!(h.resources().get(&18).unwrap().len(), 1);
         assert_eq!(h.resources().get(&12).unwrap().len(), 8);
         h.add_node(5);
-        h.print();
+        info!("{}", h);
         h.add_node(27);
-        h.print();
+        info!("{}", h);
         h.add_node(30);
-        h.print();
+        info!("{}", h);
         assert_eq!(h.resources().get(&5).unwrap().len(), 1);
         assert_eq!(h.resources().get(&12).unwrap().len(), 2);
         assert_eq!(h.resources().get(&18).unwrap().len(), 1);
@@ -572,7 +1941,7 @@ mod test {
         assert_eq!(h.resources().get(&18), Some(&vec![(16, 16)]));
 
         h.remove_node(12);
-        h.print();
+        info!("{}", h);
         assert_eq!(h.resources().get(&5).unwrap().len(), 1);
         assert_eq!(h.resources().get(&18).unwrap().len(), 3);
         assert_eq!(h.resources().get(&27).unwrap().len(), 3);
@@ -590,6 +1959,492 @@ mod test {
         remove_all();
     }
 
+    #[test]
+    fn remove_head_node_keeps_ring_usable() {
+        log::init_test_logger();
+        let mut h = HashRing::new(5);
+        h.add_node(5);
+        h.add_node(12);
+        h.add_node(18);
+        h.add_resource(3);
+        h.add_resource(10);
+        h.remove_node(5);
+        assert_eq!(h.nodes(), vec![12, 18]);
+        assert_eq!(h.resources().get(&12), Some(&vec![(3, 3), (10, 10)]));
+        // 新しい head から時計回りにたどれること
+        for hash in 0..32 {
+            let want = if (13..=18).contains(&hash) { 18 } else { 12 };
+            assert_eq!(h.get_node_value(&h.lookup(hash)), want, "hash {}", hash);
+        }
+        h.add_resource(30);
+        assert_eq!(h.resources().get(&12).unwrap().len(), 3);
+
+        // 続けて head を消しても、head より小さいノードを足しても壊れない
+        h.remove_node(12);
+        assert_eq!(h.nodes(), vec![18]);
+        assert!((0..32).all(|hash| h.get_node_value(&h.lookup(hash)) == 18));
+        h.add_node(2);
+        assert_eq!(h.nodes(), vec![2, 18]);
+        assert_eq!(h.get_node_value(&h.lookup(1)), 2);
+        assert_eq!(h.get_node_value(&h.lookup(30)), 2);
+        assert_eq!(h.get_node_value(&h.lookup(7)), 18);
+        remove_all();
+    }
+
+    #[test]
+    fn snapshot_lookup_matches_ring() {
+        log::init_test_logger();
+        let mut h = HashRing::new(5);
+        h.add_node(5);
+        h.add_node(12);
+        h.add_node(18);
+        h.add_node(29);
+        let snapshot = h.snapshot();
+        assert_eq!(snapshot.nodes(), &[5, 12, 18, 29]);
+        for hash in 0..32 {
+            let want = *h.lookup(hash).unwrap().try_lock().unwrap().value();
+            assert_eq!(snapshot.lookup(hash), Some(want), "hash {}", hash);
+        }
+        remove_all();
+    }
+
+    #[test]
+    fn iter_from_resumes_scan_across_ticks() {
+        log::init_test_logger();
+        let mut h = HashRing::new(5);
+        assert_eq!(h.iter().count(), 0);
+        assert_eq!(h.iter_from(3).count(), 0);
+        for node in [18, 5, 29, 12] {
+            h.add_node(node);
+        }
+        h.add_resource(10);
+        h.add_resource(7);
+        assert_eq!(
+            h.iter().collect::<Vec<_>>(),
+            vec![
+                (5, vec![]),
+                (12, vec![(7, 7), (10, 10)]),
+                (18, vec![]),
+                (29, vec![])
+            ]
+        );
+        assert_eq!(
+            h.iter_from(13).map(|(node, _)| node).collect::<Vec<_>>(),
+            vec![18, 29]
+        );
+        assert_eq!(h.iter_from(30).count(), 0);
+
+        // 2 ノードずつ走査し、合間にノードを増減させる
+        let mut seen = Vec::new();
+        let mut cursor = 0;
+        let mut tick = 0;
+        loop {
+            let batch: Vec<i64> = h.iter_from(cursor).take(2).map(|(node, _)| node).collect();
+            let Some(&last) = batch.last() else { break };
+            seen.extend(batch);
+            cursor = last + 1;
+            match tick {
+                0 => h.add_node(20),
+                1 => h.remove_node(5),
+                _ => {}
+            }
+            tick += 1;
+        }
+        assert_eq!(seen, vec![5, 12, 18, 20, 29]);
+        remove_all();
+    }
+
+    #[test]
+    fn collect_and_extend_nodes() {
+        log::init_test_logger();
+        let mut h: HashRing<i64> = [1 << 40, 5, 12].into_iter().collect();
+        assert_eq!(h.k, 62);
+        h.extend([18]);
+        assert_eq!(h.snapshot().nodes(), &[5, 12, 18, 1 << 40]);
+        let small: HashRing<i16> = [3].into_iter().collect();
+        assert_eq!(small.k, 14);
+        remove_all();
+    }
+
+    #[test]
+    fn snapshot_is_unaffected_by_later_writes() {
+        log::init_test_logger();
+        let mut h = HashRing::new(5);
+        h.add_node(12);
+        h.add_node(18);
+        let snapshot = h.snapshot();
+        let reader = {
+            let snapshot = snapshot.clone();
+            std::thread::spawn(move || {
+                (0..32)
+                    .map(|hash| snapshot.lookup(hash))
+                    .collect::<Vec<_>>()
+            })
+        };
+        h.add_node(5);
+        h.remove_node(18);
+        let seen = reader.join().unwrap();
+        assert!(seen
+            .iter()
+            .all(|node| *node == Some(12) || *node == Some(18)));
+        assert_eq!(snapshot.nodes(), &[12, 18]);
+        assert_eq!(h.snapshot().nodes(), &[5, 12]);
+        assert!(HashRing::<i64>::new(5).snapshot().lookup(3).is_none());
+        remove_all();
+    }
+
+    #[test]
+    fn lookup_cache_counts_hits_and_invalidates_on_membership_change() {
+        log::init_test_logger();
+        let mut h = HashRing::with_lookup_cache(5, 8);
+        assert_eq!(h.cache_stats(), Some(CacheStats::default()));
+        h.add_node(12);
+        h.add_node(18);
+        let before = h.cache_stats().unwrap();
+        let value =
+            |node: Option<Arc<Mutex<Node<i32>>>>| *node.unwrap().try_lock().unwrap().value();
+        assert_eq!(value(h.lookup(15)), 18);
+        assert_eq!(value(h.lookup(15)), 18);
+        let after = h.cache_stats().unwrap();
+        assert_eq!(after.hits - before.hits, 1);
+        assert_eq!(after.misses - before.misses, 1);
+
+        h.add_node(16);
+        assert_eq!(value(h.lookup(15)), 16);
+        h.remove_node(16);
+        assert_eq!(value(h.lookup(15)), 18);
+        h.add_resource(15);
+        assert_eq!(h.resources().get(&18), Some(&vec![(15, 15)]));
+        assert!(h.cache_stats().unwrap().hit_rate() > 0.0);
+        assert_eq!(HashRing::<i32>::new(5).cache_stats(), None);
+        remove_all();
+    }
+
+    #[test]
+    fn colliding_node_is_rejected_by_default() {
+        log::init_test_logger();
+        let mut h = HashRing::new(5);
+        assert_eq!(h.try_add_node(12), Ok(12));
+        assert_eq!(h.try_add_node(12), Err(RingError::Collision(12)));
+        assert_eq!(h.try_add_node(32), Err(RingError::OutOfRange(32)));
+        assert_eq!(h.nodes(), vec![12]);
+        remove_all();
+    }
+
+    #[test]
+    #[should_panic(expected = "node 12 already exists")]
+    fn add_node_panics_on_collision() {
+        log::init_test_logger();
+        let mut h = HashRing::new(5);
+        h.add_node(12);
+        h.add_node(12);
+    }
+
+    #[test]
+    fn next_free_policy_probes_clockwise() {
+        log::init_test_logger();
+        let mut h = HashRing::new(2).with_collision_policy(CollisionPolicy::NextFree);
+        assert_eq!(h.try_add_node(2), Ok(2));
+        assert_eq!(h.try_add_node(2), Ok(3));
+        assert_eq!(h.try_add_node(2), Ok(0));
+        assert_eq!(h.try_add_node(3), Ok(1));
+        assert_eq!(h.try_add_node(3), Err(RingError::Full));
+        assert_eq!(h.nodes(), vec![0, 1, 2, 3]);
+        remove_all();
+    }
+
+    #[test]
+    fn expire_removes_resources_past_their_ttl() {
+        log::init_test_logger();
+        let mut h = HashRing::new(5);
+        assert!(h.expire(0).is_empty());
+        h.add_node(12);
+        h.add_node(18);
+        h.add_resource_with_ttl(3, 0, 10);
+        h.add_resource_with_ttl(10, 0, 20);
+        h.add_resource_with_ttl(15, 0, 10);
+        h.add_resource(16);
+        // TTL をつけ直さずに追加し直すと期限はなくなる
+        h.add_resource_with_ttl(7, 0, 5);
+        h.add_resource(7);
+
+        let expired = h.expire(10);
+        assert_eq!(expired.get(&12), Some(&1));
+        assert_eq!(expired.get(&18), Some(&1));
+        assert_eq!(h.resources().get(&12), Some(&vec![(7, 7), (10, 10)]));
+        assert_eq!(h.resources().get(&18), Some(&vec![(16, 16)]));
+
+        // 移動したリソースも期限を引き継ぐ
+        h.add_node(11);
+        assert_eq!(h.resources().get(&11), Some(&vec![(7, 7), (10, 10)]));
+        assert_eq!(h.expire(20), HashMap::from([(11, 1)]));
+        assert_eq!(h.resources().get(&11), Some(&vec![(7, 7)]));
+        assert!(h.expire(u64::MAX).is_empty());
+        remove_all();
+    }
+
+    #[test]
+    fn rehash_into_wider_ring_with_identity_keeps_positions() {
+        log::init_test_logger();
+        let mut h = HashRing::new(5).with_collision_policy(CollisionPolicy::NextFree);
+        h.add_node(12);
+        h.add_node(18);
+        h.add_resource(3);
+        h.add_resource_with_ttl(15, 0, 10);
+        h.add_resource(30);
+
+        let rehash = h.rehash_into(20, &Identity).unwrap();
+        assert_eq!(rehash.moved, 0);
+        assert_eq!(rehash.resources, 3);
+        assert_eq!(rehash.nodes, HashMap::from([(12, 12), (18, 18)]));
+        let mut wide = rehash.ring;
+        assert_eq!(wide.summary().k, 20);
+        assert_eq!(wide.resources(), h.resources());
+        // 広がった位置にもノードを置ける
+        assert_eq!(wide.try_add_node(12), Ok(13));
+        wide.add_node(1 << 19);
+        assert_eq!(wide.lookup_key(&(1u64 << 19).to_be_bytes()), Some(1 << 19));
+        // TTL も引き継ぐ
+        assert_eq!(wide.expire(10), HashMap::from([(18, 1)]));
+    }
+
+    #[test]
+    fn rehash_into_counts_resources_that_change_owners() {
+        log::init_test_logger();
+        let mut h = HashRing::new(8);
+        for node in [10, 90, 170, 250] {
+            h.add_node(node);
+        }
+        for resource in (0..256).step_by(3) {
+            h.add_resource(resource);
+        }
+
+        let placement = Hashed(Murmur3::with_seed(7));
+        let rehash = h.rehash_into(16, &placement).unwrap();
+        let remap = |position: u64| placement.place(&position.to_be_bytes(), 16);
+        let mut moved = HashSet::new();
+        for (node, resources) in h.resources() {
+            assert_eq!(rehash.nodes[&node], remap(node));
+            for (resource, _) in resources {
+                let owner = rehash.ring.lookup(remap(resource)).unwrap();
+                if *owner.lock().unwrap().value() != remap(node) {
+                    moved.insert(remap(resource));
+                }
+            }
+        }
+        assert!(!moved.is_empty());
+        assert_eq!(rehash.moved, moved.len());
+        assert_eq!(rehash.resources, rehash.ring.summary().resources);
+    }
+
+    #[test]
+    fn rehash_into_counts_colliding_resources_once() {
+        log::init_test_logger();
+        let mut h = HashRing::new(8);
+        h.add_node(10);
+        h.add_node(200);
+        for resource in [20, 30, 40] {
+            h.add_resource(resource);
+        }
+        // ノードの位置はそのままで、リソースはすべて 5 に重なる
+        let squash = |key: &[u8]| match u64::from_be_bytes(key.try_into().unwrap()) {
+            node @ (10 | 200) => node,
+            _ => 5,
+        };
+        let rehash = h.rehash_into(8, &squash).unwrap();
+        assert_eq!(rehash.resources, 1);
+        assert_eq!(rehash.moved, 1);
+        assert_eq!(rehash.ring.resources().get(&10), Some(&vec![(5, 5)]));
+    }
+
+    #[test]
+    fn rehash_into_reports_colliding_nodes() {
+        log::init_test_logger();
+        let mut h = HashRing::new(8);
+        h.add_node(2);
+        h.add_node(3);
+        let halve = |key: &[u8]| u64::from_be_bytes(key.try_into().unwrap()) / 2;
+        assert!(matches!(
+            h.rehash_into(7, &halve),
+            Err(RingError::Collision(1))
+        ));
+    }
+
+    #[test]
+    fn rehash_into_narrower_ring_rejects_positions_outside_it() {
+        log::init_test_logger();
+        let mut h = HashRing::new(8);
+        h.add_node(10);
+        h.add_node(200);
+        assert!(matches!(
+            h.rehash_into(4, &Identity),
+            Err(RingError::OutOfRange(200))
+        ));
+
+        // ノードが収まってもリソースがはみ出せば同じ
+        let mut h = HashRing::new(8);
+        h.add_node(10);
+        h.add_resource(100);
+        assert!(matches!(
+            h.rehash_into(4, &Identity),
+            Err(RingError::OutOfRange(100))
+        ));
+        assert_eq!(h.resources().get(&10), Some(&vec![(100, 100)]));
+
+        // 全部収まれば狭めることもできる
+        h.remove_resource(100);
+        h.add_resource(3);
+        let rehash = h.rehash_into(4, &Identity).unwrap();
+        assert_eq!(rehash.ring.resources().get(&10), Some(&vec![(3, 3)]));
+    }
+
+    // モデル (ノード集合とキー集合) と照らし合わせて、リングの不変条件を確認する
+    fn check_invariants(h: &HashRing<i64>, nodes: &BTreeSet<i64>, keys: &BTreeSet<i64>) {
+        let want_nodes: Vec<i64> = nodes.iter().copied().collect();
+        assert_eq!(h.nodes(), want_nodes, "ring nodes differ from the model");
+        assert_eq!(h.snapshot().nodes(), &want_nodes[..]);
+
+        let mut owned = BTreeSet::new();
+        for (node, resources) in h.resources() {
+            assert!(nodes.contains(&node), "resources on unknown node {}", node);
+            for (key, value) in resources {
+                assert_eq!(key, value);
+                assert!(owned.insert(key), "key {} is owned by two nodes", key);
+                // 時計回りで最初のノードが持ち主
+                let want = nodes.range(key..).next().or(nodes.first()).copied();
+                assert_eq!(Some(node), want, "key {} is on the wrong node", key);
+                assert_eq!(h.get_node_value(&h.lookup(key)), node);
+            }
+        }
+        assert_eq!(&owned, keys, "keys were lost or resurrected");
+    }
+
+    #[test]
+    fn chaos_interleaved_operations_keep_invariants() {
+        log::init_test_logger();
+        for seed in 0..10u64 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            // リングを小さくして衝突・折り返し・ノード 0 個の状態を頻繁に起こす
+            let k = 6;
+            let mut h = HashRing::new(k);
+            let mut nodes = BTreeSet::new();
+            let mut keys = BTreeSet::new();
+            for _ in 0..300 {
+                let position = rng.random_range(0..1i64 << k);
+                match rng.random_range(0..4) {
+                    0 => {
+                        let added = h.try_add_node(position);
+                        if nodes.insert(position) {
+                            assert_eq!(added, Ok(position));
+                        } else {
+                            assert_eq!(added, Err(RingError::Collision(position)));
+                        }
+                    }
+                    1 => {
+                        h.remove_node(position);
+                        nodes.remove(&position);
+                        if nodes.is_empty() {
+                            // 最後のノードと一緒にリソースもなくなる
+                            keys.clear();
+                        }
+                    }
+                    2 if !nodes.is_empty() => {
+                        h.add_resource(position);
+                        keys.insert(position);
+                    }
+                    _ => {
+                        assert_eq!(h.remove_resource(position), keys.remove(&position));
+                    }
+                }
+                check_invariants(&h, &nodes, &keys);
+            }
+            h.remove_all();
+        }
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn metrics_report_lookups_migrations_and_node_resources() {
+        use crate::metrics::Registry;
+        log::init_test_logger();
+        let registry = Registry::new();
+        let lookups = || registry.counter("hash_ring_lookups_total", &[]);
+        let migrated = || registry.counter("hash_ring_migrated_resources_total", &[]);
+        let resources = |node: &str| registry.gauge("hash_ring_node_resources", &[("node", node)]);
+        let mut h = HashRing::new(5);
+        metrics::with_local_recorder(&registry, || {
+            h.add_node(12);
+            h.add_resource(3);
+            h.add_resource(10);
+            h.add_resource(20);
+            // 内部の探索は数えない
+            assert_eq!(lookups(), 0);
+            h.lookup(7);
+            h.lookup_key(b"key");
+            assert_eq!(lookups(), 2);
+
+            h.add_node(5);
+            assert_eq!(migrated(), 2);
+            h.publish_metrics();
+            assert_eq!(registry.gauge("hash_ring_nodes", &[]), Some(2.0));
+            // 20 と 3 が 5 に移り、10 だけが 12 に残る
+            assert_eq!(resources("5"), Some(2.0));
+            assert_eq!(resources("12"), Some(1.0));
+
+            h.remove_node(5);
+            assert_eq!(migrated(), 4);
+            assert_eq!(resources("5"), Some(0.0));
+            h.add_resource_with_ttl(25, 0, 10);
+            h.rehash_into(6, &Identity).unwrap();
+            assert_eq!(h.move_resource_batch(12, 12, false, 8), vec![]);
+            assert_eq!(lookups(), 2);
+        });
+        assert!(registry
+            .render()
+            .contains("hash_ring_node_resources{node=\"12\"} 1\n"));
+        h.remove_all();
+    }
+
+    #[test]
+    fn summary_and_formatting() {
+        log::init_test_logger();
+        let mut h = HashRing::new(5);
+        assert_eq!(
+            h.summary(),
+            RingSummary {
+                k: 5,
+                nodes: 0,
+                resources: 0,
+                busiest: None
+            }
+        );
+        h.add_node(12);
+        h.add_node(18);
+        h.add_resource(3);
+        h.add_resource(10);
+        h.add_resource(15);
+        assert_eq!(
+            h.summary(),
+            RingSummary {
+                k: 5,
+                nodes: 2,
+                resources: 3,
+                busiest: Some((12, 2))
+            }
+        );
+        assert_eq!(
+            h.to_string(),
+            "min: 0, max: 31\n[12, 18]\nhead: 12\n\
+             node: 12, value: [(3, 3), (10, 10)]\n\
+             node: 18, value: [(15, 15)]"
+        );
+        assert_eq!(
+            format!("{:?}", h),
+            "HashRing { k: 5, nodes: [12, 18], resources: 3, collision_policy: Reject, .. }"
+        );
+        remove_all();
+    }
+
     #[test]
     fn remove_all() {
         log::init_test_logger();
@@ -599,9 +2454,9 @@ mod test {
         h.add_node(5);
         h.add_node(27);
         h.add_node(30);
-        h.print();
+        info!("{}", h);
         h.remove_all();
-        h.print();
+        info!("{}", h);
         assert_eq!(h.nodes().len(), 0);
     }
 }
@@ -0,0 +1,487 @@
+#[cfg(any(feature = "bloom", feature = "cms"))]
+use crate::seed::mix_with_seed;
+#[cfg(any(feature = "bloom", feature = "cms", feature = "sketches"))]
+use crate::seed::SeedSequence;
+use murmurhash3::murmurhash3_x64_128 as mmh3_128;
+#[cfg(any(feature = "bloom", feature = "cms", feature = "sketches"))]
+use murmurhash3::murmurhash3_x86_32 as mmh3;
+#[cfg(any(feature = "bloom", feature = "cms", feature = "sketches"))]
+use std::any::Any;
+#[cfg(any(feature = "bloom", feature = "cms", feature = "sketches"))]
+use std::sync::Arc;
+
+/// Master seed used when a structure is not given one explicitly.
+#[cfg(any(
+    feature = "bloom",
+    feature = "cms",
+    feature = "sketches",
+    feature = "ring"
+))]
+pub(crate) const DEFAULT_SEED: u64 = 0;
+
+/// A 64-bit hash function over byte strings.
+///
+/// Backends carry their own seed or key so they can be passed around as a
+/// single value.
+pub trait Hasher64 {
+    fn hash64(&self, bytes: &[u8]) -> u64;
+}
+
+/// MurmurHash3 x64_128, truncated to its first 64-bit word.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Murmur3 {
+    pub seed: u64,
+}
+
+impl Murmur3 {
+    pub fn with_seed(seed: u64) -> Self {
+        Murmur3 { seed }
+    }
+}
+
+impl Hasher64 for Murmur3 {
+    fn hash64(&self, bytes: &[u8]) -> u64 {
+        mmh3_128(bytes, self.seed).0
+    }
+}
+
+/// MurmurHash64A, reading the input little-endian on every platform.
+///
+/// This is the hash Redis uses for its HyperLogLog (with seed
+/// `0xadc83b19`), so sketches built with it can be merged with Redis ones.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MurmurHash64A {
+    pub seed: u64,
+}
+
+impl MurmurHash64A {
+    pub fn with_seed(seed: u64) -> Self {
+        MurmurHash64A { seed }
+    }
+}
+
+impl Hasher64 for MurmurHash64A {
+    fn hash64(&self, bytes: &[u8]) -> u64 {
+        const M: u64 = 0xc6a4_a793_5bd1_e995;
+        const R: u32 = 47;
+        let mut h = self.seed ^ (bytes.len() as u64).wrapping_mul(M);
+
+        let mut chunks = bytes.chunks_exact(8);
+        for chunk in &mut chunks {
+            let mut k = u64::from_le_bytes(chunk.try_into().unwrap());
+            k = k.wrapping_mul(M);
+            k ^= k >> R;
+            k = k.wrapping_mul(M);
+            h ^= k;
+            h = h.wrapping_mul(M);
+        }
+        let tail = chunks.remainder();
+        if !tail.is_empty() {
+            for (i, &byte) in tail.iter().enumerate() {
+                h ^= (byte as u64) << (8 * i);
+            }
+            h = h.wrapping_mul(M);
+        }
+
+        h ^= h >> R;
+        h = h.wrapping_mul(M);
+        h ^ (h >> R)
+    }
+}
+
+/// XXH64, reading the input little-endian on every platform.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct XxHash64 {
+    pub seed: u64,
+}
+
+impl XxHash64 {
+    const P1: u64 = 0x9e37_79b1_85eb_ca87;
+    const P2: u64 = 0xc2b2_ae3d_27d4_eb4f;
+    const P3: u64 = 0x1656_67b1_9e37_79f9;
+    const P4: u64 = 0x85eb_ca77_c2b2_ae63;
+    const P5: u64 = 0x27d4_eb2f_1656_67c5;
+
+    pub fn with_seed(seed: u64) -> Self {
+        XxHash64 { seed }
+    }
+
+    fn round(acc: u64, lane: u64) -> u64 {
+        acc.wrapping_add(lane.wrapping_mul(Self::P2))
+            .rotate_left(31)
+            .wrapping_mul(Self::P1)
+    }
+
+    fn merge_round(acc: u64, lane: u64) -> u64 {
+        (acc ^ Self::round(0, lane))
+            .wrapping_mul(Self::P1)
+            .wrapping_add(Self::P4)
+    }
+}
+
+impl Hasher64 for XxHash64 {
+    fn hash64(&self, bytes: &[u8]) -> u64 {
+        let word = |chunk: &[u8]| u64::from_le_bytes(chunk.try_into().unwrap());
+        let mut stripes = bytes.chunks_exact(32);
+        let mut h = if bytes.len() >= 32 {
+            let mut acc = [
+                self.seed.wrapping_add(Self::P1).wrapping_add(Self::P2),
+                self.seed.wrapping_add(Self::P2),
+                self.seed,
+                self.seed.wrapping_sub(Self::P1),
+            ];
+            for stripe in &mut stripes {
+                for (lane, acc) in acc.iter_mut().enumerate() {
+                    *acc = Self::round(*acc, word(&stripe[8 * lane..8 * lane + 8]));
+                }
+            }
+            let mut h = acc[0]
+                .rotate_left(1)
+                .wrapping_add(acc[1].rotate_left(7))
+                .wrapping_add(acc[2].rotate_left(12))
+                .wrapping_add(acc[3].rotate_left(18));
+            for lane in acc {
+                h = Self::merge_round(h, lane);
+            }
+            h
+        } else {
+            self.seed.wrapping_add(Self::P5)
+        };
+        h = h.wrapping_add(bytes.len() as u64);
+
+        let mut tail = stripes.remainder();
+        while tail.len() >= 8 {
+            h ^= Self::round(0, word(&tail[..8]));
+            h = h
+                .rotate_left(27)
+                .wrapping_mul(Self::P1)
+                .wrapping_add(Self::P4);
+            tail = &tail[8..];
+        }
+        if tail.len() >= 4 {
+            let lane = u32::from_le_bytes(tail[..4].try_into().unwrap()) as u64;
+            h ^= lane.wrapping_mul(Self::P1);
+            h = h
+                .rotate_left(23)
+                .wrapping_mul(Self::P2)
+                .wrapping_add(Self::P3);
+            tail = &tail[4..];
+        }
+        for &byte in tail {
+            h ^= (byte as u64).wrapping_mul(Self::P5);
+            h = h.rotate_left(11).wrapping_mul(Self::P1);
+        }
+
+        h ^= h >> 33;
+        h = h.wrapping_mul(Self::P2);
+        h ^= h >> 29;
+        h = h.wrapping_mul(Self::P3);
+        h ^ (h >> 32)
+    }
+}
+
+/// 64-bit FNV-1a: one xor and multiply per byte. Fast on short keys and
+/// common in other services, but not keyed and weak against chosen inputs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Fnv1a64;
+
+impl Fnv1a64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+}
+
+impl Hasher64 for Fnv1a64 {
+    fn hash64(&self, bytes: &[u8]) -> u64 {
+        bytes.iter().fold(Self::OFFSET_BASIS, |h, &byte| {
+            (h ^ byte as u64).wrapping_mul(Self::PRIME)
+        })
+    }
+}
+
+/// The bytes `item`'s `Hash` impl writes, so any `Hash` value can be fed
+/// to a [`Hasher64`]. Integers are written in native byte order and std's
+/// impls may change between Rust versions, so these bytes are only stable
+/// within one build.
+#[cfg(feature = "bloom")]
+pub(crate) fn hash_bytes<T: std::hash::Hash + ?Sized>(item: &T) -> Vec<u8> {
+    struct ByteWriter(Vec<u8>);
+
+    impl std::hash::Hasher for ByteWriter {
+        fn write(&mut self, bytes: &[u8]) {
+            self.0.extend_from_slice(bytes);
+        }
+
+        fn finish(&self) -> u64 {
+            unreachable!("only the written bytes are used")
+        }
+    }
+
+    let mut writer = ByteWriter(Vec::new());
+    item.hash(&mut writer);
+    writer.0
+}
+
+/// How a filter or sketch derives one hash value per probe or row.
+#[derive(Clone)]
+#[cfg(any(feature = "bloom", feature = "cms", feature = "sketches"))]
+pub(crate) enum RowHashing {
+    /// murmur3 x86_32 with one seed per row, derived from a master seed
+    Murmur3(Vec<u32>),
+    /// a single 64-bit hash whose halves drive double hashing
+    /// (`h1 + row * h2`), so a keyed backend only runs once per item
+    Hasher(Arc<dyn DynHasher>),
+}
+
+/// A [`Hasher64`] behind a trait object that can still be compared: two
+/// hashers are the same when they have the same type and compare equal,
+/// e.g. the same backend with the same seed or keys.
+#[cfg(any(feature = "bloom", feature = "cms", feature = "sketches"))]
+pub(crate) trait DynHasher: Hasher64 + Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+    fn same_as(&self, other: &dyn DynHasher) -> bool;
+}
+
+#[cfg(any(feature = "bloom", feature = "cms", feature = "sketches"))]
+impl<H: Hasher64 + PartialEq + Send + Sync + 'static> DynHasher for H {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn same_as(&self, other: &dyn DynHasher) -> bool {
+        other.as_any().downcast_ref::<H>() == Some(self)
+    }
+}
+
+#[cfg(any(feature = "bloom", feature = "cms", feature = "sketches"))]
+impl RowHashing {
+    pub(crate) fn murmur3(rows: usize, seeds: SeedSequence) -> Self {
+        RowHashing::Murmur3((0..rows as u64).map(|row| seeds.seed(row) as u32).collect())
+    }
+
+    pub(crate) fn from_hasher<H: Hasher64 + PartialEq + Send + Sync + 'static>(hasher: H) -> Self {
+        RowHashing::Hasher(Arc::new(hasher))
+    }
+
+    pub(crate) fn hashes<'a>(&'a self, item: &'a [u8]) -> RowHashes<'a> {
+        let hash = match self {
+            RowHashing::Murmur3(_) => 0,
+            RowHashing::Hasher(hasher) => hasher.hash64(item),
+        };
+        RowHashes {
+            hashing: self,
+            input: Input::Bytes(item),
+            hash,
+        }
+    }
+
+    /// Row hashes of an integer key without serializing it. Murmur3 rows
+    /// mix the integer with splitmix64 instead, so these hashes differ from
+    /// those of the key's bytes; a keyed hasher still hashes the
+    /// little-endian bytes, so its keys stay unpredictable.
+    #[cfg(any(feature = "bloom", feature = "cms"))]
+    pub(crate) fn hashes_u64(&self, key: u64) -> RowHashes<'_> {
+        let hash = match self {
+            RowHashing::Murmur3(_) => 0,
+            RowHashing::Hasher(hasher) => hasher.hash64(&key.to_le_bytes()),
+        };
+        RowHashes {
+            hashing: self,
+            input: Input::U64(key),
+            hash,
+        }
+    }
+}
+
+#[cfg(any(feature = "bloom", feature = "cms", feature = "sketches"))]
+impl PartialEq for RowHashing {
+    /// Two hashings are equal when they place every item identically: the
+    /// same murmur3 seeds, or hashers of the same type that compare equal,
+    /// so two instances built apart with the same keys or seed match.
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (RowHashing::Murmur3(a), RowHashing::Murmur3(b)) => a == b,
+            (RowHashing::Hasher(a), RowHashing::Hasher(b)) => a.same_as(b.as_ref()),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(any(feature = "bloom", feature = "cms", feature = "sketches"))]
+enum Input<'a> {
+    Bytes(&'a [u8]),
+    #[cfg(any(feature = "bloom", feature = "cms"))]
+    U64(u64),
+}
+
+#[cfg(any(feature = "bloom", feature = "cms", feature = "sketches"))]
+pub(crate) struct RowHashes<'a> {
+    hashing: &'a RowHashing,
+    input: Input<'a>,
+    hash: u64,
+}
+
+#[cfg(any(feature = "bloom", feature = "cms", feature = "sketches"))]
+impl RowHashes<'_> {
+    /// Whether a row costs no more than a few arithmetic operations, so
+    /// computing every row up front is no dearer than computing them lazily.
+    #[cfg(feature = "bloom")]
+    pub(crate) fn rows_are_cheap(&self) -> bool {
+        matches!(self.hashing, RowHashing::Hasher(_)) || matches!(self.input, Input::U64(_))
+    }
+
+    pub(crate) fn row(&self, row: u32) -> u64 {
+        match self.hashing {
+            RowHashing::Murmur3(seeds) => {
+                let seed = seeds[row as usize];
+                match self.input {
+                    Input::Bytes(item) => mmh3(item, seed) as u64,
+                    #[cfg(any(feature = "bloom", feature = "cms"))]
+                    Input::U64(key) => mix_with_seed(key, seed as u64),
+                }
+            }
+            RowHashing::Hasher(_) => {
+                let h1 = self.hash & 0xffff_ffff;
+                let h2 = self.hash >> 32;
+                h1.wrapping_add((row as u64).wrapping_mul(h2))
+            }
+        }
+    }
+
+    /// Like [`row`](Self::row) but spanning all 64 bits, for tables larger
+    /// than 2^32 entries: murmur3 rows use the first word of x64_128 under
+    /// the row's seed, and a single hash is remixed per row.
+    #[cfg(feature = "bloom")]
+    pub(crate) fn wide_row(&self, row: u32) -> u64 {
+        match self.hashing {
+            RowHashing::Murmur3(seeds) => {
+                let seed = seeds[row as usize] as u64;
+                match self.input {
+                    Input::Bytes(item) => mmh3_128(item, seed).0,
+                    Input::U64(key) => mix_with_seed(key, seed),
+                }
+            }
+            RowHashing::Hasher(_) => mix_with_seed(self.hash, row as u64),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn murmur3_depends_on_seed() {
+        let a = Murmur3::default();
+        let b = Murmur3::with_seed(1);
+        assert_eq!(a.hash64(b"key"), Murmur3::default().hash64(b"key"));
+        assert_ne!(a.hash64(b"key"), b.hash64(b"key"));
+        assert_ne!(a.hash64(b"key"), a.hash64(b"other"));
+    }
+
+    #[test]
+    fn murmur64a_mixes_length_tail_and_seed() {
+        let h = MurmurHash64A::default();
+        // only the seed and length feed the empty-input hash
+        assert_eq!(h.hash64(b""), 0);
+        assert_ne!(MurmurHash64A::with_seed(1).hash64(b""), 0);
+        assert_ne!(h.hash64(b"abcdefgh"), h.hash64(b"abcdefgh\0"));
+        assert_ne!(h.hash64(b"abcdefghi"), h.hash64(b"abcdefghj"));
+        assert_ne!(h.hash64(b"key"), MurmurHash64A::with_seed(1).hash64(b"key"));
+    }
+
+    #[test]
+    fn xxhash64_matches_reference_values() {
+        let h = XxHash64::default();
+        assert_eq!(h.hash64(b""), 0xef46_db37_51d8_e999);
+        assert_eq!(h.hash64(b"a"), 0xd24e_c4f1_a98c_6e5b);
+        assert_eq!(h.hash64(b"abc"), 0x44bc_2cf5_ad77_0999);
+        // long enough for the four-lane stripe loop and every tail size
+        let long = b"Nobody inspects the spammish repetition";
+        assert_eq!(h.hash64(long), 0xfbce_a83c_8a37_8bf1);
+        assert_ne!(h.hash64(long), XxHash64::with_seed(1).hash64(long));
+    }
+
+    #[test]
+    fn fnv1a64_matches_reference_values() {
+        assert_eq!(Fnv1a64.hash64(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(Fnv1a64.hash64(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(Fnv1a64.hash64(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[cfg(any(feature = "bloom", feature = "cms", feature = "sketches"))]
+    #[test]
+    fn row_hashes_double_hash_a_single_value() {
+        let hashing = RowHashing::from_hasher(Murmur3::default());
+        let hash = Murmur3::default().hash64(b"item");
+        let hashes = hashing.hashes(b"item");
+        let (h1, h2) = (hash & 0xffff_ffff, hash >> 32);
+        for row in 0..4u32 {
+            assert_eq!(hashes.row(row), h1 + row as u64 * h2);
+        }
+    }
+
+    #[cfg(any(feature = "bloom", feature = "cms"))]
+    #[test]
+    fn u64_rows_are_independent_per_row() {
+        let hashing = RowHashing::murmur3(4, SeedSequence::new(7));
+        let hashes = hashing.hashes_u64(42);
+        let rows: Vec<u64> = (0..4).map(|row| hashes.row(row)).collect();
+        for (i, a) in rows.iter().enumerate() {
+            assert!(rows[i + 1..].iter().all(|b| a != b));
+        }
+        assert_eq!(hashing.hashes_u64(42).row(2), rows[2]);
+        assert_ne!(hashing.hashes_u64(43).row(2), rows[2]);
+
+        // a keyed hasher sees the little-endian bytes
+        let keyed = RowHashing::from_hasher(Murmur3::default());
+        assert_eq!(
+            keyed.hashes_u64(42).row(3),
+            keyed.hashes(&42u64.to_le_bytes()).row(3)
+        );
+    }
+
+    #[cfg(any(feature = "bloom", feature = "cms", feature = "sketches"))]
+    #[test]
+    fn hashings_are_equal_only_for_equal_hashers() {
+        // always 0, so any two of them agree on every input
+        #[derive(PartialEq)]
+        struct Zero(u8);
+        impl Hasher64 for Zero {
+            fn hash64(&self, _bytes: &[u8]) -> u64 {
+                0
+            }
+        }
+        #[derive(PartialEq)]
+        struct AlsoZero;
+        impl Hasher64 for AlsoZero {
+            fn hash64(&self, _bytes: &[u8]) -> u64 {
+                0
+            }
+        }
+
+        let zero = RowHashing::from_hasher(Zero(1));
+        assert!(zero == RowHashing::from_hasher(Zero(1)));
+        assert!(zero != RowHashing::from_hasher(Zero(2)));
+        assert!(zero != RowHashing::from_hasher(AlsoZero));
+        assert!(
+            RowHashing::from_hasher(XxHash64::with_seed(7))
+                == RowHashing::from_hasher(XxHash64::with_seed(7))
+        );
+        assert!(
+            RowHashing::from_hasher(Murmur3::default())
+                != RowHashing::murmur3(2, SeedSequence::new(DEFAULT_SEED))
+        );
+    }
+
+    #[cfg(any(feature = "bloom", feature = "cms", feature = "sketches"))]
+    #[test]
+    fn murmur3_rows_use_derived_seeds() {
+        let seeds = SeedSequence::new(7);
+        let hashing = RowHashing::murmur3(4, seeds);
+        let hashes = hashing.hashes(b"item");
+        for row in 0..4u32 {
+            let seed = seeds.seed(row as u64) as u32;
+            assert_eq!(hashes.row(row), mmh3(b"item", seed) as u64);
+        }
+    }
+}
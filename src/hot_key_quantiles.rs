@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+
+use crate::count_min_sketch::CountMinSketch;
+use crate::tdigest::TDigest;
+
+/// Tracks per-key value distributions, but only for heavy hitters.
+///
+/// Every observation is counted in a Count-Min sketch; once a key's
+/// estimated count reaches `threshold` it gets its own t-digest and later
+/// values for it are recorded there. Cold keys only cost sketch counters.
+pub struct HotKeyQuantiles {
+    sketch: CountMinSketch,
+    threshold: u32,
+    max_hot_keys: usize,
+    compression: f64,
+    digests: HashMap<Vec<u8>, TDigest>,
+}
+
+impl HotKeyQuantiles {
+    pub fn new(sketch: CountMinSketch, threshold: u32, max_hot_keys: usize) -> Self {
+        assert!(threshold > 0, "threshold must be positive");
+        HotKeyQuantiles {
+            sketch,
+            threshold,
+            max_hot_keys,
+            compression: 100.0,
+            digests: HashMap::new(),
+        }
+    }
+
+    /// Sets the t-digest compression used for newly promoted keys.
+    pub fn with_compression(mut self, compression: f64) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Records one observation of `value` for `key`.
+    pub fn record(&mut self, key: &[u8], value: f64) {
+        self.sketch.update(key, 1);
+        if let Some(digest) = self.digests.get_mut(key) {
+            digest.insert(value);
+            return;
+        }
+        if self.digests.len() < self.max_hot_keys && self.sketch.estimate(key) >= self.threshold {
+            let mut digest = TDigest::new(self.compression);
+            digest.insert(value);
+            self.digests.insert(key.to_vec(), digest);
+        }
+    }
+
+    pub fn is_hot(&self, key: &[u8]) -> bool {
+        self.digests.contains_key(key)
+    }
+
+    pub fn hot_keys(&self) -> impl Iterator<Item = &[u8]> {
+        self.digests.keys().map(|key| key.as_slice())
+    }
+
+    /// Estimated number of observations of `key`, hot or not.
+    pub fn count(&self, key: &[u8]) -> u32 {
+        self.sketch.estimate(key)
+    }
+
+    /// Value at quantile `q` of `key`'s distribution since it became hot,
+    /// or `None` if the key is not tracked.
+    pub fn quantile(&self, key: &[u8], q: f64) -> Option<f64> {
+        self.digests.get(key)?.quantile(q)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_heavy_hitters_get_quantiles() {
+        let mut hot = HotKeyQuantiles::new(CountMinSketch::new(0.001, 0.01), 10, 16);
+        for i in 0..1000 {
+            hot.record(b"hot", i as f64);
+            hot.record(format!("cold-{}", i).as_bytes(), i as f64);
+        }
+        assert!(hot.is_hot(b"hot"));
+        assert!(!hot.is_hot(b"cold-1"));
+        assert_eq!(hot.quantile(b"cold-1", 0.5), None);
+        // values before promotion are not in the digest
+        let median = hot.quantile(b"hot", 0.5).unwrap();
+        assert!((median - 504.5).abs() < 10.0, "median {}", median);
+        assert_eq!(hot.quantile(b"hot", 1.0), Some(999.0));
+    }
+
+    #[test]
+    fn hot_key_limit_bounds_tracked_keys() {
+        let mut hot = HotKeyQuantiles::new(CountMinSketch::new(0.001, 0.01), 1, 2);
+        for key in [b"a", b"b", b"c"] {
+            hot.record(key, 1.0);
+        }
+        assert_eq!(hot.hot_keys().count(), 2);
+        assert!(!hot.is_hot(b"c"));
+        assert_eq!(hot.count(b"c"), 1);
+    }
+}
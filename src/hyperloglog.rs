@@ -0,0 +1,532 @@
+use crate::decode::DecodeError;
+use crate::hasher::{Hasher64, Murmur3, MurmurHash64A, DEFAULT_SEED};
+
+/// Bits per register in the packed layout.
+pub const REGISTER_BITS: usize = 6;
+
+/// Seed Redis passes to MurmurHash64A for its HyperLogLog.
+pub const REDIS_SEED: u64 = 0xadc8_3b19;
+
+/// Precision of Redis HyperLogLogs (16384 registers).
+pub const REDIS_PRECISION: u32 = 14;
+
+/// Precision of a sketch collected from an iterator (16384 registers).
+pub const DEFAULT_PRECISION: u32 = 14;
+
+/// Change between two snapshots of one stream's [`HyperLogLog`], from
+/// [`delta`](HyperLogLog::delta).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CardinalityDelta {
+    /// Estimated distinct items in the earlier snapshot.
+    pub previous: u64,
+    /// Estimated distinct items now.
+    pub current: u64,
+    /// Registers whose rank rose since the earlier snapshot.
+    pub changed_registers: usize,
+}
+
+impl CardinalityDelta {
+    /// Distinct items first seen between the snapshots. Both counts are
+    /// estimates, so a slowly growing set can read as zero.
+    pub fn new_items(&self) -> u64 {
+        self.current.saturating_sub(self.previous)
+    }
+
+    /// Relative growth, `new_items / previous`; infinite when the earlier
+    /// snapshot was empty and the later one is not.
+    pub fn growth(&self) -> f64 {
+        match (self.previous, self.new_items()) {
+            (_, 0) => 0.0,
+            (0, _) => f64::INFINITY,
+            (previous, new) => new as f64 / previous as f64,
+        }
+    }
+
+    /// New distinct items per second over an interval of `elapsed`.
+    pub fn rate(&self, elapsed: std::time::Duration) -> f64 {
+        self.new_items() as f64 / elapsed.as_secs_f64()
+    }
+}
+
+/// HyperLogLog cardinality estimator with `2^p` registers.
+///
+/// An item's hash picks a register with its low `p` bits; the register
+/// keeps the largest rank seen, the rank being one plus the number of
+/// trailing zeros in the remaining `64 - p` bits. This is the scheme Redis
+/// uses, so a sketch built with [`HyperLogLog::redis`] can exchange
+/// registers with Redis through [`to_packed_registers`](Self::to_packed_registers).
+#[derive(Clone)]
+pub struct HyperLogLog<H = Murmur3> {
+    p: u32,
+    registers: Vec<u8>,
+    hasher: H,
+}
+
+impl HyperLogLog {
+    pub fn new(p: u32) -> Self {
+        Self::with_hasher(p, Murmur3::with_seed(DEFAULT_SEED))
+    }
+}
+
+impl HyperLogLog<MurmurHash64A> {
+    /// Creates a sketch that hashes and indexes items exactly like Redis'
+    /// `PFADD`, so its registers can be merged with a Redis sketch.
+    pub fn redis() -> Self {
+        Self::with_hasher(REDIS_PRECISION, MurmurHash64A::with_seed(REDIS_SEED))
+    }
+}
+
+impl<H: Hasher64> HyperLogLog<H> {
+    /// Creates a sketch with `2^p` registers hashing items with `hasher`.
+    /// `p` must be in `4..=18`.
+    pub fn with_hasher(p: u32, hasher: H) -> Self {
+        assert!((4..=18).contains(&p), "precision must be in 4..=18");
+        HyperLogLog {
+            p,
+            registers: vec![0; 1 << p],
+            hasher,
+        }
+    }
+
+    pub fn precision(&self) -> u32 {
+        self.p
+    }
+
+    /// One byte per register, in register order.
+    pub fn registers(&self) -> &[u8] {
+        &self.registers
+    }
+
+    /// Largest rank a register can hold, `64 - p + 1`.
+    fn max_rank(&self) -> u8 {
+        (64 - self.p + 1) as u8
+    }
+
+    pub fn insert(&mut self, item: &[u8]) {
+        let hash = self.hasher.hash64(item);
+        let index = (hash & ((1 << self.p) - 1)) as usize;
+        // the sentinel bit caps the rank at 64 - p + 1
+        let rest = (hash >> self.p) | (1 << (64 - self.p));
+        let rank = rest.trailing_zeros() as u8 + 1;
+        if rank > self.registers[index] {
+            self.registers[index] = rank;
+        }
+    }
+
+    /// Estimated number of distinct items inserted.
+    pub fn count(&self) -> u64 {
+        self.estimate().0.round() as u64
+    }
+
+    /// Relative standard error of [`count`](Self::count) once the sketch
+    /// has left its linear-counting range: `1.04 / sqrt(2^p)`.
+    pub fn relative_error(&self) -> f64 {
+        1.04 / (self.registers.len() as f64).sqrt()
+    }
+
+    /// Bounds that hold the true number of distinct items with probability
+    /// about `level`, e.g. 0.95, treating the estimate as normal around it.
+    ///
+    /// The width follows [`relative_error`](Self::relative_error) for large
+    /// counts; while linear counting is in use it follows that estimator's
+    /// standard deviation, `sqrt(m * (e^t - t - 1))` with `t = n / m`
+    /// (Whang et al.). The lower bound is clamped to zero.
+    pub fn confidence_interval(&self, level: f64) -> (f64, f64) {
+        assert!(
+            level > 0.0 && level < 1.0,
+            "confidence level must be in (0, 1)"
+        );
+        let (estimate, linear) = self.estimate();
+        let std_dev = if linear {
+            let m = self.registers.len() as f64;
+            let t = estimate / m;
+            (m * (t.exp() - t - 1.0)).sqrt()
+        } else {
+            estimate * self.relative_error()
+        };
+        let margin = normal_quantile(0.5 + level / 2.0) * std_dev;
+        ((estimate - margin).max(0.0), estimate + margin)
+    }
+
+    /// Unrounded cardinality estimate, and whether linear counting
+    /// produced it.
+    fn estimate(&self) -> (f64, bool) {
+        let m = self.registers.len() as f64;
+        let alpha = match self.registers.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m),
+        };
+        let sum: f64 = self
+            .registers
+            .iter()
+            .map(|&rank| 2f64.powi(-(rank as i32)))
+            .sum();
+        let estimate = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|&&rank| rank == 0).count();
+        if estimate <= 2.5 * m && zeros > 0 {
+            // linear counting is more accurate while many registers are empty
+            (m * (m / zeros as f64).ln(), true)
+        } else {
+            (estimate, false)
+        }
+    }
+
+    /// Whether `other` indexes items into the same registers, so the two
+    /// can be merged.
+    pub fn is_compatible(&self, other: &Self) -> bool
+    where
+        H: PartialEq,
+    {
+        self.p == other.p && self.hasher == other.hasher
+    }
+
+    /// Takes the register-wise maximum with `other`; the result estimates
+    /// the union of both streams.
+    pub fn merge(&mut self, other: &Self)
+    where
+        H: PartialEq,
+    {
+        assert!(
+            self.is_compatible(other),
+            "cannot merge sketches with different precision or hashing"
+        );
+        self.merge_registers(&other.registers);
+    }
+
+    /// Compares this sketch with `previous`, an earlier snapshot of the
+    /// same stream, whose registers can only be lower or equal.
+    pub fn delta(&self, previous: &Self) -> CardinalityDelta
+    where
+        H: PartialEq,
+    {
+        assert!(
+            self.is_compatible(previous),
+            "cannot diff sketches with different precision or hashing"
+        );
+        CardinalityDelta {
+            previous: previous.count(),
+            current: self.count(),
+            changed_registers: self
+                .registers
+                .iter()
+                .zip(&previous.registers)
+                .filter(|(now, before)| now > before)
+                .count(),
+        }
+    }
+
+    fn merge_registers(&mut self, registers: &[u8]) {
+        for (rank, &other) in self.registers.iter_mut().zip(registers) {
+            *rank = (*rank).max(other);
+        }
+    }
+
+    /// Packs the registers into `6 * 2^p` bits, the layout of Redis'
+    /// dense encoding (without its 16-byte header).
+    ///
+    /// Register `i` occupies bits `6i..6i + 6` of the buffer, where bit `b`
+    /// is bit `b % 8` (least significant first) of byte `b / 8`. A register
+    /// may straddle two bytes; its low bits are then in the high bits of
+    /// the first byte. The buffer is `(6 * 2^p + 7) / 8` bytes long.
+    pub fn to_packed_registers(&self) -> Vec<u8> {
+        let mut out = vec![0u8; packed_len(self.p)];
+        for (i, &rank) in self.registers.iter().enumerate() {
+            let bit = i * REGISTER_BITS;
+            let (byte, shift) = (bit / 8, bit % 8);
+            out[byte] |= rank << shift;
+            if shift > 8 - REGISTER_BITS {
+                out[byte + 1] |= rank >> (8 - shift);
+            }
+        }
+        out
+    }
+
+    /// Merges registers packed as by [`to_packed_registers`](Self::to_packed_registers),
+    /// e.g. taken from another system's sketch of the same precision that
+    /// hashes items the same way.
+    pub fn merge_packed_registers(&mut self, bytes: &[u8]) -> Result<(), DecodeError> {
+        let registers = unpack(self.p, self.max_rank(), bytes)?;
+        self.merge_registers(&registers);
+        Ok(())
+    }
+
+    /// Rebuilds a sketch from packed registers; `hasher` must be the one the
+    /// registers were filled with for later inserts to be meaningful.
+    pub fn from_packed_registers(p: u32, hasher: H, bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut hll = Self::with_hasher(p, hasher);
+        hll.registers = unpack(p, hll.max_rank(), bytes)?;
+        Ok(hll)
+    }
+}
+
+fn packed_len(p: u32) -> usize {
+    (REGISTER_BITS << p).div_ceil(8)
+}
+
+fn unpack(p: u32, max_rank: u8, bytes: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    let expected = packed_len(p);
+    if bytes.len() != expected {
+        return Err(DecodeError::Length {
+            expected,
+            actual: bytes.len(),
+        });
+    }
+    (0..1usize << p)
+        .map(|i| {
+            let bit = i * REGISTER_BITS;
+            let (byte, shift) = (bit / 8, bit % 8);
+            let mut word = (bytes[byte] >> shift) as u16;
+            if shift > 8 - REGISTER_BITS {
+                word |= (bytes[byte + 1] as u16) << (8 - shift);
+            }
+            let rank = (word & ((1 << REGISTER_BITS) - 1)) as u8;
+            if rank > max_rank {
+                return Err(DecodeError::Corrupt);
+            }
+            Ok(rank)
+        })
+        .collect()
+}
+
+impl<T: AsRef<[u8]>, H: Hasher64> Extend<T> for HyperLogLog<H> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, items: I) {
+        for item in items {
+            self.insert(item.as_ref());
+        }
+    }
+}
+
+/// Builds a murmur3 sketch with [`DEFAULT_PRECISION`].
+impl<T: AsRef<[u8]>> FromIterator<T> for HyperLogLog {
+    fn from_iter<I: IntoIterator<Item = T>>(items: I) -> Self {
+        let mut hll = Self::new(DEFAULT_PRECISION);
+        hll.extend(items);
+        hll
+    }
+}
+
+/// Inverse of the standard normal CDF, by Acklam's rational approximation
+/// (relative error below 1.2e-9).
+fn normal_quantile(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969_683_028_665_376e1,
+        2.209_460_984_245_205e2,
+        -2.759_285_104_469_687e2,
+        1.383_577_518_672_69e2,
+        -3.066_479_806_614_716e1,
+        2.506_628_277_459_239,
+    ];
+    const B: [f64; 5] = [
+        -5.447_609_879_822_406e1,
+        1.615_858_368_580_409e2,
+        -1.556_989_798_598_866e2,
+        6.680_131_188_771_972e1,
+        -1.328_068_155_288_572e1,
+    ];
+    const C: [f64; 6] = [
+        -7.784_894_002_430_293e-3,
+        -3.223_964_580_411_365e-1,
+        -2.400_758_277_161_838,
+        -2.549_732_539_343_734,
+        4.374_664_141_464_968,
+        2.938_163_982_698_783,
+    ];
+    const D: [f64; 4] = [
+        7.784_695_709_041_462e-3,
+        3.224_671_290_700_398e-1,
+        2.445_134_137_142_996,
+        3.754_408_661_907_416,
+    ];
+    let tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+    if p < 0.024_25 {
+        tail((-2.0 * p.ln()).sqrt())
+    } else if p > 1.0 - 0.024_25 {
+        -tail((-2.0 * (1.0 - p).ln()).sqrt())
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delta_tracks_cardinality_growth() {
+        let mut hll = HyperLogLog::new(14);
+        for i in 0..10_000u32 {
+            hll.insert(&i.to_le_bytes());
+        }
+        let previous = hll.clone();
+        for i in 5_000..15_000u32 {
+            hll.insert(&i.to_le_bytes());
+        }
+        let delta = hll.delta(&previous);
+        assert_eq!(delta.previous, previous.count());
+        assert_eq!(delta.current, hll.count());
+        assert!((4_500..=5_500).contains(&delta.new_items()), "{:?}", delta);
+        assert!((delta.growth() - 0.5).abs() < 0.06, "{:?}", delta);
+        assert!(delta.changed_registers > 0);
+        let per_second = delta.rate(std::time::Duration::from_secs(5));
+        assert_eq!(per_second, delta.new_items() as f64 / 5.0);
+
+        // re-inserting known items changes nothing
+        let mut again = hll.clone();
+        for i in 0..15_000u32 {
+            again.insert(&i.to_le_bytes());
+        }
+        let unchanged = again.delta(&hll);
+        assert_eq!(unchanged.changed_registers, 0);
+        assert_eq!(unchanged.new_items(), 0);
+        assert_eq!(unchanged.growth(), 0.0);
+        assert_eq!(hll.delta(&HyperLogLog::new(14)).growth(), f64::INFINITY);
+    }
+
+    #[test]
+    fn count_is_close_to_distinct_items() {
+        let mut hll = HyperLogLog::new(12);
+        assert_eq!(hll.count(), 0);
+        for i in 0..100u32 {
+            hll.insert(&i.to_le_bytes());
+        }
+        assert!((98..=102).contains(&hll.count()), "count {}", hll.count());
+        for i in 0..100_000u32 {
+            hll.insert(&i.to_le_bytes());
+            hll.insert(&i.to_le_bytes());
+        }
+        // standard error is 1.04 / sqrt(4096) ~ 1.6%
+        let error = (hll.count() as f64 - 100_000.0).abs() / 100_000.0;
+        assert!(error < 0.05, "error {error}");
+    }
+
+    #[test]
+    fn collect_and_extend() {
+        let mut hll: HyperLogLog = (0..1_000u32).map(u32::to_le_bytes).collect();
+        assert_eq!(hll.precision(), DEFAULT_PRECISION);
+        hll.extend(["a", "b", "a"]);
+        let error = (hll.count() as f64 - 1_002.0).abs() / 1_002.0;
+        assert!(error < 0.02, "error {error}");
+    }
+
+    #[test]
+    fn normal_quantile_matches_tables() {
+        for (p, z) in [
+            (0.5, 0.0),
+            (0.975, 1.959_964),
+            (0.995, 2.575_829),
+            (0.01, -2.326_348),
+        ] {
+            assert!((normal_quantile(p) - z).abs() < 1e-6, "p {p}");
+        }
+    }
+
+    #[test]
+    fn confidence_interval_covers_true_count() {
+        let hll = HyperLogLog::new(12);
+        assert_eq!(hll.relative_error(), 1.04 / 64.0);
+        assert_eq!(hll.confidence_interval(0.95), (0.0, 0.0));
+
+        // one sketch per seed, in the linear-counting range and beyond it
+        for n in [1_000u32, 20_000] {
+            let mut covered = 0;
+            for seed in 0..40 {
+                let mut hll = HyperLogLog::with_hasher(10, Murmur3::with_seed(seed));
+                for i in 0..n {
+                    hll.insert(&i.to_le_bytes());
+                }
+                let (low, high) = hll.confidence_interval(0.95);
+                assert!(low <= hll.count() as f64 && hll.count() as f64 <= high);
+                covered += (low..=high).contains(&(n as f64)) as usize;
+            }
+            assert!(covered >= 34, "{covered}/40 intervals cover {n}");
+        }
+    }
+
+    #[test]
+    fn merge_estimates_union() {
+        let mut a = HyperLogLog::new(12);
+        let mut b = HyperLogLog::new(12);
+        for i in 0..20_000u32 {
+            a.insert(&i.to_le_bytes());
+            b.insert(&(i + 10_000).to_le_bytes());
+        }
+        a.merge(&b);
+        let error = (a.count() as f64 - 30_000.0).abs() / 30_000.0;
+        assert!(error < 0.05, "error {error}");
+    }
+
+    #[test]
+    #[should_panic]
+    fn merge_rejects_different_precision() {
+        let mut a = HyperLogLog::new(12);
+        a.merge(&HyperLogLog::new(10));
+    }
+
+    #[test]
+    fn packed_registers_round_trip() {
+        let mut hll = HyperLogLog::redis();
+        for i in 0..5_000u32 {
+            hll.insert(&i.to_le_bytes());
+        }
+        let packed = hll.to_packed_registers();
+        assert_eq!(packed.len(), 12_288);
+        let decoded = HyperLogLog::from_packed_registers(
+            REDIS_PRECISION,
+            MurmurHash64A::with_seed(REDIS_SEED),
+            &packed,
+        )
+        .unwrap();
+        assert_eq!(decoded.registers(), hll.registers());
+        assert_eq!(decoded.count(), hll.count());
+    }
+
+    #[test]
+    fn packing_matches_redis_bit_order() {
+        let mut hll = HyperLogLog::new(4);
+        hll.registers[0] = 0b000001;
+        hll.registers[1] = 0b110011;
+        hll.registers[15] = 0b111111;
+        let packed = hll.to_packed_registers();
+        assert_eq!(packed.len(), 12);
+        // register 0 in bits 0..6, register 1 in bits 6..12
+        assert_eq!(packed[0], 0b1100_0001);
+        assert_eq!(packed[1], 0b0000_1100);
+        assert_eq!(packed[11], 0b1111_1100);
+    }
+
+    #[test]
+    fn merge_packed_registers_takes_maximum() {
+        let mut a = HyperLogLog::new(10);
+        let mut b = HyperLogLog::new(10);
+        for i in 0..3_000u32 {
+            a.insert(&i.to_le_bytes());
+            b.insert(&(i + 100_000).to_le_bytes());
+        }
+        let mut expected = a.clone();
+        expected.merge(&b);
+        a.merge_packed_registers(&b.to_packed_registers()).unwrap();
+        assert_eq!(a.registers(), expected.registers());
+    }
+
+    #[test]
+    fn unpack_rejects_bad_input() {
+        let mut hll = HyperLogLog::new(10);
+        assert!(matches!(
+            hll.merge_packed_registers(&[0; 10]),
+            Err(DecodeError::Length { expected: 768, .. })
+        ));
+        // 63 is above the largest rank, 64 - 10 + 1
+        assert_eq!(
+            hll.merge_packed_registers(&[0xff; 768]),
+            Err(DecodeError::Corrupt)
+        );
+    }
+}
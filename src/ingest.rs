@@ -0,0 +1,278 @@
+//! Async ingestion adapters for filters and sketches.
+//!
+//! Only built with the `async` feature. [`channel`] wraps any structure that
+//! implements `Extend` (Bloom and quotient filters, Count-Min sketches,
+//! HyperLogLogs) in an [`IngestSink`] that items are pushed into, and a
+//! [`SnapshotStream`] that yields a copy of the structure every `every`
+//! items. The halves implement `futures::Sink` and `futures::Stream`, so
+//! they plug into `SinkExt`/`StreamExt` combinators and tokio pipelines.
+//! No runtime is needed: the sink wakes the stream's task directly, so the
+//! two halves work across tasks or threads of any executor.
+
+use futures::{Sink, Stream};
+use std::future::poll_fn;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+/// Returned when sending into an [`IngestSink`] that was closed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Closed;
+
+impl std::fmt::Display for Closed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ingest sink is closed")
+    }
+}
+
+impl std::error::Error for Closed {}
+
+struct Shared<S> {
+    sketch: S,
+    every: u64,
+    /// Items ingested so far.
+    ingested: u64,
+    /// Value of `ingested` when the last snapshot was taken.
+    snapshot_at: u64,
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+impl<S> Shared<S> {
+    fn snapshot_due(&self) -> bool {
+        self.ingested - self.snapshot_at >= self.every
+    }
+
+    fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Wraps `sketch` in a sink and a stream of its snapshots, one every
+/// `every` items. The stream ends once the sink is closed or dropped,
+/// after a final snapshot of any items since the last one.
+pub fn channel<S: Clone>(sketch: S, every: u64) -> (IngestSink<S>, SnapshotStream<S>) {
+    assert!(every > 0, "snapshot interval must be positive");
+    let shared = Arc::new(Mutex::new(Shared {
+        sketch,
+        every,
+        ingested: 0,
+        snapshot_at: 0,
+        closed: false,
+        waker: None,
+    }));
+    let sink = IngestSink {
+        shared: Arc::clone(&shared),
+    };
+    (sink, SnapshotStream { shared })
+}
+
+/// Sending half of [`channel`]: each item is added to the sketch with
+/// `Extend` as it arrives, so the sink is always ready.
+pub struct IngestSink<S> {
+    shared: Arc<Mutex<Shared<S>>>,
+}
+
+impl<S> IngestSink<S> {
+    /// Adds `item` to the sketch.
+    pub async fn send<Item>(&mut self, item: Item) -> Result<(), Closed>
+    where
+        S: Extend<Item>,
+    {
+        poll_fn(|cx| Sink::<Item>::poll_ready(Pin::new(&mut *self), cx)).await?;
+        Pin::new(self).start_send(item)
+    }
+
+    /// Copy of the sketch as it stands, without waiting for the next
+    /// periodic snapshot.
+    pub fn snapshot(&self) -> S
+    where
+        S: Clone,
+    {
+        self.shared.lock().unwrap().sketch.clone()
+    }
+
+    fn close(&self) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.closed = true;
+        shared.wake();
+    }
+}
+
+impl<S: Extend<Item>, Item> Sink<Item> for IngestSink<S> {
+    type Error = Closed;
+
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Closed>> {
+        if self.shared.lock().unwrap().closed {
+            return Poll::Ready(Err(Closed));
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Item) -> Result<(), Closed> {
+        let mut shared = self.shared.lock().unwrap();
+        if shared.closed {
+            return Err(Closed);
+        }
+        shared.sketch.extend(std::iter::once(item));
+        shared.ingested += 1;
+        if shared.snapshot_due() {
+            shared.wake();
+        }
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Closed>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Closed>> {
+        self.close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S> Drop for IngestSink<S> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// Receiving half of [`channel`]: yields a clone of the sketch whenever at
+/// least `every` items arrived since the previous snapshot. A consumer that
+/// falls behind gets one snapshot of the latest state, not one per interval.
+pub struct SnapshotStream<S> {
+    shared: Arc<Mutex<Shared<S>>>,
+}
+
+impl<S: Clone> SnapshotStream<S> {
+    /// Waits for the next snapshot; `None` once the sink is closed and
+    /// every item is covered by a snapshot.
+    pub async fn next(&mut self) -> Option<S> {
+        poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+}
+
+impl<S: Clone> Stream for SnapshotStream<S> {
+    type Item = S;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S>> {
+        let mut shared = self.shared.lock().unwrap();
+        let pending = shared.ingested > shared.snapshot_at;
+        if shared.snapshot_due() || (shared.closed && pending) {
+            shared.snapshot_at = shared.ingested;
+            return Poll::Ready(Some(shared.sketch.clone()));
+        }
+        if shared.closed {
+            return Poll::Ready(None);
+        }
+        shared.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+#[cfg(all(test, feature = "bloom", feature = "sketches"))]
+mod test {
+    use super::*;
+    use crate::bloom_filter::BloomFilter;
+    use crate::hyperloglog::HyperLogLog;
+    use std::future::Future;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::Wake;
+    use std::thread::{self, Thread};
+
+    /// Runs `future` on the current thread, parking until it is woken.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        struct Unpark(Thread);
+        impl Wake for Unpark {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn snapshots_every_interval_and_on_close() {
+        let (mut sink, mut snapshots) = channel(HyperLogLog::new(12), 100);
+        let producer = thread::spawn(move || {
+            for i in 0..250u32 {
+                block_on(sink.send(i.to_le_bytes())).unwrap();
+            }
+        });
+        let counts: Vec<u64> = block_on(async {
+            let mut counts = Vec::new();
+            while let Some(hll) = snapshots.next().await {
+                counts.push(hll.count());
+            }
+            counts
+        });
+        producer.join().unwrap();
+        // a slow consumer may see fewer, later snapshots, but the last one
+        // always covers every item
+        assert!(!counts.is_empty() && counts.len() <= 3, "{:?}", counts);
+        assert!(counts.windows(2).all(|w| w[0] <= w[1]));
+        assert!((240..=260).contains(counts.last().unwrap()), "{:?}", counts);
+    }
+
+    #[test]
+    fn works_with_futures_combinators() {
+        use futures::{SinkExt, StreamExt};
+
+        let (mut sink, snapshots) = channel(HyperLogLog::new(12), 10);
+        block_on(async {
+            for i in 0..25u32 {
+                SinkExt::send(&mut sink, i.to_le_bytes()).await.unwrap();
+            }
+            SinkExt::<[u8; 4]>::close(&mut sink).await.unwrap();
+        });
+        let counts: Vec<u64> = block_on(snapshots.map(|hll| hll.count()).collect());
+        assert_eq!(counts.len(), 1, "{:?}", counts);
+        assert!((24..=26).contains(&counts[0]), "{:?}", counts);
+    }
+
+    #[test]
+    fn sink_wakes_stream_only_when_snapshot_is_due() {
+        struct CountWakes(AtomicUsize);
+        impl Wake for CountWakes {
+            fn wake(self: Arc<Self>) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        let wakes = Arc::new(CountWakes(AtomicUsize::new(0)));
+        let waker = Waker::from(Arc::clone(&wakes));
+        let mut cx = Context::from_waker(&waker);
+
+        let (mut sink, mut snapshots) = channel(BloomFilter::new(100, 0.01), 2);
+        assert!(Pin::new(&mut snapshots).poll_next(&mut cx).is_pending());
+        Pin::new(&mut sink).start_send(b"a").unwrap();
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 0);
+        Pin::new(&mut sink).start_send(b"b").unwrap();
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 1);
+        match Pin::new(&mut snapshots).poll_next(&mut cx) {
+            Poll::Ready(Some(filter)) => assert!(filter.lookup(b"a") && filter.lookup(b"b")),
+            other => panic!("expected a snapshot, got pending: {}", other.is_pending()),
+        }
+        assert!(sink.snapshot().lookup(b"a"));
+
+        Pin::new(&mut sink).start_send(b"c").unwrap();
+        assert!(Sink::<&[u8; 1]>::poll_close(Pin::new(&mut sink), &mut cx).is_ready());
+        assert_eq!(Pin::new(&mut sink).start_send(b"d"), Err(Closed));
+        let last = Pin::new(&mut snapshots).poll_next(&mut cx);
+        assert!(matches!(last, Poll::Ready(Some(ref filter)) if filter.lookup(b"c")));
+        assert!(matches!(
+            Pin::new(&mut snapshots).poll_next(&mut cx),
+            Poll::Ready(None)
+        ));
+    }
+}
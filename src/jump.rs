@@ -0,0 +1,277 @@
+use murmurhash3::murmurhash3_x64_128 as mmh3_128;
+
+/// Buckets a node of weight 1.0 gets in [`JumpHash`] by default.
+pub const DEFAULT_BUCKETS_PER_WEIGHT: f64 = 100.0;
+
+/// Jump consistent hash (Lamping and Veach): maps `key` to a bucket in
+/// `0..buckets`. Growing `buckets` by one moves only the keys that land in
+/// the new bucket.
+pub fn jump_hash(mut key: u64, buckets: u32) -> u32 {
+    assert!(buckets > 0, "at least one bucket is required");
+    let mut b: i64 = -1;
+    let mut j: i64 = 0;
+    while j < buckets as i64 {
+        b = j;
+        key = key.wrapping_mul(2862933555777941757).wrapping_add(1);
+        j = ((b + 1) as f64 * ((1u64 << 31) as f64 / ((key >> 33) + 1) as f64)) as i64;
+    }
+    b as u32
+}
+
+/// Weighted jump consistent hashing over `u64` node ids.
+///
+/// Jump hash only knows numbered buckets, so every node is replicated into
+/// `round(weight * buckets_per_weight)` buckets (at least one) of a table
+/// that maps buckets to nodes, and a node of weight `w` receives about a
+/// `w / sum(w)` share of the keys. New nodes and weight increases append
+/// buckets to the end of the table, which moves keys only to the growing
+/// node. Removing a node or lowering its weight compacts the table, and as
+/// with plain jump hash that also moves keys between the remaining nodes.
+#[derive(Debug, Clone)]
+pub struct JumpHash {
+    nodes: Vec<(u64, f64)>,
+    table: Vec<u64>,
+    buckets_per_weight: f64,
+}
+
+impl Default for JumpHash {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl JumpHash {
+    pub fn new() -> Self {
+        Self::with_resolution(DEFAULT_BUCKETS_PER_WEIGHT)
+    }
+
+    /// Replicates a node of weight 1.0 into `buckets_per_weight` buckets.
+    /// More buckets follow the weights more closely but make reweighting
+    /// and removal slower.
+    pub fn with_resolution(buckets_per_weight: f64) -> Self {
+        if !(buckets_per_weight.is_finite() && buckets_per_weight >= 1.0) {
+            panic!(
+                "buckets per weight {} must be at least 1",
+                buckets_per_weight
+            );
+        }
+        JumpHash {
+            nodes: Vec::new(),
+            table: Vec::new(),
+            buckets_per_weight,
+        }
+    }
+
+    pub fn add_node(&mut self, id: u64) {
+        self.add_weighted_node(id, 1.0);
+    }
+
+    /// Adds a node, or updates its weight if it already exists.
+    pub fn add_weighted_node(&mut self, id: u64, weight: f64) {
+        if !(weight.is_finite() && weight > 0.0) {
+            panic!("weight {} of node {} must be positive", weight, id);
+        }
+        let replicas = self.replicas(weight);
+        let current = match self.nodes.iter().position(|(node, _)| *node == id) {
+            Some(index) => {
+                let current = self.replicas(self.nodes[index].1);
+                self.nodes[index].1 = weight;
+                current
+            }
+            None => {
+                self.nodes.push((id, weight));
+                0
+            }
+        };
+        if replicas > current {
+            self.table
+                .extend(std::iter::repeat_n(id, replicas - current));
+        } else {
+            // drop the node's last buckets; later buckets shift down as on removal
+            let mut surplus = current - replicas;
+            for bucket in (0..self.table.len()).rev() {
+                if surplus == 0 {
+                    break;
+                }
+                if self.table[bucket] == id {
+                    self.table.remove(bucket);
+                    surplus -= 1;
+                }
+            }
+        }
+    }
+
+    pub fn remove_node(&mut self, id: u64) {
+        self.nodes.retain(|(node, _)| *node != id);
+        self.table.retain(|node| *node != id);
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn weight(&self, id: u64) -> Option<f64> {
+        self.nodes
+            .iter()
+            .find(|(node, _)| *node == id)
+            .map(|(_, weight)| *weight)
+    }
+
+    /// Total number of buckets across all nodes.
+    pub fn buckets(&self) -> usize {
+        self.table.len()
+    }
+
+    pub fn lookup(&self, key: &[u8]) -> Option<u64> {
+        if self.table.is_empty() {
+            return None;
+        }
+        let (hash, _) = mmh3_128(key, 0);
+        let bucket = jump_hash(hash, self.table.len() as u32);
+        Some(self.table[bucket as usize])
+    }
+
+    fn replicas(&self, weight: f64) -> usize {
+        ((weight * self.buckets_per_weight).round() as usize).max(1)
+    }
+}
+
+impl Extend<u64> for JumpHash {
+    fn extend<I: IntoIterator<Item = u64>>(&mut self, ids: I) {
+        for id in ids {
+            self.add_node(id);
+        }
+    }
+}
+
+impl FromIterator<u64> for JumpHash {
+    fn from_iter<I: IntoIterator<Item = u64>>(ids: I) -> Self {
+        let mut jump = Self::new();
+        jump.extend(ids);
+        jump
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn shares(j: &JumpHash, keys: u64) -> Vec<(u64, f64)> {
+        let mut counts = std::collections::BTreeMap::new();
+        for key in 0..keys {
+            let owner = j.lookup(&key.to_be_bytes()).unwrap();
+            *counts.entry(owner).or_insert(0u64) += 1;
+        }
+        counts
+            .into_iter()
+            .map(|(id, count)| (id, count as f64 / keys as f64))
+            .collect()
+    }
+
+    #[test]
+    fn jump_hash_stays_in_range() {
+        assert_eq!(jump_hash(0, 1), 0);
+        assert_eq!(jump_hash(1, 1), 0);
+        assert_eq!(jump_hash(0xdeadbeef, 1), 0);
+        assert!((0..10_000u64).all(|key| jump_hash(key, 7) < 7));
+    }
+
+    #[test]
+    fn growing_buckets_only_moves_keys_to_the_new_bucket() {
+        for buckets in 1..50 {
+            for key in 0..2000u64 {
+                let key = key.wrapping_mul(0x9e3779b97f4a7c15);
+                let before = jump_hash(key, buckets);
+                let after = jump_hash(key, buckets + 1);
+                assert!(after == before || after == buckets);
+            }
+        }
+    }
+
+    #[test]
+    fn lookup_on_empty_returns_none() {
+        let j = JumpHash::new();
+        assert!(j.is_empty());
+        assert_eq!(j.lookup(b"key"), None);
+    }
+
+    #[test]
+    fn collect_and_extend_nodes() {
+        let mut j: JumpHash = (1..=3).collect();
+        j.extend([3, 4]);
+        assert_eq!(j.len(), 4);
+        assert_eq!(j.weight(4), Some(1.0));
+        assert_eq!(j.buckets(), 4 * 100);
+    }
+
+    #[test]
+    fn weighted_assignment_is_proportional() {
+        let mut j = JumpHash::new();
+        let weights = [(10, 1.0), (20, 2.0), (30, 3.0), (40, 4.0)];
+        for (id, weight) in weights {
+            j.add_weighted_node(id, weight);
+        }
+        let total: f64 = weights.iter().map(|(_, w)| w).sum();
+        let got = shares(&j, 100_000);
+        assert_eq!(got.len(), weights.len());
+        for ((id, share), (_, weight)) in got.into_iter().zip(weights) {
+            let expected = weight / total;
+            assert!(
+                (share - expected).abs() < 0.01,
+                "node {} got share {}, expected {}",
+                id,
+                share,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn adding_or_growing_a_node_only_moves_keys_to_it() {
+        let mut j: JumpHash = (0..4).collect();
+        let owners = |j: &JumpHash| -> Vec<u64> {
+            (0u64..5000)
+                .map(|key| j.lookup(&key.to_be_bytes()).unwrap())
+                .collect()
+        };
+        let before = owners(&j);
+        j.add_weighted_node(9, 2.0);
+        let added = owners(&j);
+        j.add_weighted_node(2, 3.0);
+        let grown = owners(&j);
+        for ((old, new), newer) in before.iter().zip(&added).zip(&grown) {
+            assert!(new == old || *new == 9);
+            assert!(newer == new || *newer == 2);
+        }
+        let count = |owners: &[u64], node: u64| owners.iter().filter(|&&o| o == node).count();
+        assert!(count(&added, 9) > 0);
+        assert!(count(&grown, 2) > count(&added, 2));
+    }
+
+    #[test]
+    fn reweighting_and_removal_update_the_table() {
+        let mut j = JumpHash::with_resolution(10.0);
+        j.add_weighted_node(1, 2.0);
+        j.add_node(2);
+        assert_eq!(j.buckets(), 30);
+        j.add_weighted_node(1, 0.5);
+        assert_eq!((j.len(), j.weight(1), j.buckets()), (2, Some(0.5), 15));
+        // tiny weights still get a bucket
+        j.add_weighted_node(3, 0.01);
+        assert_eq!(j.buckets(), 16);
+        j.remove_node(1);
+        assert_eq!((j.len(), j.buckets()), (2, 11));
+        assert!((0u64..100).all(|key| j.lookup(&key.to_be_bytes()) != Some(1)));
+    }
+
+    #[test]
+    #[should_panic]
+    fn non_positive_weight_panics() {
+        let mut j = JumpHash::new();
+        j.add_weighted_node(1, -1.0);
+    }
+}
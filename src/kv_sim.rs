@@ -0,0 +1,401 @@
+//! A small sharded key-value store built from the crate's structures.
+//!
+//! Keys are hashed onto a [`HashRing`] to pick a shard. Each shard keeps its
+//! data in a map, a [`QuotientFilter`] that answers most lookups of absent
+//! keys without touching the map, and a [`CountMinSketch`] of accesses that
+//! flags hot keys. Reads and writes route through a [`RingSnapshot`] taken
+//! after the last membership change, so a request never sees a half-applied
+//! rebalance.
+
+use crate::count_min_sketch::CountMinSketch;
+use crate::hash_ring::{HashRing, HashRingInterface, RingSnapshot};
+use crate::hasher::{Hasher64, Murmur3, DEFAULT_SEED};
+use crate::quotient_filter::QuotientFilter;
+use crate::trace::TraceEvent;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+#[derive(Debug, Clone)]
+pub struct KvConfig {
+    /// The ring has `2^k` positions.
+    pub k: u32,
+    /// Initial quotient bits of each shard's filter; it grows as needed.
+    pub filter_q: u64,
+    /// Remainder bits of each shard's filter; `filter_q + filter_r` bits
+    /// of every key's fingerprint are kept.
+    pub filter_r: u64,
+    pub cms_eps: f32,
+    pub cms_delta: f32,
+    /// Estimated accesses at which a key is reported as hot.
+    pub hot_threshold: u32,
+}
+
+impl Default for KvConfig {
+    fn default() -> Self {
+        KvConfig {
+            k: 16,
+            filter_q: 8,
+            filter_r: 24,
+            cms_eps: 0.001,
+            cms_delta: 0.01,
+            hot_threshold: 1000,
+        }
+    }
+}
+
+/// Request counters since the store was created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KvStats {
+    pub gets: u64,
+    pub puts: u64,
+    pub deletes: u64,
+    pub hits: u64,
+    /// Reads of absent keys answered by the filter alone.
+    pub filtered: u64,
+    /// Reads of absent keys the filter let through to the map.
+    pub false_positives: u64,
+    /// Keys moved between shards by joins and leaves.
+    pub migrated: u64,
+}
+
+struct Shard {
+    data: HashMap<Vec<u8>, Vec<u8>>,
+    filter: QuotientFilter,
+    accesses: CountMinSketch,
+    hot: BTreeSet<Vec<u8>>,
+}
+
+impl Shard {
+    fn new(config: &KvConfig) -> Self {
+        Shard {
+            data: HashMap::new(),
+            filter: QuotientFilter::new(config.filter_q, config.filter_r),
+            accesses: CountMinSketch::new(config.cms_eps, config.cms_delta),
+            hot: BTreeSet::new(),
+        }
+    }
+
+    fn insert(&mut self, key: Vec<u8>, value: Vec<u8>) {
+        if !self.data.contains_key(&key) {
+            self.filter.insert_bytes(&key);
+        }
+        self.data.insert(key, value);
+    }
+
+    fn remove(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+        let value = self.data.remove(key)?;
+        self.filter.delete_bytes(key);
+        Some(value)
+    }
+}
+
+pub struct KvStore {
+    config: KvConfig,
+    ring: HashRing<i64>,
+    snapshot: RingSnapshot<i64>,
+    shards: BTreeMap<i64, Shard>,
+    hasher: Murmur3,
+    stats: KvStats,
+}
+
+impl KvStore {
+    /// Creates a store with one shard at each of `positions`.
+    pub fn new(config: KvConfig, positions: &[u64]) -> Self {
+        assert!(!positions.is_empty(), "at least one shard is required");
+        let mut store = KvStore {
+            ring: HashRing::new(config.k),
+            snapshot: HashRing::<i64>::new(config.k).snapshot(),
+            shards: BTreeMap::new(),
+            hasher: Murmur3::with_seed(DEFAULT_SEED),
+            stats: KvStats::default(),
+            config,
+        };
+        for &position in positions {
+            store.add_shard(position);
+        }
+        store
+    }
+
+    /// Builds a store from a trace: the leading `join`s place the initial
+    /// shards and the remaining events are applied with [`apply`](Self::apply).
+    pub fn from_trace(config: KvConfig, events: &[TraceEvent]) -> Self {
+        let initial: Vec<u64> = events
+            .iter()
+            .map_while(|event| match event {
+                TraceEvent::Join(position) => Some(*position),
+                _ => None,
+            })
+            .collect();
+        let mut store = KvStore::new(config, &initial);
+        for event in &events[initial.len()..] {
+            store.apply(event);
+        }
+        store
+    }
+
+    /// Applies one trace event; a `set` stores the key as its own value.
+    /// As in [`trace::replay`](crate::trace::replay), joins of existing
+    /// shards and leaves of absent or last shards are ignored.
+    pub fn apply(&mut self, event: &TraceEvent) {
+        match event {
+            TraceEvent::Get(key) => {
+                self.get(key.as_bytes());
+            }
+            TraceEvent::Set(key) => self.put(key.as_bytes(), key.as_bytes()),
+            TraceEvent::Join(position) => {
+                if !self.shards.contains_key(&(*position as i64)) {
+                    self.add_shard(*position);
+                }
+            }
+            TraceEvent::Leave(position) => {
+                if self.shards.contains_key(&(*position as i64)) && self.shards.len() > 1 {
+                    self.remove_shard(*position);
+                }
+            }
+        }
+    }
+
+    pub fn stats(&self) -> KvStats {
+        self.stats
+    }
+
+    /// The routing view requests are currently served from.
+    pub fn snapshot(&self) -> &RingSnapshot<i64> {
+        &self.snapshot
+    }
+
+    /// Number of keys held by each shard.
+    pub fn shard_sizes(&self) -> BTreeMap<u64, usize> {
+        self.shards
+            .iter()
+            .map(|(&position, shard)| (position as u64, shard.data.len()))
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.values().map(|shard| shard.data.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Shard position that owns `key`.
+    pub fn route(&self, key: &[u8]) -> u64 {
+        let mask = (1u64 << self.config.k) - 1;
+        let position = (self.hasher.hash64(key) & mask) as i64;
+        self.snapshot.lookup(position).expect("store has no shards") as u64
+    }
+
+    pub fn put(&mut self, key: &[u8], value: &[u8]) {
+        self.stats.puts += 1;
+        let shard = self.touch(key);
+        shard.insert(key.to_vec(), value.to_vec());
+    }
+
+    pub fn get(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+        self.stats.gets += 1;
+        let shard = self.touch(key);
+        let passed = shard.filter.lookup_bytes(key);
+        let value = if passed {
+            shard.data.get(key).cloned()
+        } else {
+            None
+        };
+        match (passed, &value) {
+            (false, _) => self.stats.filtered += 1,
+            (true, Some(_)) => self.stats.hits += 1,
+            (true, None) => self.stats.false_positives += 1,
+        }
+        value
+    }
+
+    pub fn delete(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+        self.stats.deletes += 1;
+        let position = self.route(key) as i64;
+        self.shards.get_mut(&position).unwrap().remove(key)
+    }
+
+    /// Counts an access to `key` on its shard and returns the shard.
+    fn touch(&mut self, key: &[u8]) -> &mut Shard {
+        let position = self.route(key) as i64;
+        let threshold = self.config.hot_threshold;
+        let shard = self.shards.get_mut(&position).unwrap();
+        shard.accesses.update(key, 1);
+        if shard.accesses.estimate(key) >= threshold {
+            shard.hot.insert(key.to_vec());
+        }
+        shard
+    }
+
+    /// Keys whose estimated access count reached the hot threshold, per
+    /// shard. Access counts stay on the shard that saw them, so a key that
+    /// migrates starts cold on its new shard.
+    pub fn hot_keys(&self) -> BTreeMap<u64, Vec<Vec<u8>>> {
+        self.shards
+            .iter()
+            .filter(|(_, shard)| !shard.hot.is_empty())
+            .map(|(&position, shard)| (position as u64, shard.hot.iter().cloned().collect()))
+            .collect()
+    }
+
+    /// Adds a shard at `position` and moves the keys it now owns to it.
+    pub fn add_shard(&mut self, position: u64) {
+        let position = position as i64;
+        assert!(
+            !self.shards.contains_key(&position),
+            "shard {} already exists",
+            position
+        );
+        self.ring.add_node(position);
+        self.shards.insert(position, Shard::new(&self.config));
+        self.rebalance();
+    }
+
+    /// Removes the shard at `position`, handing its keys to their new
+    /// owners. The last shard cannot be removed.
+    pub fn remove_shard(&mut self, position: u64) {
+        let position = position as i64;
+        assert!(self.shards.len() > 1, "cannot remove the last shard");
+        let shard = self
+            .shards
+            .remove(&position)
+            .unwrap_or_else(|| panic!("shard {} does not exist", position));
+        self.ring.remove_node(position);
+        self.snapshot = self.ring.snapshot();
+        for (key, value) in shard.data {
+            let owner = self.route(&key) as i64;
+            self.shards.get_mut(&owner).unwrap().insert(key, value);
+            self.stats.migrated += 1;
+        }
+    }
+
+    /// Publishes a new snapshot and moves every key whose owner changed.
+    fn rebalance(&mut self) {
+        self.snapshot = self.ring.snapshot();
+        let mut moves = Vec::new();
+        for (&position, shard) in &self.shards {
+            for key in shard.data.keys() {
+                let owner = self.route(key) as i64;
+                if owner != position {
+                    moves.push((position, owner, key.clone()));
+                }
+            }
+        }
+        for (from, to, key) in moves {
+            let value = self.shards.get_mut(&from).unwrap().remove(&key).unwrap();
+            self.shards.get_mut(&to).unwrap().insert(key, value);
+            self.stats.migrated += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log;
+
+    fn key(i: u32) -> Vec<u8> {
+        format!("key:{}", i).into_bytes()
+    }
+
+    #[test]
+    fn put_get_delete_round_trip() {
+        log::init_test_logger();
+        let mut store = KvStore::new(KvConfig::default(), &[100, 20_000, 40_000]);
+        for i in 0..2_000 {
+            store.put(&key(i), &i.to_le_bytes());
+        }
+        assert_eq!(store.len(), 2_000);
+        assert_eq!(store.shard_sizes().len(), 3);
+        for i in 0..2_000 {
+            assert_eq!(store.get(&key(i)), Some(i.to_le_bytes().to_vec()));
+        }
+        for i in 2_000..4_000 {
+            assert_eq!(store.get(&key(i)), None);
+        }
+        let stats = store.stats();
+        assert_eq!(stats.hits, 2_000);
+        // 32-bit fingerprints let almost nothing through
+        assert_eq!(stats.filtered + stats.false_positives, 2_000);
+        assert!(stats.false_positives < 5);
+
+        assert_eq!(store.delete(&key(7)), Some(7u32.to_le_bytes().to_vec()));
+        assert_eq!(store.get(&key(7)), None);
+        assert_eq!(store.delete(&key(7)), None);
+    }
+
+    #[test]
+    fn membership_changes_keep_every_key_reachable() {
+        log::init_test_logger();
+        let mut store = KvStore::new(KvConfig::default(), &[1_000, 30_000]);
+        for i in 0..3_000 {
+            store.put(&key(i), b"v");
+        }
+        store.add_shard(50_000);
+        store.add_shard(10_000);
+        let migrated = store.stats().migrated;
+        assert!(migrated > 0);
+        store.remove_shard(1_000);
+        assert!(store.stats().migrated > migrated);
+
+        assert_eq!(store.len(), 3_000);
+        assert_eq!(store.snapshot().nodes(), &[10_000, 30_000, 50_000]);
+        for (&position, &size) in &store.shard_sizes() {
+            assert!(size > 0, "shard {} is empty", position);
+        }
+        for i in 0..3_000 {
+            assert_eq!(store.get(&key(i)).as_deref(), Some(&b"v"[..]));
+        }
+        assert_eq!(store.stats().filtered, 0);
+    }
+
+    #[test]
+    fn synthetic_trace_runs_end_to_end() {
+        log::init_test_logger();
+        let events = crate::trace::SyntheticTrace {
+            keys: 1_000,
+            requests: 20_000,
+            write_ratio: 0.2,
+            seed: 3,
+            ..Default::default()
+        }
+        .generate();
+        let config = KvConfig {
+            hot_threshold: 200,
+            ..Default::default()
+        };
+        let store = KvStore::from_trace(config, &events);
+        let stats = store.stats();
+        assert_eq!(stats.gets + stats.puts, 20_000);
+        assert_eq!(
+            stats.hits + stats.filtered + stats.false_positives,
+            stats.gets
+        );
+        assert!(stats.migrated > 0);
+        // the most popular keys of a Zipf stream are hot
+        let hot: Vec<Vec<u8>> = store.hot_keys().into_values().flatten().collect();
+        assert!(hot.contains(&b"key:0".to_vec()));
+    }
+
+    #[test]
+    fn hot_keys_are_reported_on_their_shard() {
+        log::init_test_logger();
+        let config = KvConfig {
+            hot_threshold: 50,
+            ..Default::default()
+        };
+        let mut store = KvStore::new(config, &[0, 32_768]);
+        for i in 0..500 {
+            store.put(&key(i), b"v");
+        }
+        for _ in 0..100 {
+            store.get(&key(1));
+            store.get(&key(2));
+        }
+        let hot = store.hot_keys();
+        let all: Vec<&Vec<u8>> = hot.values().flatten().collect();
+        assert_eq!(all.len(), 2);
+        assert!(hot[&store.route(&key(1))].contains(&key(1)));
+        assert!(hot[&store.route(&key(2))].contains(&key(2)));
+    }
+}
@@ -1,5 +1,171 @@
+#[cfg(feature = "accuracy-tracking")]
+pub mod accuracy;
+#[cfg(feature = "sketches")]
+pub mod agg_sketch;
+#[cfg(feature = "bloom")]
 pub mod bloom_filter;
+#[cfg(feature = "bloom")]
+pub mod bloom_tree;
+pub mod builder;
+#[cfg(feature = "ring")]
+pub mod churn;
+pub mod cityhash;
+#[cfg(all(feature = "bloom", feature = "quotient"))]
+pub mod compare;
+#[cfg(feature = "bloom")]
+pub mod concurrent_bloom_filter;
+#[cfg(feature = "quotient")]
+pub mod concurrent_quotient_filter;
+#[cfg(feature = "ring")]
+pub mod consistent;
+#[cfg(feature = "cms")]
 pub mod count_min_sketch;
+#[cfg(feature = "bloom")]
+pub mod counting_bloom_filter;
+#[cfg(feature = "quotient")]
+pub mod counting_quotient_filter;
+pub mod crc;
+#[cfg(feature = "quotient")]
+pub mod cuckoo_filter;
+pub mod decode;
+#[cfg(feature = "bloom")]
+pub mod deletable_bloom_filter;
+pub mod fingerprint;
+#[cfg(any(feature = "bloom", feature = "quotient", feature = "cms"))]
+pub mod frequency;
+#[cfg(all(feature = "cms", feature = "quotient"))]
+pub mod frequency_filter;
+#[cfg(feature = "ring")]
 pub mod hash_ring;
+pub mod hasher;
+#[cfg(all(feature = "cms", feature = "sketches"))]
+pub mod hot_key_quantiles;
+#[cfg(feature = "sketches")]
+pub mod hyperloglog;
+#[cfg(feature = "async")]
+pub mod ingest;
+#[cfg(feature = "ring")]
+pub mod jump;
+#[cfg(all(feature = "ring", feature = "cms", feature = "quotient"))]
+pub mod kv_sim;
+#[cfg(any(feature = "cli", test))]
 pub mod log;
+#[cfg(feature = "ring")]
+mod lru;
+#[cfg(all(feature = "mmap", unix))]
+pub mod mapped_bloom_filter;
+#[cfg(all(feature = "bloom", feature = "quotient"))]
+pub mod membership;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "bloom")]
+pub mod partitioned_bloom_filter;
+#[cfg(feature = "bloom")]
+pub mod prefix_bloom_filter;
+pub mod prelude;
+#[cfg(feature = "quotient")]
 pub mod quotient_filter;
+#[cfg(feature = "ring")]
+pub mod rendezvous;
+#[cfg(feature = "roaring")]
+pub mod roaring;
+#[cfg(feature = "bloom")]
+pub mod rotating_bloom_filter;
+#[cfg(feature = "cms")]
+pub mod sampler;
+pub mod seed;
+#[cfg(all(feature = "ring", feature = "quotient"))]
+pub mod sharded_filter;
+#[cfg(feature = "ring")]
+pub mod simple_ch;
+pub mod siphash;
+#[cfg(feature = "cms")]
+pub mod sketch_ring;
+#[cfg(feature = "ring")]
+pub mod slots;
+#[cfg(feature = "bloom")]
+pub mod spectral_bloom_filter;
+#[cfg(feature = "quotient")]
+pub mod static_filter;
+#[cfg(feature = "tabulation")]
+pub mod tabulation;
+#[cfg(feature = "sketches")]
+pub mod tdigest;
+#[cfg(feature = "ring")]
+pub mod trace;
+
+pub use builder::BuildError;
+pub use decode::DecodeError;
+pub use fingerprint::{CanonicalFingerprinter, Fingerprinter};
+pub use hasher::{Fnv1a64, Hasher64, Murmur3, MurmurHash64A, XxHash64};
+
+#[cfg(feature = "bloom")]
+pub use bloom_filter::BloomFilter;
+#[cfg(feature = "bloom")]
+pub use bloom_tree::BloomTree;
+#[cfg(feature = "bloom")]
+pub use concurrent_bloom_filter::ConcurrentBloomFilter;
+#[cfg(feature = "bloom")]
+pub use counting_bloom_filter::CountingBloomFilter;
+#[cfg(feature = "bloom")]
+pub use deletable_bloom_filter::DeletableBloomFilter;
+#[cfg(all(feature = "mmap", unix))]
+pub use mapped_bloom_filter::MappedBloomFilter;
+#[cfg(feature = "bloom")]
+pub use partitioned_bloom_filter::PartitionedBloomFilter;
+#[cfg(feature = "bloom")]
+pub use prefix_bloom_filter::PrefixBloomFilter;
+#[cfg(feature = "bloom")]
+pub use rotating_bloom_filter::RotatingBloomFilter;
+#[cfg(feature = "bloom")]
+pub use spectral_bloom_filter::SpectralBloomFilter;
+
+#[cfg(feature = "quotient")]
+pub use concurrent_quotient_filter::ConcurrentQuotientFilter;
+#[cfg(feature = "quotient")]
+pub use counting_quotient_filter::CountingQuotientFilter;
+#[cfg(feature = "quotient")]
+pub use cuckoo_filter::CuckooFilter;
+#[cfg(feature = "quotient")]
+pub use quotient_filter::QuotientFilter;
+#[cfg(feature = "quotient")]
+pub use static_filter::StaticQuotientFilter;
+
+#[cfg(all(feature = "bloom", feature = "quotient"))]
+pub use membership::ApproxMembership;
+
+#[cfg(any(feature = "bloom", feature = "quotient", feature = "cms"))]
+pub use frequency::FrequencyEstimator;
+
+#[cfg(feature = "cms")]
+pub use count_min_sketch::CountMinSketch;
+#[cfg(all(feature = "cms", feature = "quotient"))]
+pub use frequency_filter::FrequencyFilter;
+#[cfg(all(feature = "cms", feature = "sketches"))]
+pub use hot_key_quantiles::HotKeyQuantiles;
+#[cfg(feature = "cms")]
+pub use sampler::FrequencyAwareSampler;
+#[cfg(feature = "cms")]
+pub use sketch_ring::SketchRing;
+
+#[cfg(feature = "sketches")]
+pub use agg_sketch::AggSketch;
+#[cfg(feature = "sketches")]
+pub use hyperloglog::HyperLogLog;
+#[cfg(feature = "sketches")]
+pub use tdigest::TDigest;
+
+#[cfg(feature = "ring")]
+pub use churn::RingBackend;
+#[cfg(feature = "ring")]
+pub use consistent::ConsistentHasher;
+#[cfg(feature = "ring")]
+pub use hash_ring::{HashRing, HashRingInterface, Node, Placement};
+#[cfg(feature = "ring")]
+pub use jump::JumpHash;
+#[cfg(feature = "ring")]
+pub use rendezvous::Rendezvous;
+#[cfg(all(feature = "ring", feature = "quotient"))]
+pub use sharded_filter::ShardedFilter;
+#[cfg(feature = "ring")]
+pub use slots::SlotMap;
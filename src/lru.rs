@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+const NIL: usize = usize::MAX;
+
+struct Entry<K, V> {
+    key: K,
+    value: V,
+    prev: usize,
+    next: usize,
+}
+
+/// Fixed-capacity least-recently-used map.
+///
+/// Entries live in a slab linked into a recency list, most recent first,
+/// so `get` and `insert` are O(1).
+pub(crate) struct LruCache<K, V> {
+    capacity: usize,
+    map: HashMap<K, usize>,
+    entries: Vec<Entry<K, V>>,
+    head: usize,
+    tail: usize,
+}
+
+impl<K: Hash + Eq + Copy, V> LruCache<K, V> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be positive");
+        LruCache {
+            capacity,
+            map: HashMap::with_capacity(capacity),
+            entries: Vec::with_capacity(capacity),
+            head: NIL,
+            tail: NIL,
+        }
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub(crate) fn get(&mut self, key: &K) -> Option<&V> {
+        let index = *self.map.get(key)?;
+        self.unlink(index);
+        self.push_front(index);
+        Some(&self.entries[index].value)
+    }
+
+    pub(crate) fn insert(&mut self, key: K, value: V) {
+        if let Some(&index) = self.map.get(&key) {
+            self.entries[index].value = value;
+            self.unlink(index);
+            self.push_front(index);
+            return;
+        }
+        let index = if self.entries.len() < self.capacity {
+            self.entries.push(Entry {
+                key,
+                value,
+                prev: NIL,
+                next: NIL,
+            });
+            self.entries.len() - 1
+        } else {
+            // reuse the slot of the least recently used entry
+            let index = self.tail;
+            self.unlink(index);
+            self.map.remove(&self.entries[index].key);
+            self.entries[index].key = key;
+            self.entries[index].value = value;
+            index
+        };
+        self.map.insert(key, index);
+        self.push_front(index);
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.map.clear();
+        self.entries.clear();
+        self.head = NIL;
+        self.tail = NIL;
+    }
+
+    fn unlink(&mut self, index: usize) {
+        let (prev, next) = (self.entries[index].prev, self.entries[index].next);
+        if prev == NIL {
+            self.head = next;
+        } else {
+            self.entries[prev].next = next;
+        }
+        if next == NIL {
+            self.tail = prev;
+        } else {
+            self.entries[next].prev = prev;
+        }
+    }
+
+    fn push_front(&mut self, index: usize) {
+        self.entries[index].prev = NIL;
+        self.entries[index].next = self.head;
+        if self.head != NIL {
+            self.entries[self.head].prev = index;
+        }
+        self.head = index;
+        if self.tail == NIL {
+            self.tail = index;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = LruCache::new(2);
+        cache.insert(1, "a");
+        cache.insert(2, "b");
+        assert_eq!(cache.get(&1), Some(&"a"));
+        cache.insert(3, "c");
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&1), Some(&"a"));
+        assert_eq!(cache.get(&3), Some(&"c"));
+        assert_eq!(cache.map.len(), 2);
+    }
+
+    #[test]
+    fn insert_existing_key_updates_value() {
+        let mut cache = LruCache::new(2);
+        cache.insert(1, "a");
+        cache.insert(2, "b");
+        cache.insert(1, "z");
+        cache.insert(3, "c");
+        assert_eq!(cache.get(&1), Some(&"z"));
+        assert_eq!(cache.get(&2), None);
+        cache.clear();
+        assert_eq!(cache.map.len(), 0);
+        assert_eq!(cache.get(&1), None);
+    }
+}
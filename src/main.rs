@@ -0,0 +1,186 @@
+use hash_bench::churn::{self, ChurnConfig};
+use hash_bench::compare;
+use hash_bench::membership;
+use std::process;
+use std::str::FromStr;
+
+const USAGE: &str = "usage: hash_bench <command> [options]
+
+commands:
+  churn    stream resource insertions while nodes join and leave
+           --k <bits>                 ring size is 2^k (default: 16)
+           --initial-nodes <n>        nodes before the first step (default: 8)
+           --steps <n>                number of steps (default: 20)
+           --inserts-per-step <n>     resources inserted per step (default: 1000)
+           --join-rate <p>            probability of a join per step (default: 0.2)
+           --leave-rate <p>           probability of a leave per step (default: 0.2)
+           --seed <n>                 RNG seed (default: 0)
+  fpr      measure the false-positive rate of a membership filter
+           --filter <name>            bloom, counting_bloom, cuckoo, deletable_bloom,
+                                      partitioned_bloom or quotient (default: bloom)
+           --capacity <n>             items inserted (default: 100000)
+           --rate <p>                 target false-positive rate, at most 0.5
+                                      (default: 0.01)
+           --lookups <n>              lookups of absent items (default: 1000000)
+  compare  build several membership filters on the same keys and report build
+           time, memory, hit/miss query throughput and measured false-positive rate
+           --structures <a,b,...>     filters to compare, any of bloom, counting_bloom,
+                                      cuckoo, deletable_bloom, partitioned_bloom and
+                                      quotient (default: all of them)
+           --keys <n>                 keys inserted, e.g. 1e7 (default: 1000000)
+           --fpp <p>                  target false-positive rate, at most 0.5
+                                      (default: 0.01)
+           --queries <n>              hit and miss lookups each (default: 1000000)";
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("error: {}\n\n{}", message, USAGE);
+    process::exit(2);
+}
+
+fn parse_value<T: FromStr>(flag: &str, value: Option<String>) -> T {
+    let value = value.unwrap_or_else(|| usage_error(&format!("{} requires a value", flag)));
+    value
+        .parse()
+        .unwrap_or_else(|_| usage_error(&format!("invalid value for {}: {}", flag, value)))
+}
+
+fn churn_command(mut args: impl Iterator<Item = String>) {
+    let mut config = ChurnConfig::default();
+    while let Some(flag) = args.next() {
+        match flag.as_str() {
+            "--k" => config.k = parse_value(&flag, args.next()),
+            "--initial-nodes" => config.initial_nodes = parse_value(&flag, args.next()),
+            "--steps" => config.steps = parse_value(&flag, args.next()),
+            "--inserts-per-step" => config.inserts_per_step = parse_value(&flag, args.next()),
+            "--join-rate" => config.join_rate = parse_value(&flag, args.next()),
+            "--leave-rate" => config.leave_rate = parse_value(&flag, args.next()),
+            "--seed" => config.seed = parse_value(&flag, args.next()),
+            _ => usage_error(&format!("unknown option: {}", flag)),
+        }
+    }
+    if !(0.0..=1.0).contains(&config.join_rate) || !(0.0..=1.0).contains(&config.leave_rate) {
+        usage_error("rates must be between 0 and 1");
+    }
+    if config.k == 0 || config.k >= 63 {
+        usage_error("--k must be between 1 and 62");
+    }
+
+    for report in churn::run_all(&config) {
+        println!("backend: {}", report.backend);
+        println!(
+            "{:>6} {:>6} {:>10} {:>6} {:>6} {:>9} {:>9}",
+            "step", "nodes", "resources", "joined", "left", "migrated", "stability"
+        );
+        for step in &report.steps {
+            println!(
+                "{:>6} {:>6} {:>10} {:>6} {:>6} {:>9} {:>9.4}",
+                step.step,
+                step.nodes,
+                step.resources,
+                step.joined,
+                step.left,
+                step.migrated,
+                step.stability
+            );
+        }
+        println!("total migrated: {}", report.total_migrated());
+    }
+}
+
+fn fpr_command(mut args: impl Iterator<Item = String>) {
+    let mut name = "bloom".to_string();
+    let mut capacity: u32 = 100_000;
+    let mut rate: f32 = 0.01;
+    let mut lookups: u32 = 1_000_000;
+    while let Some(flag) = args.next() {
+        match flag.as_str() {
+            "--filter" => name = parse_value(&flag, args.next()),
+            "--capacity" => capacity = parse_value(&flag, args.next()),
+            "--rate" => rate = parse_value(&flag, args.next()),
+            "--lookups" => lookups = parse_value(&flag, args.next()),
+            _ => usage_error(&format!("unknown option: {}", flag)),
+        }
+    }
+    if capacity == 0 {
+        usage_error("--capacity must be positive");
+    }
+    if !(rate > 0.0 && rate <= 0.5) {
+        usage_error("--rate must be greater than 0 and at most 0.5");
+    }
+    let Some(mut filter) = membership::build(&name, capacity, rate) else {
+        usage_error(&format!(
+            "unknown filter: {} (expected one of {})",
+            name,
+            membership::FILTERS.join(", ")
+        ));
+    };
+
+    // inserted and probed items never overlap, so every hit is a false positive
+    for i in 0..capacity as u64 {
+        filter.insert(&i.to_le_bytes());
+    }
+    let false_positives = (0..lookups as u64)
+        .filter(|i| filter.lookup(&(capacity as u64 + i).to_le_bytes()))
+        .count();
+    println!("filter: {}", name);
+    println!("capacity: {}, target rate: {}", capacity, rate);
+    println!(
+        "false positives: {} of {} ({:.6})",
+        false_positives,
+        lookups,
+        false_positives as f64 / lookups.max(1) as f64
+    );
+}
+
+/// Parses a count that may be written in scientific notation, like `1e7`.
+fn parse_count(flag: &str, value: Option<String>) -> u32 {
+    let count: f64 = parse_value(flag, value);
+    if !(count >= 1.0 && count <= u32::MAX as f64 && count.fract() == 0.0) {
+        usage_error(&format!(
+            "{} must be a whole number from 1 to {}",
+            flag,
+            u32::MAX
+        ));
+    }
+    count as u32
+}
+
+fn compare_command(mut args: impl Iterator<Item = String>) {
+    let mut structures = membership::FILTERS.join(",");
+    let mut keys: u32 = 1_000_000;
+    let mut fpp: f32 = 0.01;
+    let mut queries: u32 = 1_000_000;
+    while let Some(flag) = args.next() {
+        match flag.as_str() {
+            "--structures" => structures = parse_value(&flag, args.next()),
+            "--keys" => keys = parse_count(&flag, args.next()),
+            "--fpp" => fpp = parse_value(&flag, args.next()),
+            "--queries" => queries = parse_count(&flag, args.next()),
+            _ => usage_error(&format!("unknown option: {}", flag)),
+        }
+    }
+    if !(fpp > 0.0 && fpp <= 0.5) {
+        usage_error("--fpp must be greater than 0 and at most 0.5");
+    }
+    let structures: Vec<&str> = structures.split(',').map(str::trim).collect();
+    match compare::compare(&structures, keys, fpp, queries) {
+        Ok(rows) => {
+            println!("keys: {}, target fpp: {}, queries: {}", keys, fpp, queries);
+            print!("{}", compare::render_table(&rows, keys));
+        }
+        Err(err) => usage_error(&err.to_string()),
+    }
+}
+
+fn main() {
+    hash_bench::log::init_logger();
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {
+        Some("churn") => churn_command(args),
+        Some("fpr") => fpr_command(args),
+        Some("compare") => compare_command(args),
+        Some("-h") | Some("--help") => println!("{}", USAGE),
+        Some(command) => usage_error(&format!("unknown command: {}", command)),
+        None => usage_error("missing command"),
+    }
+}
@@ -0,0 +1,426 @@
+//! Bloom filters saved to a file and memory-mapped read-only, so one large
+//! filter can be shared by many processes without each loading a copy.
+//!
+//! The file starts with a versioned header, all integers little-endian:
+//!
+//! | offset | field                                                  |
+//! |--------|--------------------------------------------------------|
+//! | 0      | magic `b"HBBF"`                                        |
+//! | 4      | `u32` version                                          |
+//! | 8      | `u64` n, the capacity the filter was sized for         |
+//! | 16     | `u64` m                                                |
+//! | 24     | `f32` f and `u32` k                                    |
+//! | 32     | `u32` hashing: 0 for seeded murmur3, 1 for a [`Hasher64`] |
+//! | 36     | `u32` reserved, zero                                   |
+//! | 40     | `u64` hasher identity                                  |
+//! | 48     | `u64` offset of the bits                               |
+//! | 56     | murmur3 only: the `k` row seeds as `u32`               |
+//!
+//! Version 1 stored `n` and `m` as `u32` and is no longer read.
+//!
+//! The bits follow at an offset aligned to 8, as `ceil(m / 64)` `u64`
+//! words with bit `i` at bit `i % 64` of word `i / 64`.
+//!
+//! A custom hasher cannot be written to the file, so its identity is the
+//! hasher's hash of a fixed probe string, and the filter must be opened
+//! with [`BloomFilter::open_mmap_with_hasher`] and a hasher that hashes the
+//! probe the same way.
+
+use crate::bloom_filter::{probe, BloomFilter};
+use crate::decode::DecodeError;
+use crate::hasher::{Hasher64, RowHashing};
+use bitvec::field::BitField;
+use bitvec::prelude::BitVec;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+const MAGIC: [u8; 4] = *b"HBBF";
+const VERSION: u32 = 2;
+const HEADER_LEN: usize = 56;
+
+const HASHING_MURMUR3: u32 = 0;
+const HASHING_HASHER: u32 = 1;
+
+/// Hashed by a custom hasher to identify it in the header.
+const IDENTITY_PROBE: &[u8] = b"hash_bench bloom filter hasher identity";
+
+impl BloomFilter {
+    /// Writes the filter to `path` in the format of
+    /// [`mapped_bloom_filter`](crate::mapped_bloom_filter), replacing any
+    /// existing file.
+    pub fn save_to<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let summary = self.summary();
+        let (kind, identity, seeds) = match self.hashing() {
+            RowHashing::Murmur3(seeds) => (HASHING_MURMUR3, 0, seeds.as_slice()),
+            RowHashing::Hasher(hasher) => (HASHING_HASHER, hasher.hash64(IDENTITY_PROBE), &[][..]),
+        };
+        let bits_offset = bits_offset(seeds.len());
+
+        let mut header = Vec::with_capacity(bits_offset);
+        header.extend_from_slice(&MAGIC);
+        header.extend_from_slice(&VERSION.to_le_bytes());
+        header.extend_from_slice(&summary.n.to_le_bytes());
+        header.extend_from_slice(&summary.m.to_le_bytes());
+        header.extend_from_slice(&summary.f.to_le_bytes());
+        header.extend_from_slice(&summary.k.to_le_bytes());
+        header.extend_from_slice(&kind.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(&identity.to_le_bytes());
+        header.extend_from_slice(&(bits_offset as u64).to_le_bytes());
+        for seed in seeds {
+            header.extend_from_slice(&seed.to_le_bytes());
+        }
+        header.resize(bits_offset, 0);
+
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(&header)?;
+        for word in self.dense_bits().chunks(64) {
+            file.write_all(&word.load_le::<u64>().to_le_bytes())?;
+        }
+        file.into_inner()?.sync_all()
+    }
+
+    /// Maps a filter saved with murmur3 hashing, e.g. by
+    /// [`BloomFilter::new`]. The mapping is read-only and shared, so any
+    /// number of processes can open the same file and the pages are held
+    /// once.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidData`] wrapping a
+    /// [`DecodeError`] if the file is not a valid filter, and with
+    /// [`io::ErrorKind::InvalidInput`] if it was saved with a custom
+    /// hasher.
+    pub fn open_mmap<P: AsRef<Path>>(path: P) -> io::Result<MappedBloomFilter> {
+        MappedBloomFilter::open(path.as_ref(), None)
+    }
+
+    /// Maps a filter saved with a custom hasher. Fails with
+    /// [`io::ErrorKind::InvalidInput`] unless `hasher` matches the saved
+    /// hasher's identity.
+    pub fn open_mmap_with_hasher<P, H>(path: P, hasher: H) -> io::Result<MappedBloomFilter>
+    where
+        P: AsRef<Path>,
+        H: Hasher64 + PartialEq + Send + Sync + 'static,
+    {
+        MappedBloomFilter::open(path.as_ref(), Some(RowHashing::from_hasher(hasher)))
+    }
+}
+
+/// A read-only Bloom filter backed by a memory-mapped file, from
+/// [`BloomFilter::open_mmap`]. Only the pages that lookups touch are read
+/// from disk.
+pub struct MappedBloomFilter {
+    n: u64,
+    f: f32,
+    m: u64,
+    k: u32,
+    hashing: RowHashing,
+    bits_offset: usize,
+    map: Mmap,
+}
+
+impl MappedBloomFilter {
+    fn open(path: &Path, hasher: Option<RowHashing>) -> io::Result<Self> {
+        let map = Mmap::open(path)?;
+        let bytes = map.as_slice();
+        if bytes.len() < HEADER_LEN {
+            return Err(invalid_data(DecodeError::Length {
+                expected: HEADER_LEN,
+                actual: bytes.len(),
+            }));
+        }
+        if bytes[0..4] != MAGIC {
+            return Err(invalid_data(DecodeError::BadMagic));
+        }
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let u64_at =
+            |offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+        let version = u32_at(4);
+        if version != VERSION {
+            return Err(invalid_data(DecodeError::UnsupportedVersion(version)));
+        }
+        let (n, m, f, k) = (
+            u64_at(8),
+            u64_at(16),
+            f32::from_bits(u32_at(24)),
+            u32_at(28),
+        );
+        let (kind, identity) = (u32_at(32), u64_at(40));
+        if m == 0 || k == 0 || !(kind == HASHING_MURMUR3 || kind == HASHING_HASHER) {
+            return Err(invalid_data(DecodeError::Corrupt));
+        }
+        let seeds = if kind == HASHING_MURMUR3 {
+            k as usize
+        } else {
+            0
+        };
+        let bits_offset = bits_offset(seeds);
+        if u64_at(48) != bits_offset as u64 {
+            return Err(invalid_data(DecodeError::Corrupt));
+        }
+        let expected = m
+            .div_ceil(64)
+            .saturating_mul(8)
+            .saturating_add(bits_offset as u64);
+        if bytes.len() as u64 != expected {
+            return Err(invalid_data(DecodeError::Length {
+                expected: expected as usize,
+                actual: bytes.len(),
+            }));
+        }
+
+        let hashing = match (kind, hasher) {
+            (HASHING_MURMUR3, None) => {
+                RowHashing::Murmur3((0..seeds).map(|i| u32_at(HEADER_LEN + 4 * i)).collect())
+            }
+            (HASHING_HASHER, Some(RowHashing::Hasher(hasher)))
+                if hasher.hash64(IDENTITY_PROBE) == identity =>
+            {
+                RowHashing::Hasher(hasher)
+            }
+            (HASHING_HASHER, Some(_)) => return Err(invalid_input("hasher does not match")),
+            (HASHING_HASHER, None) => return Err(invalid_input("saved with a custom hasher")),
+            _ => return Err(invalid_input("saved with murmur3 hashing")),
+        };
+        Ok(MappedBloomFilter {
+            n,
+            f,
+            m,
+            k,
+            hashing,
+            bits_offset,
+            map,
+        })
+    }
+
+    fn bit(&self, index: u64) -> bool {
+        let byte = self.map.as_slice()[self.bits_offset + (index / 8) as usize];
+        byte & (1 << (index % 8)) != 0
+    }
+
+    pub fn lookup(&self, item: &[u8]) -> bool {
+        let hashes = self.hashing.hashes(item);
+        (0..self.k).all(|i| self.bit(probe(&hashes, i, self.m)))
+    }
+
+    pub fn lookup_u64(&self, key: u64) -> bool {
+        let hashes = self.hashing.hashes_u64(key);
+        (0..self.k).all(|i| self.bit(probe(&hashes, i, self.m)))
+    }
+
+    /// Number of bits `m` and of hash functions `k`.
+    pub fn geometry(&self) -> (u64, u32) {
+        (self.m, self.k)
+    }
+
+    /// Copies the mapped bits into an in-memory filter that can be updated.
+    pub fn to_bloom_filter(&self) -> BloomFilter {
+        let bits: BitVec = (0..self.m).map(|index| self.bit(index)).collect();
+        BloomFilter::from_bits(self.n, self.f, self.k, bits, self.hashing.clone())
+    }
+}
+
+/// Offset of the bits after the header and `seeds` row seeds, aligned to 8
+/// so the words can be read in place.
+fn bits_offset(seeds: usize) -> usize {
+    (HEADER_LEN + 4 * seeds).div_ceil(8) * 8
+}
+
+fn invalid_data(error: DecodeError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+fn invalid_input(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// A read-only shared mapping of a whole file.
+struct Mmap {
+    ptr: *const u8,
+    len: usize,
+}
+
+// SAFETY: the mapping is read-only and owned by this value, so sharing or
+// moving it between threads is no different from sharing a `&[u8]`
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+impl Mmap {
+    fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len() as usize;
+        if len == 0 {
+            // mmap rejects empty mappings
+            return Ok(Mmap {
+                ptr: std::ptr::NonNull::dangling().as_ptr(),
+                len,
+            });
+        }
+        // SAFETY: a fresh read-only mapping of an open file descriptor; the
+        // mapping outlives the descriptor, which is closed on return
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Mmap {
+            ptr: ptr as *const u8,
+            len,
+        })
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        // SAFETY: `ptr` points to `len` mapped bytes that live until drop.
+        // Like any file mapping, the contents change if another process
+        // rewrites the file in place; save a new file and rename it instead
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            // SAFETY: unmaps exactly the region mapped in `open`
+            unsafe {
+                libc::munmap(self.ptr as *mut libc::c_void, self.len);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log;
+    use crate::siphash::SipHash13;
+    use std::path::PathBuf;
+
+    /// A file path unique to the test and process, removed on drop.
+    struct TempPath(PathBuf);
+
+    impl TempPath {
+        fn new(name: &str) -> Self {
+            TempPath(std::env::temp_dir().join(format!(
+                "hash_bench_{}_{}.bloom",
+                name,
+                std::process::id()
+            )))
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn saved_filter_answers_like_the_original() {
+        log::init_test_logger();
+        let path = TempPath::new("round_trip");
+        let mut b = BloomFilter::new(10_000, 0.01);
+        for i in 0u32..10_000 {
+            b.insert(&i.to_be_bytes());
+        }
+        b.insert_u64(u64::MAX);
+        b.save_to(&path.0).unwrap();
+
+        let mapped = BloomFilter::open_mmap(&path.0).unwrap();
+        assert_eq!(mapped.geometry(), (b.summary().m, b.summary().k));
+        assert!(mapped.lookup_u64(u64::MAX));
+        assert!(
+            (0u32..30_000).all(|i| mapped.lookup(&i.to_be_bytes()) == b.lookup(&i.to_be_bytes()))
+        );
+
+        // a second mapping of the same file, as another process would have
+        let other = BloomFilter::open_mmap(&path.0).unwrap();
+        assert!(other.lookup(&7u32.to_be_bytes()));
+
+        let copy = mapped.to_bloom_filter();
+        assert!(copy.is_compatible(&b));
+        assert_eq!(copy.summary(), b.summary());
+    }
+
+    #[test]
+    fn seeds_are_saved() {
+        log::init_test_logger();
+        let path = TempPath::new("seeded");
+        let mut b = BloomFilter::with_seed(1000, 0.01, 42);
+        b.insert(b"key");
+        b.save_to(&path.0).unwrap();
+
+        let copy = BloomFilter::open_mmap(&path.0).unwrap().to_bloom_filter();
+        assert_eq!(copy.seeds(), b.seeds());
+        assert_ne!(copy.seeds(), BloomFilter::new(1000, 0.01).seeds());
+        assert!(copy.lookup(b"key"));
+    }
+
+    #[test]
+    fn custom_hasher_must_match() {
+        log::init_test_logger();
+        let path = TempPath::new("hasher");
+        let mut b = BloomFilter::with_hasher(1000, 0.01, SipHash13::with_keys(1, 2));
+        b.insert(b"key");
+        b.save_to(&path.0).unwrap();
+
+        let mapped =
+            BloomFilter::open_mmap_with_hasher(&path.0, SipHash13::with_keys(1, 2)).unwrap();
+        assert!(mapped.lookup(b"key"));
+        assert!(!mapped.lookup(b"absent"));
+
+        let kind = |result: io::Result<MappedBloomFilter>| result.err().map(|e| e.kind());
+        assert_eq!(
+            kind(BloomFilter::open_mmap_with_hasher(
+                &path.0,
+                SipHash13::with_keys(1, 3)
+            )),
+            Some(io::ErrorKind::InvalidInput)
+        );
+        assert_eq!(
+            kind(BloomFilter::open_mmap(&path.0)),
+            Some(io::ErrorKind::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_filters() {
+        log::init_test_logger();
+        let path = TempPath::new("invalid");
+        let decode_error = |bytes: &[u8]| {
+            std::fs::write(&path.0, bytes).unwrap();
+            let error = BloomFilter::open_mmap(&path.0).err().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+            error
+                .into_inner()
+                .unwrap()
+                .downcast::<DecodeError>()
+                .map(|error| *error)
+                .unwrap()
+        };
+        assert!(matches!(
+            decode_error(b""),
+            DecodeError::Length { actual: 0, .. }
+        ));
+        assert_eq!(decode_error(&[0; 64]), DecodeError::BadMagic);
+
+        BloomFilter::new(100, 0.01).save_to(&path.0).unwrap();
+        let saved = std::fs::read(&path.0).unwrap();
+        let mut future = saved.clone();
+        future[4] = 1;
+        assert_eq!(decode_error(&future), DecodeError::UnsupportedVersion(1));
+        assert!(matches!(
+            decode_error(&saved[..saved.len() - 8]),
+            DecodeError::Length { .. }
+        ));
+    }
+}
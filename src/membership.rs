@@ -0,0 +1,242 @@
+//! Approximate membership filters behind one object-safe trait.
+//!
+//! [`ApproxMembership`] only takes byte keys and has no generic methods,
+//! so the filters can be boxed as `dyn ApproxMembership` and picked at
+//! runtime by name with [`build`], e.g. from a command-line flag, instead
+//! of monomorphizing every caller over every filter type.
+
+use crate::bloom_filter::BloomFilter;
+use crate::counting_bloom_filter::CountingBloomFilter;
+use crate::cuckoo_filter::CuckooFilter;
+use crate::deletable_bloom_filter::DeletableBloomFilter;
+use crate::partitioned_bloom_filter::PartitionedBloomFilter;
+use crate::quotient_filter::QuotientFilter;
+
+/// Bits of a deletable Bloom filter covered by one collision bit when it is
+/// built by [`build`].
+pub const DELETABLE_REGION_BITS: u32 = 64;
+
+/// Names accepted by [`build`], in the order they are listed to users.
+pub const FILTERS: [&str; 6] = [
+    "bloom",
+    "counting_bloom",
+    "cuckoo",
+    "deletable_bloom",
+    "partitioned_bloom",
+    "quotient",
+];
+
+/// A set that may answer `true` for items never inserted, but never
+/// `false` for an inserted one.
+pub trait ApproxMembership {
+    fn insert(&mut self, item: &[u8]);
+    fn lookup(&self, item: &[u8]) -> bool;
+    /// False-positive rate the filter's design predicts after `items`
+    /// distinct inserts.
+    fn expected_false_positive_rate(&self, items: u64) -> f64;
+    /// Bytes of the filter's tables, without fixed per-struct overhead.
+    fn size_in_bytes(&self) -> usize;
+}
+
+impl ApproxMembership for BloomFilter {
+    fn insert(&mut self, item: &[u8]) {
+        BloomFilter::insert(self, item);
+    }
+
+    fn lookup(&self, item: &[u8]) -> bool {
+        BloomFilter::lookup(self, item)
+    }
+
+    fn expected_false_positive_rate(&self, items: u64) -> f64 {
+        BloomFilter::expected_false_positive_rate(self, items)
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        self.bit_array_bytes()
+    }
+}
+
+impl ApproxMembership for CountingBloomFilter {
+    fn insert(&mut self, item: &[u8]) {
+        CountingBloomFilter::insert(self, item);
+    }
+
+    fn lookup(&self, item: &[u8]) -> bool {
+        CountingBloomFilter::lookup(self, item)
+    }
+
+    fn expected_false_positive_rate(&self, items: u64) -> f64 {
+        CountingBloomFilter::expected_false_positive_rate(self, items)
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        CountingBloomFilter::size_in_bytes(self)
+    }
+}
+
+impl ApproxMembership for DeletableBloomFilter {
+    fn insert(&mut self, item: &[u8]) {
+        DeletableBloomFilter::insert(self, item);
+    }
+
+    fn lookup(&self, item: &[u8]) -> bool {
+        DeletableBloomFilter::lookup(self, item)
+    }
+
+    fn expected_false_positive_rate(&self, items: u64) -> f64 {
+        DeletableBloomFilter::expected_false_positive_rate(self, items)
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        DeletableBloomFilter::size_in_bytes(self)
+    }
+}
+
+impl ApproxMembership for PartitionedBloomFilter {
+    fn insert(&mut self, item: &[u8]) {
+        PartitionedBloomFilter::insert(self, item);
+    }
+
+    fn lookup(&self, item: &[u8]) -> bool {
+        PartitionedBloomFilter::lookup(self, item)
+    }
+
+    fn expected_false_positive_rate(&self, items: u64) -> f64 {
+        PartitionedBloomFilter::expected_false_positive_rate(self, items)
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        PartitionedBloomFilter::size_in_bytes(self)
+    }
+}
+
+/// Panics on an insert into a full filter; [`build`] sizes it so that its
+/// capacity is never reached.
+impl ApproxMembership for CuckooFilter {
+    fn insert(&mut self, item: &[u8]) {
+        assert!(CuckooFilter::insert(self, item), "cuckoo filter is full");
+    }
+
+    fn lookup(&self, item: &[u8]) -> bool {
+        CuckooFilter::lookup(self, item)
+    }
+
+    fn expected_false_positive_rate(&self, items: u64) -> f64 {
+        CuckooFilter::expected_false_positive_rate(self, items)
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        CuckooFilter::size_in_bytes(self)
+    }
+}
+
+/// Items are stored by their canonical fingerprint, as with
+/// [`QuotientFilter::insert_bytes`].
+impl ApproxMembership for QuotientFilter {
+    fn insert(&mut self, item: &[u8]) {
+        self.insert_bytes(item);
+    }
+
+    fn lookup(&self, item: &[u8]) -> bool {
+        self.lookup_bytes(item)
+    }
+
+    fn expected_false_positive_rate(&self, items: u64) -> f64 {
+        QuotientFilter::expected_false_positive_rate(self, items)
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        QuotientFilter::size_in_bytes(self)
+    }
+}
+
+/// Builds the filter called `name` (one of [`FILTERS`]) sized for `n` items
+/// at false-positive rate `f`, or `None` for an unknown name. Panics unless
+/// `f` is in `(0, 0.5]`, the range [`BloomFilterBuilder`] accepts: above it
+/// the Bloom filters would need fewer than one probe.
+///
+/// [`BloomFilterBuilder`]: crate::bloom_filter::BloomFilterBuilder
+///
+/// The cuckoo filter is sized by [`CuckooFilter::new`]. The quotient filter
+/// gets `ceil(log2(1 / f))` remainder bits and the smallest `q` of at least
+/// 6 that keeps `n` items at or below 75% load.
+pub fn build(name: &str, n: u32, f: f32) -> Option<Box<dyn ApproxMembership>> {
+    assert!(n > 0, "capacity must be positive");
+    assert!(
+        f > 0.0 && f <= 0.5,
+        "false-positive rate must be in (0, 0.5]"
+    );
+    let filter: Box<dyn ApproxMembership> = match name {
+        "bloom" => Box::new(BloomFilter::new(n.into(), f)),
+        "counting_bloom" => Box::new(CountingBloomFilter::new(n, f)),
+        "deletable_bloom" => {
+            let (m, _) = BloomFilter::geometry_u32(n, f);
+            Box::new(DeletableBloomFilter::new(
+                n,
+                f,
+                m.div_ceil(DELETABLE_REGION_BITS),
+            ))
+        }
+        "cuckoo" => Box::new(CuckooFilter::new(n, f)),
+        "partitioned_bloom" => Box::new(PartitionedBloomFilter::new(n, f)),
+        "quotient" => {
+            let r = ((1.0 / f as f64).log2().ceil() as u64).max(1);
+            let mut q = 6;
+            while (3u64 << q) / 4 < n as u64 {
+                q += 1;
+            }
+            assert!(
+                q + r <= 64,
+                "{} items at rate {} need more than 64 bits",
+                n,
+                f
+            );
+            Box::new(QuotientFilter::new(q, r))
+        }
+        _ => return None,
+    };
+    Some(filter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_filter_is_built_by_name_and_has_no_false_negatives() {
+        for name in FILTERS {
+            let mut filter = build(name, 1000, 0.01).unwrap();
+            for i in 0..1000u32 {
+                filter.insert(&i.to_le_bytes());
+            }
+            assert!(
+                (0..1000u32).all(|i| filter.lookup(&i.to_le_bytes())),
+                "{}",
+                name
+            );
+            let false_positives = (1000..11_000u32)
+                .filter(|i| filter.lookup(&i.to_le_bytes()))
+                .count();
+            assert!(false_positives < 300, "{}: {}", name, false_positives);
+        }
+        assert!(build("xor", 1000, 0.01).is_none());
+    }
+
+    #[test]
+    fn boxed_filters_mix_in_one_collection() {
+        let mut filters: Vec<(&str, Box<dyn ApproxMembership>)> = FILTERS
+            .iter()
+            .map(|&name| (name, build(name, 100, 0.05).unwrap()))
+            .collect();
+        for (_, filter) in &mut filters {
+            filter.insert(b"present");
+        }
+        assert!(filters.iter().all(|(_, filter)| filter.lookup(b"present")));
+    }
+
+    #[test]
+    #[should_panic(expected = "must be in (0, 0.5]")]
+    fn loose_rates_are_rejected() {
+        build("partitioned_bloom", 1000, 0.7);
+    }
+}
@@ -0,0 +1,158 @@
+//! Counters and gauges for long-running simulations.
+//!
+//! Only built with the `metrics` feature. Structures report through the
+//! `metrics` facade crate, so any installed recorder (a Prometheus exporter,
+//! for one) picks them up. [`Registry`] is a ready-made recorder that keeps
+//! the values in memory and renders them in the Prometheus text exposition
+//! format for scraping.
+
+use ::metrics::{Counter, Gauge, Histogram, Key, KeyName, Metadata, Recorder, SharedString, Unit};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+type Labels = Vec<(String, String)>;
+
+// gauges store the bits of an f64, as the facade's `GaugeFn for AtomicU64` does
+type Family = Mutex<BTreeMap<(String, Labels), Arc<AtomicU64>>>;
+
+/// In-memory recorder with Prometheus text rendering. Histograms are
+/// dropped.
+#[derive(Debug, Default)]
+pub struct Registry {
+    counters: Family,
+    gauges: Family,
+}
+
+fn to_labels(pairs: &[(&str, &str)]) -> Labels {
+    pairs
+        .iter()
+        .map(|(label, value)| (label.to_string(), value.to_string()))
+        .collect()
+}
+
+fn register(family: &Family, key: &Key) -> Arc<AtomicU64> {
+    let labels = key
+        .labels()
+        .map(|label| (label.key().to_string(), label.value().to_string()))
+        .collect();
+    let mut family = family.lock().unwrap();
+    let value = family.entry((key.name().to_string(), labels)).or_default();
+    Arc::clone(value)
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn counter(&self, name: &str, labels: &[(&str, &str)]) -> u64 {
+        let key = (name.to_string(), to_labels(labels));
+        self.counters
+            .lock()
+            .unwrap()
+            .get(&key)
+            .map_or(0, |value| value.load(Ordering::Relaxed))
+    }
+
+    pub fn gauge(&self, name: &str, labels: &[(&str, &str)]) -> Option<f64> {
+        let key = (name.to_string(), to_labels(labels));
+        let gauges = self.gauges.lock().unwrap();
+        let value = gauges.get(&key)?;
+        Some(f64::from_bits(value.load(Ordering::Relaxed)))
+    }
+
+    /// Renders every metric in the Prometheus text exposition format,
+    /// sorted by name and labels.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let counters = self.counters.lock().unwrap();
+        render_family(&mut out, "counter", &counters, |bits| bits.to_string());
+        let gauges = self.gauges.lock().unwrap();
+        render_family(&mut out, "gauge", &gauges, |bits| {
+            f64::from_bits(bits).to_string()
+        });
+        out
+    }
+}
+
+fn render_family(
+    out: &mut String,
+    kind: &str,
+    metrics: &BTreeMap<(String, Labels), Arc<AtomicU64>>,
+    format: impl Fn(u64) -> String,
+) {
+    let mut last = None;
+    for ((name, labels), value) in metrics {
+        if last != Some(name) {
+            writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+            last = Some(name);
+        }
+        out.push_str(name);
+        if !labels.is_empty() {
+            let pairs: Vec<String> = labels
+                .iter()
+                .map(|(label, value)| format!("{}=\"{}\"", label, escape(value)))
+                .collect();
+            write!(out, "{{{}}}", pairs.join(",")).unwrap();
+        }
+        writeln!(out, " {}", format(value.load(Ordering::Relaxed))).unwrap();
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl Recorder for Registry {
+    fn describe_counter(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+    fn describe_gauge(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+    fn describe_histogram(&self, _key: KeyName, _unit: Option<Unit>, _description: SharedString) {}
+
+    fn register_counter(&self, key: &Key, _metadata: &Metadata<'_>) -> Counter {
+        Counter::from_arc(register(&self.counters, key))
+    }
+
+    fn register_gauge(&self, key: &Key, _metadata: &Metadata<'_>) -> Gauge {
+        Gauge::from_arc(register(&self.gauges, key))
+    }
+
+    fn register_histogram(&self, _key: &Key, _metadata: &Metadata<'_>) -> Histogram {
+        Histogram::noop()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn registry_accumulates_and_renders() {
+        let registry = Registry::new();
+        ::metrics::with_local_recorder(&registry, || {
+            ::metrics::counter!("lookups_total").increment(2);
+            ::metrics::counter!("lookups_total").increment(3);
+            ::metrics::gauge!("resources", "node" => "b").set(4.0);
+            ::metrics::gauge!("resources", "node" => "a\"").set(1.5);
+            ::metrics::gauge!("resources", "node" => "b").set(7.0);
+        });
+
+        assert_eq!(registry.counter("lookups_total", &[]), 5);
+        assert_eq!(registry.counter("missing", &[]), 0);
+        assert_eq!(registry.gauge("resources", &[("node", "b")]), Some(7.0));
+        assert_eq!(
+            registry.render(),
+            "# TYPE lookups_total counter\n\
+             lookups_total 5\n\
+             # TYPE resources gauge\n\
+             resources{node=\"a\\\"\"} 1.5\n\
+             resources{node=\"b\"} 7\n"
+        );
+    }
+}
@@ -0,0 +1,216 @@
+use crate::bloom_filter::BloomFilter;
+use crate::hasher::{Hasher64, RowHashing, DEFAULT_SEED};
+use crate::seed::SeedSequence;
+use bitvec::prelude::BitVec;
+
+/// Partitioned Bloom filter: the bit array is split into `k` equal
+/// partitions and probe `i` only sets bits in partition `i`, so the probes
+/// of one item never collide with each other.
+///
+/// Each partition holds `ceil(m / k)` bits for the `m` and `k` of
+/// [`BloomFilter::new`] with the same `n` and `f`. A bit of a partition is
+/// then set by one probe per item instead of `k`, which gives a slightly
+/// higher false-positive rate, `(1 - (1 - k/m)^items)^k` against
+/// `(1 - (1 - 1/m)^(k * items))^k`, negligible once `m` is large. In
+/// exchange the fill of every partition is the same in expectation, and the
+/// partitions can be stored, probed or merged independently.
+#[derive(Clone)]
+pub struct PartitionedBloomFilter {
+    k: u32,
+    /// Bits per partition.
+    partition_bits: u32,
+    bit_array: BitVec,
+    hashing: RowHashing,
+}
+
+impl PartitionedBloomFilter {
+    pub fn new(n: u32, f: f32) -> Self {
+        let (_, k) = Self::geometry(n, f);
+        let hashing = RowHashing::murmur3(k as usize, SeedSequence::new(DEFAULT_SEED));
+        Self::with_hashing(n, f, hashing)
+    }
+
+    /// Creates a filter whose probes are derived from `hasher`.
+    pub fn with_hasher<H: Hasher64 + PartialEq + Send + Sync + 'static>(
+        n: u32,
+        f: f32,
+        hasher: H,
+    ) -> Self {
+        Self::with_hashing(n, f, RowHashing::from_hasher(hasher))
+    }
+
+    /// `m` and `k` of [`BloomFilter::new`], with at least one partition:
+    /// rates above one half would otherwise ask for none.
+    fn geometry(n: u32, f: f32) -> (u32, u32) {
+        let (m, k) = BloomFilter::geometry_u32(n, f);
+        (m, k.max(1))
+    }
+
+    fn with_hashing(n: u32, f: f32, hashing: RowHashing) -> Self {
+        let (m, k) = Self::geometry(n, f);
+        let partition_bits = m.div_ceil(k).max(1);
+        PartitionedBloomFilter {
+            k,
+            partition_bits,
+            bit_array: BitVec::repeat(false, (partition_bits * k) as usize),
+            hashing,
+        }
+    }
+
+    /// Bit of probe `row`, which lands in partition `row`.
+    fn index(&self, row: u32, hash: u64) -> usize {
+        let partition_bits = self.partition_bits as u64;
+        (row as u64 * partition_bits + hash % partition_bits) as usize
+    }
+
+    pub fn insert(&mut self, item: &[u8]) {
+        let hashes = self.hashing.hashes(item);
+        for i in 0..self.k {
+            let index = self.index(i, hashes.row(i));
+            self.bit_array.set(index, true);
+        }
+    }
+
+    pub fn lookup(&self, item: &[u8]) -> bool {
+        let hashes = self.hashing.hashes(item);
+        (0..self.k).all(|i| self.bit_array[self.index(i, hashes.row(i))])
+    }
+
+    /// Number of partitions, one per hash function.
+    pub fn partitions(&self) -> u32 {
+        self.k
+    }
+
+    pub fn partition_bits(&self) -> u32 {
+        self.partition_bits
+    }
+
+    /// Share of bits set in each partition.
+    pub fn partition_fill(&self) -> Vec<f64> {
+        self.bit_array
+            .chunks(self.partition_bits as usize)
+            .map(|partition| partition.count_ones() as f64 / self.partition_bits as f64)
+            .collect()
+    }
+
+    /// Bytes held by the bit array.
+    pub fn size_in_bytes(&self) -> usize {
+        std::mem::size_of_val(self.bit_array.as_raw_slice())
+    }
+
+    /// False-positive rate in theory after `items` distinct inserts: each
+    /// partition is filled by one probe per item, `(1 - (1 - 1/s)^items)^k`
+    /// for partitions of `s` bits.
+    pub fn expected_false_positive_rate(&self, items: u64) -> f64 {
+        let miss_one = (-1.0 / self.partition_bits as f64).ln_1p();
+        let fill = -(items as f64 * miss_one).exp_m1();
+        fill.powi(self.k as i32)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log;
+    use crate::siphash::SipHash13;
+
+    fn false_positive_rate(lookup: impl Fn(&[u8]) -> bool, from: u32, probes: u32) -> f64 {
+        let hits = (from..from + probes)
+            .filter(|i| lookup(&i.to_be_bytes()))
+            .count();
+        hits as f64 / probes as f64
+    }
+
+    #[test]
+    fn insert_lookup_and_geometry() {
+        log::init_test_logger();
+        let mut b = PartitionedBloomFilter::new(100, 0.01);
+        let plain = BloomFilter::new(100, 0.01);
+        assert_eq!(b.partitions(), plain.summary().k);
+        let bits = u64::from(b.partition_bits() * b.partitions());
+        assert!(bits >= plain.m());
+        assert!(bits < plain.m() + u64::from(b.partitions()));
+        b.insert(b"1");
+        assert!(b.lookup(b"1"));
+        assert!(!b.lookup(b"2"));
+        // one bit per partition
+        assert!(b
+            .partition_fill()
+            .iter()
+            .all(|&fill| fill * b.partition_bits() as f64 == 1.0));
+    }
+
+    #[test]
+    fn keyed_hasher_has_no_false_negatives() {
+        log::init_test_logger();
+        let mut b = PartitionedBloomFilter::with_hasher(1000, 0.01, SipHash13::with_keys(1, 2));
+        for i in 0u32..1000 {
+            b.insert(&i.to_be_bytes());
+        }
+        assert!((0u32..1000).all(|i| b.lookup(&i.to_be_bytes())));
+        let fill = b.partition_fill();
+        assert_eq!(fill.len(), b.partitions() as usize);
+        assert!(
+            fill.iter().all(|&fill| (fill - 0.5).abs() < 0.1),
+            "{:?}",
+            fill
+        );
+    }
+
+    #[test]
+    fn false_positive_rate_is_close_to_the_standard_layout() {
+        log::init_test_logger();
+        let (n, f) = (10_000, 0.01);
+        let mut partitioned = PartitionedBloomFilter::new(n, f);
+        let mut standard = BloomFilter::new(n.into(), f);
+        for i in 0..n {
+            partitioned.insert(&i.to_be_bytes());
+            standard.insert(&i.to_be_bytes());
+        }
+        let probes = 200_000;
+        let measured_partitioned = false_positive_rate(|item| partitioned.lookup(item), n, probes);
+        let measured_standard = false_positive_rate(|item| standard.lookup(item), n, probes);
+
+        let expected_partitioned = partitioned.expected_false_positive_rate(n as u64);
+        let expected_standard = standard.expected_false_positive_rate(n as u64);
+        // the partitioned layout pays a little, but only a little
+        assert!(expected_partitioned > expected_standard);
+        assert!(expected_partitioned < 1.05 * expected_standard);
+        for (measured, expected) in [
+            (measured_partitioned, expected_partitioned),
+            (measured_standard, expected_standard),
+        ] {
+            assert!(
+                (measured / expected - 1.0).abs() < 0.15,
+                "measured {}, expected {}",
+                measured,
+                expected
+            );
+        }
+        assert!(measured_partitioned < 1.3 * f as f64);
+    }
+
+    #[test]
+    fn loose_rates_keep_one_partition() {
+        log::init_test_logger();
+        for mut b in [
+            PartitionedBloomFilter::new(1000, 0.7),
+            PartitionedBloomFilter::with_hasher(1000, 0.7, SipHash13::with_keys(1, 2)),
+        ] {
+            for i in 0..1000u32 {
+                b.insert(&i.to_le_bytes());
+            }
+            assert!((0..1000u32).all(|i| b.lookup(&i.to_le_bytes())));
+        }
+    }
+
+    #[test]
+    fn small_filters_pay_more_for_partitioning() {
+        log::init_test_logger();
+        let partitioned = PartitionedBloomFilter::new(10, 0.01);
+        let standard = BloomFilter::new(10, 0.01);
+        let ratio = partitioned.expected_false_positive_rate(10)
+            / standard.expected_false_positive_rate(10);
+        assert!(ratio > 1.05, "{}", ratio);
+    }
+}
@@ -0,0 +1,132 @@
+use crate::bloom_filter::BloomFilter;
+
+/// Bloom filter over byte-string keys that also answers "could any key
+/// with this prefix exist", like the prefix blooms of RocksDB.
+///
+/// Besides each whole key, the prefixes of the key at every configured
+/// length go into a second filter; keys shorter than a length skip it. A
+/// prefix query checks the prefix cut to the longest configured length
+/// that fits, which every key starting with the prefix has inserted, so
+/// there are no false negatives. A query shorter than every length cannot
+/// be ruled out and always passes, and a query longer than its cut
+/// answers only for the cut.
+#[derive(Clone)]
+pub struct PrefixBloomFilter {
+    /// Increasing and distinct.
+    prefix_lens: Vec<usize>,
+    keys: BloomFilter,
+    prefixes: BloomFilter,
+}
+
+impl PrefixBloomFilter {
+    /// Creates a filter for `n` keys at rate `f`, indexing their prefixes
+    /// of each length in `prefix_lens`. The prefix filter is sized for one
+    /// distinct prefix per key and length, so keys that share prefixes
+    /// leave it emptier than `f` suggests. Panics if `prefix_lens` is empty
+    /// or holds a zero.
+    pub fn new(n: u64, f: f32, prefix_lens: &[usize]) -> Self {
+        assert!(
+            !prefix_lens.is_empty() && !prefix_lens.contains(&0),
+            "prefix lengths must be positive and at least one is needed"
+        );
+        let mut prefix_lens = prefix_lens.to_vec();
+        prefix_lens.sort_unstable();
+        prefix_lens.dedup();
+        let prefixes = n.saturating_mul(prefix_lens.len() as u64);
+        PrefixBloomFilter {
+            keys: BloomFilter::new(n, f),
+            prefixes: BloomFilter::new(prefixes, f),
+            prefix_lens,
+        }
+    }
+
+    /// The configured prefix lengths, in increasing order.
+    pub fn prefix_lens(&self) -> &[usize] {
+        &self.prefix_lens
+    }
+
+    /// Inserts `key` and its prefixes of the configured lengths.
+    pub fn insert(&mut self, key: &[u8]) {
+        self.keys.insert(key);
+        for &len in self.prefix_lens.iter().take_while(|&&len| len <= key.len()) {
+            self.prefixes.insert(&key[..len]);
+        }
+    }
+
+    /// Whether `key` itself may have been inserted.
+    pub fn lookup(&self, key: &[u8]) -> bool {
+        self.keys.lookup(key)
+    }
+
+    /// Whether a key starting with `prefix` may have been inserted.
+    pub fn may_contain_prefix(&self, prefix: &[u8]) -> bool {
+        match self
+            .prefix_lens
+            .iter()
+            .rev()
+            .find(|&&len| len <= prefix.len())
+        {
+            Some(&len) => self.prefixes.lookup(&prefix[..len]),
+            None => true,
+        }
+    }
+
+    /// Bytes held by the bit arrays of both filters.
+    pub fn size_in_bytes(&self) -> usize {
+        self.keys.bit_array_bytes() + self.prefixes.bit_array_bytes()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log;
+
+    #[test]
+    fn finds_keys_and_their_prefixes() {
+        log::init_test_logger();
+        let mut b = PrefixBloomFilter::new(100, 0.01, &[8, 4, 4]);
+        assert_eq!(b.prefix_lens(), &[4, 8]);
+        b.insert(b"user:0042:name");
+        b.insert(b"log");
+
+        assert!(b.lookup(b"user:0042:name") && b.lookup(b"log"));
+        // a prefix is not a key
+        assert!(!b.lookup(b"user"));
+
+        assert!(b.may_contain_prefix(b"user"));
+        assert!(b.may_contain_prefix(b"user:004"));
+        // answered by the 8-byte cut
+        assert!(b.may_contain_prefix(b"user:0042:age"));
+        assert!(!b.may_contain_prefix(b"item"));
+        assert!(!b.may_contain_prefix(b"user:0143"));
+        // "log" is shorter than every length, and so is the query
+        assert!(b.may_contain_prefix(b"lo"));
+        assert!(!b.may_contain_prefix(b"logs"));
+    }
+
+    #[test]
+    fn no_false_negatives_and_few_false_positives() {
+        log::init_test_logger();
+        let mut b = PrefixBloomFilter::new(10_000, 0.01, &[6]);
+        for user in 0u32..1000 {
+            for field in 0..10 {
+                b.insert(format!("{:06}:{}", user, field).as_bytes());
+            }
+        }
+        assert!((0u32..1000).all(|user| b.may_contain_prefix(format!("{:06}", user).as_bytes())));
+        assert!((0u32..1000).all(|user| b.may_contain_prefix(format!("{:06}:3", user).as_bytes())));
+
+        let false_positives = (1000u32..101_000)
+            .filter(|user| b.may_contain_prefix(format!("{:06}", user).as_bytes()))
+            .count();
+        // 1000 prefixes in a filter sized for 10000
+        assert!(false_positives < 100, "{}", false_positives);
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_length_prefix_is_rejected() {
+        PrefixBloomFilter::new(100, 0.01, &[0, 4]);
+    }
+}
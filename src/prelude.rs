@@ -0,0 +1,86 @@
+//! The traits and main types of the crate in one glob import.
+//!
+//! ```
+//! use hash_bench::prelude::*;
+//!
+//! # #[cfg(feature = "bloom")] {
+//! let mut filter = BloomFilter::new(1000, 0.01);
+//! filter.insert(b"key");
+//! assert!(filter.lookup(b"key"));
+//! # }
+//! ```
+//!
+//! Only the structures of the enabled features are exported.
+
+pub use crate::{BuildError, DecodeError, Fingerprinter, Hasher64, Murmur3};
+
+#[cfg(feature = "bloom")]
+pub use crate::{
+    BloomFilter, ConcurrentBloomFilter, CountingBloomFilter, DeletableBloomFilter,
+    PartitionedBloomFilter, PrefixBloomFilter, RotatingBloomFilter, SpectralBloomFilter,
+};
+
+#[cfg(feature = "quotient")]
+pub use crate::{CountingQuotientFilter, CuckooFilter, QuotientFilter, StaticQuotientFilter};
+
+#[cfg(all(feature = "bloom", feature = "quotient"))]
+pub use crate::ApproxMembership;
+
+#[cfg(any(feature = "bloom", feature = "quotient", feature = "cms"))]
+pub use crate::FrequencyEstimator;
+
+#[cfg(feature = "cms")]
+pub use crate::CountMinSketch;
+
+#[cfg(feature = "sketches")]
+pub use crate::{HyperLogLog, TDigest};
+
+#[cfg(feature = "ring")]
+pub use crate::{
+    ConsistentHasher, HashRing, HashRingInterface, JumpHash, Node, Placement, Rendezvous,
+    RingBackend,
+};
+
+#[cfg(all(
+    test,
+    feature = "bloom",
+    feature = "quotient",
+    feature = "cms",
+    feature = "ring"
+))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn prelude_covers_filters_sketches_and_rings() {
+        let mut filters: Vec<Box<dyn ApproxMembership>> = vec![
+            Box::new(BloomFilter::new(100, 0.01)),
+            Box::new(QuotientFilter::new(8, 8)),
+        ];
+        for filter in &mut filters {
+            filter.insert(b"key");
+            assert!(filter.lookup(b"key"));
+        }
+
+        let mut estimators: Vec<Box<dyn FrequencyEstimator>> = vec![
+            Box::new(CountMinSketch::new(0.01, 0.01)),
+            Box::new(CountingQuotientFilter::new(8, 8)),
+        ];
+        for estimator in &mut estimators {
+            estimator.update(b"key", 3);
+            assert_eq!(estimator.estimate(b"key"), 3);
+        }
+
+        let mut ring: HashRing<u64> = HashRing::new(8);
+        ring.add_node(10);
+        let node = ring.lookup(3).unwrap();
+        assert_eq!(*node.lock().unwrap().value(), 10);
+
+        let mut hashers: Vec<Box<dyn ConsistentHasher>> =
+            vec![Box::new(JumpHash::new()), Box::new(Rendezvous::new())];
+        for hasher in &mut hashers {
+            hasher.join(7);
+            assert_eq!(hasher.node_for(b"key"), Some(7));
+        }
+    }
+}
@@ -1,5 +1,15 @@
-#[derive(Clone, Default)]
-struct Slot {
+pub use crate::decode::DecodeError;
+use crate::fingerprint::{CanonicalFingerprinter, Fingerprinter};
+use crate::static_filter::StaticQuotientFilter;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{BuildHasher, BuildHasherDefault, Hash};
+
+/// Remainder bits of a filter collected from an iterator.
+pub const DEFAULT_REMAINDER_BITS: u64 = 8;
+
+/// One slot, unpacked: `remainder << 3` and the three flags.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Slot {
     data: u64,
 }
 
@@ -10,24 +20,24 @@ const FLAG_CONTINUED: u64 = 1 << 1;
 const FLAG_SHIFTED: u64 = 1 << 2;
 
 impl Slot {
-    fn is_empty(&self) -> bool {
+    pub(crate) fn is_empty(&self) -> bool {
         self.data == 0
     }
 
-    fn remainder(&self) -> u64 {
+    pub(crate) fn remainder(&self) -> u64 {
         self.data >> FLAG_BITS
     }
 
-    fn set_remainder(&mut self, remainder: u64) {
+    pub(crate) fn set_remainder(&mut self, remainder: u64) {
         let flags = self.data & FLAG_MASK;
         self.data = (remainder << FLAG_BITS) | flags;
     }
 
-    fn is_occupied(&self) -> bool {
+    pub(crate) fn is_occupied(&self) -> bool {
         (self.data & FLAG_OCCUPIED) != 0
     }
 
-    fn set_occupied(&mut self, value: bool) {
+    pub(crate) fn set_occupied(&mut self, value: bool) {
         if value {
             self.data |= FLAG_OCCUPIED;
         } else {
@@ -35,11 +45,11 @@ impl Slot {
         }
     }
 
-    fn is_continued(&self) -> bool {
+    pub(crate) fn is_continued(&self) -> bool {
         (self.data & FLAG_CONTINUED) != 0
     }
 
-    fn set_continued(&mut self, value: bool) {
+    pub(crate) fn set_continued(&mut self, value: bool) {
         if value {
             self.data |= FLAG_CONTINUED;
         } else {
@@ -47,11 +57,11 @@ impl Slot {
         }
     }
 
-    fn is_shifted(&self) -> bool {
+    pub(crate) fn is_shifted(&self) -> bool {
         (self.data & FLAG_SHIFTED) != 0
     }
 
-    fn set_shifted(&mut self, value: bool) {
+    pub(crate) fn set_shifted(&mut self, value: bool) {
         if value {
             self.data |= FLAG_SHIFTED;
         } else {
@@ -60,26 +70,202 @@ impl Slot {
     }
 }
 
+/// Values of `width` bits each, packed back to back into 64-bit words; a
+/// value may straddle two words.
+#[derive(Clone)]
+pub(crate) struct PackedArray {
+    /// Bits per value, 1 to 64.
+    width: u32,
+    words: Vec<u64>,
+}
+
+impl PackedArray {
+    pub(crate) fn new(len: usize, width: u32) -> Self {
+        assert!((1..=u64::BITS).contains(&width), "values are 1 to 64 bits");
+        PackedArray {
+            width,
+            words: vec![0; (len * width as usize).div_ceil(64)],
+        }
+    }
+
+    fn mask(&self) -> u64 {
+        u64::MAX >> (u64::BITS - self.width)
+    }
+
+    /// Word and bit offset at which value `idx` starts.
+    fn position(&self, idx: usize) -> (usize, u32) {
+        let bit = idx * self.width as usize;
+        (bit / 64, (bit % 64) as u32)
+    }
+
+    pub(crate) fn get(&self, idx: usize) -> u64 {
+        let (word, shift) = self.position(idx);
+        let mut value = self.words[word] >> shift;
+        if shift + self.width > u64::BITS {
+            value |= self.words[word + 1] << (u64::BITS - shift);
+        }
+        value & self.mask()
+    }
+
+    pub(crate) fn set(&mut self, idx: usize, value: u64) {
+        let (word, shift) = self.position(idx);
+        let mask = self.mask();
+        let value = value & mask;
+        self.words[word] = (self.words[word] & !(mask << shift)) | (value << shift);
+        if shift + self.width > u64::BITS {
+            let low = u64::BITS - shift;
+            let high_mask = mask >> low;
+            self.words[word + 1] = (self.words[word + 1] & !high_mask) | (value >> low);
+        }
+    }
+
+    pub(crate) fn size_in_bytes(&self) -> usize {
+        std::mem::size_of_val(self.words.as_slice())
+    }
+}
+
+/// Slots of `r + 3` bits each, remainder and flags, packed into a
+/// [`PackedArray`]. With the usual 8-bit remainders this takes 11 bits per
+/// slot instead of a whole word.
+#[derive(Clone)]
+pub(crate) struct SlotArray {
+    bits: PackedArray,
+}
+
+impl SlotArray {
+    pub(crate) fn new(len: usize, r: u64) -> Self {
+        assert!(
+            r + FLAG_BITS <= u64::BITS as u64,
+            "remainders are at most 61 bits"
+        );
+        SlotArray {
+            bits: PackedArray::new(len, (r + FLAG_BITS) as u32),
+        }
+    }
+
+    pub(crate) fn get(&self, idx: usize) -> Slot {
+        Slot {
+            data: self.bits.get(idx),
+        }
+    }
+
+    pub(crate) fn set(&mut self, idx: usize, slot: Slot) {
+        self.bits.set(idx, slot.data);
+    }
+
+    /// Applies `f` to slot `idx` and stores the result.
+    pub(crate) fn update(&mut self, idx: usize, f: impl FnOnce(&mut Slot)) {
+        let mut slot = self.get(idx);
+        f(&mut slot);
+        self.set(idx, slot);
+    }
+
+    pub(crate) fn size_in_bytes(&self) -> usize {
+        self.bits.size_in_bytes()
+    }
+}
+
+/// Displacement caused by a single insert.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InsertStats {
+    /// Number of existing entries moved one slot to the right.
+    pub shifted: usize,
+    /// Distance from the key's home bucket to the slot it was stored in.
+    pub probe_length: usize,
+    /// Whether the filter had to grow before the insert.
+    pub resized: bool,
+}
+
+/// Parameters and occupancy of a [`QuotientFilter`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuotientFilterSummary {
+    pub q: u64,
+    pub r: u64,
+    pub entries: usize,
+    /// Number of slots, `2^q`.
+    pub capacity: usize,
+    pub load_factor: f64,
+}
+
+/// Quotient filter laid out as in the rank-and-select quotient filter
+/// (Pandey et al.): the slots hold bare remainders, and per block of 64
+/// slots an occupied bitmap, a runend bitmap and an offset locate every run
+/// by rank and select. Whether a slot is in use, continues a run or is
+/// shifted is derived from these instead of being stored in the slot.
 pub struct QuotientFilter {
     q: u64,
     r: u64,
     entries: usize,
     size: usize,
-    filter: Vec<Slot>,
+    /// The `r`-bit remainder of every slot; 0 in empty slots.
+    remainders: PackedArray,
+    /// Bit `i` is set when quotient `i` has a run, 64 quotients per word.
+    occupied: Vec<u64>,
+    /// Bit `i` is set when slot `i` holds the last entry of a run.
+    runends: Vec<u64>,
+    /// Per block of 64 slots, the number of slots from the block's first
+    /// one on that hold entries of runs whose quotient lies before the
+    /// block. At most `2^q`, which `new` keeps below `2^32`.
+    offsets: Vec<u32>,
 }
 
 impl QuotientFilter {
     pub fn new(q: u64, r: u64) -> Self {
+        assert!(q < u32::BITS as u64, "at most 2^31 slots are supported");
+        // exported slots keep the three flags of the classic layout
+        assert!(
+            (1..=u64::BITS as u64 - FLAG_BITS).contains(&r),
+            "remainders are 1 to 61 bits"
+        );
         let size: usize = 1 << q;
         QuotientFilter {
             q,
             r,
             size,
             entries: 0,
-            filter: vec![Slot::default(); size],
+            remainders: PackedArray::new(size, r as u32),
+            occupied: vec![0; size.div_ceil(64)],
+            runends: vec![0; size.div_ceil(64)],
+            offsets: vec![0; size.div_ceil(64)],
+        }
+    }
+
+    fn is_occupied(&self, idx: usize) -> bool {
+        self.occupied[idx / 64] >> (idx % 64) & 1 == 1
+    }
+
+    fn set_occupied(&mut self, idx: usize, value: bool) {
+        let bit = 1 << (idx % 64);
+        if value {
+            self.occupied[idx / 64] |= bit;
+        } else {
+            self.occupied[idx / 64] &= !bit;
         }
     }
 
+    /// Occupied quotients in `lo..hi`, found by scanning the bitmap word by
+    /// word instead of probing every slot.
+    fn occupied_quotients(&self, lo: usize, hi: usize) -> impl Iterator<Item = usize> + '_ {
+        let mut word_idx = lo / 64;
+        // bits below `lo` in the first word are masked off
+        let mut word = self
+            .occupied
+            .get(word_idx)
+            .map_or(0, |&word| word & (u64::MAX << (lo % 64)));
+        std::iter::from_fn(move || loop {
+            if word != 0 {
+                let idx = word_idx * 64 + word.trailing_zeros() as usize;
+                word &= word - 1;
+                return (idx < hi).then_some(idx);
+            }
+            word_idx += 1;
+            if word_idx * 64 >= hi {
+                return None;
+            }
+            word = self.occupied[word_idx];
+        })
+    }
+
     fn prev_index(&self, idx: usize) -> usize {
         (idx + self.size - 1) % self.size
     }
@@ -88,25 +274,223 @@ impl QuotientFilter {
         (idx + 1) % self.size
     }
 
+    /// Number of slots from `from` forward to `to`, wrapping around the table.
+    fn distance(&self, from: usize, to: usize) -> usize {
+        (to + self.size - from) % self.size
+    }
+
+    /// First slot of the run of `home_idx`, or where its run would start
+    /// if it has none.
+    ///
+    /// The runs of a block's quotients are laid out in order right after
+    /// the block's offset, so the run starts after as many run ends past
+    /// the offset as the block has occupied quotients before `home_idx`, or
+    /// at `home_idx` itself if that comes later.
     fn find_run_head(&self, home_idx: usize) -> usize {
-        let mut bucket = home_idx;
-        while self.filter[bucket].is_shifted() {
-            bucket = self.prev_index(bucket);
+        let block = home_idx / 64;
+        let preceding = self.occupied[block] & ((1 << (home_idx % 64)) - 1);
+        let mut head = block * 64 + self.offsets[block] as usize;
+        if preceding != 0 {
+            head = self.select_runend(head, preceding.count_ones() as usize) + 1;
         }
+        head.max(home_idx) % self.size
+    }
 
-        let mut run_head = bucket;
-        let mut probe = bucket;
-        while probe != home_idx {
-            run_head = self.next_index(run_head);
-            while self.filter[run_head].is_continued() {
-                run_head = self.next_index(run_head);
+    /// Position of the `n`th run end at or after `from`, counting a word of
+    /// the bitmap at a time. Positions past the end of the table are not
+    /// wrapped, so they still compare with `from`.
+    fn select_runend(&self, from: usize, mut n: usize) -> usize {
+        let mut pos = from;
+        loop {
+            let idx = pos % self.size;
+            let mut word = self.runends[idx / 64] >> (idx % 64);
+            let ones = word.count_ones() as usize;
+            if ones >= n {
+                for _ in 1..n {
+                    word &= word - 1;
+                }
+                return pos + word.trailing_zeros() as usize;
             }
-            probe = self.next_index(probe);
-            while !self.filter[probe].is_occupied() {
-                probe = self.next_index(probe);
+            n -= ones;
+            pos += (64 - idx % 64).min(self.size - idx);
+        }
+    }
+
+    /// End, one past the last slot and not wrapped, of the runs of the
+    /// quotients up to `idx` that reach `idx`'s block. Slot `idx` is in use
+    /// exactly when this lies past it.
+    fn runs_end(&self, idx: usize) -> usize {
+        let block = idx / 64;
+        let upto = self.occupied[block] & (u64::MAX >> (63 - idx % 64));
+        let past = block * 64 + self.offsets[block] as usize;
+        if upto == 0 {
+            past
+        } else {
+            self.select_runend(past, upto.count_ones() as usize) + 1
+        }
+    }
+
+    fn is_used(&self, idx: usize) -> bool {
+        self.entries == self.size || self.runs_end(idx) > idx
+    }
+
+    /// Whether slot `idx` holds an entry of the same run as the slot before.
+    fn is_continued(&self, idx: usize) -> bool {
+        let prev = self.prev_index(idx);
+        !self.is_runend(prev) && self.is_used(prev)
+    }
+
+    /// First empty slot at or after `idx`, skipping the runs of the
+    /// quotients up to each candidate at once. The filter must not be full.
+    fn find_empty(&self, mut idx: usize) -> usize {
+        loop {
+            let past = self.runs_end(idx);
+            if past <= idx {
+                return idx;
+            }
+            idx = past % self.size;
+        }
+    }
+
+    fn is_runend(&self, idx: usize) -> bool {
+        self.runends[idx / 64] >> (idx % 64) & 1 == 1
+    }
+
+    fn set_runend(&mut self, idx: usize, value: bool) {
+        let bit = 1 << (idx % 64);
+        if value {
+            self.runends[idx / 64] |= bit;
+        } else {
+            self.runends[idx / 64] &= !bit;
+        }
+    }
+
+    /// Brings the block offsets up to date after an entry of `home` went
+    /// into `from` and the entries from there up to `to` moved one slot
+    /// right.
+    fn note_insert(&mut self, home: usize, from: usize, to: usize) {
+        // a block starting in the moved range gains one more slot of entries
+        // from before it
+        let block_len = self.size.min(64);
+        let mut offset = block_len - from % block_len;
+        while offset <= self.distance(from, to) {
+            self.offsets[(from + offset) % self.size / 64] += 1;
+            offset += block_len;
+        }
+
+        // and so does a block whose offset reaches the new entry, if that
+        // belongs to a run from before the block; an earlier block reaches
+        // this far only if the later one does too
+        let mut first = from - from % block_len;
+        for _ in 0..self.offsets.len() {
+            let reach = self.distance(first, from);
+            if reach > self.offsets[first / 64] as usize || self.distance(home, from) <= reach {
+                break;
+            }
+            self.offsets[first / 64] += 1;
+            first = (first + self.size - block_len) % self.size;
+        }
+    }
+
+    /// Slot `idx` in the classic layout, with the occupied, continued and
+    /// shifted flags derived from the block metadata.
+    fn slot(&self, idx: usize) -> Slot {
+        let mut slot = Slot::default();
+        slot.set_occupied(self.is_occupied(idx));
+        if self.is_used(idx) {
+            let continued = self.is_continued(idx);
+            slot.set_remainder(self.remainders.get(idx));
+            slot.set_continued(continued);
+            // a run head is in its home slot only if it heads the run of
+            // that very quotient
+            slot.set_shifted(continued || !self.is_occupied(idx) || self.find_run_head(idx) != idx);
+        }
+        slot
+    }
+
+    /// Every slot in the classic layout, deriving the flags run by run.
+    fn slots(&self) -> SlotArray {
+        let mut slots = SlotArray::new(self.size, self.r);
+        for quotient in self.occupied_quotients(0, self.size) {
+            slots.update(quotient, |slot| slot.set_occupied(true));
+            let run_head = self.find_run_head(quotient);
+            self.visit_run(run_head, |idx| {
+                slots.update(idx, |slot| {
+                    slot.set_remainder(self.remainders.get(idx));
+                    slot.set_continued(idx != run_head);
+                    slot.set_shifted(idx != quotient);
+                });
+            });
+        }
+        slots
+    }
+
+    /// Replaces the contents with `slots`, given in the classic layout,
+    /// recomputing the bitmaps and the block offsets from their flags.
+    /// Fails with [`DecodeError::Corrupt`] if a run cannot be traced back
+    /// to an occupied quotient of its cluster.
+    fn load_slots(&mut self, slots: &SlotArray) -> Result<(), DecodeError> {
+        self.occupied.fill(0);
+        self.runends.fill(0);
+        self.offsets.fill(0);
+        for idx in 0..self.size {
+            let slot = slots.get(idx);
+            self.remainders.set(idx, slot.remainder());
+            self.set_occupied(idx, slot.is_occupied());
+            let ends_run = !slot.is_empty() && !slots.get(self.next_index(idx)).is_continued();
+            self.set_runend(idx, ends_run);
+        }
+
+        // the quotient of every entry, found by walking each cluster's runs
+        // alongside its occupied quotients
+        let mut homes = vec![0; self.size];
+        let start = (0..self.size)
+            .find(|&idx| !slots.get(idx).is_shifted())
+            .ok_or(DecodeError::Corrupt)?;
+        let (mut home, mut cluster_start) = (start, start);
+        // the slot at `start` is unshifted, so this is never read for it
+        let mut after_empty = true;
+        for offset in 0..self.size {
+            let idx = (start + offset) % self.size;
+            let slot = slots.get(idx);
+            if slot.is_empty() {
+                after_empty = true;
+                continue;
+            }
+            if !slot.is_shifted() {
+                home = idx;
+                cluster_start = idx;
+            } else if after_empty {
+                return Err(DecodeError::Corrupt);
+            } else if !slot.is_continued() {
+                // the next occupied quotient, looked for at most once round
+                home = (1..self.size)
+                    .map(|step| (home + step) % self.size)
+                    .find(|&quotient| self.is_occupied(quotient))
+                    .ok_or(DecodeError::Corrupt)?;
+                if self.distance(cluster_start, home) > self.distance(cluster_start, idx) {
+                    return Err(DecodeError::Corrupt);
+                }
+            }
+            after_empty = false;
+            homes[idx] = home;
+        }
+
+        for block in 0..self.offsets.len() {
+            let first = block * 64;
+            let mut len = 0;
+            while len < self.size {
+                let idx = (first + len) % self.size;
+                if slots.get(idx).is_empty()
+                    || self.distance(homes[idx], idx) <= self.distance(first, idx)
+                {
+                    break;
+                }
+                len += 1;
             }
+            self.offsets[block] = len as u32;
         }
-        run_head
+        Ok(())
     }
 
     /// Run内の全ての要素に対してクロージャを実行する
@@ -117,41 +501,104 @@ impl QuotientFilter {
     where
         F: FnMut(usize),
     {
-        f(run_head);
-        let mut idx = self.next_index(run_head);
-        while self.filter[idx].is_continued() {
+        let mut idx = run_head;
+        loop {
             f(idx);
+            if self.is_runend(idx) {
+                break;
+            }
             idx = self.next_index(idx);
         }
     }
 
     fn collect_keys(&self) -> Vec<u64> {
-        let mut keys = Vec::with_capacity(self.entries);
-        if self.entries == 0 {
-            return keys;
-        }
+        self.iter().collect()
+    }
 
-        let size = self.size;
-        for quotient_idx in 0..size {
-            if !self.filter[quotient_idx].is_occupied() {
-                continue;
-            }
+    /// Iterates every stored fingerprint (`quotient << r | remainder`) in
+    /// ascending order, once per insert. Inserting them into a filter of the
+    /// same fingerprint width, at any `q`, rebuilds this one.
+    pub fn iter(&self) -> impl Iterator<Item = u64> + '_ {
+        self.scan_quotient_range(0, self.size as u64)
+    }
 
-            let run_head = self.find_run_head(quotient_idx);
-            self.visit_run(run_head, |slot_idx| {
-                let key = ((quotient_idx as u64) << self.r) | self.filter[slot_idx].remainder();
-                keys.push(key);
-            });
+    /// Iterates the stored fingerprints (`quotient << r | remainder`) whose
+    /// quotient lies in `lo..hi`, in quotient order.
+    ///
+    /// Lets a filter be exported partition by partition, e.g. when it is
+    /// split across nodes by key range.
+    pub fn scan_quotient_range(&self, lo: u64, hi: u64) -> impl Iterator<Item = u64> + '_ {
+        let hi = hi.min(self.size as u64) as usize;
+        self.occupied_quotients(lo.min(hi as u64) as usize, hi)
+            .flat_map(move |quotient| {
+                let mut keys = Vec::new();
+                let run_head = self.find_run_head(quotient);
+                self.visit_run(run_head, |slot_idx| {
+                    keys.push(((quotient as u64) << self.r) | self.remainders.get(slot_idx));
+                });
+                keys
+            })
+    }
+
+    /// Exports the distinct stored fingerprints into a compressed read-only
+    /// filter that answers lookups the same way.
+    pub fn to_static(&self) -> StaticQuotientFilter {
+        let mut fingerprints = self.collect_keys();
+        fingerprints.sort_unstable();
+        fingerprints.dedup();
+        StaticQuotientFilter::from_sorted(self.fingerprint_bits() as u32, &fingerprints)
+    }
+
+    /// Number of key bits the filter stores, `q + r`. Higher key bits are
+    /// ignored. [`resize`](Self::resize) widens it by one bit, while
+    /// [`resize_keep_width`](Self::resize_keep_width) and `merge` keep it.
+    pub fn fingerprint_bits(&self) -> u64 {
+        self.q + self.r
+    }
+
+    pub fn summary(&self) -> QuotientFilterSummary {
+        QuotientFilterSummary {
+            q: self.q,
+            r: self.r,
+            entries: self.entries,
+            capacity: self.size,
+            load_factor: self.entries as f64 / self.size as f64,
         }
+    }
 
-        keys
+    /// Whether every slot is in use, i.e. the next insert will resize.
+    pub(crate) fn is_full(&self) -> bool {
+        self.entries == self.size
     }
 
+    /// Slot `idx` as a word laid out as described in [`layout`].
+    pub(crate) fn slot_word(&self, idx: usize) -> u64 {
+        self.slot(idx).to_disk()
+    }
+
+    /// Doubles the table by adding a quotient bit and keeping `r`, so the
+    /// false positive rate stays the same. The fingerprint width grows by
+    /// one; keys stored before are still found as long as they fit in the
+    /// old width.
     pub fn resize(&mut self) {
-        let new_q = self.q + 1;
+        self.rebuild(self.q + 1, self.r);
+    }
 
+    /// Doubles the table by moving one bit from the remainder to the
+    /// quotient, so the fingerprint width stays `q + r` and the filter stays
+    /// mergeable with others of that width. Returns false, leaving the
+    /// filter unchanged, when `r` is already 1.
+    pub fn resize_keep_width(&mut self) -> bool {
+        if self.r <= 1 {
+            return false;
+        }
+        self.rebuild(self.q + 1, self.r - 1);
+        true
+    }
+
+    fn rebuild(&mut self, q: u64, r: u64) {
         let keys = self.collect_keys();
-        let mut new_qf = QuotientFilter::new(new_q, self.r);
+        let mut new_qf = QuotientFilter::new(q, r);
         for key in keys {
             new_qf.insert(key);
         }
@@ -161,8 +608,9 @@ impl QuotientFilter {
 
     pub fn merge(&self, other: &Self) -> Self {
         assert_eq!(
-            self.r, other.r,
-            "cannot merge filters with different remainder sizes"
+            self.fingerprint_bits(),
+            other.fingerprint_bits(),
+            "cannot merge filters with different fingerprint sizes"
         );
 
         let keys_self = self.collect_keys();
@@ -180,109 +628,378 @@ impl QuotientFilter {
                 .expect("q too large for usize");
         }
 
-        let mut merged = QuotientFilter::new(target_q, self.r);
-        for key in keys_self.into_iter().chain(keys_other.into_iter()) {
+        assert!(
+            target_q < self.fingerprint_bits(),
+            "merged filter needs more quotient bits than the fingerprint has"
+        );
+        let mut merged = QuotientFilter::new(target_q, self.fingerprint_bits() - target_q);
+        for key in keys_self.into_iter().chain(keys_other) {
             merged.insert(key);
         }
 
         merged
     }
 
-    pub fn insert(&mut self, key: u64) {
-        if self.entries == self.size {
-            self.resize();
+    /// Builds a filter holding `keys` on `threads` threads. The result is
+    /// slot for slot the filter that inserting the keys one by one gives.
+    ///
+    /// The quotient space is cut into one range per thread on multiples of
+    /// 64 slots. Each thread sorts the fingerprints of its range and lays
+    /// them out in order, every entry at its home slot or right after the
+    /// previous entry. Where a range's clusters end before the next range
+    /// starts, the split falls on an empty slot and the two are
+    /// independent; otherwise the next range's leading entries are pushed
+    /// right until they reach slots they held anyway, usually a few slots
+    /// later. Entries pushed past the end of the table are inserted
+    /// normally afterwards, so they wrap around as usual.
+    pub fn build_parallel(q: u64, r: u64, keys: &[u64], threads: usize) -> Self {
+        let mut qf = QuotientFilter::new(q, r);
+        assert!(keys.len() <= qf.size, "more keys than slots");
+        let words = qf.size.div_ceil(64);
+        let parts = threads.clamp(1, words);
+        let bounds: Vec<usize> = (0..=parts)
+            .map(|p| (words * p / parts * 64).min(qf.size))
+            .collect();
+        let fingerprint = |key| {
+            let (quotient, remainder) = qf.split(key);
+            (quotient << r) | remainder
+        };
+
+        // every thread buckets a share of the keys by range
+        let chunk_len = keys.len().div_ceil(parts).max(1);
+        let buckets: Vec<Vec<Vec<u64>>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = keys
+                .chunks(chunk_len)
+                .map(|chunk| {
+                    let bounds = &bounds;
+                    scope.spawn(move || {
+                        let mut buckets = vec![Vec::new(); parts];
+                        for &key in chunk {
+                            let fp = fingerprint(key);
+                            let home = (fp >> r) as usize;
+                            buckets[bounds.partition_point(|&b| b <= home) - 1].push(fp);
+                        }
+                        buckets
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        // every thread sorts and lays out one range, ignoring its neighbours
+        let mut ranges: Vec<(Vec<u64>, Vec<usize>)> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..parts)
+                .map(|p| {
+                    let (buckets, bounds) = (&buckets, &bounds);
+                    scope.spawn(move || {
+                        let mut fps: Vec<u64> =
+                            buckets.iter().flat_map(|b| &b[p]).copied().collect();
+                        fps.sort_unstable();
+                        let mut next = bounds[p];
+                        let positions = fps
+                            .iter()
+                            .map(|&fp| {
+                                let pos = next.max((fp >> r) as usize);
+                                next = pos + 1;
+                                pos
+                            })
+                            .collect();
+                        (fps, positions)
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        // push each range's head past the previous range's tail
+        let mut floor = 0;
+        for (_, positions) in &mut ranges {
+            for pos in positions.iter_mut() {
+                if *pos >= floor {
+                    break;
+                }
+                *pos = floor;
+                floor += 1;
+            }
+            floor = floor.max(positions.last().map_or(0, |&pos| pos + 1));
         }
 
-        let (quotient, remainder) = self.split(key);
-        let q_idx = quotient as usize;
+        let mut spilled = Vec::new();
+        for (fps, positions) in &mut ranges {
+            let placed = positions.partition_point(|&pos| pos < qf.size);
+            spilled.extend(fps.drain(placed..));
+            positions.truncate(placed);
+        }
 
-        // if the slot is empty, insert directly
-        if self.filter[q_idx].is_empty() {
-            self.filter[q_idx].set_remainder(remainder);
-            self.filter[q_idx].set_occupied(true);
-            self.entries += 1;
-            return;
+        // the ranges now cover disjoint, ordered runs of slots; neighbouring
+        // ranges may share a word of packed slots, so the threads fill
+        // unpacked slots and the packing is done in one pass
+        let slots: Vec<Vec<Slot>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = ranges
+                .iter()
+                .map(|(fps, positions)| {
+                    scope.spawn(move || {
+                        let mut prev_home = None;
+                        fps.iter()
+                            .zip(positions)
+                            .map(|(&fp, &pos)| {
+                                let home = (fp >> r) as usize;
+                                let mut slot = Slot::default();
+                                slot.set_remainder(fp & ((1 << r) - 1));
+                                slot.set_continued(prev_home == Some(home));
+                                slot.set_shifted(pos != home);
+                                prev_home = Some(home);
+                                slot
+                            })
+                            .collect()
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        let mut layout = SlotArray::new(qf.size, r);
+        for ((_, positions), slots) in ranges.iter().zip(slots) {
+            for (&pos, slot) in positions.iter().zip(slots) {
+                layout.set(pos, slot);
+            }
+        }
+        for (fps, _) in &ranges {
+            for &fp in fps {
+                layout.update((fp >> r) as usize, |slot| slot.set_occupied(true));
+            }
+            qf.entries += fps.len();
         }
+        qf.load_slots(&layout)
+            .expect("the ranges are laid out as sequential inserts would");
+        for fp in spilled {
+            qf.insert(fp);
+        }
+        qf
+    }
 
-        let already_occupied = self.filter[q_idx].is_occupied();
-        self.filter[q_idx].set_occupied(true);
+    pub fn insert(&mut self, key: u64) {
+        self.insert_with_stats(key);
+    }
 
-        let run_head = self.find_run_head(q_idx);
-        let mut insert_pos = run_head;
-        if !self.filter[insert_pos].is_empty() && self.filter[insert_pos].remainder() < remainder {
-            loop {
-                insert_pos = self.next_index(insert_pos);
-                if !(self.filter[insert_pos].is_continued()
-                    && self.filter[insert_pos].remainder() < remainder)
-                {
-                    break;
-                }
-            }
+    /// Inserts a byte-string key by its canonical fingerprint.
+    pub fn insert_bytes(&mut self, key: &[u8]) {
+        self.insert_bytes_with(&CanonicalFingerprinter, key);
+    }
+
+    /// Inserts a byte-string key using `fingerprinter`; look it up with the
+    /// same fingerprinter.
+    pub fn insert_bytes_with<F: Fingerprinter>(&mut self, fingerprinter: &F, key: &[u8]) {
+        self.reserve_keeping_width();
+        let bits = self.fingerprint_bits() as u32;
+        self.insert(fingerprinter.fingerprint(key, bits));
+    }
+
+    pub fn lookup_bytes(&self, key: &[u8]) -> bool {
+        self.lookup_bytes_with(&CanonicalFingerprinter, key)
+    }
+
+    pub fn lookup_bytes_with<F: Fingerprinter>(&self, fingerprinter: &F, key: &[u8]) -> bool {
+        self.lookup(fingerprinter.fingerprint(key, self.fingerprint_bits() as u32))
+    }
+
+    /// Inserts any `Hash` value, such as a struct or tuple, by std's
+    /// default hasher with fixed keys. Its output may change between Rust
+    /// versions, so filters filled this way should not be persisted or
+    /// shared between builds.
+    pub fn insert_item<T: Hash + ?Sized>(&mut self, item: &T) {
+        self.insert_item_with(&BuildHasherDefault::<DefaultHasher>::default(), item);
+    }
+
+    pub fn contains_item<T: Hash + ?Sized>(&self, item: &T) -> bool {
+        self.contains_item_with(&BuildHasherDefault::<DefaultHasher>::default(), item)
+    }
+
+    /// Inserts `item` by the low `q + r` bits of its hash under
+    /// `build_hasher`; look it up with the same hasher.
+    pub fn insert_item_with<S: BuildHasher, T: Hash + ?Sized>(
+        &mut self,
+        build_hasher: &S,
+        item: &T,
+    ) {
+        self.reserve_keeping_width();
+        self.insert(build_hasher.hash_one(item));
+    }
+
+    /// Makes room for one more hashed key. Hashed keys use every bit of the
+    /// fingerprint, so a full filter grows with
+    /// [`resize_keep_width`](Self::resize_keep_width) to keep the keys
+    /// already stored findable. Once `r` is down to 1 it falls back to
+    /// [`resize`](Self::resize), after which earlier keys may be missed.
+    fn reserve_keeping_width(&mut self) {
+        if self.is_full() && !self.resize_keep_width() {
+            self.resize();
         }
+    }
 
-        let inserting_at_head = insert_pos == run_head;
+    pub fn contains_item_with<S: BuildHasher, T: Hash + ?Sized>(
+        &self,
+        build_hasher: &S,
+        item: &T,
+    ) -> bool {
+        self.lookup(build_hasher.hash_one(item))
+    }
 
-        if self.filter[insert_pos].is_empty() {
-            self.filter[insert_pos].set_remainder(remainder);
-            self.filter[insert_pos].set_shifted(insert_pos != q_idx);
-            self.filter[insert_pos].set_continued(already_occupied && !inserting_at_head);
-            self.entries += 1;
-            return;
+    /// Inserts `key` and reports how far it and its neighbours were displaced.
+    pub fn insert_with_stats(&mut self, key: u64) -> InsertStats {
+        let mut stats = InsertStats::default();
+        if self.entries == self.size {
+            self.resize();
+            stats.resized = true;
         }
 
-        // shift entries to make space
-        let mut empty_pos = insert_pos;
-        while !self.filter[empty_pos].is_empty() {
-            empty_pos = self.next_index(empty_pos);
+        let (quotient, remainder) = self.split(key);
+        let q_idx = quotient as usize;
+
+        // q_idx is marked occupied only once its run is in place, so the
+        // metadata describes the slots while they are searched; a new run
+        // starts where the runs before it end
+        let already_occupied = self.is_occupied(q_idx);
+        let mut insert_pos = self.find_run_head(q_idx);
+        let mut ends_run = !already_occupied;
+        // an existing run is sorted, so the key goes before the first
+        // remainder at least as large, or after the run's last entry
+        if already_occupied {
+            while self.remainders.get(insert_pos) < remainder {
+                if self.is_runend(insert_pos) {
+                    ends_run = true;
+                    insert_pos = self.next_index(insert_pos);
+                    break;
+                }
+                insert_pos = self.next_index(insert_pos);
+            }
         }
+        stats.probe_length = self.distance(q_idx, insert_pos);
 
-        // shift entries backward from the empty slot
+        // shift entries backward from the first empty slot, taking their
+        // runend bits along
+        let empty_pos = self.find_empty(insert_pos);
+        stats.shifted = self.distance(insert_pos, empty_pos);
         let mut curr = empty_pos;
         while curr != insert_pos {
             let prev = self.prev_index(curr);
-            let prev_slot = self.filter[prev].clone();
-            self.filter[curr].set_remainder(prev_slot.remainder());
-            self.filter[curr].set_continued(prev_slot.is_continued());
-            self.filter[curr].set_shifted(true);
+            self.remainders.set(curr, self.remainders.get(prev));
+            self.set_runend(curr, self.is_runend(prev));
             curr = prev;
         }
 
-        // set the new remainder at the insertion position
-        self.filter[insert_pos].set_remainder(remainder);
-        self.filter[insert_pos].set_shifted(insert_pos != q_idx);
-        self.filter[insert_pos].set_continued(already_occupied && !inserting_at_head);
-
-        // if inserting at the start of the run, set is_continued=true for the next slot (shifted original run start)
-        if inserting_at_head {
-            let next = self.next_index(insert_pos);
-            self.filter[next].set_continued(true);
+        self.remainders.set(insert_pos, remainder);
+        self.set_runend(insert_pos, ends_run);
+        // appended to an existing run, the key takes over its end
+        if ends_run && already_occupied {
+            let prev = self.prev_index(insert_pos);
+            self.set_runend(prev, false);
         }
-
+        self.set_occupied(q_idx, true);
+        self.note_insert(q_idx, insert_pos, empty_pos);
         self.entries += 1;
+        stats
+    }
+
+    /// Bytes held by the remainders, the two bitmaps and the block offsets.
+    pub fn size_in_bytes(&self) -> usize {
+        self.remainders.size_in_bytes()
+            + std::mem::size_of_val(self.occupied.as_slice())
+            + std::mem::size_of_val(self.runends.as_slice())
+            + std::mem::size_of_val(self.offsets.as_slice())
+    }
+
+    /// False-positive rate in theory after `items` distinct inserts: the
+    /// chance that one of them shares the probe's `q + r` bit fingerprint,
+    /// about `items / 2^(q + r)`, i.e. the load times `2^-r`.
+    pub fn expected_false_positive_rate(&self, items: u64) -> f64 {
+        let match_one = 0.5f64.powi((self.q + self.r) as i32);
+        -(items as f64 * (-match_one).ln_1p()).exp_m1()
     }
 
     pub fn lookup(&self, key: u64) -> bool {
         let (quotient, remainder) = self.split(key);
         let q_idx = quotient as usize;
-        if !self.filter[q_idx].is_occupied() {
+        if !self.is_occupied(q_idx) {
             return false;
         }
 
-        let run_head = self.find_run_head(q_idx);
-        if self.filter[run_head].remainder() == remainder {
-            return true;
-        }
-
-        let mut idx = self.next_index(run_head);
-        while self.filter[idx].is_continued() {
-            if self.filter[idx].remainder() == remainder {
+        let mut idx = self.find_run_head(q_idx);
+        loop {
+            if self.remainders.get(idx) == remainder {
                 return true;
             }
+            if self.is_runend(idx) {
+                return false;
+            }
             idx = self.next_index(idx);
         }
+    }
+
+    /// Removes one occurrence of `key`, returning false if it is absent.
+    ///
+    /// The cluster holding the key is rebuilt without it, so every other
+    /// run is shifted back into canonical position and no remaining key
+    /// becomes unfindable. As with any filter, deleting a key that was never
+    /// inserted but shares a fingerprint with one that was removes the
+    /// latter.
+    pub fn delete(&mut self, key: u64) -> bool {
+        if !self.lookup(key) {
+            return false;
+        }
+        let (quotient, remainder) = self.split(key);
+
+        // the slots in use around the key's home, up to an empty slot on
+        // either side, hold every run whose quotient lies among them
+        let mut start = quotient as usize;
+        let mut len = 1;
+        while len < self.size && self.is_used(self.prev_index(start)) {
+            start = self.prev_index(start);
+            len += 1;
+        }
+        while len < self.size && self.is_used((start + len) % self.size) {
+            len += 1;
+        }
+
+        let mut keys = Vec::with_capacity(len);
+        let mut removed = false;
+        for offset in 0..len {
+            let home = (start + offset) % self.size;
+            if !self.is_occupied(home) {
+                continue;
+            }
+            let run_head = self.find_run_head(home);
+            self.visit_run(run_head, |slot_idx| {
+                let slot_remainder = self.remainders.get(slot_idx);
+                if !removed && home as u64 == quotient && slot_remainder == remainder {
+                    removed = true;
+                } else {
+                    keys.push(((home as u64) << self.r) | slot_remainder);
+                }
+            });
+        }
+        debug_assert!(removed);
+
+        for offset in 0..len {
+            let idx = (start + offset) % self.size;
+            self.remainders.set(idx, 0);
+            self.set_occupied(idx, false);
+            self.set_runend(idx, false);
+            if idx.is_multiple_of(64) {
+                self.offsets[idx / 64] = 0;
+            }
+        }
+        self.entries -= len;
+        for key in keys {
+            self.insert(key);
+        }
+        true
+    }
 
-        // Reached end of run (next run start or empty slot)
-        false
+    /// Removes one occurrence of a byte-string key inserted with
+    /// [`insert_bytes`](Self::insert_bytes).
+    pub fn delete_bytes(&mut self, key: &[u8]) -> bool {
+        let bits = self.fingerprint_bits() as u32;
+        self.delete(CanonicalFingerprinter.fingerprint(key, bits))
     }
 
     fn split(&self, key: u64) -> (u64, u64) {
@@ -292,10 +1009,219 @@ impl QuotientFilter {
     }
 }
 
+impl Extend<u64> for QuotientFilter {
+    fn extend<I: IntoIterator<Item = u64>>(&mut self, keys: I) {
+        for key in keys {
+            self.insert(key);
+        }
+    }
+}
+
+/// Picks the smallest `q` of at least 6 that keeps the collected keys at or
+/// below 75% load, with [`DEFAULT_REMAINDER_BITS`] remainder bits.
+impl FromIterator<u64> for QuotientFilter {
+    fn from_iter<I: IntoIterator<Item = u64>>(keys: I) -> Self {
+        let keys: Vec<u64> = keys.into_iter().collect();
+        let mut q = 6;
+        while (3usize << q) / 4 < keys.len() {
+            q += 1;
+        }
+        let mut filter = Self::new(q, DEFAULT_REMAINDER_BITS);
+        filter.extend(keys);
+        filter
+    }
+}
+
+impl std::fmt::Debug for QuotientFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("QuotientFilter")
+            .field("q", &self.q)
+            .field("r", &self.r)
+            .field("entries", &self.entries)
+            .field("capacity", &self.size)
+            .finish_non_exhaustive()
+    }
+}
+
+/// On-disk layout of a serialized [`QuotientFilter`], version 1.
+///
+/// Everything is little-endian and 8-byte aligned, independent of the
+/// in-memory `Slot` packing, so a file written on one architecture can be
+/// read (or memory-mapped and decoded) on any other:
+///
+/// | offset | size | field                                    |
+/// |--------|------|------------------------------------------|
+/// | 0      | 4    | magic `b"HBQF"`                          |
+/// | 4      | 4    | version (`u32`, currently 1)             |
+/// | 8      | 8    | `q` (`u64`)                              |
+/// | 16     | 8    | `r` (`u64`)                              |
+/// | 24     | 8    | number of entries (`u64`)                |
+/// | 32     | 8 × 2^q | slots (`u64` each)                    |
+///
+/// A slot is `remainder << 3 | shifted << 2 | continued << 1 | occupied`.
+pub mod layout {
+    pub const MAGIC: [u8; 4] = *b"HBQF";
+    pub const VERSION: u32 = 1;
+    pub const HEADER_LEN: usize = 32;
+    pub const SLOT_LEN: usize = 8;
+
+    pub const OCCUPIED: u64 = 1 << 0;
+    pub const CONTINUED: u64 = 1 << 1;
+    pub const SHIFTED: u64 = 1 << 2;
+    pub const REMAINDER_SHIFT: u32 = 3;
+}
+
+impl Slot {
+    fn to_disk(self) -> u64 {
+        let mut word = self.remainder() << layout::REMAINDER_SHIFT;
+        if self.is_occupied() {
+            word |= layout::OCCUPIED;
+        }
+        if self.is_continued() {
+            word |= layout::CONTINUED;
+        }
+        if self.is_shifted() {
+            word |= layout::SHIFTED;
+        }
+        word
+    }
+
+    fn from_disk(word: u64) -> Slot {
+        let mut slot = Slot::default();
+        slot.set_remainder(word >> layout::REMAINDER_SHIFT);
+        slot.set_occupied(word & layout::OCCUPIED != 0);
+        slot.set_continued(word & layout::CONTINUED != 0);
+        slot.set_shifted(word & layout::SHIFTED != 0);
+        slot
+    }
+}
+
+impl QuotientFilter {
+    /// Encodes the filter in the portable [`layout`].
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(layout::HEADER_LEN + self.size * layout::SLOT_LEN);
+        bytes.extend_from_slice(&layout::MAGIC);
+        bytes.extend_from_slice(&layout::VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.q.to_le_bytes());
+        bytes.extend_from_slice(&self.r.to_le_bytes());
+        bytes.extend_from_slice(&(self.entries as u64).to_le_bytes());
+        let slots = self.slots();
+        for idx in 0..self.size {
+            bytes.extend_from_slice(&slots.get(idx).to_disk().to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Decodes a filter written by [`QuotientFilter::to_bytes`].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        if bytes.len() < layout::HEADER_LEN {
+            return Err(DecodeError::Length {
+                expected: layout::HEADER_LEN,
+                actual: bytes.len(),
+            });
+        }
+        let word =
+            |offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+        if bytes[0..4] != layout::MAGIC {
+            return Err(DecodeError::BadMagic);
+        }
+        let version = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        if version != layout::VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let (q, r) = (word(8), word(16));
+        if q == 0 || r == 0 || q >= u32::BITS as u64 || r > 64 - layout::REMAINDER_SHIFT as u64 {
+            return Err(DecodeError::InvalidParameters { q, r });
+        }
+        let expected = (1usize << q)
+            .checked_mul(layout::SLOT_LEN)
+            .and_then(|len| len.checked_add(layout::HEADER_LEN))
+            .ok_or(DecodeError::InvalidParameters { q, r })?;
+        if bytes.len() != expected {
+            return Err(DecodeError::Length {
+                expected,
+                actual: bytes.len(),
+            });
+        }
+
+        let mut qf = QuotientFilter::new(q, r);
+        let mut slots = SlotArray::new(qf.size, r);
+        let (mut used, mut runs, mut quotients) = (0, 0, 0);
+        for idx in 0..qf.size {
+            let disk = word(layout::HEADER_LEN + idx * layout::SLOT_LEN);
+            if disk >> layout::REMAINDER_SHIFT >= 1 << r {
+                return Err(DecodeError::Corrupt);
+            }
+            let slot = Slot::from_disk(disk);
+            // an entry in its own slot heads the run of that quotient; a
+            // continued one is never in its own slot
+            if !slot.is_empty()
+                && !slot.is_shifted()
+                && (!slot.is_occupied() || slot.is_continued())
+            {
+                return Err(DecodeError::Corrupt);
+            }
+            used += !slot.is_empty() as usize;
+            runs += (!slot.is_empty() && !slot.is_continued()) as usize;
+            quotients += slot.is_occupied() as usize;
+            slots.set(idx, slot);
+        }
+        // every occupied quotient has exactly one run
+        if word(24) != used as u64 || runs != quotients {
+            return Err(DecodeError::Corrupt);
+        }
+        qf.entries = used;
+        qf.load_slots(&slots)?;
+        Ok(qf)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn collect_and_extend() {
+        let keys: Vec<u64> = (0..1000u64)
+            .map(|i| i.wrapping_mul(0x9e37_79b9_7f4a_7c15))
+            .collect();
+        let mut qf: QuotientFilter = keys.iter().copied().collect();
+        assert_eq!((qf.q, qf.r), (11, DEFAULT_REMAINDER_BITS));
+        qf.extend([1, 2, 3]);
+        assert_eq!(qf.entries, 1003);
+        assert!(keys.iter().chain(&[1, 2, 3]).all(|&key| qf.lookup(key)));
+        assert_eq!(QuotientFilter::from_iter([]).q, 6);
+    }
+
+    #[test]
+    fn build_parallel_matches_sequential_inserts() {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(0xB01D);
+        for (q, load, threads) in [
+            (4, 100, 4),
+            (8, 50, 3),
+            (10, 95, 4),
+            (12, 100, 8),
+            (12, 75, 1),
+        ] {
+            let count = (1usize << q) * load / 100;
+            let mut keys: Vec<u64> = (0..count).map(|_| rng.random()).collect();
+            // duplicates and keys crowding the end of the table, to wrap
+            keys.truncate(count - count / 10);
+            keys.extend(keys[..count / 20].to_vec());
+            while keys.len() < count {
+                keys.push(u64::MAX - rng.random_range(0..64));
+            }
+            let mut expected = QuotientFilter::new(q, 6);
+            expected.extend(keys.iter().copied());
+            let built = QuotientFilter::build_parallel(q, 6, &keys, threads);
+            assert_eq!(built.to_bytes(), expected.to_bytes(), "q {q} load {load}");
+            assert!(keys.iter().all(|&key| built.lookup(key)));
+        }
+        assert_eq!(QuotientFilter::build_parallel(8, 4, &[], 4).entries, 0);
+    }
+
     #[test]
     fn test_split() {
         let qf = QuotientFilter::new(8, 4);
@@ -315,10 +1241,10 @@ mod test {
 
         let (quotient, remainder) = qf.split(key);
         let idx = quotient as usize;
-        assert_eq!(qf.filter[idx].remainder(), remainder);
-        assert!(qf.filter[idx].is_occupied());
-        assert!(!qf.filter[idx].is_continued());
-        assert!(!qf.filter[idx].is_shifted());
+        assert_eq!(qf.slot(idx).remainder(), remainder);
+        assert!(qf.slot(idx).is_occupied());
+        assert!(!qf.slot(idx).is_continued());
+        assert!(!qf.slot(idx).is_shifted());
     }
 
     #[test]
@@ -338,16 +1264,16 @@ mod test {
 
         let (quotient, _) = qf.split(key1);
         let idx = quotient as usize;
-        assert!(qf.filter[idx].is_occupied());
+        assert!(qf.slot(idx).is_occupied());
 
         // the first remainder is stored in the quotient slot
-        assert_eq!(qf.filter[idx].remainder(), 0b0001);
-        assert!(!qf.filter[idx].is_continued());
+        assert_eq!(qf.slot(idx).remainder(), 0b0001);
+        assert!(!qf.slot(idx).is_continued());
 
         // the second remainder is stored in the next slot with continued flag set
-        assert_eq!(qf.filter[idx + 1].remainder(), 0b0010);
-        assert!(qf.filter[idx + 1].is_continued());
-        assert!(qf.filter[idx + 1].is_shifted());
+        assert_eq!(qf.slot(idx + 1).remainder(), 0b0010);
+        assert!(qf.slot(idx + 1).is_continued());
+        assert!(qf.slot(idx + 1).is_shifted());
     }
 
     #[test]
@@ -368,16 +1294,16 @@ mod test {
         assert_eq!(qf.entries, 3);
 
         let idx = 1;
-        assert!(qf.filter[idx].is_occupied());
+        assert!(qf.slot(idx).is_occupied());
 
-        assert_eq!(qf.filter[idx].remainder(), 0b0001);
-        assert_eq!(qf.filter[idx + 1].remainder(), 0b0010);
-        assert_eq!(qf.filter[idx + 2].remainder(), 0b0011);
+        assert_eq!(qf.slot(idx).remainder(), 0b0001);
+        assert_eq!(qf.slot(idx + 1).remainder(), 0b0010);
+        assert_eq!(qf.slot(idx + 2).remainder(), 0b0011);
 
         // the first element should have continued = false
-        assert!(!qf.filter[idx].is_continued());
-        assert!(qf.filter[idx + 1].is_continued());
-        assert!(qf.filter[idx + 2].is_continued());
+        assert!(!qf.slot(idx).is_continued());
+        assert!(qf.slot(idx + 1).is_continued());
+        assert!(qf.slot(idx + 2).is_continued());
     }
 
     #[test]
@@ -389,11 +1315,11 @@ mod test {
         qf.insert(0b0001_0001);
 
         assert!(
-            qf.filter[1].is_occupied(),
+            qf.slot(1).is_occupied(),
             "home bucket for quotient=1 must remain occupied"
         );
         assert!(
-            !qf.filter[2].is_occupied(),
+            !qf.slot(2).is_occupied(),
             "inserting only quotient=1 elements must not mark quotient=2 as occupied"
         );
     }
@@ -527,24 +1453,24 @@ mod test {
         assert_eq!(qf.entries, 3);
 
         // quotient=0b0001 slot (first remainder)
-        assert!(qf.filter[1].is_occupied());
-        assert_eq!(qf.filter[1].remainder(), 0b0001);
-        assert!(!qf.filter[1].is_shifted());
-        assert!(!qf.filter[1].is_continued());
+        assert!(qf.slot(1).is_occupied());
+        assert_eq!(qf.slot(1).remainder(), 0b0001);
+        assert!(!qf.slot(1).is_shifted());
+        assert!(!qf.slot(1).is_continued());
 
         // quotient=0b0010 slot
-        assert!(qf.filter[2].is_occupied());
+        assert!(qf.slot(2).is_occupied());
 
         // With the corrected insert, quotient=1's run should be contiguous
         // so filter[2] should contain the second element of quotient=1's run
-        assert_eq!(qf.filter[2].remainder(), 0b0011);
-        assert!(qf.filter[2].is_shifted());
-        assert!(qf.filter[2].is_continued());
+        assert_eq!(qf.slot(2).remainder(), 0b0011);
+        assert!(qf.slot(2).is_shifted());
+        assert!(qf.slot(2).is_continued());
 
         // quotient=0b0010's element is shifted to filter[3]
-        assert_eq!(qf.filter[3].remainder(), 0b0010);
-        assert!(qf.filter[3].is_shifted());
-        assert!(!qf.filter[3].is_continued());
+        assert_eq!(qf.slot(3).remainder(), 0b0010);
+        assert!(qf.slot(3).is_shifted());
+        assert!(!qf.slot(3).is_continued());
     }
 
     #[test]
@@ -560,8 +1486,8 @@ mod test {
         assert_eq!(qf.entries, 2);
 
         let idx = 1;
-        assert_eq!(qf.filter[idx].remainder(), 0b0001);
-        assert_eq!(qf.filter[idx + 1].remainder(), 0b0001);
+        assert_eq!(qf.slot(idx).remainder(), 0b0001);
+        assert_eq!(qf.slot(idx + 1).remainder(), 0b0001);
     }
 
     #[test]
@@ -580,13 +1506,13 @@ mod test {
         assert_eq!(qf.entries, 2);
 
         let idx = 15;
-        assert!(qf.filter[idx].is_occupied());
-        assert_eq!(qf.filter[idx].remainder(), 0b0001);
+        assert!(qf.slot(idx).is_occupied());
+        assert_eq!(qf.slot(idx).remainder(), 0b0001);
 
         // next slot wraps around to 0
-        assert_eq!(qf.filter[0].remainder(), 0b0010);
-        assert!(qf.filter[0].is_shifted());
-        assert!(qf.filter[0].is_continued());
+        assert_eq!(qf.slot(0).remainder(), 0b0010);
+        assert!(qf.slot(0).is_shifted());
+        assert!(qf.slot(0).is_continued());
     }
 
     #[test]
@@ -607,50 +1533,50 @@ mod test {
         assert_eq!(qf.entries, 5);
 
         assert!(
-            qf.filter[1].is_occupied(),
+            qf.slot(1).is_occupied(),
             "q=1 should set occupied at bucket 1"
         );
         assert!(
-            qf.filter[2].is_occupied(),
+            qf.slot(2).is_occupied(),
             "q=2 should set occupied at bucket 2"
         );
         assert!(
-            qf.filter[3].is_occupied(),
+            qf.slot(3).is_occupied(),
             "q=3 should set occupied at bucket 3"
         );
 
-        assert_eq!(qf.filter[1].remainder(), 0b0001);
-        assert!(!qf.filter[1].is_continued());
-        assert!(!qf.filter[1].is_shifted(), "first of q=1 is at home");
+        assert_eq!(qf.slot(1).remainder(), 0b0001);
+        assert!(!qf.slot(1).is_continued());
+        assert!(!qf.slot(1).is_shifted(), "first of q=1 is at home");
 
-        assert_eq!(qf.filter[2].remainder(), 0b0010);
-        assert!(qf.filter[2].is_continued());
+        assert_eq!(qf.slot(2).remainder(), 0b0010);
+        assert!(qf.slot(2).is_continued());
         assert!(
-            qf.filter[2].is_shifted(),
+            qf.slot(2).is_shifted(),
             "q=1 second element must be shifted"
         );
 
         // q=2 run: index=3,4 → remainders [1,3] (verify ascending order)
         assert_eq!(
-            qf.filter[3].remainder(),
+            qf.slot(3).remainder(),
             0b0001,
             "q=2 run must be sorted: 1 then 3"
         );
-        assert!(!qf.filter[3].is_continued());
+        assert!(!qf.slot(3).is_continued());
         assert!(
-            qf.filter[3].is_shifted(),
+            qf.slot(3).is_shifted(),
             "q=2 first element is not at home (home=2)"
         );
 
-        assert_eq!(qf.filter[4].remainder(), 0b0011);
-        assert!(qf.filter[4].is_continued());
-        assert!(qf.filter[4].is_shifted());
+        assert_eq!(qf.slot(4).remainder(), 0b0011);
+        assert!(qf.slot(4).is_continued());
+        assert!(qf.slot(4).is_shifted());
 
         // q=3 run: index=5 → remainder [2]
-        assert_eq!(qf.filter[5].remainder(), 0b0010);
-        assert!(!qf.filter[5].is_continued());
+        assert_eq!(qf.slot(5).remainder(), 0b0010);
+        assert!(!qf.slot(5).is_continued());
         assert!(
-            qf.filter[5].is_shifted(),
+            qf.slot(5).is_shifted(),
             "q=3 first element is not at home (home=3)"
         );
 
@@ -658,7 +1584,7 @@ mod test {
         // 1) run heads must have is_continued=0
         for &i in &[1, 3, 5] {
             assert!(
-                !qf.filter[i].is_continued(),
+                !qf.slot(i).is_continued(),
                 "run head must have is_continued=0 at {}",
                 i
             );
@@ -666,20 +1592,649 @@ mod test {
         // 2) run bodies (non-heads) must have is_continued=1
         for &i in &[2, 4] {
             assert!(
-                qf.filter[i].is_continued(),
+                qf.slot(i).is_continued(),
                 "run body must have is_continued=1 at {}",
                 i
             );
         }
         // 3) q=2's home (index=2) has occupied=1, but storage position is at 3 or later (= shifted elements exist)
-        assert!(qf.filter[2].is_occupied());
+        assert!(qf.slot(2).is_occupied());
         assert_ne!(
-            qf.filter[2].remainder(),
+            qf.slot(2).remainder(),
             0b0001,
             "index=2 should not store q=2's first element"
         );
     }
 
+    #[test]
+    fn test_insert_new_run_before_existing_run() {
+        let mut qf = QuotientFilter::new(4, 4);
+        // quotient=1 run occupies slots 1-2, quotient=3 sits at its home slot 3
+        qf.insert(0b0001_0101);
+        qf.insert(0b0001_0110);
+        qf.insert(0b0011_0001);
+        // quotient=2's new run must go before quotient=3's run even though
+        // its remainder is larger
+        qf.insert(0b0010_1001);
+
+        assert_eq!(qf.slot(3).remainder(), 0b1001);
+        assert!(!qf.slot(3).is_continued());
+        assert!(qf.slot(3).is_shifted());
+        assert_eq!(qf.slot(4).remainder(), 0b0001);
+        assert!(
+            !qf.slot(4).is_continued(),
+            "the displaced run head must still start its own run"
+        );
+        assert!(qf.slot(4).is_shifted());
+
+        for key in [0b0001_0101, 0b0001_0110, 0b0011_0001, 0b0010_1001] {
+            assert!(qf.lookup(key), "key {:08b} should be found", key);
+        }
+    }
+
+    #[test]
+    fn test_insert_with_stats_reports_displacement() {
+        let mut qf = QuotientFilter::new(4, 4);
+
+        // home slot is empty
+        assert_eq!(qf.insert_with_stats(0b0001_0010), InsertStats::default());
+
+        // appended to the run in the next (empty) slot
+        let stats = qf.insert_with_stats(0b0001_0011);
+        assert_eq!(stats.probe_length, 1);
+        assert_eq!(stats.shifted, 0);
+
+        // becomes the new run head, pushing both entries right
+        let stats = qf.insert_with_stats(0b0001_0001);
+        assert_eq!(stats.probe_length, 0);
+        assert_eq!(stats.shifted, 2);
+
+        // quotient=2 lands behind the quotient=1 run
+        let stats = qf.insert_with_stats(0b0010_0001);
+        assert_eq!(stats.probe_length, 2);
+        assert_eq!(stats.shifted, 0);
+        assert!(!stats.resized);
+    }
+
+    #[test]
+    fn test_insert_with_stats_wraps_around() {
+        let mut qf = QuotientFilter::new(4, 4);
+        qf.insert(0b1111_0010);
+        qf.insert(0b1111_0011);
+        let stats = qf.insert_with_stats(0b1111_0001);
+        assert_eq!(stats.probe_length, 0);
+        assert_eq!(stats.shifted, 2, "shift must continue across the table end");
+    }
+
+    #[test]
+    fn test_insert_with_stats_reports_resize() {
+        let mut qf = QuotientFilter::new(2, 4);
+        for q in 0..4u64 {
+            assert!(!qf.insert_with_stats(q << qf.r).resized);
+        }
+        assert!(qf.insert_with_stats(0b0001).resized);
+        assert_eq!(qf.size, 8);
+    }
+
+    #[test]
+    fn test_scan_quotient_range_partitions_filter() {
+        let mut qf = QuotientFilter::new(4, 4);
+        // quotients cluster so runs are shifted across the split point
+        let keys = [0x12, 0x15, 0x21, 0x2F, 0x30, 0x31, 0x33, 0x7A, 0xF0, 0xF1];
+        for key in keys {
+            qf.insert(key);
+        }
+
+        let low: Vec<u64> = qf.scan_quotient_range(0, 3).collect();
+        let high: Vec<u64> = qf.scan_quotient_range(3, 16).collect();
+        assert_eq!(low, vec![0x12, 0x15, 0x21, 0x2F]);
+        assert_eq!(high, vec![0x30, 0x31, 0x33, 0x7A, 0xF0, 0xF1]);
+
+        let mut split = QuotientFilter::new(4, 4);
+        for key in high {
+            split.insert(key);
+        }
+        assert!(split.lookup(0x33));
+        assert!(!split.lookup(0x21));
+    }
+
+    #[test]
+    fn test_iter_yields_sorted_fingerprints() {
+        let mut qf = QuotientFilter::new(6, 10);
+        let keys: Vec<u64> = (0..60u64).map(|i| (i * 0x9E37) % (1 << 16)).collect();
+        for &key in keys.iter().chain(&keys[..5]) {
+            qf.insert(key | 1 << 40);
+        }
+        let mut expected: Vec<u64> = keys.iter().chain(&keys[..5]).copied().collect();
+        expected.sort_unstable();
+        assert_eq!(qf.iter().collect::<Vec<_>>(), expected);
+
+        // re-sharded into a filter twice as wide in quotient bits
+        let mut wider = QuotientFilter::new(7, 9);
+        wider.extend(qf.iter());
+        assert_eq!(wider.iter().collect::<Vec<_>>(), expected);
+        assert_eq!(QuotientFilter::new(4, 4).iter().count(), 0);
+    }
+
+    #[test]
+    fn test_scan_quotient_range_clamps_bounds() {
+        let mut qf = QuotientFilter::new(3, 4);
+        qf.insert(0x75);
+        qf.insert(0x70);
+        assert_eq!(
+            qf.scan_quotient_range(7, 100).collect::<Vec<_>>(),
+            vec![0x70, 0x75]
+        );
+        assert_eq!(qf.scan_quotient_range(5, 2).count(), 0);
+        assert_eq!(qf.scan_quotient_range(100, 200).count(), 0);
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let mut qf = QuotientFilter::new(4, 6);
+        for key in [0x012, 0x015, 0x021, 0x3FF, 0x3C0, 0x031] {
+            qf.insert(key);
+        }
+        let bytes = qf.to_bytes();
+        assert_eq!(bytes.len(), layout::HEADER_LEN + 16 * layout::SLOT_LEN);
+        assert_eq!(&bytes[0..4], b"HBQF");
+        assert_eq!(&bytes[8..16], &[4, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&bytes[24..32], &[6, 0, 0, 0, 0, 0, 0, 0]);
+
+        let decoded = QuotientFilter::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.collect_keys(), qf.collect_keys());
+        assert!(decoded.lookup(0x3C0));
+        assert_eq!(decoded.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_slot_disk_encoding_is_explicit() {
+        let mut qf = QuotientFilter::new(2, 4);
+        qf.insert(0b01_0011);
+        let bytes = qf.to_bytes();
+        // slot 1: remainder 0b0011, occupied
+        let slot = &bytes[layout::HEADER_LEN + layout::SLOT_LEN..][..layout::SLOT_LEN];
+        assert_eq!(slot, &[0b0001_1001, 0, 0, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_from_bytes_rejects_bad_input() {
+        let bytes = QuotientFilter::new(3, 4).to_bytes();
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert_eq!(
+            QuotientFilter::from_bytes(&bad_magic).err(),
+            Some(DecodeError::BadMagic)
+        );
+        assert_eq!(
+            QuotientFilter::from_bytes(&bytes[..bytes.len() - 1]).err(),
+            Some(DecodeError::Length {
+                expected: bytes.len(),
+                actual: bytes.len() - 1
+            })
+        );
+        let mut bad_version = bytes.clone();
+        bad_version[4] = 2;
+        assert_eq!(
+            QuotientFilter::from_bytes(&bad_version).err(),
+            Some(DecodeError::UnsupportedVersion(2))
+        );
+        let mut bad_slot = bytes.clone();
+        bad_slot[layout::HEADER_LEN] = 0xFF;
+        assert_eq!(
+            QuotientFilter::from_bytes(&bad_slot).err(),
+            Some(DecodeError::Corrupt)
+        );
+    }
+
+    #[test]
+    fn test_from_bytes_checks_entry_count() {
+        let mut qf = QuotientFilter::new(4, 4);
+        for key in [0x11, 0x12, 0x35] {
+            qf.insert(key);
+        }
+        let bytes = qf.to_bytes();
+        for entries in [0u64, 2, 4, 16] {
+            let mut bad = bytes.clone();
+            bad[24..32].copy_from_slice(&entries.to_le_bytes());
+            assert_eq!(
+                QuotientFilter::from_bytes(&bad).err(),
+                Some(DecodeError::Corrupt),
+                "{entries} entries"
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_bytes_checks_slot_flags() {
+        let bytes = QuotientFilter::new(2, 4).to_bytes();
+        let rem = 1 << layout::REMAINDER_SHIFT;
+        let slots = |words: [u64; 4]| {
+            let mut bad = bytes.clone();
+            for (idx, word) in words.iter().enumerate() {
+                let at = layout::HEADER_LEN + idx * layout::SLOT_LEN;
+                bad[at..at + 8].copy_from_slice(&word.to_le_bytes());
+            }
+            let entries = words.iter().filter(|&&word| word != 0).count() as u64;
+            bad[24..32].copy_from_slice(&entries.to_le_bytes());
+            bad
+        };
+        let cases = [
+            // an entry in its own slot whose quotient is not occupied
+            [rem, 0, 0, 0],
+            // a continued entry in its own slot
+            [rem | layout::OCCUPIED | layout::CONTINUED, 0, 0, 0],
+            // two runs but one occupied quotient
+            [rem | layout::OCCUPIED, rem | layout::SHIFTED, 0, 0],
+            // an occupied quotient with no run
+            [
+                rem | layout::OCCUPIED,
+                rem | layout::OCCUPIED | layout::SHIFTED | layout::CONTINUED,
+                0,
+                0,
+            ],
+        ];
+        for words in cases {
+            assert_eq!(
+                QuotientFilter::from_bytes(&slots(words)).err(),
+                Some(DecodeError::Corrupt),
+                "{words:?}"
+            );
+        }
+        // the same shapes, made consistent, decode
+        let valid = [
+            rem | layout::OCCUPIED,
+            rem | layout::OCCUPIED | layout::SHIFTED,
+            0,
+            0,
+        ];
+        let qf = QuotientFilter::from_bytes(&slots(valid)).unwrap();
+        assert!(qf.lookup(1 << 4 | 1) && qf.lookup(1));
+    }
+
+    #[test]
+    fn test_from_bytes_rejects_untraceable_runs() {
+        let bytes = QuotientFilter::new(1, 4).to_bytes();
+        // a shifted entry with no entry before it to be shifted by
+        let mut after_empty = bytes.clone();
+        after_empty[layout::HEADER_LEN] = layout::SHIFTED as u8;
+        // a run of a quotient that no slot marks occupied
+        let mut no_quotient = bytes.clone();
+        no_quotient[layout::HEADER_LEN] = (1 << layout::REMAINDER_SHIFT | layout::SHIFTED) as u8;
+        no_quotient[layout::HEADER_LEN + layout::SLOT_LEN] = 1 << layout::REMAINDER_SHIFT;
+        for bad in [after_empty, no_quotient] {
+            assert_eq!(
+                QuotientFilter::from_bytes(&bad).err(),
+                Some(DecodeError::Corrupt)
+            );
+        }
+    }
+
+    #[test]
+    fn test_resize_keeps_fingerprint_width() {
+        let mut qf = QuotientFilter::new(3, 5);
+        // bit 7 is the top fingerprint bit; it becomes a quotient bit
+        let keys = [0b1000_0001, 0b0000_0001, 0b1111_1111];
+        for key in keys {
+            qf.insert(key);
+        }
+        assert!(qf.resize_keep_width());
+        assert_eq!((qf.q, qf.r), (4, 4));
+        assert_eq!(qf.fingerprint_bits(), 8);
+        for key in keys {
+            assert!(qf.lookup(key), "key {:08b} lost by resize", key);
+        }
+        assert!(!qf.lookup(0b1000_0010));
+    }
+
+    #[test]
+    fn test_resize_keep_width_stops_at_one_remainder_bit() {
+        let mut qf = QuotientFilter::new(3, 1);
+        qf.insert(0b1011);
+        assert!(!qf.resize_keep_width());
+        assert_eq!((qf.q, qf.r), (3, 1));
+        assert!(qf.lookup(0b1011));
+    }
+
+    #[test]
+    fn test_small_filter_grows_many_times() {
+        let mut qf = QuotientFilter::new(2, 2);
+        // every key fits the fingerprint width it is inserted at
+        let keys: Vec<u64> = (0..256).collect();
+        for &key in &keys {
+            qf.insert(key);
+        }
+        assert_eq!(qf.r, 2, "growth must keep the remainder width");
+        assert_eq!(qf.q, 8);
+        assert_eq!(qf.entries, keys.len());
+        for &key in &keys {
+            assert!(qf.lookup(key), "key {:x} lost while growing", key);
+        }
+    }
+
+    #[test]
+    fn test_byte_keys_grow_past_one_remainder_bit() {
+        let mut qf = QuotientFilter::new(2, 2);
+        for i in 0..64 {
+            qf.insert_bytes(format!("key-{}", i).as_bytes());
+        }
+        assert_eq!(qf.entries, 64);
+        assert_eq!((qf.q, qf.r), (6, 1));
+    }
+
+    #[test]
+    fn test_byte_keys_survive_resize_and_merge() {
+        let mut left = QuotientFilter::new(4, 20);
+        let mut right = QuotientFilter::new(6, 18);
+        let keys: Vec<String> = (0..100).map(|i| format!("key-{}", i)).collect();
+        for key in &keys[..50] {
+            left.insert_bytes(key.as_bytes());
+        }
+        for key in &keys[50..] {
+            right.insert_bytes(key.as_bytes());
+        }
+        assert!(left.q > 4, "left filter should have resized");
+        let merged = left.merge(&right);
+        assert_eq!(merged.fingerprint_bits(), 24);
+        for key in &keys {
+            assert!(merged.lookup_bytes(key.as_bytes()), "{} missing", key);
+        }
+        let false_positives = (0..1000)
+            .filter(|i| merged.lookup_bytes(format!("absent-{}", i).as_bytes()))
+            .count();
+        assert!(false_positives < 5, "{} false positives", false_positives);
+    }
+
+    #[test]
+    fn test_hashed_items_survive_resize() {
+        #[derive(Hash)]
+        struct Session {
+            user: u32,
+            device: &'static str,
+        }
+
+        let mut qf = QuotientFilter::new(4, 20);
+        for user in 0..100 {
+            qf.insert_item(&Session {
+                user,
+                device: "phone",
+            });
+            qf.insert_item(&(user, -1i64));
+        }
+        assert!(qf.q > 4, "filter should have resized");
+        for user in 0..100 {
+            assert!(qf.contains_item(&Session {
+                user,
+                device: "phone",
+            }));
+            assert!(qf.contains_item(&(user, -1i64)));
+        }
+        let false_positives = (0..1000)
+            .filter(|&user| {
+                qf.contains_item(&Session {
+                    user,
+                    device: "laptop",
+                })
+            })
+            .count();
+        assert!(false_positives < 5, "{} false positives", false_positives);
+    }
+
+    #[test]
+    fn test_hashed_items_use_the_given_hasher() {
+        use std::collections::hash_map::RandomState;
+
+        let hasher = RandomState::new();
+        let mut qf = QuotientFilter::new(8, 16);
+        for word in ["alpha", "beta", "gamma"] {
+            qf.insert_item_with(&hasher, word);
+        }
+        assert!(["alpha", "beta", "gamma"]
+            .iter()
+            .all(|word| qf.contains_item_with(&hasher, word)));
+        // the fingerprint is the hash cut to q + r bits
+        assert!(qf.lookup(hasher.hash_one("beta")));
+        assert!(!qf.contains_item_with(&hasher, "delta"));
+    }
+
+    #[test]
+    #[should_panic(expected = "different fingerprint sizes")]
+    fn test_merge_rejects_different_fingerprint_sizes() {
+        QuotientFilter::new(4, 4).merge(&QuotientFilter::new(4, 5));
+    }
+
+    /// Replays `ops` (`Ok(key)` inserts, `Err(key)` deletes) on a fresh
+    /// filter and a multiset model, checking after every step that lookups
+    /// agree with the model for every key in `universe`.
+    fn check_against_model(q: u64, r: u64, ops: &[Result<u64, u64>], universe: &[u64]) {
+        let mut qf = QuotientFilter::new(q, r);
+        let mut model: std::collections::HashMap<u64, usize> = Default::default();
+        for op in ops {
+            match *op {
+                Ok(key) => {
+                    qf.insert(key);
+                    *model.entry(key).or_default() += 1;
+                }
+                Err(key) => {
+                    let count = model.entry(key).or_default();
+                    assert_eq!(qf.delete(key), *count > 0, "delete {key} in {ops:?}");
+                    *count = count.saturating_sub(1);
+                }
+            }
+            assert_eq!(qf.entries, model.values().sum::<usize>(), "{ops:?}");
+            for &key in universe {
+                // keys fit in q + r bits, so lookups are exact
+                let want = model.get(&key).is_some_and(|&count| count > 0);
+                assert_eq!(qf.lookup(key), want, "lookup {key} after {ops:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_delete_exhaustive_small_sequences() {
+        // q = 3, r = 2; quotients 0, 1, 2, 6 and 7 so runs collide, shift
+        // into each other and wrap around the end of the table
+        let key = |quotient: u64, remainder: u64| (quotient << 2) | remainder;
+        let universe = [
+            key(0, 0),
+            key(0, 1),
+            key(0, 3),
+            key(1, 2),
+            key(2, 2),
+            key(6, 3),
+            key(7, 0),
+            key(7, 1),
+        ];
+        let alphabet: Vec<Result<u64, u64>> = universe
+            .iter()
+            .flat_map(|&key| [Ok(key), Err(key)])
+            .collect();
+        let mut ops = Vec::new();
+        fn walk(
+            ops: &mut Vec<Result<u64, u64>>,
+            depth: usize,
+            alphabet: &[Result<u64, u64>],
+            universe: &[u64],
+        ) {
+            check_against_model(3, 2, ops, universe);
+            if depth == 0 {
+                return;
+            }
+            for &op in alphabet {
+                ops.push(op);
+                walk(ops, depth - 1, alphabet, universe);
+                ops.pop();
+            }
+        }
+        walk(&mut ops, 4, &alphabet, &universe);
+    }
+
+    #[test]
+    fn test_delete_random_long_sequences() {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+        let universe: Vec<u64> = (0..1 << 7).collect();
+        for seed in 0..50 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            // up to 12 live keys in 16 slots keeps clusters long without resizing
+            let mut live = 0usize;
+            let ops: Vec<Result<u64, u64>> = (0..40)
+                .map(|_| {
+                    let key = rng.random_range(0..1 << 7);
+                    if live < 12 && rng.random_bool(0.6) {
+                        live += 1;
+                        Ok(key)
+                    } else {
+                        live = live.saturating_sub(1);
+                        Err(key)
+                    }
+                })
+                .collect();
+            check_against_model(4, 3, &ops, &universe);
+        }
+    }
+
+    #[test]
+    fn test_delete_bytes_matches_insert_bytes() {
+        let mut qf = QuotientFilter::new(6, 20);
+        qf.insert_bytes(b"alpha");
+        qf.insert_bytes(b"beta");
+        assert!(qf.delete_bytes(b"alpha"));
+        assert!(!qf.lookup_bytes(b"alpha"));
+        assert!(qf.lookup_bytes(b"beta"));
+        assert!(!qf.delete_bytes(b"alpha"));
+    }
+
+    #[test]
+    fn test_delete_removes_one_duplicate() {
+        let mut qf = QuotientFilter::new(4, 4);
+        qf.insert(0x35);
+        qf.insert(0x35);
+        assert!(qf.delete(0x35));
+        assert!(qf.lookup(0x35));
+        assert!(qf.delete(0x35));
+        assert!(!qf.lookup(0x35));
+        assert!(!qf.delete(0x35));
+        assert!((0..qf.size).all(|idx| qf.slot(idx).is_empty()));
+    }
+
+    #[test]
+    fn test_summary_and_debug() {
+        let mut qf = QuotientFilter::new(4, 4);
+        qf.insert(0x12);
+        qf.insert(0x13);
+        let summary = qf.summary();
+        assert_eq!((summary.entries, summary.capacity), (2, 16));
+        assert_eq!(summary.load_factor, 0.125);
+        assert_eq!(
+            format!("{:?}", qf),
+            "QuotientFilter { q: 4, r: 4, entries: 2, capacity: 16, .. }"
+        );
+    }
+
+    #[test]
+    fn test_derived_slot_flags_match_the_runs() {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(11);
+        let mut qf = QuotientFilter::new(7, 8);
+        let mut keys = Vec::new();
+        for round in 0..400 {
+            if round % 3 == 2 && !keys.is_empty() {
+                let key = keys.swap_remove(rng.random_range(0..keys.len()));
+                assert!(qf.delete(key));
+            } else {
+                let key = rng.random_range(0..1u64 << 15);
+                qf.insert(key);
+                keys.push(key);
+            }
+            if round % 50 == 0 {
+                qf = QuotientFilter::from_bytes(&qf.to_bytes()).unwrap();
+            }
+            // flags derived slot by slot agree with those read off the runs
+            let slots = qf.slots();
+            for idx in 0..qf.size {
+                assert_eq!(qf.slot(idx).data, slots.get(idx).data, "slot {idx}");
+            }
+        }
+        let mut expected: Vec<u64> = keys.iter().map(|&key| key & ((1 << 15) - 1)).collect();
+        expected.sort_unstable();
+        assert_eq!(qf.collect_keys(), expected);
+    }
+
+    #[test]
+    fn test_block_metadata_matches_rebuild() {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        // tables below one block, of exactly one, and of several
+        let mut rng = StdRng::seed_from_u64(34);
+        for q in [3u64, 6, 8] {
+            for _ in 0..20 {
+                let mut qf = QuotientFilter::new(q, 4);
+                let mut keys = Vec::new();
+                for _ in 0..3 << q {
+                    if rng.random_bool(0.4) && !keys.is_empty() {
+                        let key = keys.swap_remove(rng.random_range(0..keys.len()));
+                        assert!(qf.delete(key));
+                    } else if !qf.is_full() {
+                        let key = rng.random_range(0..1u64 << (q + 4));
+                        qf.insert(key);
+                        keys.push(key);
+                    }
+                    // decoding recomputes the metadata from the slot flags
+                    let rebuilt = QuotientFilter::from_bytes(&qf.to_bytes()).unwrap();
+                    assert_eq!(qf.runends, rebuilt.runends, "q {q}");
+                    assert_eq!(qf.offsets, rebuilt.offsets, "q {q}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_occupied_quotients_skips_empty_words() {
+        let mut qf = QuotientFilter::new(9, 4);
+        for quotient in [0u64, 63, 64, 200, 511] {
+            qf.insert(quotient << 4);
+        }
+        let all: Vec<usize> = qf.occupied_quotients(0, 512).collect();
+        assert_eq!(all, vec![0, 63, 64, 200, 511]);
+        let middle: Vec<usize> = qf.occupied_quotients(63, 200).collect();
+        assert_eq!(middle, vec![63, 64]);
+        assert_eq!(qf.occupied_quotients(65, 65).count(), 0);
+        assert_eq!(qf.occupied_quotients(512, 512).count(), 0);
+    }
+
+    #[test]
+    fn test_slots_are_packed() {
+        // 61-bit remainders fill a whole word per slot
+        for r in [1u64, 5, 8, 13, 61] {
+            let slot_at = |idx: usize| {
+                let mut slot = Slot::default();
+                slot.set_remainder((idx as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15) >> (64 - r));
+                slot.set_occupied(idx.is_multiple_of(2));
+                slot.set_continued(idx.is_multiple_of(3));
+                slot.set_shifted(idx.is_multiple_of(5));
+                slot
+            };
+            let mut slots = SlotArray::new(100, r);
+            for idx in 0..100 {
+                slots.set(idx, slot_at(idx));
+            }
+            // rewriting a slot leaves its neighbours alone
+            slots.update(50, |slot| slot.set_remainder(0));
+            slots.set(50, slot_at(50));
+            for idx in 0..100 {
+                assert_eq!(slots.get(idx).data, slot_at(idx).data, "r {r} slot {idx}");
+            }
+        }
+
+        let qf = QuotientFilter::new(16, 8);
+        // bare remainders, two bitmaps and a u32 offset per 64 slots
+        assert_eq!(
+            qf.size_in_bytes(),
+            (1 << 16) + (1 << 16) / 4 + (1 << 16) / 16
+        );
+    }
+
     #[test]
     fn test_lookup_empty_filter() {
         let qf = QuotientFilter::new(4, 4);
@@ -690,15 +2245,19 @@ mod test {
     #[test]
     fn test_lookup_simple_hit() {
         let mut qf = QuotientFilter::new(4, 4);
+        let mut slots = SlotArray::new(qf.size, qf.r);
         let key = 0b00010001;
         let (quotient, remainder) = qf.split(key);
         let idx = quotient as usize;
 
-        qf.filter[idx].set_remainder(remainder);
-        qf.filter[idx].set_occupied(true);
-        qf.filter[idx].set_continued(false);
-        qf.filter[idx].set_shifted(false);
+        slots.update(idx, |slot| {
+            slot.set_remainder(remainder);
+            slot.set_occupied(true);
+            slot.set_continued(false);
+            slot.set_shifted(false);
+        });
         qf.entries = 1;
+        qf.load_slots(&slots).unwrap();
 
         assert!(qf.lookup(key));
     }
@@ -706,25 +2265,33 @@ mod test {
     #[test]
     fn test_lookup_with_run() {
         let mut qf = QuotientFilter::new(4, 4);
+        let mut slots = SlotArray::new(qf.size, qf.r);
         let quotient = 0b0001;
         let idx = quotient as usize;
 
-        qf.filter[idx].set_remainder(0b0001);
-        qf.filter[idx].set_occupied(true);
-        qf.filter[idx].set_continued(false);
-        qf.filter[idx].set_shifted(false);
-
-        qf.filter[idx + 1].set_remainder(0b0010);
-        qf.filter[idx + 1].set_occupied(false);
-        qf.filter[idx + 1].set_continued(true);
-        qf.filter[idx + 1].set_shifted(true);
-
-        qf.filter[idx + 2].set_remainder(0b0011);
-        qf.filter[idx + 2].set_occupied(false);
-        qf.filter[idx + 2].set_continued(true);
-        qf.filter[idx + 2].set_shifted(true);
+        slots.update(idx, |slot| {
+            slot.set_remainder(0b0001);
+            slot.set_occupied(true);
+            slot.set_continued(false);
+            slot.set_shifted(false);
+        });
+
+        slots.update(idx + 1, |slot| {
+            slot.set_remainder(0b0010);
+            slot.set_occupied(false);
+            slot.set_continued(true);
+            slot.set_shifted(true);
+        });
+
+        slots.update(idx + 2, |slot| {
+            slot.set_remainder(0b0011);
+            slot.set_occupied(false);
+            slot.set_continued(true);
+            slot.set_shifted(true);
+        });
 
         qf.entries = 3;
+        qf.load_slots(&slots).unwrap();
 
         let key1 = (quotient << qf.r) | 0b0001;
         let key2 = (quotient << qf.r) | 0b0010;
@@ -740,28 +2307,38 @@ mod test {
     #[test]
     fn test_lookup_multiple_different_quotients() {
         let mut qf = QuotientFilter::new(4, 4);
-
-        qf.filter[1].set_remainder(0b0001);
-        qf.filter[1].set_occupied(true);
-        qf.filter[1].set_continued(false);
-        qf.filter[1].set_shifted(false);
-
-        qf.filter[3].set_remainder(0b0010);
-        qf.filter[3].set_occupied(true);
-        qf.filter[3].set_continued(false);
-        qf.filter[3].set_shifted(false);
-
-        qf.filter[5].set_remainder(0b0011);
-        qf.filter[5].set_occupied(true);
-        qf.filter[5].set_continued(false);
-        qf.filter[5].set_shifted(false);
-
-        qf.filter[7].set_remainder(0b0100);
-        qf.filter[7].set_occupied(true);
-        qf.filter[7].set_continued(false);
-        qf.filter[7].set_shifted(false);
+        let mut slots = SlotArray::new(qf.size, qf.r);
+
+        slots.update(1, |slot| {
+            slot.set_remainder(0b0001);
+            slot.set_occupied(true);
+            slot.set_continued(false);
+            slot.set_shifted(false);
+        });
+
+        slots.update(3, |slot| {
+            slot.set_remainder(0b0010);
+            slot.set_occupied(true);
+            slot.set_continued(false);
+            slot.set_shifted(false);
+        });
+
+        slots.update(5, |slot| {
+            slot.set_remainder(0b0011);
+            slot.set_occupied(true);
+            slot.set_continued(false);
+            slot.set_shifted(false);
+        });
+
+        slots.update(7, |slot| {
+            slot.set_remainder(0b0100);
+            slot.set_occupied(true);
+            slot.set_continued(false);
+            slot.set_shifted(false);
+        });
 
         qf.entries = 4;
+        qf.load_slots(&slots).unwrap();
 
         // Test that each different quotient can be found
         let key1 = (0b0001 << qf.r) | 0b0001;
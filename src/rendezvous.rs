@@ -0,0 +1,216 @@
+use murmurhash3::murmurhash3_x64_128 as mmh3_128;
+
+/// Rendezvous (highest random weight) hashing over `u64` node ids.
+///
+/// Each key is assigned to the node with the highest score. Scores use the
+/// logarithmic method `-w / ln(h)` where `h` is the (node, key) hash mapped
+/// into (0, 1), so a node with weight `w` receives a `w / sum(w)` share of
+/// the keys.
+#[derive(Debug, Clone, Default)]
+pub struct Rendezvous {
+    nodes: Vec<(u64, f64)>,
+}
+
+impl Rendezvous {
+    pub fn new() -> Self {
+        Rendezvous { nodes: Vec::new() }
+    }
+
+    pub fn add_node(&mut self, id: u64) {
+        self.add_weighted_node(id, 1.0);
+    }
+
+    /// Adds a node, or updates its weight if it already exists.
+    pub fn add_weighted_node(&mut self, id: u64, weight: f64) {
+        if !(weight.is_finite() && weight > 0.0) {
+            panic!("weight {} of node {} must be positive", weight, id);
+        }
+        match self.nodes.iter_mut().find(|(node, _)| *node == id) {
+            Some(node) => node.1 = weight,
+            None => self.nodes.push((id, weight)),
+        }
+    }
+
+    pub fn remove_node(&mut self, id: u64) {
+        self.nodes.retain(|(node, _)| *node != id);
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn weight(&self, id: u64) -> Option<f64> {
+        self.nodes
+            .iter()
+            .find(|(node, _)| *node == id)
+            .map(|(_, weight)| *weight)
+    }
+
+    pub fn lookup(&self, key: &[u8]) -> Option<u64> {
+        let mut best: Option<(u64, f64)> = None;
+        for &(id, weight) in &self.nodes {
+            let score = Self::score(id, weight, key);
+            // ties are broken by the smaller node id so the result does not
+            // depend on insertion order
+            let better = match best {
+                None => true,
+                Some((best_id, best_score)) => {
+                    score > best_score || (score == best_score && id < best_id)
+                }
+            };
+            if better {
+                best = Some((id, score));
+            }
+        }
+        best.map(|(id, _)| id)
+    }
+
+    fn score(id: u64, weight: f64, key: &[u8]) -> f64 {
+        let (hash, _) = mmh3_128(key, id);
+        // map the 53 most significant bits into the open interval (0, 1)
+        let unit = ((hash >> 11) as f64 + 0.5) / (1u64 << 53) as f64;
+        -weight / unit.ln()
+    }
+}
+
+impl Extend<u64> for Rendezvous {
+    fn extend<I: IntoIterator<Item = u64>>(&mut self, ids: I) {
+        for id in ids {
+            self.add_node(id);
+        }
+    }
+}
+
+impl FromIterator<u64> for Rendezvous {
+    fn from_iter<I: IntoIterator<Item = u64>>(ids: I) -> Self {
+        let mut rendezvous = Self::new();
+        rendezvous.extend(ids);
+        rendezvous
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn shares(r: &Rendezvous, keys: u64) -> Vec<(u64, f64)> {
+        let mut counts = std::collections::BTreeMap::new();
+        for key in 0..keys {
+            let owner = r.lookup(&key.to_be_bytes()).unwrap();
+            *counts.entry(owner).or_insert(0u64) += 1;
+        }
+        counts
+            .into_iter()
+            .map(|(id, count)| (id, count as f64 / keys as f64))
+            .collect()
+    }
+
+    #[test]
+    fn lookup_on_empty_returns_none() {
+        let r = Rendezvous::new();
+        assert!(r.is_empty());
+        assert_eq!(r.lookup(b"key"), None);
+    }
+
+    #[test]
+    fn collect_and_extend_nodes() {
+        let mut r: Rendezvous = (1..=3).collect();
+        r.extend([3, 4]);
+        assert_eq!(r.len(), 4);
+        assert_eq!(r.weight(4), Some(1.0));
+    }
+
+    #[test]
+    fn lookup_is_deterministic_and_order_independent() {
+        let mut a = Rendezvous::new();
+        let mut b = Rendezvous::new();
+        for id in [1, 2, 3, 4] {
+            a.add_node(id);
+        }
+        for id in [4, 3, 2, 1] {
+            b.add_node(id);
+        }
+        for key in 0u64..1000 {
+            let key = key.to_be_bytes();
+            assert_eq!(a.lookup(&key), b.lookup(&key));
+        }
+    }
+
+    #[test]
+    fn removing_a_node_only_moves_its_keys() {
+        let mut r = Rendezvous::new();
+        for id in 0..5 {
+            r.add_node(id);
+        }
+        let before: Vec<u64> = (0u64..2000)
+            .map(|key| r.lookup(&key.to_be_bytes()).unwrap())
+            .collect();
+        r.remove_node(3);
+        for (key, owner) in (0u64..2000).zip(before) {
+            let now = r.lookup(&key.to_be_bytes()).unwrap();
+            if owner != 3 {
+                assert_eq!(now, owner, "key {} must keep its owner", key);
+            } else {
+                assert_ne!(now, 3);
+            }
+        }
+    }
+
+    #[test]
+    fn equal_weights_split_evenly() {
+        let mut r = Rendezvous::new();
+        for id in 0..4 {
+            r.add_node(id);
+        }
+        for (id, share) in shares(&r, 100_000) {
+            assert!(
+                (share - 0.25).abs() < 0.01,
+                "node {} got share {}",
+                id,
+                share
+            );
+        }
+    }
+
+    #[test]
+    fn weighted_assignment_is_proportional() {
+        let mut r = Rendezvous::new();
+        let weights = [(10, 1.0), (20, 2.0), (30, 3.0), (40, 4.0)];
+        for (id, weight) in weights {
+            r.add_weighted_node(id, weight);
+        }
+        let total: f64 = weights.iter().map(|(_, w)| w).sum();
+        let got = shares(&r, 100_000);
+        assert_eq!(got.len(), weights.len());
+        for ((id, share), (_, weight)) in got.into_iter().zip(weights) {
+            let expected = weight / total;
+            assert!(
+                (share - expected).abs() < 0.01,
+                "node {} got share {}, expected {}",
+                id,
+                share,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn reweighting_updates_existing_node() {
+        let mut r = Rendezvous::new();
+        r.add_node(1);
+        r.add_weighted_node(1, 5.0);
+        assert_eq!(r.len(), 1);
+        assert_eq!(r.weight(1), Some(5.0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn non_positive_weight_panics() {
+        let mut r = Rendezvous::new();
+        r.add_weighted_node(1, 0.0);
+    }
+}
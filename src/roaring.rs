@@ -0,0 +1,249 @@
+//! A minimal Roaring bitmap for sparse bit arrays.
+//!
+//! Values are split into a 16-bit high key and a 16-bit low part. Each key
+//! owns a container holding its low parts, either as a sorted array (sparse)
+//! or as a 65536-bit bitmap (dense), switching at 4096 values as in the
+//! Roaring paper. Only the operations the filters need are implemented.
+
+use bitvec::prelude::{BitSlice, BitVec};
+
+/// Above this many values an array container is larger than a bitmap.
+const ARRAY_LIMIT: usize = 4096;
+const BITMAP_WORDS: usize = 1024;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Container {
+    Array(Vec<u16>),
+    Bitmap(Box<[u64; BITMAP_WORDS]>, usize),
+}
+
+impl Container {
+    fn contains(&self, low: u16) -> bool {
+        match self {
+            Container::Array(values) => values.binary_search(&low).is_ok(),
+            Container::Bitmap(words, _) => words[low as usize / 64] & (1 << (low % 64)) != 0,
+        }
+    }
+
+    fn insert(&mut self, low: u16) -> bool {
+        match self {
+            Container::Array(values) => {
+                let Err(pos) = values.binary_search(&low) else {
+                    return false;
+                };
+                if values.len() < ARRAY_LIMIT {
+                    values.insert(pos, low);
+                    return true;
+                }
+                *self = Container::bitmap_from(values);
+                self.insert(low)
+            }
+            Container::Bitmap(words, len) => {
+                let word = &mut words[low as usize / 64];
+                let bit = 1 << (low % 64);
+                if *word & bit != 0 {
+                    return false;
+                }
+                *word |= bit;
+                *len += 1;
+                true
+            }
+        }
+    }
+
+    fn bitmap_from(values: &[u16]) -> Container {
+        let mut words = Box::new([0u64; BITMAP_WORDS]);
+        for &value in values {
+            words[value as usize / 64] |= 1 << (value % 64);
+        }
+        Container::Bitmap(words, values.len())
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Container::Array(values) => values.len(),
+            Container::Bitmap(_, len) => *len,
+        }
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = u16> + '_> {
+        match self {
+            Container::Array(values) => Box::new(values.iter().copied()),
+            Container::Bitmap(words, _) => {
+                Box::new(words.iter().enumerate().flat_map(|(i, &word)| {
+                    (0..64)
+                        .filter(move |bit| word & (1 << bit) != 0)
+                        .map(move |bit| (i * 64 + bit) as u16)
+                }))
+            }
+        }
+    }
+
+    fn union_with(&mut self, other: &Container) {
+        match (&mut *self, other) {
+            (Container::Bitmap(words, len), Container::Bitmap(other_words, _)) => {
+                for (word, other_word) in words.iter_mut().zip(other_words.iter()) {
+                    *word |= other_word;
+                }
+                *len = words.iter().map(|w| w.count_ones() as usize).sum();
+            }
+            _ => {
+                for low in other.iter() {
+                    self.insert(low);
+                }
+            }
+        }
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        match self {
+            Container::Array(values) => values.len() * 2,
+            Container::Bitmap(..) => BITMAP_WORDS * 8,
+        }
+    }
+}
+
+/// A compressed set of `u32` values.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RoaringBitmap {
+    /// (high 16 bits, container), sorted by key
+    containers: Vec<(u16, Container)>,
+}
+
+impl RoaringBitmap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn split(value: u32) -> (u16, u16) {
+        ((value >> 16) as u16, value as u16)
+    }
+
+    fn find(&self, high: u16) -> Result<usize, usize> {
+        self.containers.binary_search_by_key(&high, |(key, _)| *key)
+    }
+
+    pub fn contains(&self, value: u32) -> bool {
+        let (high, low) = Self::split(value);
+        self.find(high)
+            .is_ok_and(|pos| self.containers[pos].1.contains(low))
+    }
+
+    /// Adds `value`, returning whether it was newly inserted.
+    pub fn insert(&mut self, value: u32) -> bool {
+        let (high, low) = Self::split(value);
+        let pos = match self.find(high) {
+            Ok(pos) => pos,
+            Err(pos) => {
+                self.containers
+                    .insert(pos, (high, Container::Array(Vec::new())));
+                pos
+            }
+        };
+        self.containers[pos].1.insert(low)
+    }
+
+    pub fn len(&self) -> u64 {
+        self.containers.iter().map(|(_, c)| c.len() as u64).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.containers.is_empty()
+    }
+
+    /// Iterates the values in increasing order.
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        self.containers.iter().flat_map(|(high, container)| {
+            container
+                .iter()
+                .map(move |low| ((*high as u32) << 16) | low as u32)
+        })
+    }
+
+    pub fn union_with(&mut self, other: &RoaringBitmap) {
+        for (high, container) in &other.containers {
+            match self.find(*high) {
+                Ok(pos) => self.containers[pos].1.union_with(container),
+                Err(pos) => self.containers.insert(pos, (*high, container.clone())),
+            }
+        }
+    }
+
+    /// Approximate heap size of the containers.
+    pub fn size_in_bytes(&self) -> usize {
+        self.containers
+            .iter()
+            .map(|(_, c)| 2 + c.size_in_bytes())
+            .sum()
+    }
+
+    pub fn from_bitslice(bits: &BitSlice) -> Self {
+        let mut bitmap = RoaringBitmap::new();
+        for index in bits.iter_ones() {
+            bitmap.insert(index as u32);
+        }
+        bitmap
+    }
+
+    /// Expands into a dense `BitVec` of `len` bits.
+    pub fn to_bitvec(&self, len: usize) -> BitVec {
+        let mut bits = BitVec::new();
+        bits.resize(len, false);
+        for value in self.iter() {
+            bits.set(value as usize, true);
+        }
+        bits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_contains_across_containers() {
+        let mut bitmap = RoaringBitmap::new();
+        assert!(bitmap.insert(3));
+        assert!(!bitmap.insert(3));
+        assert!(bitmap.insert(70_000));
+        assert!(bitmap.insert(u32::MAX));
+        assert!(bitmap.contains(3));
+        assert!(bitmap.contains(70_000));
+        assert!(bitmap.contains(u32::MAX));
+        assert!(!bitmap.contains(4));
+        assert_eq!(bitmap.len(), 3);
+        assert_eq!(bitmap.iter().collect::<Vec<_>>(), vec![3, 70_000, u32::MAX]);
+    }
+
+    #[test]
+    fn array_container_converts_to_bitmap() {
+        let mut bitmap = RoaringBitmap::new();
+        for value in (0..20_000).step_by(3) {
+            bitmap.insert(value);
+        }
+        assert!(matches!(bitmap.containers[0].1, Container::Bitmap(..)));
+        assert_eq!(bitmap.len(), 6667);
+        assert!(bitmap.contains(19_998));
+        assert!(!bitmap.contains(19_999));
+        assert_eq!(bitmap.iter().count(), 6667);
+    }
+
+    #[test]
+    fn union_and_bitvec_round_trip() {
+        let mut a = RoaringBitmap::new();
+        let mut b = RoaringBitmap::new();
+        for value in (0..10_000).step_by(2) {
+            a.insert(value);
+        }
+        for value in [1, 2, 100_001] {
+            b.insert(value);
+        }
+        a.union_with(&b);
+        assert_eq!(a.len(), 5002);
+        assert!(a.contains(1) && a.contains(100_001));
+
+        let bits = a.to_bitvec(100_002);
+        assert_eq!(bits.count_ones(), 5002);
+        assert_eq!(RoaringBitmap::from_bitslice(&bits), a);
+    }
+}
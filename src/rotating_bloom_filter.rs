@@ -0,0 +1,176 @@
+use std::collections::VecDeque;
+
+use crate::bloom_filter::BloomFilter;
+
+/// A Bloom filter that forgets: `generations` filters of which inserts go
+/// to the newest, and each [`tick`](Self::tick) drops the oldest and starts
+/// a fresh one.
+///
+/// Ticking every period `T` answers "seen in the last `T` periods", give or
+/// take one: an item is remembered for between `generations - 1` and
+/// `generations` ticks after its last insert. That suits deduplication of
+/// streams where repeats arrive close together, without the filter ever
+/// filling up. Each generation is sized for the items of one period, and a
+/// lookup passes if any generation accepts it, so the false-positive rate
+/// is about `generations` times that of one generation.
+#[derive(Clone)]
+pub struct RotatingBloomFilter {
+    /// Oldest first; the back receives inserts.
+    generations: VecDeque<BloomFilter>,
+}
+
+impl RotatingBloomFilter {
+    /// Creates `generations` filters, each sized for `n` items at rate `f`.
+    pub fn new(n: u64, f: f32, generations: usize) -> Self {
+        Self::with_template(BloomFilter::new(n, f), generations)
+    }
+
+    /// Creates generations that are clones of `template`, which lets
+    /// callers pick the geometry and hasher. `template` is expected to be
+    /// empty.
+    pub fn with_template(template: BloomFilter, generations: usize) -> Self {
+        assert!(generations > 0, "at least one generation is needed");
+        RotatingBloomFilter {
+            generations: std::iter::repeat_n(template, generations).collect(),
+        }
+    }
+
+    /// Number of generations kept.
+    pub fn generations(&self) -> usize {
+        self.generations.len()
+    }
+
+    fn newest(&mut self) -> &mut BloomFilter {
+        self.generations.back_mut().unwrap()
+    }
+
+    pub fn insert(&mut self, item: &[u8]) {
+        self.newest().insert(item);
+    }
+
+    pub fn lookup(&self, item: &[u8]) -> bool {
+        // newest first, where recent repeats are found
+        self.generations
+            .iter()
+            .rev()
+            .any(|generation| generation.lookup(item))
+    }
+
+    /// Inserts `item` and returns whether it was new, i.e. not seen within
+    /// the retained generations; the usual dedup check. An item found only
+    /// in an older generation is inserted into the newest again, so a
+    /// steady stream of repeats is never forgotten.
+    pub fn insert_if_new(&mut self, item: &[u8]) -> bool {
+        if !self.newest().insert(item) {
+            return false;
+        }
+        // skip the newest, which has just taken it
+        !self
+            .generations
+            .iter()
+            .rev()
+            .skip(1)
+            .any(|generation| generation.lookup(item))
+    }
+
+    /// Drops the oldest generation and starts an empty one for new inserts.
+    /// The dropped filter is cleared and reused, so ticking allocates
+    /// nothing.
+    pub fn tick(&mut self) {
+        let mut oldest = self.generations.pop_front().unwrap();
+        oldest.clear();
+        self.generations.push_back(oldest);
+    }
+
+    /// Bytes held by the bit arrays of all generations.
+    pub fn size_in_bytes(&self) -> usize {
+        self.generations
+            .iter()
+            .map(BloomFilter::bit_array_bytes)
+            .sum()
+    }
+
+    /// False-positive rate in theory when every generation holds
+    /// `items_per_generation` distinct items: the chance that any of them
+    /// accepts an absent item.
+    pub fn expected_false_positive_rate(&self, items_per_generation: u64) -> f64 {
+        let miss_all: f64 = self
+            .generations
+            .iter()
+            .map(|generation| 1.0 - generation.expected_false_positive_rate(items_per_generation))
+            .product();
+        1.0 - miss_all
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log;
+
+    #[test]
+    fn items_expire_after_the_last_generation() {
+        log::init_test_logger();
+        let mut filter = RotatingBloomFilter::new(1000, 0.01, 3);
+        filter.insert(b"old");
+        filter.tick();
+        filter.insert(b"new");
+        filter.tick();
+        assert!(filter.lookup(b"old") && filter.lookup(b"new"));
+        filter.tick();
+        assert!(!filter.lookup(b"old") && filter.lookup(b"new"));
+        filter.tick();
+        assert!(!filter.lookup(b"new"));
+    }
+
+    #[test]
+    fn insert_if_new_deduplicates_a_stream() {
+        log::init_test_logger();
+        let mut filter = RotatingBloomFilter::new(1000, 0.001, 2);
+        assert!(filter.insert_if_new(b"event-1"));
+        assert!(!filter.insert_if_new(b"event-1"));
+        filter.tick();
+        // found in the older generation and carried into the newest
+        assert!(!filter.insert_if_new(b"event-1"));
+        filter.tick();
+        assert!(!filter.insert_if_new(b"event-1"));
+        filter.tick();
+        filter.tick();
+        assert!(filter.insert_if_new(b"event-1"));
+
+        let fresh = (0u32..1000)
+            .filter(|i| filter.insert_if_new(&i.to_be_bytes()))
+            .count();
+        assert!(fresh >= 995, "{}", fresh);
+        assert!((0u32..1000).all(|i| !filter.insert_if_new(&i.to_be_bytes())));
+    }
+
+    #[test]
+    fn false_positives_grow_with_generations() {
+        log::init_test_logger();
+        let mut filter = RotatingBloomFilter::new(10_000, 0.01, 4);
+        let bytes = filter.size_in_bytes();
+        for generation in 0..4u32 {
+            for i in 0..10_000u32 {
+                filter.insert(&(generation * 10_000 + i).to_be_bytes());
+            }
+            filter.tick();
+        }
+        assert_eq!(filter.size_in_bytes(), bytes);
+        // three full generations are left, and one empty
+        let expected = 1.0
+            - (1.0 - BloomFilter::new(10_000, 0.01).expected_false_positive_rate(10_000)).powi(3);
+        let probes = 100_000u32;
+        let hits = (1_000_000..1_000_000 + probes)
+            .filter(|i| filter.lookup(&i.to_be_bytes()))
+            .count();
+        let measured = hits as f64 / probes as f64;
+        assert!(
+            (measured / expected - 1.0).abs() < 0.15,
+            "measured {}, expected {}",
+            measured,
+            expected
+        );
+        assert!(filter.expected_false_positive_rate(10_000) > expected);
+    }
+}
@@ -0,0 +1,103 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::count_min_sketch::CountMinSketch;
+
+/// Samples a stream with probability inversely proportional to each item's
+/// frequency so far, as estimated by a Count-Min sketch.
+///
+/// An item seen `f` times is kept with probability `min(1, budget / f)`, so
+/// roughly the first `budget` occurrences of every item survive while heavy
+/// hitters are thinned out. Because the sketch only overestimates, rare
+/// items are never sampled less than intended.
+pub struct FrequencyAwareSampler<R = StdRng> {
+    sketch: CountMinSketch,
+    budget: f64,
+    rng: R,
+    seen: u64,
+    kept: u64,
+}
+
+impl FrequencyAwareSampler {
+    pub fn new(sketch: CountMinSketch, budget: f64) -> Self {
+        Self::with_seed(sketch, budget, rand::rng().random())
+    }
+
+    /// Like `new`, but with a fixed RNG seed for reproducible samples.
+    pub fn with_seed(sketch: CountMinSketch, budget: f64, seed: u64) -> Self {
+        Self::with_rng(sketch, budget, StdRng::seed_from_u64(seed))
+    }
+}
+
+impl<R: Rng> FrequencyAwareSampler<R> {
+    /// Creates a sampler that draws its keep decisions from `rng`.
+    pub fn with_rng(sketch: CountMinSketch, budget: f64, rng: R) -> Self {
+        assert!(budget > 0.0, "budget must be positive");
+        FrequencyAwareSampler {
+            sketch,
+            budget,
+            rng,
+            seen: 0,
+            kept: 0,
+        }
+    }
+
+    /// Counts `item` and decides whether to keep this occurrence.
+    pub fn sample(&mut self, item: &[u8]) -> bool {
+        self.sketch.update(item, 1);
+        self.seen += 1;
+        let frequency = self.sketch.estimate(item) as f64;
+        let keep = self.rng.random::<f64>() < self.budget / frequency;
+        if keep {
+            self.kept += 1;
+        }
+        keep
+    }
+
+    /// Estimated number of occurrences of `item` seen so far.
+    pub fn frequency(&self, item: &[u8]) -> u32 {
+        self.sketch.estimate(item)
+    }
+
+    pub fn seen(&self) -> u64 {
+        self.seen
+    }
+
+    pub fn kept(&self) -> u64 {
+        self.kept
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_rare_items_and_thins_heavy_hitters() {
+        let mut sampler =
+            FrequencyAwareSampler::with_seed(CountMinSketch::new(0.0001, 0.01), 5.0, 7);
+        let mut hot_kept = 0;
+        for i in 0..10_000u32 {
+            if sampler.sample(b"hot") {
+                hot_kept += 1;
+            }
+            assert!(sampler.sample(format!("rare-{}", i).as_bytes()));
+        }
+        assert_eq!(sampler.seen(), 20_000);
+        assert_eq!(sampler.frequency(b"hot"), 10_000);
+        // about 5 * (1 + ln(10000 / 5)) ~= 43
+        assert!((20..80).contains(&hot_kept), "kept {} hot items", hot_kept);
+        assert_eq!(sampler.kept(), 10_000 + hot_kept);
+    }
+
+    #[test]
+    fn injected_rng_makes_samples_reproducible() {
+        let run = |rng: StdRng| {
+            let mut sampler =
+                FrequencyAwareSampler::with_rng(CountMinSketch::new(0.01, 0.1), 1.0, rng);
+            (0..1000)
+                .map(|_| sampler.sample(b"hot"))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(run(StdRng::seed_from_u64(3)), run(StdRng::seed_from_u64(3)));
+    }
+}
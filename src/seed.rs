@@ -0,0 +1,92 @@
+//! Seed derivation for structures that need several hash functions.
+//!
+//! Seeding hash function `i` with `i` itself gives closely related seeds;
+//! `SeedSequence` instead derives every sub-seed from one master seed through
+//! splitmix64, so the per-row functions behave independently while the whole
+//! structure stays reproducible from a single value.
+
+const GOLDEN_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+fn mix64(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Hashes an integer key under `seed` with the splitmix64 finalizer, as
+/// if `key` were the state of a stream started at `seed`.
+#[cfg(any(feature = "bloom", feature = "cms"))]
+pub(crate) fn mix_with_seed(key: u64, seed: u64) -> u64 {
+    mix64(key.wrapping_add(seed.wrapping_mul(GOLDEN_GAMMA)))
+}
+
+/// Advances `state` and returns the next splitmix64 output.
+pub fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(GOLDEN_GAMMA);
+    mix64(*state)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SeedSequence {
+    master: u64,
+}
+
+impl SeedSequence {
+    pub fn new(master: u64) -> Self {
+        SeedSequence { master }
+    }
+
+    pub fn master(&self) -> u64 {
+        self.master
+    }
+
+    /// Returns sub-seed `index`, i.e. the `index`-th output of a splitmix64
+    /// stream started at the master seed.
+    pub fn seed(&self, index: u64) -> u64 {
+        mix64(
+            self.master
+                .wrapping_add(index.wrapping_add(1).wrapping_mul(GOLDEN_GAMMA)),
+        )
+    }
+
+    pub fn seeds(&self, count: usize) -> Vec<u64> {
+        (0..count as u64).map(|index| self.seed(index)).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn splitmix64_matches_reference_stream() {
+        let mut state = 0;
+        assert_eq!(splitmix64(&mut state), 0xe220_a839_7b1d_cdaf);
+        assert_eq!(splitmix64(&mut state), 0x6e78_9e6a_a1b9_65f4);
+        assert_eq!(splitmix64(&mut state), 0x06c4_5d18_8009_454f);
+    }
+
+    #[test]
+    fn seed_is_random_access_into_the_stream() {
+        let seq = SeedSequence::new(1234);
+        let mut state = 1234;
+        for index in 0..10 {
+            assert_eq!(seq.seed(index), splitmix64(&mut state));
+        }
+        assert_eq!(
+            seq.seeds(10),
+            (0..10).map(|i| seq.seed(i)).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn master_seed_determines_sub_seeds() {
+        let a = SeedSequence::new(1);
+        let b = SeedSequence::new(2);
+        assert_eq!(a.seeds(8), SeedSequence::new(1).seeds(8));
+        assert_ne!(a.seeds(8), b.seeds(8));
+        let distinct: HashSet<u64> = a.seeds(1000).into_iter().collect();
+        assert_eq!(distinct.len(), 1000);
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::BTreeMap;
+
+use crate::hash_ring::{HashRing, HashRingInterface, RingSnapshot};
+use crate::hasher::{Hasher64, Murmur3, DEFAULT_SEED};
+use crate::quotient_filter::QuotientFilter;
+
+/// A membership filter split into quotient-filter shards placed on a
+/// consistent-hash ring.
+///
+/// Items are hashed to a `q + r` bit fingerprint whose top `k` bits are
+/// its ring position; the fingerprint goes to the shard owning that
+/// position. Shards are identified by their own ring position. Because
+/// quotient filters can enumerate their fingerprints, adding or removing
+/// a shard only rebuilds the one neighbour whose range changes.
+pub struct ShardedFilter {
+    k: u32,
+    q: u64,
+    r: u64,
+    hasher: Murmur3,
+    ring: HashRing<i64>,
+    snapshot: RingSnapshot<i64>,
+    shards: BTreeMap<u64, QuotientFilter>,
+}
+
+impl ShardedFilter {
+    /// Creates a filter without shards on a ring of `2^k` positions, where
+    /// each shard starts as a `QuotientFilter::new(q, r)`.
+    pub fn new(k: u32, q: u64, r: u64) -> Self {
+        assert!(
+            k as u64 <= q + r && q + r <= 64,
+            "need k <= q + r <= 64 fingerprint bits"
+        );
+        let ring = HashRing::new(k);
+        let snapshot = ring.snapshot();
+        ShardedFilter {
+            k,
+            q,
+            r,
+            hasher: Murmur3::with_seed(DEFAULT_SEED),
+            ring,
+            snapshot,
+            shards: BTreeMap::new(),
+        }
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Positions of the current shards, in ring order.
+    pub fn shards(&self) -> impl Iterator<Item = u64> + '_ {
+        self.shards.keys().copied()
+    }
+
+    pub fn shard(&self, position: u64) -> Option<&QuotientFilter> {
+        self.shards.get(&position)
+    }
+
+    fn fingerprint(&self, item: &[u8]) -> u64 {
+        // the width stays fixed so shards that resize keep matching lookups
+        let bits = self.q + self.r;
+        let hash = self.hasher.hash64(item);
+        if bits == 64 {
+            hash
+        } else {
+            hash & ((1 << bits) - 1)
+        }
+    }
+
+    fn position(&self, fingerprint: u64) -> u64 {
+        if self.k == 0 {
+            return 0;
+        }
+        fingerprint >> (self.q + self.r - self.k as u64)
+    }
+
+    fn owner(&self, fingerprint: u64) -> Option<u64> {
+        let position = self.position(fingerprint) as i64;
+        self.snapshot.lookup(position).map(|owner| owner as u64)
+    }
+
+    /// The shard `item` is routed to, or `None` without shards.
+    pub fn shard_for(&self, item: &[u8]) -> Option<u64> {
+        self.owner(self.fingerprint(item))
+    }
+
+    pub fn insert(&mut self, item: &[u8]) {
+        let fingerprint = self.fingerprint(item);
+        let owner = self.owner(fingerprint).expect("no shards to insert into");
+        self.shards.get_mut(&owner).unwrap().insert(fingerprint);
+    }
+
+    pub fn lookup(&self, item: &[u8]) -> bool {
+        let fingerprint = self.fingerprint(item);
+        self.owner(fingerprint)
+            .is_some_and(|owner| self.shards[&owner].lookup(fingerprint))
+    }
+
+    /// Adds an empty shard at `position` and moves to it the fingerprints
+    /// it now owns from its successor. Returns how many were moved.
+    pub fn add_shard(&mut self, position: u64) -> usize {
+        assert!(
+            !self.shards.contains_key(&position),
+            "shard {} already exists",
+            position
+        );
+        let donor = self.snapshot.lookup(position as i64).map(|d| d as u64);
+        self.ring.add_node(position as i64);
+        self.snapshot = self.ring.snapshot();
+        self.shards
+            .insert(position, QuotientFilter::new(self.q, self.r));
+
+        let Some(donor) = donor else {
+            return 0;
+        };
+        let (moved, kept): (Vec<u64>, Vec<u64>) = self.shards[&donor]
+            .iter()
+            .partition(|&fingerprint| self.owner(fingerprint) == Some(position));
+        if moved.is_empty() {
+            return 0;
+        }
+        let mut rebuilt = QuotientFilter::new(self.q, self.r);
+        for fingerprint in kept {
+            rebuilt.insert(fingerprint);
+        }
+        self.shards.insert(donor, rebuilt);
+        let shard = self.shards.get_mut(&position).unwrap();
+        for &fingerprint in &moved {
+            shard.insert(fingerprint);
+        }
+        moved.len()
+    }
+
+    /// Removes the shard at `position`, handing its fingerprints to its
+    /// successor. Returns how many were moved, or `None` if there is no
+    /// such shard. Removing the last shard drops its contents.
+    pub fn remove_shard(&mut self, position: u64) -> Option<usize> {
+        let removed = self.shards.remove(&position)?;
+        self.ring.remove_node(position as i64);
+        self.snapshot = self.ring.snapshot();
+
+        let Some(heir) = self.snapshot.lookup(position as i64) else {
+            return Some(0);
+        };
+        let heir = self.shards.get_mut(&(heir as u64)).unwrap();
+        let mut moved = 0;
+        for fingerprint in removed.iter() {
+            heir.insert(fingerprint);
+            moved += 1;
+        }
+        Some(moved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log;
+
+    fn items(range: std::ops::Range<u32>) -> impl Iterator<Item = Vec<u8>> {
+        range.map(|i| format!("item-{}", i).into_bytes())
+    }
+
+    #[test]
+    fn routes_items_to_successor_shard() {
+        log::init_test_logger();
+        let mut filter = ShardedFilter::new(8, 10, 12);
+        assert_eq!(filter.shard_for(b"x"), None);
+        assert!(!filter.lookup(b"x"));
+        for position in [16, 100, 200] {
+            filter.add_shard(position);
+        }
+        for item in items(0..1000) {
+            filter.insert(&item);
+        }
+        for item in items(0..1000) {
+            assert!(filter.lookup(&item));
+            let owner = filter.shard_for(&item).unwrap();
+            let position = filter.position(filter.fingerprint(&item));
+            let expected = [16, 100, 200]
+                .into_iter()
+                .find(|&shard| shard >= position)
+                .unwrap_or(16);
+            assert_eq!(owner, expected);
+        }
+        let total: usize = filter
+            .shards()
+            .map(|s| filter.shard(s).unwrap().iter().count())
+            .sum();
+        assert_eq!(total, 1000);
+    }
+
+    #[test]
+    fn add_and_remove_shard_keep_members() {
+        log::init_test_logger();
+        let mut filter = ShardedFilter::new(8, 8, 12);
+        filter.add_shard(128);
+        for item in items(0..2000) {
+            filter.insert(&item);
+        }
+        let moved = filter.add_shard(64);
+        assert!(moved > 0);
+        assert!(filter.add_shard(200) > 0);
+        assert_eq!(filter.shard_count(), 3);
+        assert!(items(0..2000).all(|item| filter.lookup(&item)));
+
+        let before = filter.shard(64).unwrap().iter().count();
+        assert_eq!(filter.remove_shard(64), Some(before));
+        assert_eq!(filter.remove_shard(64), None);
+        assert!(items(0..2000).all(|item| filter.lookup(&item)));
+
+        let false_positives = items(2000..4000).filter(|item| filter.lookup(item)).count();
+        assert!(false_positives < 20, "{} false positives", false_positives);
+    }
+}
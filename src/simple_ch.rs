@@ -0,0 +1,257 @@
+//! Minimal consistent hashing over a `BTreeMap`.
+//!
+//! Unlike [`hash_ring::HashRing`](crate::hash_ring::HashRing), which links
+//! nodes placed at caller-chosen positions and stores resources on them,
+//! this ring places named nodes by hashing their names onto the full `u64`
+//! space and only answers ownership queries.
+
+use crate::hash_ring::{Hashed, Placement, RingError};
+use crate::hasher::{Murmur3, DEFAULT_SEED};
+use std::collections::BTreeMap;
+
+/// Handle to a node on a [`HashRing`], returned by
+/// [`add_node`](HashRing::add_node). It is the node's ring position, so it
+/// stays valid until the node is removed and never needs the name again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodeId(u64);
+
+impl NodeId {
+    /// Position of the node on the ring.
+    pub fn position(self) -> u64 {
+        self.0
+    }
+}
+
+/// Consistent-hash ring of named nodes. A key belongs to the first node at
+/// or after its hash, wrapping around to the smallest position.
+///
+/// Names and keys are placed with a [`Placement`] over the full 64 bits,
+/// murmur3 by default.
+#[derive(Debug, Clone)]
+pub struct HashRing<N, P = Hashed<Murmur3>> {
+    nodes: BTreeMap<u64, N>,
+    placement: P,
+}
+
+impl<N: AsRef<[u8]>> Default for HashRing<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<N: AsRef<[u8]>> HashRing<N> {
+    pub fn new() -> Self {
+        Self::with_hasher(Murmur3::with_seed(DEFAULT_SEED))
+    }
+
+    /// Places nodes and keys with `hasher` instead of the default murmur3.
+    pub fn with_hasher(hasher: Murmur3) -> Self {
+        Self::with_placement(Hashed(hasher))
+    }
+}
+
+impl<N: AsRef<[u8]>, P: Placement> HashRing<N, P> {
+    /// Places nodes and keys with `placement`, e.g.
+    /// [`Identity`](crate::hash_ring::Identity) to put a node named by the
+    /// big-endian bytes of a position at exactly that position.
+    pub fn with_placement(placement: P) -> Self {
+        HashRing {
+            nodes: BTreeMap::new(),
+            placement,
+        }
+    }
+
+    /// Ring position of a node name or key.
+    pub fn position(&self, bytes: &[u8]) -> u64 {
+        self.placement.place(bytes, u64::BITS)
+    }
+
+    /// Adds `node` at the hash of its name and returns its handle. A node
+    /// whose name hashes to an occupied position is rejected, since
+    /// replacing the existing node would silently reassign its keys.
+    pub fn add_node(&mut self, node: N) -> Result<NodeId, RingError<u64>> {
+        let position = self.position(node.as_ref());
+        if self.nodes.contains_key(&position) {
+            return Err(RingError::Collision(position));
+        }
+        self.nodes.insert(position, node);
+        Ok(NodeId(position))
+    }
+
+    pub fn get(&self, id: NodeId) -> Option<&N> {
+        self.nodes.get(&id.0)
+    }
+
+    /// Removes the node and gives it back, or `None` if it was already
+    /// removed.
+    pub fn remove(&mut self, id: NodeId) -> Option<N> {
+        self.nodes.remove(&id.0)
+    }
+
+    /// Owner of `key`.
+    pub fn lookup(&self, key: &[u8]) -> Option<(NodeId, &N)> {
+        let hash = self.position(key);
+        self.nodes
+            .range(hash..)
+            .next()
+            .or_else(|| self.nodes.iter().next())
+            .map(|(&position, node)| (NodeId(position), node))
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Nodes in ascending position order, whatever order they were added in.
+    pub fn iter(&self) -> impl Iterator<Item = (NodeId, &N)> {
+        self.iter_from(0)
+    }
+
+    /// Nodes at or after `position` in ascending order, without wrapping
+    /// around. A scan split across ticks resumes from the position after
+    /// the last node it saw, and membership may change in between.
+    pub fn iter_from(&self, position: u64) -> impl Iterator<Item = (NodeId, &N)> {
+        self.nodes
+            .range(position..)
+            .map(|(&position, node)| (NodeId(position), node))
+    }
+}
+
+/// Adds every node; panics if a name collides with an existing node, like
+/// [`hash_ring::HashRing`](crate::hash_ring::HashRing) does.
+impl<N: AsRef<[u8]>, P: Placement> Extend<N> for HashRing<N, P> {
+    fn extend<I: IntoIterator<Item = N>>(&mut self, nodes: I) {
+        for node in nodes {
+            if let Err(err) = self.add_node(node) {
+                panic!("{}", err);
+            }
+        }
+    }
+}
+
+impl<N: AsRef<[u8]>> FromIterator<N> for HashRing<N> {
+    fn from_iter<I: IntoIterator<Item = N>>(nodes: I) -> Self {
+        let mut ring = Self::new();
+        ring.extend(nodes);
+        ring
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn handles_survive_other_membership_changes() {
+        let mut ring = HashRing::new();
+        let a = ring.add_node("node-a".to_string()).unwrap();
+        let b = ring.add_node("node-b".to_string()).unwrap();
+        let c = ring.add_node("node-c".to_string()).unwrap();
+        assert_eq!(ring.len(), 3);
+        assert_eq!(a.position(), ring.position(b"node-a"));
+
+        assert_eq!(ring.remove(b).as_deref(), Some("node-b"));
+        assert_eq!(ring.get(a).map(String::as_str), Some("node-a"));
+        assert_eq!(ring.get(c).map(String::as_str), Some("node-c"));
+        assert_eq!(ring.get(b), None);
+        assert_eq!(ring.remove(b), None);
+    }
+
+    #[test]
+    fn lookup_picks_next_node_and_wraps() {
+        let mut ring = HashRing::new();
+        assert!(ring.lookup(b"key").is_none());
+        let ids: Vec<NodeId> = (0..8)
+            .map(|i| ring.add_node(format!("node-{}", i)).unwrap())
+            .collect();
+        let mut sorted = ids.clone();
+        sorted.sort();
+        assert_eq!(ring.iter().map(|(id, _)| id).collect::<Vec<_>>(), sorted);
+
+        for i in 0..1_000u32 {
+            let key = i.to_le_bytes();
+            let hash = ring.position(&key);
+            let expected = sorted
+                .iter()
+                .find(|id| id.position() >= hash)
+                .unwrap_or(&sorted[0]);
+            assert_eq!(ring.lookup(&key).unwrap().0, *expected);
+        }
+    }
+
+    #[test]
+    fn duplicate_names_are_rejected() {
+        let mut ring = HashRing::new();
+        let id = ring.add_node("node").unwrap();
+        assert_eq!(
+            ring.add_node("node"),
+            Err(RingError::Collision(id.position()))
+        );
+        assert_eq!(ring.len(), 1);
+    }
+
+    #[test]
+    fn iter_from_resumes_scan_across_ticks() {
+        let mut ring = HashRing::with_placement(crate::hash_ring::Identity);
+        for position in [40u64, 10, 30, 20, u64::MAX] {
+            ring.add_node(position.to_be_bytes()).unwrap();
+        }
+        let positions = |from| {
+            ring.iter_from(from)
+                .map(|(id, _)| id.position())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(positions(25), vec![30, 40, u64::MAX]);
+        assert_eq!(
+            positions(0),
+            ring.iter().map(|(id, _)| id.position()).collect::<Vec<_>>()
+        );
+
+        let mut seen = Vec::new();
+        let mut cursor = Some(0);
+        while let Some(from) = cursor {
+            let batch: Vec<NodeId> = ring.iter_from(from).take(2).map(|(id, _)| id).collect();
+            let Some(&last) = batch.last() else { break };
+            seen.extend(batch.iter().map(|id| id.position()));
+            cursor = last.position().checked_add(1);
+            if seen.len() == 2 {
+                ring.add_node(35u64.to_be_bytes()).unwrap();
+                ring.remove(NodeId(40));
+            }
+        }
+        assert_eq!(seen, vec![10, 20, 30, 35, u64::MAX]);
+    }
+
+    #[test]
+    fn collect_and_extend_nodes() {
+        let mut ring: HashRing<String> = (0..4).map(|i| format!("node-{}", i)).collect();
+        ring.extend(["node-4".to_string()]);
+        assert_eq!(ring.len(), 5);
+        assert!(ring.iter().any(|(_, node)| node == "node-4"));
+    }
+
+    #[test]
+    #[should_panic(expected = "already exists")]
+    fn extend_panics_on_duplicate_node() {
+        let mut ring: HashRing<&str> = ["node"].into_iter().collect();
+        ring.extend(["node"]);
+    }
+
+    #[test]
+    fn identity_placement_uses_names_as_positions() {
+        let mut ring = HashRing::with_placement(crate::hash_ring::Identity);
+        let id = ring.add_node(100u64.to_be_bytes()).unwrap();
+        assert_eq!(id.position(), 100);
+        ring.add_node(u64::MAX.to_be_bytes()).unwrap();
+        assert_eq!(ring.lookup(&100u64.to_be_bytes()).unwrap().0, id);
+        assert_eq!(ring.lookup(&[0]).unwrap().0, id);
+        assert_eq!(
+            ring.lookup(&101u64.to_be_bytes()).unwrap().0.position(),
+            u64::MAX
+        );
+    }
+}
@@ -0,0 +1,191 @@
+//! SipHash-1-3 and SipHash-2-4 keyed hashing.
+//!
+//! Unlike murmur3 with a public seed, SipHash with a secret key makes it
+//! infeasible to craft inputs that collide inside a filter or sketch.
+use crate::hasher::Hasher64;
+
+#[derive(Clone, Copy)]
+struct State {
+    v0: u64,
+    v1: u64,
+    v2: u64,
+    v3: u64,
+}
+
+impl State {
+    fn new(k0: u64, k1: u64) -> Self {
+        State {
+            v0: k0 ^ 0x736f_6d65_7073_6575,
+            v1: k1 ^ 0x646f_7261_6e64_6f6d,
+            v2: k0 ^ 0x6c79_6765_6e65_7261,
+            v3: k1 ^ 0x7465_6462_7974_6573,
+        }
+    }
+
+    fn round(&mut self) {
+        self.v0 = self.v0.wrapping_add(self.v1);
+        self.v1 = self.v1.rotate_left(13);
+        self.v1 ^= self.v0;
+        self.v0 = self.v0.rotate_left(32);
+        self.v2 = self.v2.wrapping_add(self.v3);
+        self.v3 = self.v3.rotate_left(16);
+        self.v3 ^= self.v2;
+        self.v0 = self.v0.wrapping_add(self.v3);
+        self.v3 = self.v3.rotate_left(21);
+        self.v3 ^= self.v0;
+        self.v2 = self.v2.wrapping_add(self.v1);
+        self.v1 = self.v1.rotate_left(17);
+        self.v1 ^= self.v2;
+        self.v2 = self.v2.rotate_left(32);
+    }
+
+    fn compress<const C: usize>(&mut self, m: u64) {
+        self.v3 ^= m;
+        for _ in 0..C {
+            self.round();
+        }
+        self.v0 ^= m;
+    }
+}
+
+fn siphash<const C: usize, const D: usize>(k0: u64, k1: u64, bytes: &[u8]) -> u64 {
+    let mut state = State::new(k0, k1);
+    let mut chunks = bytes.chunks_exact(8);
+    for chunk in &mut chunks {
+        state.compress::<C>(u64::from_le_bytes(chunk.try_into().unwrap()));
+    }
+    let mut last = (bytes.len() as u64) << 56;
+    for (i, &byte) in chunks.remainder().iter().enumerate() {
+        last |= (byte as u64) << (8 * i);
+    }
+    state.compress::<C>(last);
+
+    state.v2 ^= 0xff;
+    for _ in 0..D {
+        state.round();
+    }
+    state.v0 ^ state.v1 ^ state.v2 ^ state.v3
+}
+
+fn split_key(key: &[u8; 16]) -> (u64, u64) {
+    (
+        u64::from_le_bytes(key[..8].try_into().unwrap()),
+        u64::from_le_bytes(key[8..].try_into().unwrap()),
+    )
+}
+
+/// SipHash-1-3: the faster variant used by Rust's standard `HashMap`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SipHash13 {
+    k0: u64,
+    k1: u64,
+}
+
+impl SipHash13 {
+    pub fn new(key: [u8; 16]) -> Self {
+        let (k0, k1) = split_key(&key);
+        Self::with_keys(k0, k1)
+    }
+
+    pub fn with_keys(k0: u64, k1: u64) -> Self {
+        SipHash13 { k0, k1 }
+    }
+}
+
+impl Hasher64 for SipHash13 {
+    fn hash64(&self, bytes: &[u8]) -> u64 {
+        siphash::<1, 3>(self.k0, self.k1, bytes)
+    }
+}
+
+/// SipHash-2-4: the conservative variant from the original paper.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SipHash24 {
+    k0: u64,
+    k1: u64,
+}
+
+impl SipHash24 {
+    pub fn new(key: [u8; 16]) -> Self {
+        let (k0, k1) = split_key(&key);
+        Self::with_keys(k0, k1)
+    }
+
+    pub fn with_keys(k0: u64, k1: u64) -> Self {
+        SipHash24 { k0, k1 }
+    }
+}
+
+impl Hasher64 for SipHash24 {
+    fn hash64(&self, bytes: &[u8]) -> u64 {
+        siphash::<2, 4>(self.k0, self.k1, bytes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::hash::Hasher;
+
+    fn reference_key() -> [u8; 16] {
+        std::array::from_fn(|i| i as u8)
+    }
+
+    #[test]
+    fn siphash24_matches_paper_vectors() {
+        let h = SipHash24::new(reference_key());
+        assert_eq!(h.hash64(b""), 0x726f_db47_dd0e_0e31);
+        let message: Vec<u8> = (0..15).collect();
+        assert_eq!(h.hash64(&message), 0xa129_ca61_49be_45e5);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn siphash24_matches_std() {
+        let h = SipHash24::with_keys(7, 11);
+        for len in 0..40 {
+            let message: Vec<u8> = (0..len).map(|i| (i * 13) as u8).collect();
+            let mut reference = std::hash::SipHasher::new_with_keys(7, 11);
+            reference.write(&message);
+            assert_eq!(h.hash64(&message), reference.finish(), "length {}", len);
+        }
+    }
+
+    #[test]
+    fn siphash13_matches_reference_vectors() {
+        // the SipHash-1-3 test vectors: key 00..0f, message 00..len-1,
+        // output as little-endian bytes
+        let vectors: [[u8; 8]; 17] = [
+            [0xdc, 0xc4, 0x0f, 0x05, 0x58, 0x01, 0xac, 0xab],
+            [0x93, 0xca, 0x57, 0x7d, 0xf3, 0x9b, 0xf4, 0xc9],
+            [0x4d, 0xd4, 0xc7, 0x4d, 0x02, 0x9b, 0xcb, 0x82],
+            [0xfb, 0xf7, 0xdd, 0xe7, 0xb8, 0x0a, 0xf8, 0x8b],
+            [0x28, 0x83, 0xd3, 0x88, 0x60, 0x57, 0x75, 0xcf],
+            [0x67, 0x3b, 0x53, 0x49, 0x2f, 0xd5, 0xf9, 0xde],
+            [0xa7, 0x22, 0x9f, 0xc5, 0x50, 0x2b, 0x0d, 0xc5],
+            [0x40, 0x11, 0xb1, 0x9b, 0x98, 0x7d, 0x92, 0xd3],
+            [0x8e, 0x9a, 0x29, 0x8d, 0x11, 0x95, 0x90, 0x36],
+            [0xe4, 0x3d, 0x06, 0x6c, 0xb3, 0x8e, 0xa4, 0x25],
+            [0x7f, 0x09, 0xff, 0x92, 0xee, 0x85, 0xde, 0x79],
+            [0x52, 0xc3, 0x4d, 0xf9, 0xc1, 0x18, 0xc1, 0x70],
+            [0xa2, 0xd9, 0xb4, 0x57, 0xb1, 0x84, 0xa3, 0x78],
+            [0xa7, 0xff, 0x29, 0x12, 0x0c, 0x76, 0x6f, 0x30],
+            [0x34, 0x5d, 0xf9, 0xc0, 0x11, 0xa1, 0x5a, 0x60],
+            [0x56, 0x99, 0x51, 0x2a, 0x6d, 0xd8, 0x20, 0xd3],
+            [0x66, 0x8b, 0x90, 0x7d, 0x1a, 0xdd, 0x4f, 0xcc],
+        ];
+        let h = SipHash13::new(reference_key());
+        for (len, expected) in vectors.iter().enumerate() {
+            let message: Vec<u8> = (0..len as u8).collect();
+            let expected = u64::from_le_bytes(*expected);
+            assert_eq!(h.hash64(&message), expected, "length {}", len);
+        }
+    }
+
+    #[test]
+    fn key_changes_output() {
+        let a = SipHash13::with_keys(1, 2);
+        let b = SipHash13::with_keys(2, 1);
+        assert_ne!(a.hash64(b"item"), b.hash64(b"item"));
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::VecDeque;
+
+use crate::count_min_sketch::CountMinSketch;
+
+/// A ring of Count-Min sketches, one per fixed time interval.
+///
+/// Timestamps are caller-supplied ticks (seconds, milliseconds, ...), and
+/// bucket `b` covers `[b * interval, (b + 1) * interval)`. Only the newest
+/// `retention` buckets are kept; older ones are evicted as time advances.
+pub struct SketchRing {
+    interval: u64,
+    retention: usize,
+    template: CountMinSketch,
+    /// (bucket id, sketch), oldest first
+    buckets: VecDeque<(u64, CountMinSketch)>,
+}
+
+impl SketchRing {
+    pub fn new(eps: f32, delta: f32, interval: u64, retention: usize) -> Self {
+        Self::with_template(CountMinSketch::new(eps, delta), interval, retention)
+    }
+
+    /// Creates a ring whose buckets are clones of `template`, which lets
+    /// callers pick the hasher. `template` is expected to be empty.
+    pub fn with_template(template: CountMinSketch, interval: u64, retention: usize) -> Self {
+        assert!(interval > 0, "interval must be positive");
+        assert!(retention > 0, "retention must be at least one bucket");
+        SketchRing {
+            interval,
+            retention,
+            template,
+            buckets: VecDeque::with_capacity(retention),
+        }
+    }
+
+    pub fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    /// Number of buckets currently held.
+    pub fn len(&self) -> usize {
+        self.buckets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buckets.is_empty()
+    }
+
+    fn bucket_of(&self, now: u64) -> u64 {
+        now / self.interval
+    }
+
+    /// Drops every bucket that fell out of the retention window at `now`.
+    pub fn advance(&mut self, now: u64) {
+        let oldest = self.oldest_for(self.bucket_of(now));
+        self.evict_before(oldest);
+    }
+
+    /// Counts `freq` occurrences of `item` at time `now`. Late updates still
+    /// land in their own interval; updates older than the retention window
+    /// are dropped.
+    pub fn update(&mut self, now: u64, item: &[u8], freq: u32) {
+        let id = self.bucket_of(now);
+        if let Some((newest, _)) = self.buckets.back() {
+            if id < self.oldest_for(*newest) {
+                return;
+            }
+        }
+        let pos = match self.buckets.binary_search_by_key(&id, |(id, _)| *id) {
+            Ok(pos) => pos,
+            Err(pos) => {
+                self.buckets.insert(pos, (id, self.template.clone()));
+                pos
+            }
+        };
+        self.buckets[pos].1.update(item, freq);
+        self.advance(now);
+    }
+
+    fn oldest_for(&self, newest: u64) -> u64 {
+        newest.saturating_sub(self.retention as u64 - 1)
+    }
+
+    fn evict_before(&mut self, oldest: u64) {
+        while matches!(self.buckets.front(), Some((id, _)) if *id < oldest) {
+            self.buckets.pop_front();
+        }
+    }
+
+    /// Merges the buckets overlapping the last `window` ticks ending at
+    /// `now` into one sketch.
+    pub fn window(&self, now: u64, window: u64) -> CountMinSketch {
+        let last = self.bucket_of(now);
+        let first = self.bucket_of(now.saturating_sub(window.saturating_sub(1)));
+        let mut merged = self.template.clone();
+        for (_, sketch) in self
+            .buckets
+            .iter()
+            .filter(|(id, _)| (first..=last).contains(id))
+        {
+            merged.merge(sketch);
+        }
+        merged
+    }
+
+    /// Estimates the frequency of `item` over the last `window` ticks
+    /// ending at `now`, at bucket granularity.
+    pub fn estimate(&self, now: u64, window: u64, item: &[u8]) -> u32 {
+        self.window(now, window).estimate(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates_over_recent_windows() {
+        let mut ring = SketchRing::new(0.01, 0.1, 10, 6);
+        for t in 0..60 {
+            ring.update(t, b"key", 1);
+        }
+        assert_eq!(ring.len(), 6);
+        assert_eq!(ring.estimate(59, 10, b"key"), 10);
+        assert_eq!(ring.estimate(59, 30, b"key"), 30);
+        assert_eq!(ring.estimate(59, 60, b"key"), 60);
+        assert_eq!(ring.estimate(59, 60, b"other"), 0);
+    }
+
+    #[test]
+    fn expired_buckets_are_evicted() {
+        let mut ring = SketchRing::new(0.01, 0.1, 10, 3);
+        ring.update(0, b"key", 5);
+        ring.update(25, b"key", 1);
+        assert_eq!(ring.estimate(25, 30, b"key"), 6);
+        ring.update(30, b"key", 1);
+        assert_eq!(ring.estimate(30, 1000, b"key"), 2);
+        ring.advance(1000);
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn late_updates_land_in_their_bucket() {
+        let mut ring = SketchRing::new(0.01, 0.1, 10, 2);
+        ring.update(25, b"key", 1);
+        ring.update(5, b"key", 2);
+        ring.update(12, b"key", 4);
+        assert_eq!(ring.estimate(19, 10, b"key"), 4);
+        assert_eq!(ring.estimate(29, 30, b"key"), 5);
+        // bucket 0 was already outside the retention window
+        assert_eq!(ring.len(), 2);
+    }
+}
@@ -0,0 +1,270 @@
+use crate::crc::crc16;
+use std::collections::BTreeMap;
+
+/// Number of hash slots, as in Redis Cluster.
+pub const SLOT_COUNT: usize = 16384;
+
+/// Maps a key to its slot.
+///
+/// If the key contains a non-empty hash tag (`{...}`), only the tag is
+/// hashed so related keys can be forced into the same slot.
+pub fn key_slot(key: &[u8]) -> usize {
+    let hashed = match key.iter().position(|&b| b == b'{') {
+        Some(open) => match key[open + 1..].iter().position(|&b| b == b'}') {
+            Some(len) if len > 0 => &key[open + 1..open + 1 + len],
+            _ => key,
+        },
+        None => key,
+    };
+    crc16(hashed) as usize % SLOT_COUNT
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Migration {
+    pub slot: usize,
+    pub from: Option<u64>,
+    pub to: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MigrationPlan {
+    pub migrations: Vec<Migration>,
+}
+
+impl MigrationPlan {
+    pub fn len(&self) -> usize {
+        self.migrations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.migrations.is_empty()
+    }
+}
+
+/// Slot-based sharding: keys map to a fixed number of slots and every slot
+/// is owned by one node. Membership changes are expressed as migration plans
+/// that move whole slots between nodes.
+#[derive(Debug, Clone)]
+pub struct SlotMap {
+    owners: Vec<Option<u64>>,
+}
+
+impl Default for SlotMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SlotMap {
+    pub fn new() -> Self {
+        SlotMap {
+            owners: vec![None; SLOT_COUNT],
+        }
+    }
+
+    /// Splits the slot space into contiguous, evenly sized ranges.
+    pub fn with_nodes(nodes: &[u64]) -> Self {
+        let mut map = Self::new();
+        if nodes.is_empty() {
+            return map;
+        }
+        for (slot, owner) in map.owners.iter_mut().enumerate() {
+            *owner = Some(nodes[slot * nodes.len() / SLOT_COUNT]);
+        }
+        map
+    }
+
+    pub fn owner(&self, slot: usize) -> Option<u64> {
+        self.owners[slot]
+    }
+
+    pub fn lookup(&self, key: &[u8]) -> Option<u64> {
+        self.owner(key_slot(key))
+    }
+
+    pub fn assign(&mut self, slot: usize, node: u64) {
+        self.owners[slot] = Some(node);
+    }
+
+    /// Returns the slots owned by each node.
+    pub fn slots_by_node(&self) -> BTreeMap<u64, Vec<usize>> {
+        let mut by_node: BTreeMap<u64, Vec<usize>> = BTreeMap::new();
+        for (slot, owner) in self.owners.iter().enumerate() {
+            if let Some(node) = owner {
+                by_node.entry(*node).or_default().push(slot);
+            }
+        }
+        by_node
+    }
+
+    pub fn nodes(&self) -> Vec<u64> {
+        self.slots_by_node().into_keys().collect()
+    }
+
+    /// Plans the slots a new node takes over so that all nodes end up with
+    /// an (almost) equal share. Unassigned slots are claimed first, then
+    /// slots are taken from the nodes owning the most slots.
+    pub fn plan_add_node(&self, node: u64) -> MigrationPlan {
+        let mut by_node = self.slots_by_node();
+        let mut plan = MigrationPlan::default();
+        if by_node.contains_key(&node) {
+            return plan;
+        }
+        for (slot, owner) in self.owners.iter().enumerate() {
+            if owner.is_none() {
+                plan.migrations.push(Migration {
+                    slot,
+                    from: None,
+                    to: node,
+                });
+            }
+        }
+
+        let target = SLOT_COUNT / (by_node.len() + 1);
+        while plan.len() < target {
+            let (&donor, slots) = by_node
+                .iter_mut()
+                .max_by_key(|(id, slots)| (slots.len(), std::cmp::Reverse(**id)))
+                .expect("a donor must exist while the new node is below its share");
+            let slot = slots.pop().unwrap();
+            plan.migrations.push(Migration {
+                slot,
+                from: Some(donor),
+                to: node,
+            });
+        }
+        plan
+    }
+
+    /// Plans moving every slot of `node` to the remaining nodes, always
+    /// handing the next slot to the node that currently owns the fewest.
+    pub fn plan_remove_node(&self, node: u64) -> MigrationPlan {
+        let mut by_node = self.slots_by_node();
+        let mut plan = MigrationPlan::default();
+        let Some(slots) = by_node.remove(&node) else {
+            return plan;
+        };
+        let mut loads: BTreeMap<u64, usize> = by_node
+            .into_iter()
+            .map(|(id, slots)| (id, slots.len()))
+            .collect();
+        if loads.is_empty() {
+            return plan;
+        }
+        for slot in slots {
+            let (&to, load) = loads
+                .iter_mut()
+                .min_by_key(|(id, load)| (**load, **id))
+                .unwrap();
+            *load += 1;
+            plan.migrations.push(Migration {
+                slot,
+                from: Some(node),
+                to,
+            });
+        }
+        plan
+    }
+
+    pub fn apply(&mut self, plan: &MigrationPlan) {
+        for migration in &plan.migrations {
+            assert_eq!(
+                self.owners[migration.slot], migration.from,
+                "slot {} is not owned by the planned source",
+                migration.slot
+            );
+            self.owners[migration.slot] = Some(migration.to);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn key_slot_matches_redis() {
+        assert_eq!(key_slot(b"foo"), 12182);
+        assert_eq!(key_slot(b"somekey"), 11058);
+    }
+
+    #[test]
+    fn hash_tags_select_same_slot() {
+        assert_eq!(
+            key_slot(b"{user1000}.following"),
+            key_slot(b"{user1000}.followers")
+        );
+        // an empty tag hashes the whole key
+        assert_ne!(key_slot(b"foo{}{bar}"), key_slot(b"bar"));
+        assert_eq!(key_slot(b"foo{{bar}}zap"), key_slot(b"{bar"));
+    }
+
+    #[test]
+    fn with_nodes_splits_evenly() {
+        let map = SlotMap::with_nodes(&[1, 2, 3]);
+        let by_node = map.slots_by_node();
+        assert_eq!(by_node.len(), 3);
+        for slots in by_node.values() {
+            assert!(slots.len() == SLOT_COUNT / 3 || slots.len() == SLOT_COUNT / 3 + 1);
+        }
+        assert!(map.lookup(b"anything").is_some());
+    }
+
+    #[test]
+    fn add_node_to_empty_map_claims_all_slots() {
+        let mut map = SlotMap::new();
+        assert_eq!(map.lookup(b"foo"), None);
+        let plan = map.plan_add_node(7);
+        assert_eq!(plan.len(), SLOT_COUNT);
+        assert!(plan.migrations.iter().all(|m| m.from.is_none()));
+        map.apply(&plan);
+        assert_eq!(map.lookup(b"foo"), Some(7));
+    }
+
+    #[test]
+    fn add_node_rebalances_and_moves_only_planned_slots() {
+        let mut map = SlotMap::with_nodes(&[1, 2, 3]);
+        let before = map.clone();
+        let plan = map.plan_add_node(4);
+        assert_eq!(plan.len(), SLOT_COUNT / 4);
+        map.apply(&plan);
+
+        for (node, slots) in map.slots_by_node() {
+            assert!(
+                slots.len().abs_diff(SLOT_COUNT / 4) <= 1,
+                "node {} owns {} slots",
+                node,
+                slots.len()
+            );
+        }
+        let moved = (0..SLOT_COUNT)
+            .filter(|&slot| before.owner(slot) != map.owner(slot))
+            .count();
+        assert_eq!(moved, plan.len());
+        assert!(map.plan_add_node(4).is_empty());
+    }
+
+    #[test]
+    fn remove_node_hands_slots_to_remaining_nodes() {
+        let mut map = SlotMap::with_nodes(&[1, 2, 3, 4]);
+        let owned = map.slots_by_node()[&2].len();
+        let plan = map.plan_remove_node(2);
+        assert_eq!(plan.len(), owned);
+        assert!(plan.migrations.iter().all(|m| m.from == Some(2)));
+        map.apply(&plan);
+        assert_eq!(map.nodes(), vec![1, 3, 4]);
+        let counts: Vec<usize> = map.slots_by_node().values().map(|s| s.len()).collect();
+        let (min, max) = (counts.iter().min().unwrap(), counts.iter().max().unwrap());
+        assert!(max - min <= 1, "unbalanced after removal: {:?}", counts);
+        assert!(map.plan_remove_node(2).is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn apply_rejects_stale_plan() {
+        let mut map = SlotMap::with_nodes(&[1, 2]);
+        let plan = map.plan_remove_node(1);
+        map.apply(&plan);
+        map.apply(&plan);
+    }
+}
@@ -0,0 +1,198 @@
+use crate::bloom_filter::BloomFilter;
+use crate::hasher::{Hasher64, RowHashing, DEFAULT_SEED};
+use crate::seed::SeedSequence;
+use bitvec::prelude::BitVec;
+
+/// Spectral Bloom filter (Cohen and Matias): every bit of a Bloom filter
+/// becomes a 32-bit counter, and the smallest of an item's `k` counters
+/// estimates how often it was inserted.
+///
+/// Updates use minimum increase: only the counters that are below the
+/// item's new estimate are raised to it, so a counter shared with a more
+/// frequent item is left alone. Estimates never undercount, and overcount
+/// far less than a [`CountMinSketch`](crate::count_min_sketch::CountMinSketch)
+/// of the same memory, which adds to every row. The price is that items
+/// cannot be removed: a counter no longer says how much each item put in.
+///
+/// The geometry and hashing match [`BloomFilter::new`] with the same `n`
+/// and `f`, so membership is answered like a Bloom filter's and
+/// [`to_bloom_filter`](Self::to_bloom_filter) keeps just that.
+#[derive(Clone)]
+pub struct SpectralBloomFilter {
+    n: u32,
+    f: f32,
+    m: u32,
+    k: u32,
+    counters: Vec<u32>,
+    hashing: RowHashing,
+}
+
+impl SpectralBloomFilter {
+    pub fn new(n: u32, f: f32) -> Self {
+        let (_, k) = BloomFilter::geometry_u32(n, f);
+        let hashing = RowHashing::murmur3(k as usize, SeedSequence::new(DEFAULT_SEED));
+        Self::with_hashing(n, f, hashing)
+    }
+
+    /// Creates a filter whose probes are derived from `hasher`.
+    pub fn with_hasher<H: Hasher64 + PartialEq + Send + Sync + 'static>(
+        n: u32,
+        f: f32,
+        hasher: H,
+    ) -> Self {
+        Self::with_hashing(n, f, RowHashing::from_hasher(hasher))
+    }
+
+    fn with_hashing(n: u32, f: f32, hashing: RowHashing) -> Self {
+        let (m, k) = BloomFilter::geometry_u32(n, f);
+        SpectralBloomFilter {
+            n,
+            f,
+            m,
+            k,
+            counters: vec![0; m as usize],
+            hashing,
+        }
+    }
+
+    fn indexes(&self, item: &[u8]) -> Vec<usize> {
+        let hashes = self.hashing.hashes(item);
+        (0..self.k)
+            .map(|i| (hashes.row(i) % self.m as u64) as usize)
+            .collect()
+    }
+
+    pub fn insert(&mut self, item: &[u8]) {
+        self.update(item, 1);
+    }
+
+    /// Records `freq` more occurrences of `item`, raising each of its
+    /// counters to at least the new estimate. Counters saturate at
+    /// `u32::MAX`.
+    pub fn update(&mut self, item: &[u8], freq: u32) {
+        let indexes = self.indexes(item);
+        let Some(estimate) = indexes.iter().map(|&index| self.counters[index]).min() else {
+            return;
+        };
+        let target = estimate.saturating_add(freq);
+        for index in indexes {
+            let counter = &mut self.counters[index];
+            *counter = (*counter).max(target);
+        }
+    }
+
+    /// Upper bound on how often `item` was inserted: the smallest of its
+    /// counters.
+    pub fn estimate(&self, item: &[u8]) -> u32 {
+        self.indexes(item)
+            .into_iter()
+            .map(|index| self.counters[index])
+            .min()
+            .unwrap_or(0)
+    }
+
+    pub fn lookup(&self, item: &[u8]) -> bool {
+        self.estimate(item) > 0
+    }
+
+    /// Bytes held by the counters.
+    pub fn size_in_bytes(&self) -> usize {
+        std::mem::size_of_val(self.counters.as_slice())
+    }
+
+    /// False-positive rate in theory after `items` distinct inserts, the
+    /// same as a [`BloomFilter`] of this geometry.
+    pub fn expected_false_positive_rate(&self, items: u64) -> f64 {
+        crate::bloom_filter::expected_false_positive_rate(self.m.into(), self.k, items)
+    }
+
+    /// Drops the counts, keeping a plain `BloomFilter` with a bit set for
+    /// every non-zero counter.
+    pub fn to_bloom_filter(&self) -> BloomFilter {
+        let bits: BitVec = self.counters.iter().map(|&count| count > 0).collect();
+        BloomFilter::from_bits(self.n.into(), self.f, self.k, bits, self.hashing.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log;
+
+    #[test]
+    fn counts_and_membership() {
+        log::init_test_logger();
+        let mut b = SpectralBloomFilter::new(100, 0.01);
+        b.insert(b"1");
+        b.insert(b"1");
+        b.update(b"2", 5);
+        assert_eq!(b.estimate(b"1"), 2);
+        assert_eq!(b.estimate(b"2"), 5);
+        assert_eq!(b.estimate(b"3"), 0);
+        assert!(b.lookup(b"1") && !b.lookup(b"3"));
+
+        let frozen = b.to_bloom_filter();
+        assert!(frozen.lookup(b"1") && frozen.lookup(b"2") && !frozen.lookup(b"3"));
+        assert!(frozen.is_compatible(&BloomFilter::new(100, 0.01)));
+    }
+
+    #[test]
+    fn saturates_instead_of_wrapping() {
+        log::init_test_logger();
+        let mut b = SpectralBloomFilter::new(100, 0.01);
+        b.update(b"hot", u32::MAX - 1);
+        b.update(b"hot", 5);
+        assert_eq!(b.estimate(b"hot"), u32::MAX);
+    }
+
+    /// Mean overcount of `estimate` over every key of `truth`; panics on an
+    /// undercount.
+    #[cfg(feature = "cms")]
+    fn mean_overcount(truth: &[(Vec<u8>, u32)], estimate: impl Fn(&[u8]) -> u32) -> f64 {
+        let total: u64 = truth
+            .iter()
+            .map(|(key, count)| {
+                let estimated = estimate(key);
+                assert!(estimated >= *count, "{:?}: {} < {}", key, estimated, count);
+                (estimated - count) as u64
+            })
+            .sum();
+        total as f64 / truth.len() as f64
+    }
+
+    #[test]
+    #[cfg(feature = "cms")]
+    fn overcounts_less_than_count_min_of_the_same_size() {
+        use crate::count_min_sketch::CountMinSketch;
+        use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+        log::init_test_logger();
+        // a skewed stream of 4000 keys, more than the filter is sized for,
+        // inserted one occurrence at a time in random order
+        let truth: Vec<(Vec<u8>, u32)> = (0u32..4000)
+            .map(|i| (i.to_be_bytes().to_vec(), 1 + 1000 / (i + 1)))
+            .collect();
+        let mut stream: Vec<&[u8]> = truth
+            .iter()
+            .flat_map(|(key, count)| std::iter::repeat_n(key.as_slice(), *count as usize))
+            .collect();
+        stream.shuffle(&mut StdRng::seed_from_u64(7));
+
+        let mut spectral = SpectralBloomFilter::new(2000, 0.01);
+        // as many counters, in `k` rows of `m / k`
+        let (m, k) = (spectral.m as f32, spectral.k as f32);
+        let mut cms = CountMinSketch::new(std::f32::consts::E / (m / k).floor(), (-k).exp());
+        for item in &stream {
+            spectral.insert(item);
+            cms.update(item, 1);
+        }
+
+        let spectral_error = mean_overcount(&truth, |key| spectral.estimate(key));
+        let cms_error = mean_overcount(&truth, |key| cms.estimate(key));
+        assert!(
+            spectral_error < 0.5 * cms_error,
+            "spectral {}, count-min {}",
+            spectral_error,
+            cms_error
+        );
+    }
+}
@@ -0,0 +1,298 @@
+//! A read-only, compressed copy of a quotient filter.
+//!
+//! A [`QuotientFilter`] spends `r + 3` bits on every slot, used or not, to
+//! stay cheap to update. Once it stops changing, its sorted fingerprints
+//! can be stored as an Elias-Fano sequence instead: each fingerprint is
+//! split Golomb-Rice style into `l` low bits kept verbatim and a high part
+//! written in unary, which costs about `2 + l` bits per distinct
+//! fingerprint and still answers lookups without decoding the whole set.
+//! Build dynamically, then serve the [`StaticQuotientFilter`].
+
+use crate::fingerprint::{CanonicalFingerprinter, Fingerprinter};
+use crate::quotient_filter::QuotientFilter;
+
+/// Every `ZERO_SAMPLE`th zero of the high bits has its position indexed,
+/// so a bucket is found by scanning at most this many zeros.
+const ZERO_SAMPLE: usize = 64;
+
+/// Sorted distinct fingerprints of `bits` bits in Elias-Fano form.
+///
+/// Answers the same as the filter it was exported from: a key is found iff
+/// its low `bits` bits equal a stored fingerprint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaticQuotientFilter {
+    bits: u32,
+    len: usize,
+    low_bits: u32,
+    /// `len` values of `low_bits` bits, packed back to back.
+    low: Vec<u64>,
+    /// For every bucket of equal high parts, one set bit per fingerprint
+    /// followed by a zero.
+    high: Vec<u64>,
+    /// Position in `high` of zero number `i * ZERO_SAMPLE`.
+    zero_samples: Vec<usize>,
+}
+
+impl StaticQuotientFilter {
+    /// Encodes `fingerprints`, which must be sorted, distinct and fit in
+    /// `bits` bits.
+    pub fn from_sorted(bits: u32, fingerprints: &[u64]) -> Self {
+        assert!((1..=64).contains(&bits), "fingerprint bits must be 1..=64");
+        let len = fingerprints.len();
+        // floor(log2(2^bits / len)) low bits leave about `len` buckets
+        let len_log2 = match len {
+            0 | 1 => 0,
+            _ => 64 - (len as u64 - 1).leading_zeros(),
+        };
+        let low_bits = bits.saturating_sub(len_log2);
+        let buckets = 1usize << (bits - low_bits);
+
+        let mut low = vec![0u64; (len * low_bits as usize).div_ceil(64)];
+        let high_len = len + buckets;
+        let mut high = vec![0u64; high_len.div_ceil(64)];
+        let mut previous = None;
+        for (i, &fingerprint) in fingerprints.iter().enumerate() {
+            assert!(
+                bits == 64 || fingerprint >> bits == 0,
+                "fingerprint {:#x} is wider than {} bits",
+                fingerprint,
+                bits
+            );
+            assert!(
+                previous < Some(fingerprint),
+                "fingerprints must be sorted and distinct"
+            );
+            previous = Some(fingerprint);
+
+            write_bits(&mut low, i * low_bits as usize, low_bits, fingerprint);
+            let position = high_part(fingerprint, low_bits) as usize + i;
+            high[position / 64] |= 1 << (position % 64);
+        }
+
+        let mut zero_samples = Vec::with_capacity(buckets.div_ceil(ZERO_SAMPLE));
+        let mut zeros = 0;
+        for position in 0..high_len {
+            if high[position / 64] & (1 << (position % 64)) == 0 {
+                if zeros % ZERO_SAMPLE == 0 {
+                    zero_samples.push(position);
+                }
+                zeros += 1;
+            }
+        }
+
+        StaticQuotientFilter {
+            bits,
+            len,
+            low_bits,
+            low,
+            high,
+            zero_samples,
+        }
+    }
+
+    /// Number of distinct fingerprints stored.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of key bits compared, the `q + r` of the source filter.
+    pub fn fingerprint_bits(&self) -> u32 {
+        self.bits
+    }
+
+    /// Bytes of the low bits, high bits and zero index.
+    pub fn size_in_bytes(&self) -> usize {
+        std::mem::size_of_val(self.low.as_slice())
+            + std::mem::size_of_val(self.high.as_slice())
+            + std::mem::size_of_val(self.zero_samples.as_slice())
+    }
+
+    /// False-positive rate in theory: the chance that one of the stored
+    /// fingerprints equals the probe's.
+    pub fn expected_false_positive_rate(&self) -> f64 {
+        let match_one = 0.5f64.powi(self.bits as i32);
+        -(self.len as f64 * (-match_one).ln_1p()).exp_m1()
+    }
+
+    pub fn contains(&self, key: u64) -> bool {
+        let fingerprint = if self.bits == 64 {
+            key
+        } else {
+            key & ((1 << self.bits) - 1)
+        };
+        let bucket = high_part(fingerprint, self.low_bits) as usize;
+        let low = fingerprint & low_mask(self.low_bits);
+
+        // bucket `b`'s set bits follow the `b`th zero; the `i`th set bit
+        // overall belongs to fingerprint `i`
+        let mut position = match bucket {
+            0 => 0,
+            _ => self.select_zero(bucket - 1) + 1,
+        };
+        let mut index = position - bucket;
+        while self.high[position / 64] & (1 << (position % 64)) != 0 {
+            let stored = read_bits(&self.low, index * self.low_bits as usize, self.low_bits);
+            if stored >= low {
+                return stored == low;
+            }
+            position += 1;
+            index += 1;
+        }
+        false
+    }
+
+    /// Looks up a byte-string key by its canonical fingerprint, as stored by
+    /// [`QuotientFilter::insert_bytes`].
+    pub fn contains_bytes(&self, key: &[u8]) -> bool {
+        self.contains_bytes_with(&CanonicalFingerprinter, key)
+    }
+
+    pub fn contains_bytes_with<F: Fingerprinter>(&self, fingerprinter: &F, key: &[u8]) -> bool {
+        self.contains(fingerprinter.fingerprint(key, self.bits))
+    }
+
+    /// Iterates the stored fingerprints in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = u64> + '_ {
+        let mut position = 0;
+        (0..self.len).map(move |index| {
+            while self.high[position / 64] & (1 << (position % 64)) == 0 {
+                position += 1;
+            }
+            let bucket = (position - index) as u64;
+            position += 1;
+            let low = read_bits(&self.low, index * self.low_bits as usize, self.low_bits);
+            bucket.checked_shl(self.low_bits).unwrap_or(0) | low
+        })
+    }
+
+    /// Position of zero number `rank` in the high bits.
+    fn select_zero(&self, rank: usize) -> usize {
+        let start = self.zero_samples[rank / ZERO_SAMPLE];
+        let mut remaining = rank % ZERO_SAMPLE;
+        let mut word_idx = start / 64;
+        let mut zeros = !self.high[word_idx] & (u64::MAX << (start % 64));
+        loop {
+            let count = zeros.count_ones() as usize;
+            if remaining < count {
+                for _ in 0..remaining {
+                    zeros &= zeros - 1;
+                }
+                return word_idx * 64 + zeros.trailing_zeros() as usize;
+            }
+            remaining -= count;
+            word_idx += 1;
+            zeros = !self.high[word_idx];
+        }
+    }
+}
+
+impl From<&QuotientFilter> for StaticQuotientFilter {
+    fn from(filter: &QuotientFilter) -> Self {
+        filter.to_static()
+    }
+}
+
+fn high_part(fingerprint: u64, low_bits: u32) -> u64 {
+    fingerprint.checked_shr(low_bits).unwrap_or(0)
+}
+
+fn low_mask(bits: u32) -> u64 {
+    if bits >= 64 {
+        u64::MAX
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+fn write_bits(words: &mut [u64], offset: usize, bits: u32, value: u64) {
+    if bits == 0 {
+        return;
+    }
+    let value = value & low_mask(bits);
+    let (word, shift) = (offset / 64, offset % 64);
+    words[word] |= value << shift;
+    if shift + bits as usize > 64 {
+        words[word + 1] |= value >> (64 - shift);
+    }
+}
+
+fn read_bits(words: &[u64], offset: usize, bits: u32) -> u64 {
+    if bits == 0 {
+        return 0;
+    }
+    let (word, shift) = (offset / 64, offset % 64);
+    let mut value = words[word] >> shift;
+    if shift + bits as usize > 64 {
+        value |= words[word + 1] << (64 - shift);
+    }
+    value & low_mask(bits)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    #[test]
+    fn answers_like_the_source_filter() {
+        let mut rng = StdRng::seed_from_u64(17);
+        let mut qf = QuotientFilter::new(12, 10);
+        for _ in 0..3000 {
+            qf.insert(rng.random());
+        }
+        let exported = qf.to_static();
+        assert_eq!(exported.fingerprint_bits(), 22);
+        for _ in 0..50_000 {
+            let key: u64 = rng.random();
+            assert_eq!(exported.contains(key), qf.lookup(key), "{:#x}", key);
+        }
+        let mut stored: Vec<u64> = qf.scan_quotient_range(0, 1 << 12).collect();
+        stored.sort_unstable();
+        stored.dedup();
+        assert!(stored.iter().all(|&fp| exported.contains(fp)));
+        assert_eq!(exported.iter().collect::<Vec<_>>(), stored);
+    }
+
+    #[test]
+    fn is_smaller_than_the_source_filter() {
+        let mut qf = QuotientFilter::new(16, 8);
+        for i in 0..(3u64 << 16) / 4 {
+            qf.insert_bytes(&i.to_le_bytes());
+        }
+        let exported = StaticQuotientFilter::from(&qf);
+        assert!((0..1000u64).all(|i| exported.contains_bytes(&i.to_le_bytes())));
+        // 8 low bits, under 3 high bits and the zero index per fingerprint,
+        // against r = 8 bits, two bitmap bits and half a bit of block offset
+        // per slot at 75% load
+        assert!(exported.size_in_bytes() * 9 < qf.size_in_bytes() * 8);
+        assert!(exported.size_in_bytes() * 8 < 12 * exported.len());
+    }
+
+    #[test]
+    fn handles_empty_full_width_and_dense_sets() {
+        let empty = StaticQuotientFilter::from_sorted(20, &[]);
+        assert!(empty.is_empty() && !empty.contains(0) && !empty.contains(7));
+
+        let wide = StaticQuotientFilter::from_sorted(64, &[0, 5, u64::MAX - 1, u64::MAX]);
+        assert!([0, 5, u64::MAX - 1, u64::MAX]
+            .iter()
+            .all(|&k| wide.contains(k)));
+        assert!(!wide.contains(6) && !wide.contains(u64::MAX - 2));
+
+        // every 4-bit value: no low bits, one bucket per value
+        let all: Vec<u64> = (0..16).collect();
+        let dense = StaticQuotientFilter::from_sorted(4, &all);
+        assert!((0..64).all(|k| dense.contains(k)));
+        assert_eq!(dense.iter().collect::<Vec<_>>(), all);
+    }
+
+    #[test]
+    #[should_panic(expected = "sorted and distinct")]
+    fn rejects_unsorted_fingerprints() {
+        StaticQuotientFilter::from_sorted(8, &[3, 1]);
+    }
+}
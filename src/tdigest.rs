@@ -0,0 +1,177 @@
+use std::f64::consts::PI;
+
+/// A merging t-digest (Dunning & Ertl) for streaming quantile estimates.
+///
+/// Values are buffered and periodically merged into centroids whose size is
+/// bounded by the `k1` scale function, which keeps the tails accurate.
+#[derive(Clone, Debug)]
+pub struct TDigest {
+    compression: f64,
+    /// (mean, weight), sorted by mean
+    centroids: Vec<(f64, f64)>,
+    buffer: Vec<f64>,
+    count: u64,
+    min: f64,
+    max: f64,
+}
+
+impl TDigest {
+    pub fn new(compression: f64) -> Self {
+        assert!(compression >= 1.0, "compression must be at least 1");
+        TDigest {
+            compression,
+            centroids: Vec::new(),
+            buffer: Vec::new(),
+            count: 0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn min(&self) -> Option<f64> {
+        (!self.is_empty()).then_some(self.min)
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        (!self.is_empty()).then_some(self.max)
+    }
+
+    pub fn insert(&mut self, value: f64) {
+        assert!(!value.is_nan(), "cannot insert NaN");
+        self.buffer.push(value);
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        if self.buffer.len() >= self.buffer_capacity() {
+            self.compress();
+        }
+    }
+
+    fn buffer_capacity(&self) -> usize {
+        (self.compression * 5.0) as usize
+    }
+
+    fn scale(&self, q: f64) -> f64 {
+        self.compression / (2.0 * PI) * (2.0 * q - 1.0).asin()
+    }
+
+    /// Merges buffered values into the centroids.
+    pub fn compress(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let mut points: Vec<(f64, f64)> = self.buffer.drain(..).map(|v| (v, 1.0)).collect();
+        points.append(&mut self.centroids);
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let total: f64 = points.iter().map(|(_, w)| w).sum();
+        let mut merged = Vec::with_capacity(self.compression as usize);
+        let mut current = points[0];
+        let mut cumulative = 0.0;
+        let mut limit = self.scale(0.0) + 1.0;
+        for &(mean, weight) in &points[1..] {
+            let q = (cumulative + current.1 + weight) / total;
+            if self.scale(q) <= limit {
+                current.1 += weight;
+                current.0 += (mean - current.0) * weight / current.1;
+            } else {
+                cumulative += current.1;
+                merged.push(current);
+                limit = self.scale(cumulative / total) + 1.0;
+                current = (mean, weight);
+            }
+        }
+        merged.push(current);
+        self.centroids = merged;
+    }
+
+    /// Estimates the value at quantile `q` in `[0, 1]`, or `None` when empty.
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        assert!((0.0..=1.0).contains(&q), "quantile must be in [0, 1]");
+        if self.is_empty() {
+            return None;
+        }
+        if !self.buffer.is_empty() {
+            let mut flushed = self.clone();
+            flushed.compress();
+            return flushed.quantile(q);
+        }
+
+        let total = self.count as f64;
+        let target = q * total;
+        let first = self.centroids[0];
+        if target <= first.1 / 2.0 {
+            return Some(self.interpolate(self.min, first.0, target / (first.1 / 2.0)));
+        }
+        let mut cumulative = 0.0;
+        for pair in self.centroids.windows(2) {
+            let (left, right) = (pair[0], pair[1]);
+            let left_center = cumulative + left.1 / 2.0;
+            let right_center = cumulative + left.1 + right.1 / 2.0;
+            if target <= right_center {
+                let t = (target - left_center) / (right_center - left_center);
+                return Some(self.interpolate(left.0, right.0, t));
+            }
+            cumulative += left.1;
+        }
+        let last = self.centroids[self.centroids.len() - 1];
+        let last_center = total - last.1 / 2.0;
+        let t = (target - last_center) / (last.1 / 2.0);
+        Some(self.interpolate(last.0, self.max, t))
+    }
+
+    fn interpolate(&self, from: f64, to: f64, t: f64) -> f64 {
+        (from + (to - from) * t.clamp(0.0, 1.0)).clamp(self.min, self.max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_digest_has_no_quantiles() {
+        let digest = TDigest::new(100.0);
+        assert_eq!(digest.quantile(0.5), None);
+        assert_eq!(digest.min(), None);
+    }
+
+    #[test]
+    fn single_value() {
+        let mut digest = TDigest::new(100.0);
+        digest.insert(7.0);
+        assert_eq!(digest.quantile(0.0), Some(7.0));
+        assert_eq!(digest.quantile(0.5), Some(7.0));
+        assert_eq!(digest.quantile(1.0), Some(7.0));
+    }
+
+    #[test]
+    fn uniform_quantiles_are_close() {
+        let mut digest = TDigest::new(100.0);
+        // a scrambled 0..100_000 so the input is not pre-sorted
+        for i in 0..100_000u64 {
+            digest.insert(((i * 7919) % 100_000) as f64);
+        }
+        assert!(digest.centroids.len() < 200);
+        for (q, expected) in [(0.01, 1_000.0), (0.5, 50_000.0), (0.99, 99_000.0)] {
+            let estimate = digest.quantile(q).unwrap();
+            assert!(
+                (estimate - expected).abs() < 500.0,
+                "q={} estimate={} expected={}",
+                q,
+                estimate,
+                expected
+            );
+        }
+        assert_eq!(digest.quantile(0.0), Some(0.0));
+        assert_eq!(digest.quantile(1.0), Some(99_999.0));
+    }
+}
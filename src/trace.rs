@@ -0,0 +1,401 @@
+//! Replays recorded key traces against the ring backends.
+//!
+//! A trace is plain text, one event per line:
+//!
+//! ```text
+//! # comments and blank lines are ignored
+//! join 1200
+//! get user:42
+//! set session:9f1c
+//! leave 1200
+//! ```
+//!
+//! `join` and `leave` take a ring position; `get` and `set` take any
+//! whitespace-free key, which is hashed onto the ring. Every backend sees
+//! the same events, and each node fronts its keys with an LRU cache, so the
+//! report shows how well a placement keeps hot keys on the node that
+//! already cached them as membership changes.
+
+use crate::churn::{JumpBackend, RendezvousBackend, RingBackend, SlotsBackend};
+use crate::hash_ring::HashRing;
+use crate::hasher::{Hasher64, Murmur3, DEFAULT_SEED};
+use crate::lru::LruCache;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraceEvent {
+    Get(String),
+    Set(String),
+    Join(u64),
+    Leave(u64),
+}
+
+/// Why a line of a trace could not be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceError {
+    /// 1-based line number.
+    pub line: usize,
+    pub reason: &'static str,
+}
+
+impl std::fmt::Display for TraceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+impl std::error::Error for TraceError {}
+
+pub fn parse_trace(text: &str) -> Result<Vec<TraceEvent>, TraceError> {
+    let mut events = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let error = |reason| TraceError {
+            line: index + 1,
+            reason,
+        };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let (op, arg) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(op), Some(arg), None) => (op, arg),
+            _ => return Err(error("expected an operation and one argument")),
+        };
+        let position = || arg.parse::<u64>().map_err(|_| error("invalid position"));
+        events.push(match op {
+            "get" => TraceEvent::Get(arg.to_string()),
+            "set" => TraceEvent::Set(arg.to_string()),
+            "join" => TraceEvent::Join(position()?),
+            "leave" => TraceEvent::Leave(position()?),
+            _ => return Err(error("unknown operation")),
+        });
+    }
+    Ok(events)
+}
+
+/// Writes events in the format read by [`parse_trace`].
+pub fn format_trace(events: &[TraceEvent]) -> String {
+    let mut out = String::new();
+    for event in events {
+        let line = match event {
+            TraceEvent::Get(key) => format!("get {}\n", key),
+            TraceEvent::Set(key) => format!("set {}\n", key),
+            TraceEvent::Join(position) => format!("join {}\n", position),
+            TraceEvent::Leave(position) => format!("leave {}\n", position),
+        };
+        out.push_str(&line);
+    }
+    out
+}
+
+/// Parameters of a synthetic trace for when no recorded one is at hand.
+#[derive(Debug, Clone)]
+pub struct SyntheticTrace {
+    pub k: u32,
+    pub initial_nodes: usize,
+    pub keys: usize,
+    pub requests: usize,
+    /// Key popularity follows a Zipf law with this exponent; around 1.0 is
+    /// typical of web and cache workloads.
+    pub zipf_exponent: f64,
+    /// Fraction of requests that write instead of read.
+    pub write_ratio: f64,
+    /// Joins and leaves, alternating, spread evenly over the requests.
+    pub membership_changes: usize,
+    pub seed: u64,
+}
+
+impl Default for SyntheticTrace {
+    fn default() -> Self {
+        SyntheticTrace {
+            k: 16,
+            initial_nodes: 8,
+            keys: 10_000,
+            requests: 100_000,
+            zipf_exponent: 1.0,
+            write_ratio: 0.1,
+            membership_changes: 4,
+            seed: 0,
+        }
+    }
+}
+
+impl SyntheticTrace {
+    pub fn generate(&self) -> Vec<TraceEvent> {
+        assert!(self.k > 0 && self.k < 63, "k must be in 1..63");
+        assert!(self.keys > 0, "at least one key is required");
+        let ring_size = 1u64 << self.k;
+        assert!(
+            self.initial_nodes > 0 && (self.initial_nodes as u64) < ring_size,
+            "initial nodes must fit the ring with room to join"
+        );
+        let mut rng = StdRng::seed_from_u64(self.seed);
+
+        let mut cdf = Vec::with_capacity(self.keys);
+        let mut total = 0.0;
+        for rank in 1..=self.keys {
+            total += 1.0 / (rank as f64).powf(self.zipf_exponent);
+            cdf.push(total);
+        }
+
+        let mut events = Vec::new();
+        let mut nodes = BTreeSet::new();
+        let join = |nodes: &mut BTreeSet<u64>, rng: &mut StdRng| loop {
+            let position = rng.random_range(0..ring_size);
+            if nodes.insert(position) {
+                return TraceEvent::Join(position);
+            }
+        };
+        while nodes.len() < self.initial_nodes {
+            events.push(join(&mut nodes, &mut rng));
+        }
+
+        let interval = (self.requests / (self.membership_changes + 1)).max(1);
+        let mut changes = 0;
+        for request in 0..self.requests {
+            if changes < self.membership_changes && request > 0 && request % interval == 0 {
+                if changes % 2 == 0 || nodes.len() == 1 {
+                    events.push(join(&mut nodes, &mut rng));
+                } else {
+                    let victim = *nodes.iter().nth(rng.random_range(0..nodes.len())).unwrap();
+                    nodes.remove(&victim);
+                    events.push(TraceEvent::Leave(victim));
+                }
+                changes += 1;
+            }
+            let point = rng.random_range(0.0..total);
+            let rank = cdf.partition_point(|&c| c <= point).min(self.keys - 1);
+            let key = format!("key:{}", rank);
+            events.push(if rng.random_bool(self.write_ratio) {
+                TraceEvent::Set(key)
+            } else {
+                TraceEvent::Get(key)
+            });
+        }
+        events
+    }
+}
+
+/// Ownership movement caused by one `join` or `leave`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MembershipChange {
+    /// Number of requests replayed before the change.
+    pub after_requests: usize,
+    pub event: TraceEvent,
+    /// Keys seen so far whose owner changed.
+    pub migrated: usize,
+    pub keys: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceReport {
+    pub backend: &'static str,
+    pub requests: usize,
+    /// Requests found in the owning node's cache.
+    pub hits: usize,
+    /// Requests made while no node was present.
+    pub unrouted: usize,
+    pub distinct_keys: usize,
+    /// Requests served by the busiest node over the mean of the nodes that
+    /// served any; 1.0 is a perfectly even split.
+    pub load_imbalance: f64,
+    pub membership: Vec<MembershipChange>,
+}
+
+impl TraceReport {
+    pub fn hit_rate(&self) -> f64 {
+        if self.requests == 0 {
+            0.0
+        } else {
+            self.hits as f64 / self.requests as f64
+        }
+    }
+
+    pub fn total_migrated(&self) -> usize {
+        self.membership.iter().map(|change| change.migrated).sum()
+    }
+}
+
+/// Replays `events` against every ring backend in the crate.
+pub fn replay_all(events: &[TraceEvent], k: u32, cache_capacity: usize) -> Vec<TraceReport> {
+    let mut hash_ring: HashRing<i64> = HashRing::new(k);
+    let ring_report = replay(&mut hash_ring, events, k, cache_capacity);
+    hash_ring.remove_all();
+
+    let rendezvous_report = replay(&mut RendezvousBackend::default(), events, k, cache_capacity);
+    let jump_report = replay(&mut JumpBackend::default(), events, k, cache_capacity);
+    let slots_report = replay(&mut SlotsBackend::default(), events, k, cache_capacity);
+    vec![ring_report, rendezvous_report, jump_report, slots_report]
+}
+
+/// Replays `events` against `backend` on a ring of size `2^k`, giving each
+/// node an LRU cache of `cache_capacity` keys.
+///
+/// A request hits when its key is cached on the node that owns it now; a
+/// node's cache is dropped when it leaves, so keys that migrate start cold.
+/// Joins of present nodes and leaves of absent ones are ignored, and so is
+/// the leave of the last node.
+pub fn replay<B: RingBackend>(
+    backend: &mut B,
+    events: &[TraceEvent],
+    k: u32,
+    cache_capacity: usize,
+) -> TraceReport {
+    assert!(k > 0 && k < 63, "k must be in 1..63");
+    assert!(cache_capacity > 0, "cache capacity must be positive");
+    let hasher = Murmur3::with_seed(DEFAULT_SEED);
+    let mask = (1u64 << k) - 1;
+
+    let mut nodes: HashMap<u64, LruCache<u64, ()>> = HashMap::new();
+    let mut served: HashMap<u64, usize> = HashMap::new();
+    let mut keys = HashSet::new();
+    let mut report = TraceReport {
+        backend: backend.name(),
+        requests: 0,
+        hits: 0,
+        unrouted: 0,
+        distinct_keys: 0,
+        load_imbalance: 1.0,
+        membership: Vec::new(),
+    };
+
+    for event in events {
+        match event {
+            TraceEvent::Get(key) | TraceEvent::Set(key) => {
+                report.requests += 1;
+                let position = hasher.hash64(key.as_bytes()) & mask;
+                let Some(owner) = backend.owner(position) else {
+                    report.unrouted += 1;
+                    continue;
+                };
+                if keys.insert(position) {
+                    backend.insert(position);
+                }
+                *served.entry(owner).or_default() += 1;
+                let cache = nodes.get_mut(&owner).expect("owner is a live node");
+                if cache.get(&position).is_some() {
+                    report.hits += 1;
+                } else {
+                    cache.insert(position, ());
+                }
+            }
+            TraceEvent::Join(position) | TraceEvent::Leave(position) => {
+                let joining = matches!(event, TraceEvent::Join(_));
+                let valid = if joining {
+                    *position <= mask && !nodes.contains_key(position)
+                } else {
+                    nodes.contains_key(position) && nodes.len() > 1
+                };
+                if !valid {
+                    continue;
+                }
+                let before = backend.owners();
+                if joining {
+                    backend.join(*position);
+                    nodes.insert(*position, LruCache::new(cache_capacity));
+                } else {
+                    backend.leave(*position);
+                    nodes.remove(position);
+                }
+                let after = backend.owners();
+                let migrated = before
+                    .iter()
+                    .filter(|(key, owner)| after.get(key) != Some(owner))
+                    .count();
+                report.membership.push(MembershipChange {
+                    after_requests: report.requests,
+                    event: event.clone(),
+                    migrated,
+                    keys: after.len(),
+                });
+            }
+        }
+    }
+
+    report.distinct_keys = keys.len();
+    if let Some(&busiest) = served.values().max() {
+        let mean = served.values().sum::<usize>() as f64 / served.len() as f64;
+        report.load_imbalance = busiest as f64 / mean;
+    }
+    report
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::log;
+
+    #[test]
+    fn parse_and_format_round_trip() {
+        let text = "# warm up\njoin 3\n\nget user:1\n  set user:2  \nleave 3\n";
+        let events = parse_trace(text).unwrap();
+        assert_eq!(
+            events,
+            vec![
+                TraceEvent::Join(3),
+                TraceEvent::Get("user:1".to_string()),
+                TraceEvent::Set("user:2".to_string()),
+                TraceEvent::Leave(3),
+            ]
+        );
+        assert_eq!(parse_trace(&format_trace(&events)).unwrap(), events);
+    }
+
+    #[test]
+    fn parse_reports_line_numbers() {
+        let error = parse_trace("join 1\nget\n").unwrap_err();
+        assert_eq!(error.line, 2);
+        assert_eq!(
+            parse_trace("join x").unwrap_err().reason,
+            "invalid position"
+        );
+        assert_eq!(
+            parse_trace("# ok\ndelete a").unwrap_err(),
+            TraceError {
+                line: 2,
+                reason: "unknown operation"
+            }
+        );
+    }
+
+    #[test]
+    fn replay_counts_hits_and_migrations() {
+        log::init_test_logger();
+        let events = SyntheticTrace {
+            k: 12,
+            keys: 500,
+            requests: 5_000,
+            membership_changes: 2,
+            seed: 7,
+            ..Default::default()
+        }
+        .generate();
+        let reports = replay_all(&events, 12, 64);
+        let backends: Vec<&str> = reports.iter().map(|r| r.backend).collect();
+        assert_eq!(backends, vec!["hash_ring", "rendezvous", "jump", "slots"]);
+        for report in &reports {
+            assert_eq!(report.requests, 5_000);
+            assert_eq!(report.unrouted, 0);
+            assert!(report.distinct_keys <= 500);
+            // skewed keys mostly hit the per-node caches
+            assert!(report.hit_rate() > 0.5, "{:?}", report);
+            assert_eq!(report.membership.len(), 8 + 2);
+            assert!(report.total_migrated() > 0);
+            assert!(report.load_imbalance >= 1.0);
+        }
+        assert_eq!(replay_all(&events, 12, 64), reports);
+    }
+
+    #[test]
+    fn replay_ignores_invalid_membership_events() {
+        log::init_test_logger();
+        let events = parse_trace("get a\njoin 5\njoin 5\nleave 9\nleave 5\nget a\nget a").unwrap();
+        let report = replay(&mut RendezvousBackend::default(), &events, 4, 8);
+        assert_eq!(report.requests, 3);
+        assert_eq!(report.unrouted, 1);
+        assert_eq!(report.hits, 1);
+        assert_eq!(report.membership.len(), 1);
+    }
+}
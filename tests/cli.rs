@@ -0,0 +1,77 @@
+//! Argument checks of the `hash_bench` binary: bad flag values end in a
+//! usage error, not a panic.
+
+use std::process::Command;
+
+fn run(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_hash_bench"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn rates_above_one_half_are_usage_errors() {
+    for args in [
+        &["fpr", "--filter", "partitioned_bloom", "--rate", "0.6"][..],
+        &[
+            "compare",
+            "--fpp",
+            "0.7",
+            "--keys",
+            "1000",
+            "--queries",
+            "1000",
+        ],
+    ] {
+        let output = run(args);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.code(), Some(2), "{args:?}: {stderr}");
+        assert!(stderr.contains("at most 0.5"), "{args:?}: {stderr}");
+    }
+}
+
+#[test]
+fn rate_of_one_half_is_accepted() {
+    let output = run(&[
+        "fpr",
+        "--filter",
+        "partitioned_bloom",
+        "--capacity",
+        "1000",
+        "--rate",
+        "0.5",
+        "--lookups",
+        "1000",
+    ]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+fn compare_runs_the_listed_structures() {
+    let output = run(&[
+        "compare",
+        "--structures",
+        "bloom,cuckoo,quotient",
+        "--keys",
+        "1000",
+        "--queries",
+        "1000",
+    ]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let structures: Vec<&str> = stdout
+        .lines()
+        .skip(2)
+        .filter_map(|line| line.split_whitespace().next())
+        .collect();
+    assert_eq!(structures, ["bloom", "cuckoo", "quotient"], "{stdout}");
+}
@@ -0,0 +1,196 @@
+//! False-positive budget of every membership filter.
+//!
+//! Each filter is built at several sizes and filled to several loads, then
+//! probed with millions of items that were never inserted. The measured
+//! false-positive rate must stay within `FPR_BUDGET` (default 1.2) times
+//! the rate the filter's design predicts, so a change to hashing or layout
+//! that silently costs accuracy fails here. The suite is slow outside
+//! release builds, so it is ignored by default:
+//!
+//!     cargo test --release --test fpr_budget -- --ignored
+//!
+//! `FPR_BUDGET_PROBES` (default 2000000) sets the probes per case.
+//!
+//! A quicker check runs by default: Bloom filters of a few `(n, f)`
+//! settings are filled with random keys and the measured rate must be
+//! within `FPR_TOLERANCE` (default 0.2) of the theory, above or below.
+
+use hash_bench::membership::{self, ApproxMembership};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+const SIZES: [u32; 3] = [1_000, 50_000, 1_000_000];
+const LOADS: [f64; 3] = [0.25, 0.5, 1.0];
+const RATES: [f32; 2] = [0.01, 0.001];
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .map(|value| {
+            value
+                .parse()
+                .unwrap_or_else(|_| panic!("invalid {}: {}", name, value))
+        })
+        .unwrap_or(default)
+}
+
+/// Inserts `items` distinct keys and returns the share of `probes` absent
+/// keys the filter accepts. Inserted and probed keys are disjoint ranges.
+fn measure(filter: &mut dyn ApproxMembership, items: u64, probes: u64) -> f64 {
+    for key in 0..items {
+        filter.insert(&key.to_le_bytes());
+    }
+    let hits = (items..items + probes)
+        .filter(|key| filter.lookup(&key.to_le_bytes()))
+        .count();
+    hits as f64 / probes as f64
+}
+
+/// Like [`measure`], with `items` random keys and `probes` random keys
+/// that were never inserted: the top bit tells the two sets apart.
+fn measure_random(filter: &mut dyn ApproxMembership, items: u64, probes: u64, seed: u64) -> f64 {
+    let mut rng = StdRng::seed_from_u64(seed);
+    for _ in 0..items {
+        filter.insert(&(rng.random::<u64>() >> 1).to_le_bytes());
+    }
+    let hits = (0..probes)
+        .filter(|_| filter.lookup(&(rng.random::<u64>() | 1 << 63).to_le_bytes()))
+        .count();
+    hits as f64 / probes as f64
+}
+
+fn check_budget(name: &str) {
+    let budget: f64 = env_or("FPR_BUDGET", 1.2);
+    let probes: u64 = env_or("FPR_BUDGET_PROBES", 2_000_000);
+    let mut failures = Vec::new();
+    for n in SIZES {
+        for f in RATES {
+            for load in LOADS {
+                let mut filter = membership::build(name, n, f).unwrap();
+                let items = (n as f64 * load) as u64;
+                let measured = measure(filter.as_mut(), items, probes);
+                let expected = filter.expected_false_positive_rate(items);
+                // a few standard deviations of slack keeps tiny expected
+                // rates from failing on sampling noise alone
+                let noise = 4.0 * (expected / probes as f64).sqrt();
+                let verdict = if measured <= budget * expected + noise {
+                    "ok"
+                } else {
+                    failures.push((n, f, load, measured, expected));
+                    "OVER"
+                };
+                println!(
+                    "{} n={} f={} load={}: measured {:.6}, expected {:.6} ({:.2}x) {}",
+                    name,
+                    n,
+                    f,
+                    load,
+                    measured,
+                    expected,
+                    measured / expected,
+                    verdict
+                );
+            }
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} exceeds {}x its expected false-positive rate: {:?}",
+        name,
+        budget,
+        failures
+    );
+}
+
+#[test]
+#[ignore]
+fn bloom_stays_within_budget() {
+    check_budget("bloom");
+}
+
+#[test]
+#[ignore]
+fn counting_bloom_stays_within_budget() {
+    check_budget("counting_bloom");
+}
+
+#[test]
+#[ignore]
+fn cuckoo_stays_within_budget() {
+    check_budget("cuckoo");
+}
+
+#[test]
+#[ignore]
+fn deletable_bloom_stays_within_budget() {
+    check_budget("deletable_bloom");
+}
+
+#[test]
+#[ignore]
+fn partitioned_bloom_stays_within_budget() {
+    check_budget("partitioned_bloom");
+}
+
+#[test]
+#[ignore]
+fn quotient_stays_within_budget() {
+    check_budget("quotient");
+}
+
+#[test]
+fn bloom_matches_theory_on_random_keys() {
+    let tolerance: f64 = env_or("FPR_TOLERANCE", 0.2);
+    let probes = 200_000;
+    let mut failures = Vec::new();
+    for (seed, (n, f)) in [
+        (1_000, 0.01),
+        (10_000, 0.01),
+        (10_000, 0.001),
+        (50_000, 0.05),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let mut filter = membership::build("bloom", n, f).unwrap();
+        let measured = measure_random(filter.as_mut(), n as u64, probes, seed as u64);
+        let expected = filter.expected_false_positive_rate(n as u64);
+        let noise = 4.0 * (expected / probes as f64).sqrt();
+        let deviation = (measured - expected).abs();
+        let verdict = if deviation <= tolerance * expected + noise {
+            "ok"
+        } else {
+            failures.push((n, f, measured, expected));
+            "OFF"
+        };
+        println!(
+            "bloom n={} f={} random keys: measured {:.6}, expected {:.6} ({:.2}x) {}",
+            n,
+            f,
+            measured,
+            expected,
+            measured / expected,
+            verdict
+        );
+    }
+    assert!(
+        failures.is_empty(),
+        "bloom deviates from its expected false-positive rate by more than {}: {:?}",
+        tolerance,
+        failures
+    );
+}
+
+#[test]
+fn every_filter_has_a_budget_test() {
+    assert_eq!(
+        membership::FILTERS,
+        [
+            "bloom",
+            "counting_bloom",
+            "cuckoo",
+            "deletable_bloom",
+            "partitioned_bloom",
+            "quotient"
+        ]
+    );
+}